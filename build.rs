@@ -0,0 +1,12 @@
+fn main() {
+    // Only the grpc feature has anything to generate.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/helmet.proto");
+        let files = protox::compile(["proto/helmet.proto"], ["proto"]).unwrap();
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_fds(files)
+            .unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/ingest.rs"]
+#[allow(dead_code)]
+mod ingest;
+
+use ingest::Format;
+
+// Pictures for `POST /display-url` and MBTiles tiles, in whichever format
+// the first byte picks.
+fuzz_target!(|data: &[u8]| {
+    let Some((&pick, data)) = data.split_first() else {
+        return;
+    };
+    let format = if pick & 1 == 0 { Format::Png } else { Format::Jpeg };
+    if let Ok((rgba, (w, h))) = ingest::decode_rgba(data, format) {
+        assert_eq!(rgba.len(), w * h);
+    }
+});
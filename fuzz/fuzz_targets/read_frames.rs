@@ -0,0 +1,19 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/ingest.rs"]
+#[allow(dead_code)]
+mod ingest;
+#[path = "../../src/rawframe.rs"]
+mod rawframe;
+
+// `.frames` uploads, as the library checks them before saving.
+fuzz_target!(|data: &[u8]| {
+    let mut input = Cursor::new(data);
+    while let Ok(Some(frame)) = rawframe::read_frame(&mut input, (64, 64)) {
+        assert_eq!(frame.len(), 64 * 64);
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/ingest.rs"]
+#[allow(dead_code)]
+mod ingest;
+
+// Library images and uploads, as the image and animation modes read them.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = ingest::read_png(data) {
+        ingest::unpack_1bit(&raw);
+    }
+    let _ = ingest::read_png_g(data, (64, 64), [0; 3]);
+});
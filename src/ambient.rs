@@ -0,0 +1,201 @@
+use std::{
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::Result;
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::I2cdev;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, ToSchema)]
+pub struct Ambient {
+    pub lux: f64,
+    // 0 for darkest, up to the number of `steps_lux`.
+    pub level: usize,
+}
+
+pub type AmbientRx = watch::Receiver<Option<Ambient>>;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LightSensor {
+    Bh1750,
+    Tsl2561,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AmbientConfig {
+    // No sensor means no ambient light tracking.
+    pub sensor: Option<LightSensor>,
+    pub bus: String,
+    // Defaults to the sensor's usual address.
+    pub address: Option<u8>,
+    pub poll_millis: u64,
+    // Lux boundaries between levels, ascending.
+    pub steps_lux: Vec<f64>,
+    // How far past a boundary (as a fraction of it) the light has to go
+    // before the level changes, so hovering around one doesn't flicker.
+    pub hysteresis: f64,
+    // Follow the level with the threshold grey frames are cut at: stricter
+    // in the dark, looser in daylight.
+    pub adjust_threshold: bool,
+}
+
+impl Default for AmbientConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            bus: "/dev/i2c-1".to_owned(),
+            address: None,
+            poll_millis: 500,
+            steps_lux: vec![10.0, 200.0, 2000.0],
+            hysteresis: 0.2,
+            adjust_threshold: false,
+        }
+    }
+}
+
+impl AmbientConfig {
+    // Threshold for `level`, spread evenly from 0xC0 in the dark to 0x40 in
+    // the brightest level.
+    pub fn threshold(&self, level: usize) -> u8 {
+        let top = self.steps_lux.len().max(1);
+        let level = level.min(top);
+        (0xC0 - ((0xC0 - 0x40) * level / top)) as u8
+    }
+}
+
+// Moves at most one boundary per reading, and only once `lux` is clearly
+// past it.
+fn next_level(config: &AmbientConfig, level: usize, lux: f64) -> usize {
+    let steps = &config.steps_lux;
+    if level < steps.len() && lux > steps[level] * (1.0 + config.hysteresis) {
+        level + 1
+    } else if level > 0 && lux < steps[level - 1] * (1.0 - config.hysteresis) {
+        level - 1
+    } else {
+        level
+    }
+}
+
+// Without hysteresis, for the first reading.
+fn initial_level(config: &AmbientConfig, lux: f64) -> usize {
+    config.steps_lux.iter().filter(|&&step| lux > step).count()
+}
+
+const BH1750_ADDR: u8 = 0x23;
+// Continuous high-resolution mode, 1 lux per 1.2 counts.
+const BH1750_CONTINUOUS_HIGH_RES: u8 = 0x10;
+const BH1750_COUNTS_PER_LUX: f64 = 1.2;
+
+const TSL2561_ADDR: u8 = 0x39;
+const TSL2561_COMMAND: u8 = 0x80;
+const TSL2561_WORD: u8 = 0x20;
+
+enum Sensor {
+    Bh1750(I2cdev, u8),
+    Tsl2561(I2cdev, u8),
+}
+
+impl Sensor {
+    fn open(config: &AmbientConfig, sensor: LightSensor) -> Result<Self> {
+        let mut i2c = I2cdev::new(&config.bus)?;
+        Ok(match sensor {
+            LightSensor::Bh1750 => {
+                let addr = config.address.unwrap_or(BH1750_ADDR);
+                i2c.write(addr, &[BH1750_CONTINUOUS_HIGH_RES])?;
+                Sensor::Bh1750(i2c, addr)
+            }
+            LightSensor::Tsl2561 => {
+                let addr = config.address.unwrap_or(TSL2561_ADDR);
+                // Power on, with the default 402ms integration and 1x gain.
+                i2c.write(addr, &[TSL2561_COMMAND, 0x03])?;
+                Sensor::Tsl2561(i2c, addr)
+            }
+        })
+    }
+
+    fn lux(&mut self) -> Result<f64> {
+        match self {
+            Sensor::Bh1750(i2c, addr) => {
+                let mut buf = [0u8; 2];
+                i2c.read(*addr, &mut buf)?;
+                Ok(u16::from_be_bytes(buf) as f64 / BH1750_COUNTS_PER_LUX)
+            }
+            Sensor::Tsl2561(i2c, addr) => {
+                let mut read = |reg: u8| -> Result<f64> {
+                    let mut buf = [0u8; 2];
+                    i2c.write_read(*addr, &[TSL2561_COMMAND | TSL2561_WORD | reg], &mut buf)?;
+                    Ok(u16::from_le_bytes(buf) as f64)
+                };
+                // Broadband and infrared-only channels.
+                let (ch0, ch1) = (read(0x0C)?, read(0x0E)?);
+                Ok(tsl2561_lux(ch0, ch1))
+            }
+        }
+    }
+}
+
+// The datasheet's empirical formula for the T/FN/CL package. It's given for
+// 16x gain, hence the scaling at 1x.
+fn tsl2561_lux(ch0: f64, ch1: f64) -> f64 {
+    if ch0 == 0.0 {
+        return 0.0;
+    }
+    let ratio = ch1 / ch0;
+    let lux = if ratio <= 0.5 {
+        (0.0304 * ch0) - (0.062 * ch0 * ratio.powf(1.4))
+    } else if ratio <= 0.61 {
+        (0.0224 * ch0) - (0.031 * ch1)
+    } else if ratio <= 0.80 {
+        (0.0128 * ch0) - (0.0153 * ch1)
+    } else if ratio <= 1.30 {
+        (0.00146 * ch0) - (0.00112 * ch1)
+    } else {
+        0.0
+    };
+    (lux * 16.0).max(0.0)
+}
+
+// Polls the light sensor on its own thread, like the IMU.
+//
+// The panel firmware has no brightness command, so the level can't dim the
+// LEDs themselves; `adjust_threshold` is the only thing that acts on it.
+pub fn spawn_poller(config: &AmbientConfig) -> Result<Option<AmbientRx>> {
+    let Some(kind) = config.sensor else {
+        return Ok(None);
+    };
+    let mut sensor = Sensor::open(config, kind)?;
+    println!("[ambient] Polling {kind:?} on {}...", config.bus);
+    let (tx, rx) = watch::channel(None);
+    let config = config.clone();
+    spawn(move || {
+        let mut level = None;
+        loop {
+            sleep(Duration::from_millis(config.poll_millis.max(1)));
+            let lux = match sensor.lux() {
+                Ok(lux) => lux,
+                Err(e) => {
+                    println!("[ambient] Read failed: {e}");
+                    continue;
+                }
+            };
+            let next = match level {
+                Some(level) => next_level(&config, level, lux),
+                None => initial_level(&config, lux),
+            };
+            if level != Some(next) {
+                println!("[ambient] Level {next} at {lux:.0} lux.");
+            }
+            level = Some(next);
+            if tx.send(Some(Ambient { lux, level: next })).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(Some(rx))
+}
@@ -0,0 +1,191 @@
+use std::{
+    fs,
+    path::PathBuf,
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::I2cdev;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::events::{self, HelmetEvent};
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, ToSchema)]
+pub struct Reading {
+    pub percent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volts: Option<f64>,
+    // Only known for sources that report it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charging: Option<bool>,
+}
+
+impl Reading {
+    // Charging never counts as low, however empty.
+    pub fn is_low(&self, low_percent: f64) -> bool {
+        self.percent < low_percent && self.charging != Some(true)
+    }
+}
+
+pub type BatteryRx = watch::Receiver<Reading>;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    // A kernel power_supply directory, e.g. from a UPS HAT driver
+    Sysfs,
+    // Fuel gauge; reports state of charge directly
+    Max17048,
+    // Voltage only; charge is estimated between `empty_volts` and
+    // `full_volts`
+    Ina219,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    // No source means no battery monitoring.
+    pub source: Option<Source>,
+    pub sysfs_path: PathBuf,
+    pub bus: String,
+    // Defaults to the chip's usual address.
+    pub address: Option<u8>,
+    pub poll_secs: u64,
+    // Below this the whole panel turns into a warning.
+    pub low_percent: f64,
+    // Show a small battery icon in the corner.
+    pub icon: bool,
+    pub empty_volts: f64,
+    pub full_volts: f64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            source: None,
+            sysfs_path: PathBuf::from("/sys/class/power_supply/BAT0"),
+            bus: "/dev/i2c-1".to_owned(),
+            address: None,
+            poll_secs: 30,
+            low_percent: 10.0,
+            icon: true,
+            empty_volts: 3.3,
+            full_volts: 4.2,
+        }
+    }
+}
+
+const MAX17048_ADDR: u8 = 0x36;
+// VCELL is in units of 78.125uV.
+const MAX17048_VOLTS_PER_LSB: f64 = 78.125e-6;
+
+const INA219_ADDR: u8 = 0x40;
+// The bus voltage register holds millivolts / 4 in its top 13 bits.
+const INA219_VOLTS_PER_LSB: f64 = 0.004;
+
+fn read_u16(i2c: &mut I2cdev, addr: u8, reg: u8) -> Result<u16> {
+    let mut buf = [0u8; 2];
+    i2c.write_read(addr, &[reg], &mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_sysfs(config: &BatteryConfig) -> Result<Reading> {
+    let read = |name: &str| -> Result<String> {
+        let path = config.sysfs_path.join(name);
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(text.trim().to_owned())
+    };
+    let percent = read("capacity")?.parse()?;
+    // Microvolts, when the driver bothers.
+    let volts = read("voltage_now").ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .map(|uv| uv / 1e6);
+    let charging = read("status").ok().map(|s| s == "Charging" || s == "Full");
+    Ok(Reading { percent, volts, charging })
+}
+
+// An opened `Source`.
+enum Gauge {
+    Sysfs,
+    Max17048(I2cdev, u8),
+    Ina219(I2cdev, u8),
+}
+
+impl Gauge {
+    fn open(config: &BatteryConfig, source: Source) -> Result<Self> {
+        Ok(match source {
+            Source::Sysfs => Gauge::Sysfs,
+            Source::Max17048 => Gauge::Max17048(
+                I2cdev::new(&config.bus)?,
+                config.address.unwrap_or(MAX17048_ADDR),
+            ),
+            Source::Ina219 => Gauge::Ina219(
+                I2cdev::new(&config.bus)?,
+                config.address.unwrap_or(INA219_ADDR),
+            ),
+        })
+    }
+
+    fn read(&mut self, config: &BatteryConfig) -> Result<Reading> {
+        match self {
+            Gauge::Sysfs => read_sysfs(config),
+            Gauge::Max17048(i2c, addr) => {
+                let volts = read_u16(i2c, *addr, 0x02)? as f64 * MAX17048_VOLTS_PER_LSB;
+                // Whole percent in the high byte, 1/256ths in the low.
+                let percent = read_u16(i2c, *addr, 0x04)? as f64 / 256.0;
+                Ok(Reading { percent: percent.min(100.0), volts: Some(volts), charging: None })
+            }
+            Gauge::Ina219(i2c, addr) => {
+                let volts = (read_u16(i2c, *addr, 0x02)? >> 3) as f64 * INA219_VOLTS_PER_LSB;
+                let range = config.full_volts - config.empty_volts;
+                let percent = ((volts - config.empty_volts) / range * 100.0).clamp(0.0, 100.0);
+                Ok(Reading { percent, volts: Some(volts), charging: None })
+            }
+        }
+    }
+}
+
+// Polls the battery on its own thread, like the IMU.
+pub fn spawn_poller(config: &BatteryConfig) -> Result<Option<BatteryRx>> {
+    let Some(source) = config.source else {
+        return Ok(None);
+    };
+    let mut gauge = Gauge::open(config, source)?;
+    // Fail at startup, not silently in the background, if it's misconfigured.
+    let first = gauge.read(config)?;
+    println!("[battery] {source:?} at {:.0}%.", first.percent);
+    let mut was_low = check_low(&first, config, false);
+    let (tx, rx) = watch::channel(first);
+    let config = config.clone();
+    spawn(move || {
+        loop {
+            sleep(Duration::from_secs(config.poll_secs.max(1)));
+            match gauge.read(&config) {
+                Ok(reading) => {
+                    was_low = check_low(&reading, &config, was_low);
+                    if tx.send(reading).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => println!("[battery] Read failed: {e}"),
+            }
+        }
+    });
+    Ok(Some(rx))
+}
+
+// Whether `reading` is low, telling event listeners if it only just became
+// so.
+fn check_low(reading: &Reading, config: &BatteryConfig, was_low: bool) -> bool {
+    let low = reading.is_low(config.low_percent);
+    if low && !was_low {
+        println!("[battery] Low, at {:.0}%.", reading.percent);
+        events::publish(HelmetEvent::LowBattery { percent: reading.percent });
+    }
+    low
+}
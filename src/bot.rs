@@ -0,0 +1,277 @@
+use std::{
+    thread::{sleep, spawn},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::Sender;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    jobs::{self, Priority},
+    mode,
+    picture::{self, DisplayUrlConfig},
+    session,
+    Update,
+    BOT_IMAGE_FILENAME,
+    PANEL_DIMS,
+};
+
+const API: &str = "https://api.telegram.org";
+
+const RETRY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    // From @BotFather. None means no bot.
+    pub token: Option<String>,
+    // Telegram user IDs the bot takes messages from; anyone else is ignored.
+    pub allowed_users: Vec<i64>,
+    // How long each request for new messages waits for one to come in.
+    pub poll_secs: u64,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self { token: None, allowed_users: vec![], poll_secs: 30 }
+    }
+}
+
+impl BotConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.token.is_some() && self.allowed_users.is_empty() {
+            bail!("bot.allowed_users: needed with a token, or nobody could use the bot");
+        }
+        if self.poll_secs == 0 {
+            bail!("bot.poll_secs: must be above 0");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BotUpdate {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    // Unix seconds.
+    date: u64,
+    chat: Chat,
+    from: Option<User>,
+    text: Option<String>,
+    caption: Option<String>,
+    // The same photo at several sizes, smallest first.
+    photo: Option<Vec<PhotoSize>>,
+    // Images sent as files, which Telegram leaves uncompressed.
+    document: Option<Document>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct PhotoSize {
+    file_id: String,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    file_id: String,
+    mime_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FileInfo {
+    file_path: String,
+}
+
+// Talks to Telegram's bot API on its own thread. Text sent to the bot is
+// shown in the text mode and pictures in the image mode, as over HTTP, and
+// /mode <name> switches modes.
+pub fn spawn_bot(config: &BotConfig, images: &DisplayUrlConfig, tx: &'static Sender<Update>) {
+    let Some(token) = config.token.clone() else {
+        return;
+    };
+    let bot = Bot {
+        token,
+        config: config.clone(),
+        images: images.clone(),
+        agent: ureq::Agent::config_builder()
+            // Long enough for the long poll, and then some.
+            .timeout_global(Some(Duration::from_secs(config.poll_secs + 10)))
+            .build()
+            .new_agent(),
+        tx,
+    };
+    spawn(move || {
+        // Messages from before starting up are left unanswered.
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut offset = 0;
+        // Only logged once while it keeps failing the same way.
+        let mut last_error = None;
+        println!("[bot] Polling Telegram...");
+        loop {
+            let updates: Vec<BotUpdate> = match bot.call(&format!(
+                "getUpdates?offset={offset}&timeout={}&allowed_updates=[\"message\"]",
+                bot.config.poll_secs,
+            )) {
+                Ok(updates) => updates,
+                Err(e) => {
+                    let error = e.to_string();
+                    if last_error.as_ref() != Some(&error) {
+                        println!("[bot] Polling failed: {error}");
+                        last_error = Some(error);
+                    }
+                    sleep(RETRY);
+                    continue;
+                }
+            };
+            last_error = None;
+            for update in updates {
+                offset = update.update_id + 1;
+                let Some(message) = update.message.filter(|m| m.date >= started) else {
+                    continue;
+                };
+                let user = message.from.as_ref().map(|user| user.id);
+                if !user.is_some_and(|id| bot.config.allowed_users.contains(&id)) {
+                    println!("[bot] Ignoring a message from user {user:?}.");
+                    continue;
+                }
+                let reply = match bot.handle(&message) {
+                    Ok(reply) => reply,
+                    Err(e) => format!("Couldn't: {e:#}"),
+                };
+                if let Err(e) = bot.send_message(message.chat.id, &reply) {
+                    println!("[bot] Replying failed: {e}");
+                }
+            }
+        }
+    });
+}
+
+struct Bot {
+    token: String,
+    config: BotConfig,
+    images: DisplayUrlConfig,
+    agent: ureq::Agent,
+    tx: &'static Sender<Update>,
+}
+
+impl Bot {
+    // The token is part of every URL, so these never go in errors or logs.
+    fn call<T: DeserializeOwned>(&self, method: &str) -> Result<T> {
+        let url = format!("{API}/bot{}/{method}", self.token);
+        let body = self.agent.get(&url).call()?.body_mut().read_to_string()?;
+        unwrap(serde_json::from_str(&body)?)
+    }
+
+    fn send_message(&self, chat: i64, text: &str) -> Result<()> {
+        let url = format!("{API}/bot{}/sendMessage", self.token);
+        let body = serde_json::json!({ "chat_id": chat, "text": text }).to_string();
+        let mut response = self.agent.post(&url)
+            .header("Content-Type", "application/json")
+            .send(&body)?;
+        let response = response.body_mut().read_to_string()?;
+        unwrap::<serde_json::Value>(serde_json::from_str(&response)?)?;
+        Ok(())
+    }
+
+    fn show(&self, update: Update) -> Result<()> {
+        session::ensure_free()?;
+        jobs::send(self.tx, update, Priority::Normal)
+    }
+
+    // What to answer `message` with.
+    fn handle(&self, message: &Message) -> Result<String> {
+        if let Some(text) = &message.text {
+            if let Some(command) = text.strip_prefix('/') {
+                return self.command(command);
+            }
+            println!("[bot] Showing text...");
+            self.show(Update::Text { text: text.clone() })?;
+            return Ok("Showing it.".to_owned());
+        }
+        let file_id = match (&message.photo, &message.document) {
+            (Some(sizes), _) => sizes.last().map(|size| &size.file_id),
+            (None, Some(document)) => match document.mime_type.as_deref() {
+                Some("image/png" | "image/jpeg") => Some(&document.file_id),
+                _ => bail!("only PNG and JPEG files can be shown"),
+            },
+            (None, None) => None,
+        };
+        let Some(file_id) = file_id else {
+            bail!("only text and pictures can be shown");
+        };
+        println!("[bot] Downloading picture...");
+        let file: FileInfo = self.call(&format!("getFile?file_id={file_id}"))?;
+        let url = format!("{API}/file/bot{}/{}", self.token, file.file_path);
+        let data = self.agent.get(&url).call()?
+            .body_mut()
+            .with_config()
+            .limit(self.images.max_kb * 1024)
+            .read_to_vec()?;
+        let Some(format) = picture::sniff(&data) else {
+            bail!("only PNG and JPEG pictures can be shown");
+        };
+        let (image, dims) = picture::decode(&data, format, picture::background())?;
+        let frame = picture::fit(&image, dims, PANEL_DIMS);
+        picture::save_png(&frame, PANEL_DIMS, BOT_IMAGE_FILENAME)?;
+        self.show(Update::SendFile { path: BOT_IMAGE_FILENAME.into() })?;
+        // A caption goes nowhere; the panel can only show one thing.
+        Ok(match message.caption {
+            Some(_) => "Showing the picture, without its caption.".to_owned(),
+            None => "Showing it.".to_owned(),
+        })
+    }
+
+    // `command` without its slash, e.g. "mode clock".
+    fn command(&self, command: &str) -> Result<String> {
+        let mut words = command.split_whitespace();
+        // In groups, commands can come as /mode@SomeBot.
+        let name = words.next().unwrap_or_default();
+        let name = name.split('@').next().unwrap_or_default();
+        match (name, words.next()) {
+            ("mode", Some(mode)) => {
+                if mode::lookup(mode).is_none() {
+                    bail!("there's no {mode} mode; /modes lists them");
+                }
+                println!("[bot] Switching to {mode} mode...");
+                self.show(Update::Mode { mode: mode.to_owned(), params: None })?;
+                Ok(format!("Switching to {mode} mode."))
+            }
+            ("modes", None) => Ok(mode::REGISTRY.iter()
+                .map(|info| format!("{} - {}", info.name, info.about))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            _ => Ok(concat!(
+                "Send text or a picture to show it, ",
+                "/mode <name> to switch modes or /modes to list them.",
+            ).to_owned()),
+        }
+    }
+}
+
+fn unwrap<T>(response: Response<T>) -> Result<T> {
+    match (response.ok, response.result) {
+        (true, Some(result)) => Ok(result),
+        _ => bail!("{}", response.description.unwrap_or_else(|| "request failed".into())),
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+        OnceLock,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+
+// A capture is this, then the Unix time it started in microseconds (u64),
+// then one record per read or write: b'>' for bytes sent to the MCU or b'<'
+// for bytes from it, microseconds since the start (u64), the length (u32)
+// and the bytes. Everything is little-endian.
+const MAGIC: &[u8; 8] = b"FHCAP\0\0\x01";
+
+const SENT: u8 = b'>';
+const RECEIVED: u8 = b'<';
+
+struct Capture {
+    out: Mutex<File>,
+    start: Instant,
+    failed: AtomicBool,
+}
+
+static CAPTURE: OnceLock<Capture> = OnceLock::new();
+
+pub fn init(path: &Path) -> Result<()> {
+    let mut out = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    out.write_all(MAGIC)?;
+    out.write_all(&(since_epoch.as_micros() as u64).to_le_bytes())?;
+    println!("[capture] Recording serial traffic to {}...", path.display());
+    let capture = Capture { out: Mutex::new(out), start: Instant::now(), failed: false.into() };
+    let _ = CAPTURE.set(capture);
+    Ok(())
+}
+
+fn record(direction: u8, data: &[u8]) {
+    let Some(capture) = CAPTURE.get() else {
+        return;
+    };
+    if data.is_empty() || capture.failed.load(Ordering::Relaxed) {
+        return;
+    }
+    let at = capture.start.elapsed().as_micros() as u64;
+    let mut entry = Vec::with_capacity(13 + data.len());
+    entry.push(direction);
+    entry.extend_from_slice(&at.to_le_bytes());
+    entry.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    entry.extend_from_slice(data);
+    // In one go, unbuffered, so nothing is lost if the process exits
+    // mid-frame.
+    if let Err(e) = capture.out.lock().unwrap().write_all(&entry) {
+        println!("[capture] Couldn't write the capture, so stopping it: {e}");
+        capture.failed.store(true, Ordering::Relaxed);
+    }
+}
+
+// Passes everything through to the inner port, noting down what got
+// through if capturing.
+pub struct Captured<T>(pub T);
+
+impl<T: Read> Read for Captured<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        record(RECEIVED, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Captured<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        record(SENT, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Prints a capture as hex and ASCII, 16 bytes to a line, each record headed
+// by its time since the start and which way it went.
+pub fn dump(path: &Path) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut input = BufReader::new(file);
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic).context("reading the header")?;
+    if &magic != MAGIC {
+        bail!("{} isn't a --capture file", path.display());
+    }
+    let started = read_u64(&mut input)?;
+    writeln!(out, "Started at {}.{:06} (Unix time)", started / 1_000_000, started % 1_000_000)?;
+    let (mut sent, mut received) = (0, 0);
+    loop {
+        let mut direction = [0];
+        if input.read(&mut direction)? == 0 {
+            break;
+        }
+        let at = read_u64(&mut input)?;
+        let mut len = [0; 4];
+        input.read_exact(&mut len)?;
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        input.read_exact(&mut data).context("the last record is cut short")?;
+        let arrow = match direction[0] {
+            SENT => {
+                sent += data.len();
+                "->"
+            }
+            RECEIVED => {
+                received += data.len();
+                "<-"
+            }
+            other => bail!("unknown direction {other:#04x} in the capture"),
+        };
+        let time = format!("{}.{:06}", at / 1_000_000, at % 1_000_000);
+        for (i, line) in data.chunks(16).enumerate() {
+            let hex: Vec<_> = line.iter().map(|byte| format!("{byte:02x}")).collect();
+            let ascii: String = line.iter()
+                .map(|&byte| match byte {
+                    b' ' | b'!'..=b'~' => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            let (time, arrow) = if i == 0 { (time.as_str(), arrow) } else { ("", "") };
+            writeln!(out, "{time:>14} {arrow:2} {:04x}  {:<47}  |{ascii}|", i * 16, hex.join(" "))?;
+        }
+    }
+    writeln!(out, "{sent} bytes sent, {received} received")?;
+    Ok(())
+}
+
+fn read_u64(input: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
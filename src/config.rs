@@ -0,0 +1,235 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    ambient::AmbientConfig,
+    battery::BatteryConfig,
+    bot::BotConfig,
+    cors::CorsConfig,
+    eyes::EyesConfig,
+    fault::FaultConfig,
+    flash::FlashConfig,
+    gps::GpsConfig,
+    grpc::GrpcConfig,
+    heart_rate::HeartRateConfig,
+    hooks::HooksConfig,
+    hotplug::HotplugConfig,
+    http_limits::HttpLimitsConfig,
+    imu::ImuConfig,
+    input::InputConfig,
+    jobs::JobsConfig,
+    layout::Layout,
+    library::LibraryConfig,
+    low_power::LowPowerConfig,
+    mask::MaskConfig,
+    mdns::MdnsConfig,
+    mode::{
+        audio::AudioConfig,
+        calendar::CalendarConfig,
+        camera::CameraOpts,
+        clock::ClockConfig,
+        console::ConsoleConfig,
+        headlines::HeadlinesConfig,
+        hud::HudConfig,
+        life::LifeConfig,
+        map::MapConfig,
+        morse::MorseConfig,
+        navigate::NavigationConfig,
+        now_playing::NowPlayingConfig,
+        pomodoro::PomodoroConfig,
+        receive::ReceiveConfig,
+        screensaver::ScreensaverConfig,
+        speedometer::SpeedometerConfig,
+        split::SplitConfig,
+        test_pattern::TestPatternConfig,
+        timer::TimerConfig,
+        vehicle::VehicleConfig,
+        vnc::VncConfig,
+        weather::WeatherConfig,
+    },
+    obd::ObdConfig,
+    overlay::{compass::CompassConfig, temporal::TemporalDitherConfig},
+    picture::{DisplayUrlConfig, ImagesConfig},
+    protocol::Protocol,
+    safety::SafetyConfig,
+    schedule::Schedule,
+    session::SessionConfig,
+    splash::SplashConfig,
+    state::StateConfig,
+    track::TrackConfig,
+    transition::TransitionConfig,
+    waypoint::WaypointConfig,
+    webhook::WebhooksConfig,
+    ttf::FontConfig,
+    wiring::WiringConfig,
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "fett-helmet.toml";
+
+// Mode settings never shown over HTTP.
+const SECRET_PARAMS: &[&str] = &["api_key", "password"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub schedule: Schedule,
+    pub screensaver: ScreensaverConfig,
+    pub low_power: LowPowerConfig,
+    pub mode: ModesConfig,
+    pub imu: ImuConfig,
+    pub compass: CompassConfig,
+    pub temporal_dither: TemporalDitherConfig,
+    pub transition: TransitionConfig,
+    pub safety: SafetyConfig,
+    pub battery: BatteryConfig,
+    pub ambient: AmbientConfig,
+    pub font: FontConfig,
+    pub library: LibraryConfig,
+    pub display_url: DisplayUrlConfig,
+    pub images: ImagesConfig,
+    pub mask: MaskConfig,
+    pub wiring: WiringConfig,
+    pub eyes: EyesConfig,
+    pub protocol: Protocol,
+    pub flash: FlashConfig,
+    pub hotplug: HotplugConfig,
+    pub jobs: JobsConfig,
+    pub input: InputConfig,
+    pub state: StateConfig,
+    pub track: TrackConfig,
+    pub waypoints: WaypointConfig,
+    pub gps: GpsConfig,
+    pub heart_rate: HeartRateConfig,
+    pub obd: ObdConfig,
+    pub hooks: HooksConfig,
+    pub webhooks: WebhooksConfig,
+    pub bot: BotConfig,
+    pub cors: CorsConfig,
+    pub http_limits: HttpLimitsConfig,
+    pub session: SessionConfig,
+    pub grpc: GrpcConfig,
+    pub mdns: MdnsConfig,
+    pub splash: SplashConfig,
+    pub faults: FaultConfig,
+}
+
+// One `[mode.<name>]` section per mode that has settings, named as in the
+// registry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModesConfig {
+    pub map: MapConfig,
+    pub navigate: NavigationConfig,
+    pub clock: ClockConfig,
+    pub hud: HudConfig,
+    // How messages sent to the text mode are laid out.
+    pub text: Layout,
+    pub life: LifeConfig,
+    pub audio: AudioConfig,
+    pub weather: WeatherConfig,
+    pub now_playing: NowPlayingConfig,
+    pub calendar: CalendarConfig,
+    pub headlines: HeadlinesConfig,
+    pub camera: CameraOpts,
+    pub test_pattern: TestPatternConfig,
+    pub console: ConsoleConfig,
+    pub speedometer: SpeedometerConfig,
+    pub vehicle: VehicleConfig,
+    pub receive: ReceiveConfig,
+    pub vnc: VncConfig,
+    pub timer: TimerConfig,
+    pub pomodoro: PomodoroConfig,
+    pub morse: MorseConfig,
+    pub split: SplitConfig,
+}
+
+impl Config {
+    // A missing file is not an error; everything has a default.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)?;
+        let config: Self = toml::from_str(&text)
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        config.validate()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.schedule.validate()?;
+        self.protocol.validate()?;
+        self.eyes.validate()?;
+        self.input.validate()?;
+        self.waypoints.validate()?;
+        self.temporal_dither.validate()?;
+        self.transition.validate()?;
+        self.safety.validate()?;
+        self.low_power.validate()?;
+        self.hooks.validate()?;
+        self.webhooks.validate()?;
+        self.bot.validate()?;
+        self.cors.validate()?;
+        self.http_limits.validate()?;
+        self.grpc.validate()?;
+        self.mdns.validate()?;
+        self.heart_rate.validate()?;
+        self.faults.validate()?;
+        self.mode.validate()
+    }
+}
+
+impl ModesConfig {
+    fn validate(&self) -> Result<()> {
+        self.map.validate()?;
+        self.clock.validate()?;
+        self.weather.validate()?;
+        self.calendar.validate()?;
+        self.headlines.validate()?;
+        self.life.validate()?;
+        self.vehicle.validate()?;
+        self.receive.validate()?;
+        self.timer.validate()?;
+        self.pomodoro.validate()?;
+        self.morse.validate()?;
+        self.split.validate()
+    }
+
+    // The settings of mode `name` as a JSON object, or `None` for a mode
+    // that has none. Secrets are left out.
+    pub fn params(&self, name: &str) -> Option<Value> {
+        let mut all = serde_json::to_value(self).ok()?;
+        let mut section = all.get_mut(name).map(Value::take)?;
+        if let Value::Object(fields) = &mut section {
+            for secret in SECRET_PARAMS {
+                fields.remove(*secret);
+            }
+        }
+        Some(section)
+    }
+
+    // A copy with the fields of `params`, a JSON object, replacing those of
+    // mode `name`'s settings.
+    pub fn with_params(&self, name: &str, params: &Value) -> Result<Self> {
+        let Value::Object(params) = params else {
+            bail!("parameters must be a JSON object");
+        };
+        let mut all = serde_json::to_value(self)?;
+        let Some(Value::Object(section)) = all.get_mut(name) else {
+            if params.is_empty() {
+                return Ok(self.clone());
+            }
+            bail!("{name} mode takes no parameters");
+        };
+        section.extend(params.clone());
+        let modes: Self = serde_json::from_value(all)
+            .with_context(|| format!("mode.{name}"))?;
+        modes.validate()?;
+        Ok(modes)
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use crossbeam_channel::{bounded, Sender};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+use crate::{
+    jobs::{self, Priority},
+    mode,
+    session,
+    Update,
+};
+
+// Also the body of HTTP replies that can fail.
+#[derive(Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// One line from a client: an `Update`, and for things to show, optionally
+// where they go in the queue, e.g.
+// `{"cmd": "text", "text": "turn back", "priority": "alert"}`.
+#[derive(Deserialize)]
+struct Command {
+    #[serde(flatten)]
+    update: Update,
+    #[serde(default)]
+    priority: Priority,
+}
+
+// Where the control socket goes: the user's runtime directory if there is
+// one, else a directory of the server's own under /run.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("fett-helmet.sock"),
+        None => PathBuf::from("/run/fett-helmet/fett-helmet.sock"),
+    }
+}
+
+// Accepts newline-delimited JSON commands (see `Update`) on a Unix socket
+// and answers each line with a one-line JSON reply.
+pub async fn serve_control_socket(
+    path: impl AsRef<Path>,
+    tx: &'static Sender<Update>,
+) -> Result<()> {
+    let path = path.as_ref();
+    // Anyone who can connect can show any file the server can read, or flash
+    // the MCU, so nobody but its user gets near it.
+    if let Some(dir) = path.parent() {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    // A socket left behind by a previous run would make bind fail.
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    println!("[control socket] Listening on {}...", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, tx).await {
+                println!("[control socket] Client error: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    stream: UnixStream,
+    tx: &'static Sender<Update>,
+) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = serde_json::from_str::<Command>(&line);
+        let reply = match command.map(|command| (command.update, command.priority)) {
+            Ok((Update::Mode { mode, .. }, _)) if mode::lookup(&mode).is_none() => {
+                Reply { ok: false, error: Some(format!("unknown mode {mode:?}")) }
+            }
+            // Flashing takes the panel away from whoever has control too.
+            Ok((Update::Flash { .. }, _)) if session::held() => {
+                let error = session::ensure_free().err().map(|e| format!("{e:#}"));
+                Reply { ok: error.is_none(), error }
+            }
+            Ok((Update::Flash { firmware, .. }, _)) => {
+                println!("[control socket] Flashing {}...", firmware.display());
+                let (reply_tx, reply_rx) = bounded(1);
+                // Handing it over waits on the manager, like the reply below,
+                // so both are done off the runtime.
+                let update = Update::Flash { firmware, reply: Some(reply_tx) };
+                tokio::task::spawn_blocking(move || tx.send(update)).await??;
+                // Answered once flashing is done, which can take a while.
+                match tokio::task::spawn_blocking(move || reply_rx.recv()).await? {
+                    Ok(Ok(())) => Reply { ok: true, error: None },
+                    Ok(Err(e)) => Reply { ok: false, error: Some(e) },
+                    Err(e) => Reply { ok: false, error: Some(e.to_string()) },
+                }
+            }
+            Ok((update, priority)) => {
+                println!("[control socket] Queueing...");
+                let sent = tokio::task::spawn_blocking(move || {
+                    session::ensure_free().and_then(|()| jobs::send(tx, update, priority))
+                });
+                match sent.await? {
+                    Ok(()) => Reply { ok: true, error: None },
+                    Err(e) => Reply { ok: false, error: Some(format!("{e:#}")) },
+                }
+            }
+            Err(e) => Reply { ok: false, error: Some(e.to_string()) },
+        };
+        let mut out = serde_json::to_vec(&reply)?;
+        out.push(b'\n');
+        write.write_all(&out).await?;
+    }
+    Ok(())
+}
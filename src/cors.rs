@@ -0,0 +1,82 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use warp::{
+    http::{header, HeaderValue, StatusCode},
+    reply::Response,
+    Reply,
+};
+
+// How long a browser may remember a preflight's answer.
+const MAX_AGE_SECS: u32 = 600;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    // Origins other than the helmet's own whose pages may call the API, e.g.
+    // "https://example.com", or "*" for any. None means only the control page
+    // served from here can.
+    pub allowed_origins: Vec<String>,
+}
+
+impl CorsConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (i, origin) in self.allowed_origins.iter().enumerate() {
+            if origin == "*" {
+                continue;
+            }
+            let Some((_, host)) = origin.split_once("://") else {
+                bail!("cors.allowed_origins[{i}]: must be \"*\" or e.g. \"https://example.com\"");
+            };
+            if host.is_empty() || host.contains('/') {
+                bail!("cors.allowed_origins[{i}]: must be only a scheme and host, with no path");
+            }
+        }
+        Ok(())
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+// `reply`, saying the browser may hand it to `origin`'s page if that's
+// allowed. Otherwise it goes out as it is, which a browser keeps from
+// other origins' pages; same-origin requests are unaffected either way.
+pub fn allow(config: &CorsConfig, origin: Option<String>, reply: impl Reply) -> Response {
+    let mut response = reply.into_response();
+    if let Some(origin) = origin.filter(|origin| config.allows(origin)) {
+        add_origin(&mut response, &origin);
+    }
+    response
+}
+
+// The answer to a browser asking whether `origin`'s page may make a
+// request other than a simple GET.
+pub fn preflight(config: &CorsConfig, origin: String) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if !config.allows(&origin) {
+        return response;
+    }
+    add_origin(&mut response, &origin);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, DELETE"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("content-type, x-session"),
+    );
+    headers.insert(header::ACCESS_CONTROL_MAX_AGE, MAX_AGE_SECS.into());
+    response
+}
+
+fn add_origin(response: &mut Response, origin: &str) {
+    let Ok(value) = HeaderValue::from_str(origin) else {
+        return;
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
+    // The answer depends on who's asking, so caches mustn't share it.
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+}
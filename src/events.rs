@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::input::InputEvent;
+
+// What `GET /api/v1/events` tells its WebSocket clients about.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HelmetEvent {
+    // Sent first on connecting, so a client needn't wait for changes.
+    State { mode: Option<&'static str>, display_on: bool },
+    Mode { mode: &'static str },
+    Display { on: bool },
+    Input { event: InputEvent },
+    Error { message: String },
+    // Crossing below `battery.low_percent` while not charging.
+    LowBattery { percent: f64 },
+    // Reading from the MCU failed, e.g. because its USB cable came out.
+    SerialDisconnected { port: String, error: String },
+}
+
+struct State {
+    mode: Option<&'static str>,
+    display_on: bool,
+}
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<HelmetEvent> = broadcast::channel(64).0;
+    static ref STATE: Mutex<State> = Mutex::new(State { mode: None, display_on: true });
+}
+
+pub fn publish(event: HelmetEvent) {
+    {
+        let mut state = STATE.lock().unwrap();
+        match event {
+            HelmetEvent::Mode { mode } => state.mode = Some(mode),
+            HelmetEvent::Display { on } => state.display_on = on,
+            _ => {}
+        }
+    }
+    // Nobody listening is fine.
+    let _ = EVENTS.send(event);
+}
+
+pub fn error(message: String) {
+    publish(HelmetEvent::Error { message });
+}
+
+// The current state, and a receiver for everything after it.
+pub fn subscribe() -> (HelmetEvent, broadcast::Receiver<HelmetEvent>) {
+    let state = STATE.lock().unwrap();
+    let current = HelmetEvent::State { mode: state.mode, display_on: state.display_on };
+    // Subscribed under the lock, so no change falls between the two.
+    (current, EVENTS.subscribe())
+}
@@ -0,0 +1,199 @@
+use std::{thread, time::Duration};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::{mode::Panel, preview, INVERT_IMAGE, MCU_SERIAL_PORT};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Content {
+    // Both eyes show the same frame.
+    #[default]
+    Mirrored,
+    // Modes draw one frame twice as wide, the left half going to the left
+    // eye and the right half to the right.
+    Independent,
+}
+
+// One MCU and its panel.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    pub port: String,
+    // Degrees clockwise frames are turned on their way to the panel: 0, 90,
+    // 180 or 270.
+    pub rotation: u16,
+    pub invert: bool,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self { port: MCU_SERIAL_PORT.to_owned(), rotation: 90, invert: INVERT_IMAGE }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EyesConfig {
+    // Both or neither; with neither there's one panel on the usual port.
+    pub left: Option<LinkConfig>,
+    pub right: Option<LinkConfig>,
+    pub content: Content,
+}
+
+impl EyesConfig {
+    pub fn validate(&self) -> Result<()> {
+        let (left, right) = match (&self.left, &self.right) {
+            (Some(left), Some(right)) => (left, right),
+            (None, None) => return Ok(()),
+            _ => bail!("eyes: needs both left and right, or neither"),
+        };
+        for (eye, link) in [("left", left), ("right", right)] {
+            if ![0, 90, 180, 270].contains(&link.rotation) {
+                bail!("eyes.{eye}.rotation: must be 0, 90, 180 or 270");
+            }
+        }
+        if left.port == right.port {
+            bail!("eyes.right.port: must differ from eyes.left.port");
+        }
+        Ok(())
+    }
+
+    // The links to open, left first.
+    pub fn links(&self) -> Vec<LinkConfig> {
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => vec![left.clone(), right.clone()],
+            _ => vec![LinkConfig::default()],
+        }
+    }
+}
+
+// The helmet's panels: one, or one per eye. With two, each frame goes out
+// on both links at once, so sending takes no longer than it does to one.
+pub struct Displays<P> {
+    links: Vec<P>,
+    content: Content,
+    last_frame: Option<Vec<u8>>,
+}
+
+impl<P: Panel + Send> Displays<P> {
+    pub fn new(links: Vec<P>, content: Content) -> Self {
+        Self { links, content, last_frame: None }
+    }
+
+    pub fn links(&self) -> &[P] {
+        &self.links
+    }
+
+    // Shows `frame`, the size of one panel, on every panel in turn, e.g.
+    // splash screens whatever the content.
+    pub fn mirror(&mut self, frame: Vec<u8>) -> Result<()> {
+        for link in &mut self.links {
+            link.show(frame.clone())?;
+        }
+        preview::set(&frame, self.links[0].dims());
+        self.last_frame = None;
+        Ok(())
+    }
+
+    // Each link's share of `frame`.
+    fn split(&self, frame: &[u8]) -> Vec<Vec<u8>> {
+        if self.content == Content::Mirrored {
+            return vec![frame.to_vec(); self.links.len()];
+        }
+        let (w, h) = self.links[0].dims();
+        let n = self.links.len();
+        (0..n)
+            .map(|i| {
+                (0..h)
+                    .flat_map(|y| {
+                        let start = (y * n + i) * w;
+                        frame[start..start + w].iter().copied()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl<P: Panel + Send> Panel for Displays<P> {
+    fn dims(&self) -> (usize, usize) {
+        let (w, h) = self.links[0].dims();
+        match self.content {
+            Content::Mirrored => (w, h),
+            Content::Independent => (w * self.links.len(), h),
+        }
+    }
+
+    fn show(&mut self, frame: Vec<u8>) -> Result<()> {
+        let (w, h) = self.dims();
+        if frame.len() != w * h {
+            bail!("frame is {} bytes, not {w}x{h}", frame.len());
+        }
+        if let [link] = self.links.as_mut_slice() {
+            link.show(frame.clone())?;
+            preview::set(&frame, (w, h));
+            self.last_frame = Some(frame);
+            return Ok(());
+        }
+        let mut shares = self.split(&frame).into_iter();
+        let first = shares.next().unwrap();
+        let (head, rest) = self.links.split_at_mut(1);
+        thread::scope(|scope| {
+            let others: Vec<_> = rest.iter_mut()
+                .zip(shares)
+                .map(|(link, share)| scope.spawn(move || link.show(share)))
+                .collect();
+            let result = head[0].show(first);
+            others.into_iter()
+                .map(|other| other.join().unwrap())
+                .fold(result, Result::and)
+        })?;
+        preview::set(&frame, (w, h));
+        self.last_frame = Some(frame);
+        Ok(())
+    }
+
+    fn last_frame(&self) -> Option<&[u8]> {
+        self.last_frame.as_deref()
+    }
+
+    // The slower link's round trip.
+    fn ping(&mut self) -> Result<Duration> {
+        let mut slowest = Duration::ZERO;
+        for link in &mut self.links {
+            slowest = slowest.max(link.ping()?);
+        }
+        Ok(slowest)
+    }
+
+    fn last_send(&self) -> Option<Duration> {
+        self.links.iter().filter_map(|link| link.last_send()).max()
+    }
+
+    // To each link in turn, so flashing does both MCUs.
+    fn lend_port(&mut self, user: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        for link in &mut self.links {
+            link.lend_port(user)?;
+        }
+        self.last_frame = None;
+        Ok(())
+    }
+
+    fn reattach(&mut self, was: &str, port: &str) -> Result<()> {
+        for link in &mut self.links {
+            link.reattach(was, port)?;
+        }
+        Ok(())
+    }
+
+    // The left eye's, with two.
+    fn release_port(&mut self) -> Result<String> {
+        self.links[0].release_port()
+    }
+
+    fn take_back_port(&mut self) -> Result<()> {
+        self.links[0].take_back_port()
+    }
+}
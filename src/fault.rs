@@ -0,0 +1,106 @@
+use std::{
+    io::{self, Read, Write},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Result};
+use rand::{rngs::Xoshiro256PlusPlus, RngExt, SeedableRng};
+use serde::Deserialize;
+
+// For exercising the flow control and chunk resends without a flaky cable.
+// Never wanted on the helmet itself.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultConfig {
+    // Chances, from 0 to 1, that each byte written to the MCU is dropped,
+    // sent twice, or sent with one bit flipped.
+    pub drop: f64,
+    pub duplicate: f64,
+    pub corrupt: f64,
+    // The same seed makes the same faults, byte for byte, each time the
+    // port opens.
+    pub seed: u64,
+}
+
+impl FaultConfig {
+    pub fn validate(&self) -> Result<()> {
+        let rates = [("drop", self.drop), ("duplicate", self.duplicate), ("corrupt", self.corrupt)];
+        for (key, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                bail!("faults.{key}: must be from 0 to 1");
+            }
+        }
+        if self.drop + self.duplicate + self.corrupt > 1.0 {
+            bail!("faults: drop, duplicate and corrupt must add up to at most 1");
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.drop + self.duplicate + self.corrupt > 0.0
+    }
+}
+
+static FAULTS: OnceLock<FaultConfig> = OnceLock::new();
+
+pub fn init(config: &FaultConfig) {
+    if config.is_enabled() {
+        println!("[faults] Mangling bytes sent to the MCU, as configured...");
+        let _ = FAULTS.set(*config);
+    }
+}
+
+// `sink`, mangled as configured, if it is.
+pub fn wrap<W: Read + Write + Send + 'static>(sink: W) -> Box<dyn crate::Sink> {
+    match FAULTS.get() {
+        Some(config) => Box::new(Faulty::new(sink, config)),
+        None => Box::new(sink),
+    }
+}
+
+// Writes through to `inner`, but not faithfully. Reads are left alone.
+pub struct Faulty<W> {
+    inner: W,
+    config: FaultConfig,
+    rng: Xoshiro256PlusPlus,
+    out: Vec<u8>,
+}
+
+impl<W> Faulty<W> {
+    pub fn new(inner: W, config: &FaultConfig) -> Self {
+        let rng = Xoshiro256PlusPlus::seed_from_u64(config.seed);
+        Self { inner, config: *config, rng, out: Vec::new() }
+    }
+}
+
+impl<W: Read> Read for Faulty<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<W: Write> Write for Faulty<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let FaultConfig { drop, duplicate, corrupt, .. } = self.config;
+        self.out.clear();
+        for &byte in buf {
+            let roll: f64 = self.rng.random();
+            if roll < drop {
+                continue;
+            }
+            if roll < drop + duplicate {
+                self.out.extend_from_slice(&[byte, byte]);
+            } else if roll < drop + duplicate + corrupt {
+                self.out.push(byte ^ 1 << self.rng.random_range(0..8));
+            } else {
+                self.out.push(byte);
+            }
+        }
+        self.inner.write_all(&self.out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
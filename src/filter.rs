@@ -0,0 +1,241 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+pub use crate::ingest::luma;
+use crate::mask;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum MapFilter {
+    Threshold,
+    Edges,
+    // Roads solid, water dotted and labels only when asked for. Water needs
+    // the map image in colour.
+    HighContrast,
+}
+
+// A rendered map image. `rgb` only if loadmap.sh left it in colour.
+pub struct MapImage {
+    pub dims: (usize, usize),
+    pub gray: Vec<u8>,
+    pub rgb: Option<Vec<[u8; 3]>>,
+}
+
+impl MapFilter {
+    // `labels` is whether map labels are kept, for filters that can tell
+    // them apart.
+    pub fn apply(self, image: MapImage, dims: (usize, usize), labels: bool) -> Vec<u8> {
+        match self {
+            MapFilter::Threshold => image.gray,
+            MapFilter::Edges => edges(&image.gray, dims, EDGE_THRESHOLD),
+            MapFilter::HighContrast => high_contrast(&image, dims, labels),
+        }
+    }
+}
+
+// Gradient magnitude (|gx| + |gy|) above which a pixel counts as an edge.
+const EDGE_THRESHOLD: u16 = 192;
+
+// Sobel edge extraction, producing a clean 0x00/0xFF image. Pixels outside
+// the image are treated as copies of the nearest edge pixel, so the border
+// itself never shows up as an edge.
+pub fn edges(data: &[u8], dims: (usize, usize), threshold: u16) -> Vec<u8> {
+    let (w, h) = dims;
+    assert!(data.len() == (w * h));
+    let at = |x: isize, y: isize| -> i32 {
+        let x = x.clamp(0, w as isize - 1) as usize;
+        let y = y.clamp(0, h as isize - 1) as usize;
+        data[(y * w) + x] as i32
+    };
+    let mut out = vec![0u8; data.len()];
+    for y in 0..(h as isize) {
+        for x in 0..(w as isize) {
+            let gx = (at(x + 1, y - 1) + 2 * at(x + 1, y) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2 * at(x - 1, y) + at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) + 2 * at(x, y + 1) + at(x + 1, y + 1))
+                - (at(x - 1, y - 1) + 2 * at(x, y - 1) + at(x + 1, y - 1));
+            let magnitude = gx.unsigned_abs() + gy.unsigned_abs();
+            if magnitude >= threshold as u32 {
+                out[(y as usize * w) + x as usize] = 0xFF;
+            }
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Feature {
+    Land,
+    Road,
+    Water,
+    Label,
+}
+
+// Going by the standard OSM style: white and warm-coloured roads, blue
+// water, and grey to black labels.
+fn classify_rgb([r, g, b]: [u8; 3]) -> Feature {
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    if luma(r, g, b) < 128 && max - min < 48 {
+        Feature::Label
+    } else if b as i16 > r as i16 + 30 && b >= g {
+        Feature::Water
+    } else if min > 250 || (r > 0xE0 && r as i16 - b as i16 > 50) {
+        Feature::Road
+    } else {
+        Feature::Land
+    }
+}
+
+// Water and the coloured roads are lost in grey, leaving only white roads.
+fn classify_gray(luma: u8) -> Feature {
+    match luma {
+        0..100 => Feature::Label,
+        250.. => Feature::Road,
+        _ => Feature::Land,
+    }
+}
+
+// Clean 0x00/0xFF output for the dithered panel. A suppressed label takes
+// the feature around it, so it doesn't leave a hole in a road or a lake.
+pub fn high_contrast(image: &MapImage, dims: (usize, usize), labels: bool) -> Vec<u8> {
+    let (w, h) = dims;
+    let features: Vec<Feature> = match &image.rgb {
+        Some(rgb) => rgb.iter().copied().map(classify_rgb).collect(),
+        None => image.gray.iter().copied().map(classify_gray).collect(),
+    };
+    assert!(features.len() == (w * h));
+    let around = |x: usize, y: usize, r: usize, feature: Feature| {
+        let mut n = 0;
+        for ny in y.saturating_sub(r)..(y + r + 1).min(h) {
+            for nx in x.saturating_sub(r)..(x + r + 1).min(w) {
+                n += (features[(ny * w) + nx] == feature) as usize;
+            }
+        }
+        n
+    };
+    let underneath = |x: usize, y: usize| {
+        // Roads are thin, so one running past wins over what's beside it.
+        if around(x, y, 1, Feature::Road) >= 2 {
+            Feature::Road
+        } else if around(x, y, 2, Feature::Water) > around(x, y, 2, Feature::Land) {
+            Feature::Water
+        } else {
+            Feature::Land
+        }
+    };
+    let mut out = vec![0u8; features.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let mut feature = features[(y * w) + x];
+            if feature == Feature::Label && !labels {
+                feature = underneath(x, y);
+            }
+            let on = match feature {
+                Feature::Road | Feature::Label => true,
+                // Sparse dots, so a road crossing water still stands out.
+                Feature::Water => x % 2 == 0 && y % 2 == 0,
+                Feature::Land => false,
+            };
+            if on {
+                out[(y * w) + x] = 0xFF;
+            }
+        }
+    }
+    out
+}
+
+// Turns `data` clockwise by `degrees` about its centre into `dst_dims`
+// around the same centre, taking the nearest source pixel. Anything from
+// outside the source is left off.
+pub fn rotate(
+    data: &[u8],
+    src_dims: (usize, usize),
+    degrees: f64,
+    dst_dims: (usize, usize),
+) -> Vec<u8> {
+    let (sw, sh) = src_dims;
+    let (dw, dh) = dst_dims;
+    assert!(data.len() == (sw * sh));
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let mut out = vec![0u8; dw * dh];
+    for y in 0..dh {
+        for x in 0..dw {
+            let dx = x as f64 + 0.5 - (dw as f64 / 2.0);
+            let dy = y as f64 + 0.5 - (dh as f64 / 2.0);
+            let sx = (sw as f64 / 2.0) + (cos * dx) + (sin * dy);
+            let sy = (sh as f64 / 2.0) - (sin * dx) + (cos * dy);
+            if sx >= 0.0 && sy >= 0.0 && (sx as usize) < sw && (sy as usize) < sh {
+                out[(y * dw) + x] = data[(sy as usize * sw) + sx as usize];
+            }
+        }
+    }
+    out
+}
+
+// Box-filter downscale of a grayscale image. Each destination pixel is the
+// mean of the source pixels that map onto it.
+pub fn downscale(
+    data: &[u8],
+    src_dims: (usize, usize),
+    dst_dims: (usize, usize),
+) -> Vec<u8> {
+    let (sw, sh) = src_dims;
+    let (dw, dh) = dst_dims;
+    assert!(data.len() == (sw * sh));
+    let mut out = Vec::with_capacity(dw * dh);
+    for dy in 0..dh {
+        let y0 = dy * sh / dh;
+        let y1 = ((dy + 1) * sh / dh).max(y0 + 1);
+        for dx in 0..dw {
+            let x0 = dx * sw / dw;
+            let x1 = ((dx + 1) * sw / dw).max(x0 + 1);
+            let mut sum = 0u32;
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    sum += data[(y * sw) + x] as u32;
+                }
+            }
+            out.push((sum / ((y1 - y0) * (x1 - x0)) as u32) as u8);
+        }
+    }
+    out
+}
+
+// Floyd-Steinberg error diffusion down to 0x00/0xFF. `data` is taken to be
+// a whole frame: pixels stuck in the panel's mask come out as they're stuck,
+// and their error goes to the neighbours that can still change.
+pub fn dither(data: &[u8], dims: (usize, usize)) -> Vec<u8> {
+    let (w, h) = dims;
+    assert!(data.len() == (w * h));
+    let mask = mask::get();
+    let mut err: Vec<i16> = data.iter().map(|&p| p as i16).collect();
+    let mut out = vec![0u8; data.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let i = (y * w) + x;
+            let old = err[i];
+            let new = match mask.and_then(|m| m.at(i, w)) {
+                Some(true) => 0xFF,
+                Some(false) => 0x00,
+                None if old > (u8::MAX / 2) as i16 => 0xFF,
+                None => 0x00,
+            };
+            out[i] = new as u8;
+            let e = old - new;
+            if x + 1 < w {
+                err[i + 1] += e * 7 / 16;
+            }
+            if y + 1 < h {
+                if x > 0 {
+                    err[i + w - 1] += e * 3 / 16;
+                }
+                err[i + w] += e * 5 / 16;
+                if x + 1 < w {
+                    err[i + w + 1] += e / 16;
+                }
+            }
+        }
+    }
+    out
+}
+
@@ -0,0 +1,101 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Result};
+use crossbeam_channel::unbounded;
+use serde::Deserialize;
+
+use crate::progress::Progress;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlashConfig {
+    pub program: String,
+    // `{port}` and `{firmware}` are replaced by the serial port and the
+    // firmware file.
+    pub args: Vec<String>,
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        Self {
+            program: "avrdude".into(),
+            args: ["-c", "arduino", "-p", "atmega328p", "-b", "115200", "-P", "{port}"]
+                .into_iter()
+                .chain(["-D", "-U", "flash:w:{firmware}:i"])
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+// Runs the flasher on `port`, which must not be open. Its output is echoed,
+// and any percentages in it are published as progress.
+pub fn flash(config: &FlashConfig, port: &str, firmware: &Path) -> Result<()> {
+    if !firmware.is_file() {
+        bail!("no firmware file at {}", firmware.display());
+    }
+    let firmware = firmware.to_string_lossy();
+    let args: Vec<String> = config
+        .args
+        .iter()
+        .map(|arg| arg.replace("{port}", port).replace("{firmware}", &firmware))
+        .collect();
+    println!("[flash] Running {} {}...", config.program, args.join(" "));
+    let mut child = Command::new(&config.program)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("starting {}: {e}", config.program))?;
+    let (tx, rx) = unbounded();
+    let outputs: [Box<dyn Read + Send>; 2] = [
+        Box::new(child.stdout.take().unwrap()),
+        Box::new(child.stderr.take().unwrap()),
+    ];
+    for output in outputs {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            // Flashers redraw progress lines with carriage returns.
+            for line in BufReader::new(output).split(b'\r') {
+                let Ok(line) = line else { break };
+                for line in String::from_utf8_lossy(&line).lines() {
+                    let _ = tx.send(line.trim().to_string());
+                }
+            }
+        });
+    }
+    drop(tx);
+    let mut prog = Progress::new("flash_firmware", 100);
+    let mut done = 0;
+    for line in rx {
+        if line.is_empty() {
+            continue;
+        }
+        println!("[flash] {line}");
+        if let Some(percent) = percent(&line).filter(|&p| p > done) {
+            prog.inc(percent - done);
+            done = percent;
+        }
+    }
+    prog.finish();
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} failed: {status}", config.program);
+    }
+    Ok(())
+}
+
+// The last "NN%" or "NN %" in a line of flasher output.
+fn percent(line: &str) -> Option<u64> {
+    let end = line.rfind('%')?;
+    let digits = line[..end].trim_end();
+    let start = digits
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    digits[start..].parse().ok().filter(|&p| p <= 100)
+}
@@ -0,0 +1,119 @@
+// A grayscale drawing surface in the same layout `HelmetMcu::send_rotated`
+// expects: row-major, one byte per pixel, 0x00 off and 0xFF on.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub dims: (usize, usize),
+    pub pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(dims: (usize, usize)) -> Self {
+        Self {
+            dims,
+            pixels: vec![0; dims.0 * dims.1],
+        }
+    }
+
+    fn index(&self, x: isize, y: isize) -> Option<usize> {
+        let (w, h) = self.dims;
+        if x < 0 || y < 0 || x as usize >= w || y as usize >= h {
+            None
+        } else {
+            Some((y as usize * w) + x as usize)
+        }
+    }
+
+    // Out-of-bounds writes are clipped, so callers can draw partially
+    // off-screen without checking.
+    pub fn set(&mut self, x: isize, y: isize, on: bool) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = if on { 0xFF } else { 0x00 };
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+    }
+
+    // Bresenham, endpoints inclusive.
+    pub fn line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, on: bool) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.set(x, y, on);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    pub fn rect(&mut self, x: isize, y: isize, w: isize, h: isize, on: bool) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        let (x1, y1) = (x + w - 1, y + h - 1);
+        self.line(x, y, x1, y, on);
+        self.line(x, y1, x1, y1, on);
+        self.line(x, y, x, y1, on);
+        self.line(x1, y, x1, y1, on);
+    }
+
+    pub fn fill_rect(&mut self, x: isize, y: isize, w: isize, h: isize, on: bool) {
+        let (fw, fh) = (self.dims.0 as isize, self.dims.1 as isize);
+        for yy in y.max(0)..(y + h).min(fh) {
+            for xx in x.max(0)..(x + w).min(fw) {
+                self.set(xx, yy, on);
+            }
+        }
+    }
+
+    // Copies a grayscale image of `dims` in with its top-left at (x, y),
+    // thresholding it the same way the panel does. Unlike drawing, this
+    // overwrites unlit pixels too, so later layers can go on top.
+    pub fn blit(&mut self, x: isize, y: isize, src: &[u8], dims: (usize, usize)) {
+        let (w, h) = dims;
+        assert!(src.len() == (w * h));
+        for sy in 0..h {
+            for sx in 0..w {
+                let on = src[(sy * w) + sx] > (u8::MAX / 2);
+                self.set(x + sx as isize, y + sy as isize, on);
+            }
+        }
+    }
+
+    // Like `blit`, but only where `mask` is set, so what's already drawn
+    // shows through the rest.
+    pub fn blit_masked(
+        &mut self,
+        x: isize,
+        y: isize,
+        src: &[u8],
+        mask: &[bool],
+        dims: (usize, usize),
+    ) {
+        let (w, h) = dims;
+        assert!(src.len() == (w * h) && mask.len() == (w * h));
+        for sy in 0..h {
+            for sx in 0..w {
+                let i = (sy * w) + sx;
+                if mask[i] {
+                    self.set(x + sx as isize, y + sy as isize, src[i] > (u8::MAX / 2));
+                }
+            }
+        }
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Error, Result};
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// GPS fixes closer together than this don't give a trustworthy course.
+const MIN_COURSE_M: f64 = 5.0;
+
+// Ground width of a pixel of zoom 0 web map tiles at the equator.
+const TILE_METRES_PER_PIXEL: f64 = 156_543.034;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+// "lat,lon", as sent to `POST /api/v1/coords`.
+impl FromStr for LatLon {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (lat, lon) = s.split_once(',')
+            .ok_or_else(|| anyhow!("expected lat,lon, got {s:?}"))?;
+        Ok(Self { lat: lat.trim().parse()?, lon: lon.trim().parse()? })
+    }
+}
+
+impl fmt::Display for LatLon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.lat, self.lon)
+    }
+}
+
+impl LatLon {
+    // Great-circle distance in metres.
+    pub fn distance(self, to: LatLon) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), to.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (to.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2)
+            + (lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2));
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+
+    // Initial bearing towards `to`, in degrees clockwise from north.
+    pub fn bearing(self, to: LatLon) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), to.lat.to_radians());
+        let dlon = (to.lon - self.lon).to_radians();
+        let y = dlon.sin() * lat2.cos();
+        let x = (lat1.cos() * lat2.sin()) - (lat1.sin() * lat2.cos() * dlon.cos());
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    // Flat (east, north) offset in metres from `origin`. Good enough over
+    // the few kilometres a single route leg covers.
+    pub fn local_xy(self, origin: LatLon) -> (f64, f64) {
+        let x = (self.lon - origin.lon).to_radians() * origin.lat.to_radians().cos();
+        let y = (self.lat - origin.lat).to_radians();
+        (x * EARTH_RADIUS_M, y * EARTH_RADIUS_M)
+    }
+}
+
+// Direction of travel, from the bearing between successive GPS fixes.
+#[derive(Clone, Debug, Default)]
+pub struct Course {
+    last_fix: Option<LatLon>,
+    pub bearing: Option<f64>,
+}
+
+impl Course {
+    pub fn update(&mut self, fix: LatLon) {
+        match self.last_fix {
+            Some(last) if last.distance(fix) < MIN_COURSE_M => {}
+            Some(last) => {
+                self.bearing = Some(last.bearing(fix));
+                self.last_fix = Some(fix);
+            }
+            None => self.last_fix = Some(fix),
+        }
+    }
+}
+
+// Ground width of a web map pixel at `lat` and `zoom`.
+pub fn metres_per_pixel(lat: f64, zoom: u8) -> f64 {
+    TILE_METRES_PER_PIXEL * lat.to_radians().cos() / 2f64.powi(zoom as i32)
+}
+
+// Signed difference `to - from` between two bearings, in (-180, 180].
+pub fn turn_angle(from: f64, to: f64) -> f64 {
+    let d = (to - from).rem_euclid(360.0);
+    if d > 180.0 { d - 360.0 } else { d }
+}
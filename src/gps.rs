@@ -0,0 +1,150 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::{
+    geo::LatLon,
+    hooks::{self, HookEvent},
+    low_power,
+};
+
+const RECONNECT: Duration = Duration::from_secs(5);
+
+// Fixes closer than this to the last counted one are taken as the receiver
+// wandering while standing still, and left out of the trip.
+const MIN_STEP_M: f64 = 5.0;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpsConfig {
+    // gpsd's address, e.g. "127.0.0.1:2947". None means no GPS.
+    pub gpsd: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fix {
+    pub at: LatLon,
+    // Metres per second and degrees clockwise from north, when the receiver
+    // reports them.
+    pub speed: Option<f64>,
+    pub course: Option<f64>,
+    // Distance covered since the server started, in metres.
+    pub trip_m: f64,
+}
+
+// Latest fix, or `None` while there isn't one.
+pub type GpsRx = watch::Receiver<Option<Fix>>;
+
+// Follows gpsd on its own thread. gpsd often comes up after us, or goes
+// away with the receiver, so it's retried rather than failing at startup.
+pub fn spawn_poller(config: &GpsConfig) -> Result<Option<GpsRx>> {
+    let Some(addr) = config.gpsd.clone() else {
+        return Ok(None);
+    };
+    let (tx, rx) = watch::channel(None);
+    spawn(move || {
+        let mut trip = Trip::default();
+        // Only logged once while it keeps failing the same way.
+        let mut last_error = None;
+        loop {
+            let result = watch_gpsd(&addr, &tx, &mut trip);
+            if tx.is_closed() {
+                return;
+            }
+            // With nobody watching, gpsd can stop talking to the receiver.
+            if low_power::is_low_power() {
+                println!("[gps] Low power, letting go of gpsd...");
+                tx.send_replace(None);
+                low_power::wait_until_awake();
+                continue;
+            }
+            if let Some(last) = tx.send_replace(None) {
+                fix_lost(last);
+            }
+            match result {
+                Ok(()) => {
+                    println!("[gps] gpsd at {addr} closed the connection.");
+                    last_error = None;
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    if last_error.as_ref() != Some(&error) {
+                        println!("[gps] gpsd at {addr}: {error}");
+                        last_error = Some(error);
+                    }
+                }
+            }
+            sleep(RECONNECT);
+        }
+    });
+    Ok(Some(rx))
+}
+
+#[derive(Default)]
+struct Trip {
+    last: Option<LatLon>,
+    metres: f64,
+}
+
+impl Trip {
+    fn update(&mut self, fix: LatLon) -> f64 {
+        match self.last {
+            Some(last) if last.distance(fix) < MIN_STEP_M => {}
+            Some(last) => {
+                self.metres += last.distance(fix);
+                self.last = Some(fix);
+            }
+            None => self.last = Some(fix),
+        }
+        self.metres
+    }
+}
+
+fn watch_gpsd(addr: &str, tx: &watch::Sender<Option<Fix>>, trip: &mut Trip) -> Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(b"?WATCH={\"enable\":true,\"json\":true};\n")?;
+    println!("[gps] Watching gpsd at {addr}...");
+    for line in BufReader::new(stream).lines() {
+        let report: Value = match serde_json::from_str(&line?) {
+            Ok(report) => report,
+            Err(_) => continue,
+        };
+        // Time-position-velocity reports, with at least a 2D fix.
+        if report["class"] != "TPV" {
+            continue;
+        }
+        let (mode, lat, lon) = (&report["mode"], &report["lat"], &report["lon"]);
+        let fix = match (mode.as_u64(), lat.as_f64(), lon.as_f64()) {
+            (Some(2..), Some(lat), Some(lon)) => {
+                let at = LatLon { lat, lon };
+                Some(Fix {
+                    at,
+                    speed: report["speed"].as_f64(),
+                    course: report["track"].as_f64(),
+                    trip_m: trip.update(at),
+                })
+            }
+            _ => None,
+        };
+        if tx.is_closed() || low_power::is_low_power() {
+            break;
+        }
+        if let (None, Some(last)) = (fix, tx.send_replace(fix)) {
+            fix_lost(last);
+        }
+    }
+    Ok(())
+}
+
+fn fix_lost(last: Fix) {
+    println!("[gps] Lost the fix at {}.", last.at);
+    hooks::fire(HookEvent::GpsFixLost, &[("coords", &last.at.to_string())]);
+}
@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcConfig {
+    // Where to serve the service in proto/helmet.proto, e.g. "0.0.0.0:50051".
+    // None means no gRPC; builds without the grpc feature have none anyway.
+    pub listen: Option<String>,
+}
+
+impl GrpcConfig {
+    pub fn validate(&self) -> Result<()> {
+        let Some(listen) = &self.listen else {
+            return Ok(());
+        };
+        if listen.parse::<SocketAddr>().is_err() {
+            bail!("grpc.listen: must be an address and port, e.g. \"0.0.0.0:50051\"");
+        }
+        if !cfg!(feature = "grpc") {
+            bail!("grpc.listen: this build has no gRPC; rebuild with --features grpc");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "grpc")]
+pub use server::serve;
+
+#[cfg(feature = "grpc")]
+mod server {
+    use std::{net::SocketAddr, pin::Pin};
+
+    use anyhow::Result;
+    use crossbeam_channel::Sender;
+    use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+    use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+    use super::GrpcConfig;
+    use crate::{
+        config::ModesConfig,
+        events::{self, HelmetEvent},
+        input::InputEvent,
+        jobs::{self, Priority},
+        mode,
+        picture::{self, DisplayUrlConfig},
+        session,
+        start_mode,
+        Update,
+        PANEL_DIMS,
+    };
+
+    mod proto {
+        tonic::include_proto!("helmet.v1");
+    }
+
+    use proto::{
+        event,
+        helmet_server::{Helmet, HelmetServer},
+        input,
+        Empty,
+        Event,
+        Frame,
+        Image,
+        ModeInfo,
+        ModeList,
+        SetModeRequest,
+        StreamSummary,
+    };
+
+    struct Service {
+        modes: ModesConfig,
+        images: DisplayUrlConfig,
+        tx: &'static Sender<Update>,
+    }
+
+    pub async fn serve(
+        config: &GrpcConfig,
+        modes: ModesConfig,
+        images: DisplayUrlConfig,
+        tx: &'static Sender<Update>,
+    ) -> Result<()> {
+        let Some(listen) = &config.listen else {
+            return Ok(());
+        };
+        let addr: SocketAddr = listen.parse()?;
+        println!("[grpc] Listening on {addr}...");
+        Server::builder()
+            .add_service(HelmetServer::new(Service { modes, images, tx }))
+            .serve(addr)
+            .await?;
+        Ok(())
+    }
+
+    impl Service {
+        // The mode manager takes updates one at a time, so anything not
+        // queued waits its turn off the runtime.
+        async fn send(&self, update: Update) -> Result<(), Status> {
+            let tx = self.tx;
+            session::ensure_free().map_err(|e| Status::failed_precondition(e.to_string()))?;
+            tokio::task::spawn_blocking(move || jobs::send(tx, update, Priority::Normal))
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(|e| Status::unavailable(format!("{e:#}")))
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Helmet for Service {
+        async fn display_image(&self, request: Request<Image>) -> Result<Response<Empty>, Status> {
+            let data = request.into_inner().data;
+            if data.len() as u64 > self.images.max_kb * 1024 {
+                return Err(Status::invalid_argument("image is bigger than display_url.max_kb"));
+            }
+            let Some(format) = picture::sniff(&data) else {
+                return Err(Status::invalid_argument("only PNG and JPEG images can be shown"));
+            };
+            println!("[grpc] Showing an image...");
+            let frame = tokio::task::spawn_blocking(move || {
+                picture::decode(&data, format, picture::background())
+                    .map(|(image, dims)| picture::fit(&image, dims, PANEL_DIMS))
+            })
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(|e| Status::invalid_argument(format!("{e:#}")))?;
+            self.send(Update::Frame { frame }).await?;
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn set_mode(
+            &self,
+            request: Request<SetModeRequest>,
+        ) -> Result<Response<Empty>, Status> {
+            let request = request.into_inner();
+            println!("[grpc] Switching to {} mode...", request.name);
+            let params = request.params_json.unwrap_or_default();
+            let update = start_mode(&self.modes, request.name, params.as_bytes())
+                .map_err(|e| Status::invalid_argument(format!("{e:#}")))?;
+            self.send(update).await?;
+            Ok(Response::new(Empty {}))
+        }
+
+        async fn list_modes(&self, _: Request<Empty>) -> Result<Response<ModeList>, Status> {
+            let modes = mode::REGISTRY.iter()
+                .map(|info| ModeInfo {
+                    name: info.name.to_owned(),
+                    about: info.about.to_owned(),
+                    params_json: self.modes.params(info.name)
+                        .unwrap_or_else(|| serde_json::json!({}))
+                        .to_string(),
+                })
+                .collect();
+            Ok(Response::new(ModeList { modes }))
+        }
+
+        async fn stream_frames(
+            &self,
+            request: Request<Streaming<Frame>>,
+        ) -> Result<Response<StreamSummary>, Status> {
+            let mut frames = request.into_inner();
+            let size = PANEL_DIMS.0 * PANEL_DIMS.1;
+            let mut sent = 0;
+            println!("[grpc] Streaming frames...");
+            while let Some(frame) = frames.message().await? {
+                if frame.pixels.len() != size {
+                    return Err(Status::invalid_argument(format!(
+                        "frame {sent} is {} bytes, not {size}",
+                        frame.pixels.len(),
+                    )));
+                }
+                self.send(Update::Frame { frame: frame.pixels }).await?;
+                sent += 1;
+            }
+            println!("[grpc] End of stream after {sent} frames.");
+            Ok(Response::new(StreamSummary { frames: sent }))
+        }
+
+        type SubscribeEventsStream =
+            Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send + 'static>>;
+
+        async fn subscribe_events(
+            &self,
+            _: Request<Empty>,
+        ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+            let (current, updates) = events::subscribe();
+            let events = tokio_stream::once(current)
+                // A client that fell behind just misses some events.
+                .chain(BroadcastStream::new(updates).filter_map(|event| event.ok()))
+                .map(|event| Ok(Event { event: Some(convert(event)) }));
+            Ok(Response::new(Box::pin(events)))
+        }
+    }
+
+    fn convert(event: HelmetEvent) -> event::Event {
+        match event {
+            HelmetEvent::State { mode, display_on } => event::Event::State(proto::State {
+                mode: mode.map(str::to_owned),
+                display_on,
+            }),
+            HelmetEvent::Mode { mode } => {
+                event::Event::Mode(proto::ModeChanged { mode: mode.to_owned() })
+            }
+            HelmetEvent::Display { on } => event::Event::Display(proto::Display { on }),
+            HelmetEvent::Input { event } => {
+                let input = match event {
+                    InputEvent::Press { button } => input::Input::Press(button.into()),
+                    InputEvent::Release { button } => input::Input::Release(button.into()),
+                    InputEvent::Encoder { delta } => input::Input::Encoder(delta.into()),
+                    InputEvent::Status { code } => input::Input::Status(code.into()),
+                };
+                event::Event::Input(proto::Input { input: Some(input) })
+            }
+            HelmetEvent::Error { message } => event::Event::Error(proto::Error { message }),
+            HelmetEvent::LowBattery { percent } => {
+                event::Event::LowBattery(proto::LowBattery { percent })
+            }
+            HelmetEvent::SerialDisconnected { port, error } => {
+                event::Event::SerialDisconnected(proto::SerialDisconnected { port, error })
+            }
+        }
+    }
+}
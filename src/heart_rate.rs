@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use bluer::{AdapterEvent, Address, Device, Uuid};
+use serde::Deserialize;
+use tokio::{sync::watch, time::{sleep, timeout}};
+use tokio_stream::StreamExt;
+
+const RECONNECT: Duration = Duration::from_secs(5);
+
+// A strap that's gone this long without a reading has usually been taken
+// off or walked away from, whether or not BlueZ has noticed yet.
+const SILENCE: Duration = Duration::from_secs(10);
+
+// The standard Heart Rate service and its Heart Rate Measurement
+// characteristic.
+const HEART_RATE_SERVICE: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb);
+const MEASUREMENT: Uuid = Uuid::from_u128(0x00002a37_0000_1000_8000_00805f9b34fb);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartRateConfig {
+    pub enabled: bool,
+    // The strap's Bluetooth address. Without one, the first strap found
+    // advertising heart rate is used.
+    pub address: Option<String>,
+}
+
+impl HeartRateConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(address) = &self.address {
+            if address.parse::<Address>().is_err() {
+                bail!("heart_rate.address: must be like \"AA:BB:CC:DD:EE:FF\"");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeartRate {
+    Searching,
+    Connecting(String),
+    // The strap's name, and its last reading with when it came. Straps send
+    // about one a second.
+    Connected { name: String, reading: Option<(u16, Instant)> },
+}
+
+pub type HeartRateRx = watch::Receiver<HeartRate>;
+
+// Follows the strap on its own task, finding it again whenever it drops out,
+// so modes only ever look at the latest state.
+pub fn spawn_monitor(config: &HeartRateConfig) -> Result<Option<HeartRateRx>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let address = config.address.as_deref().map(str::parse::<Address>).transpose()?;
+    let (tx, rx) = watch::channel(HeartRate::Searching);
+    tokio::spawn(async move {
+        // Only logged once while it keeps failing the same way.
+        let mut last_error = None;
+        loop {
+            let result = follow_strap(address, &tx).await;
+            if tx.is_closed() {
+                return;
+            }
+            tx.send_replace(HeartRate::Searching);
+            match result {
+                Ok(()) => {
+                    println!("[heart rate] Lost the strap.");
+                    last_error = None;
+                }
+                Err(e) => {
+                    let error = format!("{e:#}");
+                    if last_error.as_ref() != Some(&error) {
+                        println!("[heart rate] {error}");
+                        last_error = Some(error);
+                    }
+                }
+            }
+            sleep(RECONNECT).await;
+        }
+    });
+    Ok(Some(rx))
+}
+
+// Finds a strap, connects and passes on readings until it goes quiet.
+async fn follow_strap(address: Option<Address>, tx: &watch::Sender<HeartRate>) -> Result<()> {
+    let session = bluer::Session::new().await?;
+    let adapter = session.default_adapter().await?;
+    adapter.set_powered(true).await?;
+    // Discovery stops with the stream, which some adapters need before
+    // they'll connect.
+    let device = {
+        let events = adapter.discover_devices().await?;
+        tokio::pin!(events);
+        loop {
+            let Some(event) = events.next().await else {
+                bail!("discovery stopped");
+            };
+            let AdapterEvent::DeviceAdded(found) = event else {
+                continue;
+            };
+            if address.is_some_and(|address| address != found) {
+                continue;
+            }
+            let device = adapter.device(found)?;
+            let uuids = device.uuids().await?.unwrap_or_default();
+            if address.is_some() || uuids.contains(&HEART_RATE_SERVICE) {
+                break device;
+            }
+        }
+    };
+    let name = device.alias().await.unwrap_or_else(|_| device.address().to_string());
+    println!("[heart rate] Connecting to {name}...");
+    tx.send_replace(HeartRate::Connecting(name.clone()));
+    if !device.is_connected().await? {
+        device.connect().await?;
+    }
+    let measurement = find_measurement(&device).await?;
+    let notifications = measurement.notify().await?;
+    tokio::pin!(notifications);
+    println!("[heart rate] Connected to {name}.");
+    tx.send_replace(HeartRate::Connected { name: name.clone(), reading: None });
+    loop {
+        let value = match timeout(SILENCE, notifications.next()).await {
+            Ok(Some(value)) => value,
+            Ok(None) | Err(_) => break,
+        };
+        if tx.is_closed() {
+            break;
+        }
+        if let Some(bpm) = parse_measurement(&value) {
+            let reading = Some((bpm, Instant::now()));
+            tx.send_replace(HeartRate::Connected { name: name.clone(), reading });
+        }
+    }
+    let _ = device.disconnect().await;
+    Ok(())
+}
+
+async fn find_measurement(device: &Device) -> Result<bluer::gatt::remote::Characteristic> {
+    for service in device.services().await? {
+        if service.uuid().await? != HEART_RATE_SERVICE {
+            continue;
+        }
+        for characteristic in service.characteristics().await? {
+            if characteristic.uuid().await? == MEASUREMENT {
+                return Ok(characteristic);
+            }
+        }
+    }
+    Err(anyhow!("{} has no heart rate measurement", device.address()))
+}
+
+// The flags byte says whether beats per minute follow as one byte or two,
+// little-endian. Energy and RR intervals after that aren't needed.
+fn parse_measurement(value: &[u8]) -> Option<u16> {
+    match value {
+        [flags, bpm, ..] if flags & 1 == 0 => Some(*bpm as u16),
+        [_, low, high, ..] => Some(u16::from_le_bytes([*low, *high])),
+        _ => None,
+    }
+}
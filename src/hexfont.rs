@@ -0,0 +1,71 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+// Height of every glyph in a .hex font, and the row the baseline sits under.
+pub const HEIGHT: usize = 16;
+pub const ASCENT: usize = 14;
+
+pub struct Glyph {
+    // 8 or 16.
+    pub width: usize,
+    // One entry per row, most significant bit leftmost.
+    pub rows: [u16; HEIGHT],
+}
+
+impl Glyph {
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        (self.rows[y] >> (self.width - 1 - x)) & 1 == 1
+    }
+}
+
+// A bitmap font in GNU Unifont's .hex format: one `CODEPOINT:BITMAP` line per
+// glyph, both in hex, with the bitmap either 8x16 or 16x16 pixels.
+pub struct HexFont {
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl HexFont {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading hex font {}", path.display()))?;
+        let mut glyphs = HashMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let glyph = parse_line(line)
+                .with_context(|| format!("{}:{}", path.display(), n + 1))?;
+            if let Some((c, glyph)) = glyph {
+                glyphs.insert(c, glyph);
+            }
+        }
+        println!("[hexfont] Loaded {} glyphs from {}.", glyphs.len(), path.display());
+        Ok(Self { glyphs })
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c)
+    }
+}
+
+fn parse_line(line: &str) -> Result<Option<(char, Glyph)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (code, bitmap) = line.split_once(':').ok_or_else(|| anyhow!("missing ':'"))?;
+    let code = u32::from_str_radix(code, 16)?;
+    // Surrogates and the like; nothing could ask for them anyway.
+    let Some(c) = char::from_u32(code) else {
+        return Ok(None);
+    };
+    let digits = match bitmap.len() {
+        32 => 2,
+        64 => 4,
+        len => bail!("bitmap of {len} hex digits for U+{code:04X}"),
+    };
+    let mut rows = [0u16; HEIGHT];
+    for (row, chunk) in rows.iter_mut().zip(bitmap.as_bytes().chunks(digits)) {
+        *row = u16::from_str_radix(std::str::from_utf8(chunk)?, 16)?;
+    }
+    Ok(Some((c, Glyph { width: digits * 4, rows })))
+}
@@ -0,0 +1,165 @@
+use std::{
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    FrameSent,
+    ModeChanged,
+    GpsFixLost,
+    SerialDisconnected,
+}
+
+impl HookEvent {
+    // As in the config.
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::FrameSent => "frame_sent",
+            HookEvent::ModeChanged => "mode_changed",
+            HookEvent::GpsFixLost => "gps_fix_lost",
+            HookEvent::SerialDisconnected => "serial_disconnected",
+        }
+    }
+
+    // What `{name}` in a hook's arguments can stand for.
+    fn placeholders(self) -> &'static [&'static str] {
+        match self {
+            // How long sending it took.
+            HookEvent::FrameSent => &["millis"],
+            HookEvent::ModeChanged => &["mode"],
+            // Where the last fix was.
+            HookEvent::GpsFixLost => &["coords"],
+            HookEvent::SerialDisconnected => &["port", "error"],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // While this many are still running, the hook isn't run again.
+    #[serde(default = "default_max_running")]
+    pub max_running: usize,
+}
+
+fn default_max_running() -> usize {
+    1
+}
+
+impl Hook {
+    fn validate(&self) -> Result<()> {
+        if self.command.is_empty() {
+            bail!("command: must not be empty");
+        }
+        if self.max_running == 0 {
+            bail!("max_running: must be at least 1");
+        }
+        let known = self.event.placeholders();
+        for arg in &self.args {
+            if let Some(name) = placeholders(arg).find(|name| !known.contains(name)) {
+                let event = self.event.name();
+                bail!("args: {{{name}}} isn't set for {event}; there's {}", list(known));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub hook: Vec<Hook>,
+}
+
+impl HooksConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (i, hook) in self.hook.iter().enumerate() {
+            if let Err(e) = hook.validate() {
+                bail!("hooks.hook[{i}].{e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Running {
+    hook: Hook,
+    count: Arc<AtomicUsize>,
+}
+
+static HOOKS: OnceLock<Vec<Running>> = OnceLock::new();
+
+pub fn init(config: &HooksConfig) {
+    let hooks = config.hook.iter()
+        .map(|hook| Running { hook: hook.clone(), count: Arc::default() })
+        .collect();
+    let _ = HOOKS.set(hooks);
+}
+
+// Runs the hooks for `event` in the background, with `{name}` in their
+// arguments replaced from `vars`. Their output is only shown if they fail.
+pub fn fire(event: HookEvent, vars: &[(&str, &str)]) {
+    let Some(hooks) = HOOKS.get() else {
+        return;
+    };
+    for running in hooks.iter().filter(|running| running.hook.event == event) {
+        let hook = &running.hook;
+        let count = running.count.clone();
+        // Claimed before starting, so a burst of events can't overshoot.
+        let claimed = count.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+            (n < hook.max_running).then_some(n + 1)
+        });
+        if claimed.is_err() {
+            continue;
+        }
+        let args: Vec<String> = hook.args.iter()
+            .map(|arg| {
+                vars.iter().fold(arg.clone(), |arg, (name, value)| {
+                    arg.replace(&format!("{{{name}}}"), value)
+                })
+            })
+            .collect();
+        let command = hook.command.clone();
+        thread::spawn(move || {
+            let output = Command::new(&command)
+                .args(&args)
+                .stdin(Stdio::null())
+                .output();
+            match output {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    println!("[hooks] {command} failed ({}): {}", output.status, stderr.trim());
+                }
+                Err(e) => println!("[hooks] Couldn't run {command}: {e}"),
+            }
+            count.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+}
+
+// The names of the `{name}` placeholders in `arg`.
+fn placeholders(arg: &str) -> impl Iterator<Item = &str> {
+    arg.split('{').skip(1).filter_map(|rest| {
+        let name = &rest[..rest.find('}')?];
+        (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            .then_some(name)
+    })
+}
+
+fn list(names: &[&str]) -> String {
+    names.iter().map(|name| format!("{{{name}}}")).collect::<Vec<_>>().join(", ")
+}
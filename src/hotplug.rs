@@ -0,0 +1,113 @@
+use std::{
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use serde::Deserialize;
+use serialport::SerialPortType;
+
+use crate::Update;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotplugConfig {
+    // Watches for the MCU's serial adapter being unplugged and coming back,
+    // perhaps under another name, and picks it up again when it does.
+    pub enabled: bool,
+    pub poll_millis: u64,
+}
+
+impl Default for HotplugConfig {
+    fn default() -> Self {
+        Self { enabled: true, poll_millis: 1000 }
+    }
+}
+
+// What the adapter is recognised by: its USB IDs and serial number, which
+// stay the same whatever it's called, or for one that isn't USB its name.
+#[derive(Debug, PartialEq)]
+enum Identity {
+    Usb { vid: u16, pid: u16, serial_number: Option<String> },
+    Name(String),
+}
+
+impl Identity {
+    fn of(port: &str) -> Result<Self> {
+        let found = serialport::available_ports()?.into_iter()
+            .find(|info| info.port_name == port);
+        Ok(match found.map(|info| info.port_type) {
+            Some(SerialPortType::UsbPort(usb)) => Identity::Usb {
+                vid: usb.vid,
+                pid: usb.pid,
+                serial_number: usb.serial_number,
+            },
+            _ => Identity::Name(port.to_owned()),
+        })
+    }
+
+    // The adapter's name now, or `None` while it's unplugged.
+    fn find(&self) -> Result<Option<String>> {
+        let found = serialport::available_ports()?.into_iter().find(|info| match self {
+            Identity::Usb { vid, pid, serial_number } => matches!(
+                &info.port_type,
+                SerialPortType::UsbPort(usb)
+                    if usb.vid == *vid && usb.pid == *pid && usb.serial_number == *serial_number
+            ),
+            Identity::Name(name) => info.port_name == *name,
+        });
+        Ok(found.map(|info| info.port_name))
+    }
+}
+
+// Polls the port list on its own thread, telling the mode manager to
+// reattach whenever the adapter on `port` reappears.
+pub fn spawn_watcher(config: &HotplugConfig, port: &str, tx: &'static Sender<Update>) {
+    if !config.enabled {
+        return;
+    }
+    let identity = match Identity::of(port) {
+        Ok(identity) => identity,
+        Err(e) => {
+            println!("[hotplug] Not watching {port}: {e:#}");
+            return;
+        }
+    };
+    let poll = Duration::from_millis(config.poll_millis.max(100));
+    let mut present = Some(port.to_owned());
+    // What the MCU knows it as.
+    let mut attached = port.to_owned();
+    spawn(move || {
+        // Only logged once while it keeps failing the same way.
+        let mut last_error = None;
+        loop {
+            sleep(poll);
+            let found = match identity.find() {
+                Ok(found) => found,
+                Err(e) => {
+                    let error = format!("{e:#}");
+                    if last_error.as_ref() != Some(&error) {
+                        println!("[hotplug] Listing ports failed: {error}");
+                        last_error = Some(error);
+                    }
+                    continue;
+                }
+            };
+            last_error = None;
+            match (&present, &found) {
+                (Some(name), None) => println!("[hotplug] {name} unplugged."),
+                // Back, or unplugged and back under another name between polls.
+                (_, Some(name)) if present.as_ref() != Some(name) => {
+                    println!("[hotplug] Found the adapter on {name}; reattaching...");
+                    let was = std::mem::replace(&mut attached, name.clone());
+                    if tx.send(Update::Reattach { was, port: name.clone() }).is_err() {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            present = found;
+        }
+    });
+}
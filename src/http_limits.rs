@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use warp::{
+    http::{Method, StatusCode},
+    reject::Reject,
+    Filter, Rejection, Reply,
+};
+
+// Clients are forgotten once there are this many and they've caught up.
+const MAX_CLIENTS: usize = 256;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpLimitsConfig {
+    // Requests that change something (POST, PUT and DELETE) allowed a minute
+    // from each client address, and how many it may make at once beyond
+    // that. Zero turns the limit off.
+    pub per_minute: u32,
+    pub burst: u32,
+    // The largest JSON or text body accepted; library uploads go by
+    // `library.max_upload_kb` instead.
+    pub max_body_kb: u64,
+}
+
+impl Default for HttpLimitsConfig {
+    fn default() -> Self {
+        Self { per_minute: 60, burst: 10, max_body_kb: 16 }
+    }
+}
+
+impl HttpLimitsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.per_minute > 0 && self.burst == 0 {
+            bail!("http_limits.burst: must be at least 1");
+        }
+        if self.max_body_kb == 0 {
+            bail!("http_limits.max_body_kb: must be at least 1");
+        }
+        Ok(())
+    }
+
+    pub fn max_body(&self) -> u64 {
+        self.max_body_kb * 1024
+    }
+}
+
+// What a client has left to spend, refilled at `per_minute`.
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+    // Whether its last request was turned down, so that's logged once.
+    limited: bool,
+}
+
+struct Limiter {
+    config: HttpLimitsConfig,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+static LIMITER: OnceLock<Limiter> = OnceLock::new();
+
+pub fn init(config: &HttpLimitsConfig) {
+    if config.per_minute == 0 {
+        println!("[http_limits] Not rate limiting.");
+        return;
+    }
+    let _ = LIMITER.set(Limiter { config: *config, clients: Mutex::new(HashMap::new()) });
+}
+
+// Turned down for making requests too fast; `retry` is how long until the
+// next would be let through.
+#[derive(Debug)]
+struct TooManyRequests {
+    retry: Duration,
+}
+
+impl Reject for TooManyRequests {}
+
+// A body over `max_body_kb`, or one sent in chunks so there's no telling.
+#[derive(Debug)]
+pub enum BadBody {
+    TooLarge(u64),
+    NoLength,
+}
+
+impl fmt::Display for BadBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BadBody::TooLarge(max) => write!(f, "body is over {max} bytes"),
+            BadBody::NoLength => f.write_str("a content-length header is required"),
+        }
+    }
+}
+
+impl Reject for BadBody {}
+
+// Whether a request may change something, so counts against the limit.
+pub fn mutating(method: &Method) -> bool {
+    [Method::POST, Method::PUT, Method::DELETE].contains(method)
+}
+
+// Spends one of the client's requests if this one changes something, or
+// turns it down if it has none left.
+pub fn check() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::addr::remote())
+        .and_then(|method: Method, addr: Option<SocketAddr>| async move {
+            match (LIMITER.get(), addr) {
+                (Some(limiter), Some(addr)) if mutating(&method) => limiter.spend(addr.ip())
+                    .map_err(|retry| warp::reject::custom(TooManyRequests { retry })),
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+}
+
+// Like `warp::body::content_length_limit`, but lets through requests that
+// send no body at all, so `curl -X POST` starts a mode with its defaults.
+pub fn body_limit(max: u64) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and(warp::header::optional::<String>("transfer-encoding"))
+        .and_then(move |length: Option<u64>, chunked: Option<String>| async move {
+            match length {
+                Some(length) if length > max => Err(warp::reject::custom(BadBody::TooLarge(max))),
+                None if chunked.is_some() => Err(warp::reject::custom(BadBody::NoLength)),
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+}
+
+impl Limiter {
+    fn spend(&self, ip: IpAddr) -> Result<(), Duration> {
+        let rate = f64::from(self.config.per_minute) / 60.0;
+        let burst = f64::from(self.config.burst);
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            clients.retain(|_, bucket| {
+                bucket.tokens + (now - bucket.at).as_secs_f64() * rate < burst
+            });
+        }
+        // New addresses come cheap, over IPv6 especially, so if none have
+        // caught up the one heard from longest ago makes room.
+        if clients.len() >= MAX_CLIENTS && !clients.contains_key(&ip) {
+            let oldest = clients.iter().min_by_key(|(_, bucket)| bucket.at).map(|(&ip, _)| ip);
+            if let Some(oldest) = oldest {
+                clients.remove(&oldest);
+            }
+        }
+        let bucket = clients.entry(ip)
+            .or_insert(Bucket { tokens: burst, at: now, limited: false });
+        bucket.tokens = (bucket.tokens + (now - bucket.at).as_secs_f64() * rate).min(burst);
+        bucket.at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            if bucket.limited {
+                println!("[http_limits] {ip} slowed down.");
+                bucket.limited = false;
+            }
+            return Ok(());
+        }
+        if !bucket.limited {
+            println!(
+                "[http_limits] {ip} is over {} requests a minute; turning some down...",
+                self.config.per_minute,
+            );
+            bucket.limited = true;
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+}
+
+// A 429 in the API's envelope for a request `check` turned down, saying
+// when to try again. Anything else is passed on.
+pub async fn rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let Some(limited) = rejection.find::<TooManyRequests>() else {
+        return Err(rejection);
+    };
+    let secs = limited.retry.as_secs_f64().ceil().max(1.0) as u64;
+    let reply = crate::reply_err(
+        format!("too many requests; try again in {secs}s"),
+        StatusCode::TOO_MANY_REQUESTS,
+    );
+    Ok(warp::reply::with_header(reply, "retry-after", secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    #[test]
+    fn fresh_addresses_never_grow_past_the_cap() {
+        let config = HttpLimitsConfig { per_minute: 1, burst: 1, ..Default::default() };
+        let limiter = Limiter { config, clients: Mutex::new(HashMap::new()) };
+        for i in 0..(MAX_CLIENTS as u128 * 4) {
+            let ip = IpAddr::V6(Ipv6Addr::from(i));
+            limiter.spend(ip).unwrap();
+            // Spent, so it hasn't caught up.
+            assert!(limiter.spend(ip).is_err());
+        }
+        assert!(limiter.clients.lock().unwrap().len() <= MAX_CLIENTS);
+    }
+}
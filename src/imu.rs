@@ -0,0 +1,216 @@
+use std::{
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::I2cdev;
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::low_power::{self, LowPowerConfig};
+
+// Where the helmet is pointing, in degrees. Heading is clockwise from north
+// (or from wherever the sensor started, for one without a magnetometer);
+// pitch is positive looking up.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Orientation {
+    pub heading: f64,
+    pub pitch: f64,
+}
+
+// Latest orientation, or `None` until the first reading.
+pub type OrientationRx = watch::Receiver<Option<Orientation>>;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensor {
+    // Accelerometer and gyro only, so heading is relative and drifts
+    Mpu6050,
+    // Fused absolute orientation
+    Bno055,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImuConfig {
+    // No sensor means no orientation; modes fall back to north-up.
+    pub sensor: Option<Sensor>,
+    pub bus: String,
+    // Defaults to the sensor's usual address.
+    pub address: Option<u8>,
+    pub poll_millis: u64,
+    // Added to every heading, for a sensor not mounted facing forwards.
+    pub heading_offset: f64,
+}
+
+impl Default for ImuConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            bus: "/dev/i2c-1".to_owned(),
+            address: None,
+            poll_millis: 50,
+            heading_offset: 0.0,
+        }
+    }
+}
+
+fn read_i16s<const N: usize>(
+    i2c: &mut I2cdev,
+    addr: u8,
+    reg: u8,
+    big_endian: bool,
+) -> Result<[i16; N]> {
+    let mut buf = vec![0u8; N * 2];
+    i2c.write_read(addr, &[reg], &mut buf)?;
+    let mut out = [0i16; N];
+    for (v, b) in out.iter_mut().zip(buf.chunks_exact(2)) {
+        *v = if big_endian {
+            i16::from_be_bytes([b[0], b[1]])
+        } else {
+            i16::from_le_bytes([b[0], b[1]])
+        };
+    }
+    Ok(out)
+}
+
+trait Reader {
+    fn read(&mut self, i2c: &mut I2cdev) -> Result<Orientation>;
+}
+
+const MPU6050_ADDR: u8 = 0x68;
+// LSB per g at ±2g, and per degree/second at ±250°/s.
+const MPU6050_ACCEL_SCALE: f64 = 16384.0;
+const MPU6050_GYRO_SCALE: f64 = 131.0;
+// How much each new accelerometer reading pulls the gyro-integrated pitch.
+const MPU6050_ACCEL_WEIGHT: f64 = 0.02;
+
+struct Mpu6050 {
+    addr: u8,
+    gyro_bias: [f64; 3],
+    last: Instant,
+    heading: f64,
+    pitch: f64,
+}
+
+impl Mpu6050 {
+    fn init(i2c: &mut I2cdev, addr: u8) -> Result<Self> {
+        // Out of sleep mode.
+        i2c.write(addr, &[0x6B, 0x00])?;
+        sleep(Duration::from_millis(100));
+        // Averaged while the helmet (hopefully) sits still.
+        println!("[imu] Calibrating gyro, keep still...");
+        let mut sum = [0f64; 3];
+        const SAMPLES: usize = 100;
+        for _ in 0..SAMPLES {
+            let raw: [i16; 3] = read_i16s(i2c, addr, 0x43, true)?;
+            for (s, r) in sum.iter_mut().zip(raw) {
+                *s += r as f64 / MPU6050_GYRO_SCALE;
+            }
+            sleep(Duration::from_millis(5));
+        }
+        let gyro_bias = sum.map(|s| s / SAMPLES as f64);
+        let mut this = Self { addr, gyro_bias, last: Instant::now(), heading: 0.0, pitch: 0.0 };
+        this.pitch = this.accel_pitch(i2c)?;
+        Ok(this)
+    }
+
+    fn accel_pitch(&self, i2c: &mut I2cdev) -> Result<f64> {
+        let [ax, ay, az] = read_i16s::<3>(i2c, self.addr, 0x3B, true)?
+            .map(|v| v as f64 / MPU6050_ACCEL_SCALE);
+        Ok((-ax).atan2(((ay * ay) + (az * az)).sqrt()).to_degrees())
+    }
+}
+
+impl Reader for Mpu6050 {
+    fn read(&mut self, i2c: &mut I2cdev) -> Result<Orientation> {
+        let dt = self.last.elapsed().as_secs_f64();
+        self.last = Instant::now();
+        let raw: [i16; 3] = read_i16s(i2c, self.addr, 0x43, true)?;
+        let [_, gy, gz] = [0, 1, 2]
+            .map(|i| (raw[i] as f64 / MPU6050_GYRO_SCALE) - self.gyro_bias[i]);
+        // Complementary filter: the gyro is smooth but drifts, the
+        // accelerometer is noisy but knows where down is.
+        let accel = self.accel_pitch(i2c)?;
+        self.pitch = ((1.0 - MPU6050_ACCEL_WEIGHT) * (self.pitch + (gy * dt)))
+            + (MPU6050_ACCEL_WEIGHT * accel);
+        // Nothing to correct yaw against, so it's integrated as-is.
+        self.heading = (self.heading - (gz * dt)).rem_euclid(360.0);
+        Ok(Orientation { heading: self.heading, pitch: self.pitch })
+    }
+}
+
+const BNO055_ADDR: u8 = 0x28;
+const BNO055_CHIP_ID: u8 = 0xA0;
+// Euler angles come as 1/16ths of a degree.
+const BNO055_EULER_SCALE: f64 = 16.0;
+
+struct Bno055 {
+    addr: u8,
+}
+
+impl Bno055 {
+    fn init(i2c: &mut I2cdev, addr: u8) -> Result<Self> {
+        let mut id = [0u8];
+        i2c.write_read(addr, &[0x00], &mut id)?;
+        if id[0] != BNO055_CHIP_ID {
+            bail!("no BNO055 at {addr:#04x} (chip id {:#04x})", id[0]);
+        }
+        // Config mode, then NDOF fusion with the magnetometer.
+        i2c.write(addr, &[0x3D, 0x00])?;
+        sleep(Duration::from_millis(25));
+        i2c.write(addr, &[0x3D, 0x0C])?;
+        sleep(Duration::from_millis(20));
+        Ok(Self { addr })
+    }
+}
+
+impl Reader for Bno055 {
+    fn read(&mut self, i2c: &mut I2cdev) -> Result<Orientation> {
+        let [heading, _roll, pitch] = read_i16s::<3>(i2c, self.addr, 0x1A, false)?
+            .map(|v| v as f64 / BNO055_EULER_SCALE);
+        Ok(Orientation { heading, pitch })
+    }
+}
+
+// Polls the sensor on its own thread. Modes see the newest reading through
+// the returned channel and never wait on the bus themselves.
+pub fn spawn_poller(
+    config: &ImuConfig,
+    low_power: &LowPowerConfig,
+) -> Result<Option<OrientationRx>> {
+    let Some(sensor) = config.sensor else {
+        return Ok(None);
+    };
+    let mut i2c = I2cdev::new(&config.bus)?;
+    let mut reader: Box<dyn Reader + Send> = match sensor {
+        Sensor::Mpu6050 => {
+            Box::new(Mpu6050::init(&mut i2c, config.address.unwrap_or(MPU6050_ADDR))?)
+        }
+        Sensor::Bno055 => {
+            Box::new(Bno055::init(&mut i2c, config.address.unwrap_or(BNO055_ADDR))?)
+        }
+    };
+    println!("[imu] Polling {sensor:?} on {}...", config.bus);
+    let (tx, rx) = watch::channel(None);
+    let interval = Duration::from_millis(config.poll_millis);
+    let low_power_interval = Duration::from_millis(low_power.imu_poll_millis);
+    let offset = config.heading_offset;
+    spawn(move || {
+        loop {
+            match reader.read(&mut i2c) {
+                Ok(mut orientation) => {
+                    orientation.heading = (orientation.heading + offset).rem_euclid(360.0);
+                    if tx.send(Some(orientation)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => println!("[imu] Read failed: {e}"),
+            }
+            low_power::sleep(interval, low_power_interval);
+        }
+    });
+    Ok(Some(rx))
+}
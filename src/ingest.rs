@@ -0,0 +1,179 @@
+use std::io::Cursor;
+
+use anyhow::{bail, Result};
+use jpeg_decoder::PixelFormat;
+use png::{BitDepth, ColorType, Decoder as PngDec, Limits, Transformations};
+
+// Decoding of images that come in from outside: panel frames from the
+// library and uploads, and pictures to be fitted to the panel. Nothing here
+// reaches into the rest of the crate, so the fuzz targets in `fuzz/` can
+// build it on its own.
+
+pub type Rgb = [u8; 3];
+pub type Rgba = [u8; 4];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+}
+
+// Anything bigger would take a long time and a lot of memory to decode on
+// a Pi, only to be shrunk to a handful of pixels.
+const MAX_PIXELS: u64 = 25_000_000;
+
+fn check_size(width: u32, height: u32) -> Result<()> {
+    // In u64, as `usize` can't hold the product on the Pi.
+    if width as u64 * height as u64 > MAX_PIXELS {
+        bail!("image is too big ({width}x{height})");
+    }
+    Ok(())
+}
+
+// Caps what the PNG decoder will buffer along the way, e.g. for oversized
+// text chunks, at what the largest allowed image would take.
+fn png_decoder(data: &[u8]) -> PngDec<Cursor<&[u8]>> {
+    let limits = Limits { bytes: MAX_PIXELS as usize * 4 };
+    PngDec::new_with_limits(Cursor::new(data), limits)
+}
+
+// Integer approximation of Rec. 601 luma.
+pub fn luma(r: u8, g: u8, b: u8) -> u8 {
+    (((r as u32 * 77) + (g as u32 * 150) + (b as u32 * 29)) >> 8) as u8
+}
+
+// `pixel` laid over `background` as far as it's opaque.
+pub fn over(pixel: Rgba, background: Rgb) -> Rgb {
+    let a = pixel[3] as u32;
+    [0, 1, 2].map(|i| {
+        (((pixel[i] as u32 * a) + (background[i] as u32 * (255 - a)) + 127) / 255) as u8
+    })
+}
+
+// Decodes any PNG or JPEG to 8-bit grayscale over `background`, returning it
+// with its size.
+pub fn decode(data: &[u8], format: Format, background: Rgb) -> Result<(Vec<u8>, (usize, usize))> {
+    let (rgb, dims) = decode_rgb(data, format, background)?;
+    Ok((rgb.into_iter().map(|[r, g, b]| luma(r, g, b)).collect(), dims))
+}
+
+// Decodes any PNG or JPEG to 8-bit RGB over `background`, returning it with
+// its size.
+pub fn decode_rgb(
+    data: &[u8],
+    format: Format,
+    background: Rgb,
+) -> Result<(Vec<Rgb>, (usize, usize))> {
+    let (rgba, dims) = decode_rgba(data, format)?;
+    Ok((rgba.into_iter().map(|px| over(px, background)).collect(), dims))
+}
+
+// Decodes any PNG or JPEG to 8-bit RGBA, returning it with its size. JPEGs
+// and PNGs without transparency come out fully opaque.
+pub fn decode_rgba(data: &[u8], format: Format) -> Result<(Vec<Rgba>, (usize, usize))> {
+    match format {
+        Format::Png => {
+            let mut decoder = png_decoder(data);
+            decoder.set_transformations(Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info()?;
+            check_size(reader.info().width, reader.info().height)?;
+            let mut buf = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buf)?;
+            buf.truncate(info.buffer_size());
+            let dims = (info.width as usize, info.height as usize);
+            let rgba = match info.color_type {
+                ColorType::Grayscale => buf.into_iter().map(|v| [v, v, v, 255]).collect(),
+                ColorType::GrayscaleAlpha => buf.chunks_exact(2)
+                    .map(|px| [px[0], px[0], px[0], px[1]])
+                    .collect(),
+                ColorType::Rgb => buf.chunks_exact(3)
+                    .map(|px| [px[0], px[1], px[2], 255])
+                    .collect(),
+                ColorType::Rgba => buf.chunks_exact(4)
+                    .map(|px| [px[0], px[1], px[2], px[3]])
+                    .collect(),
+                // Expanded to RGB(A) by `normalize_to_color8`, along with
+                // tRNS transparency.
+                ColorType::Indexed => unreachable!(),
+            };
+            Ok((rgba, dims))
+        }
+        Format::Jpeg => {
+            let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(data));
+            decoder.set_max_decoding_buffer_size(MAX_PIXELS as usize * 4);
+            decoder.read_info()?;
+            let Some(info) = decoder.info() else {
+                bail!("JPEG has no image");
+            };
+            check_size(info.width as u32, info.height as u32)?;
+            let pixels = decoder.decode()?;
+            let dims = (info.width as usize, info.height as usize);
+            let rgba = match info.pixel_format {
+                PixelFormat::L8 => pixels.into_iter().map(|v| [v, v, v, 255]).collect(),
+                // Big-endian; the high byte is enough.
+                PixelFormat::L16 => pixels.chunks_exact(2)
+                    .map(|px| [px[0], px[0], px[0], 255])
+                    .collect(),
+                PixelFormat::RGB24 => pixels.chunks_exact(3)
+                    .map(|px| [px[0], px[1], px[2], 255])
+                    .collect(),
+                PixelFormat::CMYK32 => pixels.chunks_exact(4)
+                    .map(|px| {
+                        let k = 255 - px[3] as u32;
+                        let [r, g, b] = [0, 1, 2].map(|i| ((255 - px[i] as u32) * k / 255) as u8);
+                        [r, g, b, 255]
+                    })
+                    .collect(),
+            };
+            Ok((rgba, dims))
+        }
+    }
+}
+
+// Decodes a panel-sized PNG of any colour type and depth to 8-bit
+// grayscale, over `background` where it's transparent. 1-bit grayscale ones
+// are packed LSB first, as the panel frames have always been stored.
+pub fn read_png_g(data: &[u8], dims: (usize, usize), background: Rgb) -> Result<Vec<u8>> {
+    let reader = png_decoder(data).read_info()?;
+    let info = reader.info();
+    if (info.width as usize, info.height as usize) != dims {
+        bail!("image is {}x{}, panel is {}x{}", info.width, info.height, dims.0, dims.1);
+    }
+    match (info.color_type, info.bit_depth, &info.trns) {
+        (ColorType::Grayscale, BitDepth::Eight, None) => read_png(data),
+        (ColorType::Grayscale, BitDepth::One, _) => {
+            // Each row starts on a byte boundary.
+            let raw = read_png(data)?;
+            let row_bytes = dims.0.div_ceil(8);
+            Ok(raw.chunks_exact(row_bytes).flat_map(|row| {
+                let mut row = unpack_1bit(row);
+                row.truncate(dims.0);
+                row
+            }).collect())
+        }
+        // RGB, palettes, alpha and 2, 4 and 16-bit samples.
+        _ => Ok(decode(data, Format::Png, background)?.0),
+    }
+}
+
+pub fn unpack_1bit(raw_buf: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0u8; raw_buf.len() * 8];
+    for i in 0..(raw_buf.len()) {
+        for j in 0..8 {
+            if (raw_buf[i] & (1 << j)) > 0 {
+                buf[(i * 8) + j] = 0xFF;
+            }
+        }
+    }
+    buf
+}
+
+// The first frame's samples as stored, with no conversion.
+pub fn read_png(data: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = png_decoder(data).read_info()?;
+    check_size(reader.info().width, reader.info().height)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    Ok(buf)
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{self, HelmetEvent},
+    hooks::{self, HookEvent},
+    protocol,
+};
+
+// Something that happened on the helmet itself, as reported by the MCU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputEvent {
+    Press { button: u8 },
+    Release { button: u8 },
+    // Detents turned since the last report, positive clockwise.
+    Encoder { delta: i8 },
+    // Firmware-defined, e.g. a fault after a brown-out.
+    Status { code: u8 },
+}
+
+// What the mode manager does about an input, rather than passing it to the
+// active mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    NextMode,
+    PreviousMode,
+    ZoomIn,
+    ZoomOut,
+    // Blanks the panel until the next input.
+    DisplayOff,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonBinding {
+    pub id: u8,
+    #[serde(default)]
+    pub action: Option<Action>,
+    // Taken instead when the button is held for `long_press_millis`. With
+    // one set, `action` waits for the release to tell the two apart.
+    #[serde(default)]
+    pub long_press: Option<Action>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    // Actions taken when a button is pressed.
+    pub button: Vec<ButtonBinding>,
+    pub long_press_millis: u64,
+    pub encoder_up: Option<Action>,
+    pub encoder_down: Option<Action>,
+    // What next_mode and previous_mode step through.
+    pub cycle: Vec<String>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            button: vec![],
+            long_press_millis: 800,
+            encoder_up: None,
+            encoder_down: None,
+            cycle: ["map", "clock", "hud", "stats"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl InputConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (i, name) in self.cycle.iter().enumerate() {
+            if crate::mode::lookup(name).is_none() {
+                bail!("input.cycle[{i}]: unknown mode {name:?}");
+            }
+        }
+        Ok(())
+    }
+
+    // The mode after (or before) `from` in the cycle; the first if `from`
+    // isn't in it.
+    pub fn step(&self, from: &str, backwards: bool) -> Option<&str> {
+        let len = self.cycle.len();
+        let next = match self.cycle.iter().position(|name| name == from) {
+            Some(i) if backwards => (i + len - 1) % len,
+            Some(i) => (i + 1) % len,
+            None => 0,
+        };
+        self.cycle.get(next).map(String::as_str)
+    }
+}
+
+pub enum Mapped {
+    Action(Action),
+    // Left for the active mode.
+    Event(InputEvent),
+}
+
+// Turns input events into the configured actions, keeping track of held
+// buttons to spot long presses.
+pub struct InputMapper {
+    pub config: InputConfig,
+    held: HashMap<u8, Instant>,
+}
+
+impl InputMapper {
+    pub fn new(config: InputConfig) -> Self {
+        Self { config, held: HashMap::new() }
+    }
+
+    fn binding(&self, button: u8) -> Option<&ButtonBinding> {
+        self.config.button.iter().find(|b| b.id == button)
+    }
+
+    // `None` while a press could still become a long one.
+    pub fn map(&mut self, event: InputEvent) -> Option<Mapped> {
+        let action = match event {
+            InputEvent::Press { button } => match self.binding(button) {
+                Some(ButtonBinding { long_press: Some(_), .. }) => {
+                    self.held.insert(button, Instant::now());
+                    return None;
+                }
+                Some(binding) => binding.action,
+                None => None,
+            },
+            InputEvent::Release { button } => match self.held.remove(&button) {
+                Some(pressed) => {
+                    let binding = self.binding(button)?;
+                    let long = Duration::from_millis(self.config.long_press_millis);
+                    // Nothing if the short press isn't bound.
+                    let action = if pressed.elapsed() >= long {
+                        binding.long_press
+                    } else {
+                        binding.action
+                    };
+                    return action.map(Mapped::Action);
+                }
+                None => None,
+            },
+            InputEvent::Encoder { delta } if delta > 0 => self.config.encoder_up,
+            InputEvent::Encoder { delta } if delta < 0 => self.config.encoder_down,
+            _ => None,
+        };
+        Some(action.map_or(Mapped::Event(event), Mapped::Action))
+    }
+}
+
+// Splits what the MCU sends into messages starting with `prefix`, and
+// anything else, like ping echoes. Input messages are three bytes; reports
+// of free buffer space (kind C), about chunks (A and N) and answering hello
+// (H) carry a little-endian u16 instead.
+// With an `escape`, XON and XOFF are flow control and escaped bytes are
+// data.
+pub struct Parser {
+    prefix: u8,
+    escape: Option<u8>,
+    escaped: bool,
+    pending: Vec<u8>,
+}
+
+// The MCU saying how fast it can take frames, whether it got them, and what
+// it can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    // Bytes it has room for.
+    Credit(u16),
+    Xoff,
+    Xon,
+    // Chunks, by index, that it did and didn't get intact.
+    Ack(u16),
+    Nak(u16),
+    // Feature bits, as `protocol::HELLO_*`.
+    Hello(u16),
+}
+
+pub enum Parsed {
+    Input(InputEvent),
+    Flow(Flow),
+    Byte(u8),
+}
+
+impl Parser {
+    pub fn new(prefix: u8, escape: Option<u8>) -> Self {
+        Self { prefix, escape, escaped: false, pending: Vec::with_capacity(4) }
+    }
+
+    pub fn feed(&mut self, mut byte: u8) -> Option<Parsed> {
+        if let Some(escape) = self.escape {
+            if self.escaped {
+                self.escaped = false;
+                byte ^= 0x20;
+            } else if byte == escape {
+                self.escaped = true;
+                return None;
+            } else if byte == protocol::XOFF {
+                return Some(Parsed::Flow(Flow::Xoff));
+            } else if byte == protocol::XON {
+                return Some(Parsed::Flow(Flow::Xon));
+            }
+        }
+        if self.pending.is_empty() && byte != self.prefix {
+            return Some(Parsed::Byte(byte));
+        }
+        self.pending.push(byte);
+        let wide = matches!(self.pending.get(1), Some(b'C' | b'A' | b'N' | b'H'));
+        if self.pending.len() < if wide { 4 } else { 3 } {
+            return None;
+        }
+        let (kind, value) = (self.pending[1], self.pending[2]);
+        if wide {
+            let value = u16::from_le_bytes([value, self.pending[3]]);
+            self.pending.clear();
+            return Some(Parsed::Flow(match kind {
+                b'C' => Flow::Credit(value),
+                b'A' => Flow::Ack(value),
+                b'N' => Flow::Nak(value),
+                _ => Flow::Hello(value),
+            }));
+        }
+        self.pending.clear();
+        let event = match kind {
+            b'P' => InputEvent::Press { button: value },
+            b'R' => InputEvent::Release { button: value },
+            b'E' => InputEvent::Encoder { delta: value as i8 },
+            b'S' => InputEvent::Status { code: value },
+            _ => {
+                println!("[input] Unknown message kind {kind:#04x}.");
+                return None;
+            }
+        };
+        Some(Parsed::Input(event))
+    }
+}
+
+// A second handle on the serial port, for reading only.
+pub type ReadHandle = Box<dyn Read + Send>;
+
+// Reads the MCU's side of the serial port on its own thread until stopped.
+pub struct Reader {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Reader {
+    // `serial` needs a read timeout, which bounds how long `stop` takes.
+    pub fn spawn(
+        mut serial: ReadHandle,
+        port: String,
+        mut parser: Parser,
+        inputs: Sender<InputEvent>,
+        flow: Sender<Flow>,
+        bytes: Sender<u8>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let thread = spawn(move || {
+            let mut buf = [0u8; 64];
+            while !stopping.load(Ordering::Relaxed) {
+                let n = match serial.read(&mut buf) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(e) => {
+                        println!("[input] Serial read failed: {e}");
+                        let error = e.to_string();
+                        hooks::fire(HookEvent::SerialDisconnected, &[
+                            ("port", &port),
+                            ("error", &error),
+                        ]);
+                        events::publish(HelmetEvent::SerialDisconnected { port, error });
+                        break;
+                    }
+                };
+                for &byte in &buf[..n] {
+                    // Nobody listening, or a backlog nobody reads, drops it.
+                    match parser.feed(byte) {
+                        Some(Parsed::Input(event)) => {
+                            let _ = inputs.try_send(event);
+                        }
+                        Some(Parsed::Flow(report)) => {
+                            let _ = flow.try_send(report);
+                        }
+                        Some(Parsed::Byte(byte)) => {
+                            let _ = bytes.try_send(byte);
+                        }
+                        None => {}
+                    }
+                }
+            }
+        });
+        Self { stop, thread }
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+        OnceLock,
+    },
+    thread::spawn,
+};
+
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{bounded, select, Receiver, Sender};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::Update;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    // Text, maps, images and mode switches wait here for the mode manager,
+    // kept across restarts.
+    pub path: PathBuf,
+    // More are refused, rather than the helmet working through a backlog
+    // for hours.
+    pub max_pending: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self { path: PathBuf::from("jobs.sqlite"), max_pending: 100 }
+    }
+}
+
+// Which jobs go first. Within a class, the oldest does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    // Only shown when nothing else is waiting, e.g. a slideshow.
+    Background,
+    #[default]
+    Normal,
+    // Safety and navigation alerts. These cut short the frame being sent,
+    // if the firmware has `protocol.abort`.
+    Alert,
+}
+
+impl Priority {
+    fn rank(self) -> i64 {
+        self as i64
+    }
+
+    fn from_rank(rank: i64) -> Self {
+        match rank {
+            ..=0 => Priority::Background,
+            1 => Priority::Normal,
+            _ => Priority::Alert,
+        }
+    }
+}
+
+// A job still waiting, as `GET /api/v1/queue` lists them.
+#[derive(Serialize, ToSchema)]
+pub struct Queued {
+    pub id: i64,
+    pub priority: Priority,
+    // UTC, as SQLite writes it.
+    pub submitted: String,
+    // What it'll do, as the control socket would put it.
+    #[schema(value_type = Object)]
+    pub job: serde_json::Value,
+}
+
+struct Queue {
+    db: Mutex<Connection>,
+    // Signalled whenever jobs are added or removed, so the feeder looks
+    // again at what's next.
+    changed: (Sender<()>, Receiver<()>),
+    max_pending: usize,
+}
+
+static QUEUE: OnceLock<Queue> = OnceLock::new();
+
+// Set from an alert being queued until the manager takes its next update,
+// which is the alert, for frames being sent meanwhile to stop short.
+static PREEMPTING: AtomicBool = AtomicBool::new(false);
+
+// Opens the queue, with whatever was left in it when the server last
+// stopped.
+pub fn init(config: &JobsConfig) -> Result<()> {
+    let db = Connection::open(&config.path)
+        .with_context(|| format!("opening {}", config.path.display()))?;
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            priority INTEGER NOT NULL,
+            submitted TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            job TEXT NOT NULL
+        )",
+    )?;
+    let left = pending(&db)?;
+    if left > 0 {
+        println!("[jobs] {left} left from before the restart.");
+    }
+    let max_pending = config.max_pending;
+    let _ = QUEUE.set(Queue { db: Mutex::new(db), changed: bounded(1), max_pending });
+    Ok(())
+}
+
+// Whether `update` is something to show, which can wait its turn, rather
+// than something the manager has to answer or act on now.
+fn is_job(update: &Update) -> bool {
+    matches!(
+        update,
+        Update::Coords { .. } | Update::Text { .. } | Update::SendFile { .. } | Update::Mode { .. }
+    )
+}
+
+// Queues `update`, to be shown ahead of anything of lower `priority`, or
+// hands it straight to the manager if it isn't a display job or there's no
+// queue. Only the latter waits for the manager.
+pub fn send(tx: &Sender<Update>, update: Update, priority: Priority) -> Result<()> {
+    let Some(queue) = QUEUE.get().filter(|_| is_job(&update)) else {
+        tx.send(update).context("the mode manager has stopped")?;
+        return Ok(());
+    };
+    let db = queue.db.lock().unwrap();
+    let pending = pending(&db)?;
+    if pending >= queue.max_pending {
+        bail!("{pending} jobs are already waiting; try again later");
+    }
+    db.execute(
+        "INSERT INTO jobs (priority, job) VALUES (?1, ?2)",
+        params![priority.rank(), serde_json::to_string(&update)?],
+    )?;
+    if priority == Priority::Alert {
+        println!("[jobs] Alert queued; cutting short whatever's being sent...");
+        PREEMPTING.store(true, Ordering::Relaxed);
+    } else if pending > 0 {
+        println!("[jobs] Queued behind {pending} more.");
+    }
+    let _ = queue.changed.0.try_send(());
+    Ok(())
+}
+
+// Whether a frame being sent should stop where it is, for an alert.
+pub fn preempting() -> bool {
+    PREEMPTING.load(Ordering::Relaxed)
+}
+
+// Called by the manager as it takes each update, before it draws anything
+// for it. Alerts already waiting don't then cut short the one it has.
+pub fn taken() {
+    PREEMPTING.store(false, Ordering::Relaxed);
+}
+
+// The jobs waiting, in the order they'll go.
+pub fn list() -> Result<Vec<Queued>> {
+    let Some(queue) = QUEUE.get() else {
+        return Ok(Vec::new());
+    };
+    let db = queue.db.lock().unwrap();
+    let mut select = db.prepare(
+        "SELECT id, priority, submitted, job FROM jobs ORDER BY priority DESC, id",
+    )?;
+    let rows = select.query_map([], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, String>(3)?))
+    })?;
+    let mut jobs = Vec::new();
+    for row in rows {
+        let (id, priority, submitted, job) = row?;
+        jobs.push(Queued {
+            id,
+            priority: Priority::from_rank(priority),
+            submitted,
+            job: serde_json::from_str(&job)?,
+        });
+    }
+    Ok(jobs)
+}
+
+// Takes job `id` off the queue, if the manager doesn't have it yet.
+pub fn remove(id: i64) -> Result<()> {
+    let Some(queue) = QUEUE.get() else {
+        bail!("nothing is queued");
+    };
+    let db = queue.db.lock().unwrap();
+    if db.execute("DELETE FROM jobs WHERE id = ?1", [id])? == 0 {
+        bail!("no job {id} is waiting");
+    }
+    let alerts: i64 = db.query_row(
+        "SELECT COUNT(*) FROM jobs WHERE priority = ?1",
+        [Priority::Alert.rank()],
+        |row| row.get(0),
+    )?;
+    if alerts == 0 {
+        PREEMPTING.store(false, Ordering::Relaxed);
+    }
+    let _ = queue.changed.0.try_send(());
+    Ok(())
+}
+
+// Feeds queued jobs to the manager one at a time, by priority and then age.
+// Each stays queued until the manager has taken it, and while one waits for
+// the manager, any change to the queue has the feeder look again at what's
+// next.
+pub fn spawn_feeder(tx: &'static Sender<Update>) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    spawn(move || loop {
+        let (id, job) = match next(queue) {
+            Ok(next) => next,
+            Err(e) => {
+                println!("[jobs] Reading the queue failed, so stopping: {e:#}");
+                return;
+            }
+        };
+        match serde_json::from_str::<Update>(&job) {
+            Ok(update) => select! {
+                send(tx, update) -> sent => if sent.is_err() {
+                    return;
+                },
+                recv(queue.changed.1) -> _ => continue,
+            },
+            Err(e) => println!("[jobs] Dropping job {id}, which doesn't parse: {e}"),
+        }
+        let db = queue.db.lock().unwrap();
+        // Or it would come round again, and again.
+        if let Err(e) = db.execute("DELETE FROM jobs WHERE id = ?1", [id]) {
+            println!("[jobs] Removing job {id} failed, so stopping: {e}");
+            return;
+        }
+    });
+}
+
+fn pending(db: &Connection) -> Result<usize> {
+    let count: i64 = db.query_row("SELECT COUNT(*) FROM jobs", [], |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+// Waits for the next job.
+fn next(queue: &Queue) -> Result<(i64, String)> {
+    loop {
+        let next = queue.db.lock().unwrap()
+            .query_row(
+                "SELECT id, job FROM jobs ORDER BY priority DESC, id LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some(next) = next {
+            return Ok(next);
+        }
+        let _ = queue.changed.1.recv();
+    }
+}
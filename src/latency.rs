@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::mode::Panel;
+
+pub const DEFAULT_COUNT: usize = 10;
+pub const MAX_COUNT: usize = 100;
+
+// Body of `GET /api/v1/latency`. Round trips are the serial link plus the MCU
+// turning a ping around; `frame_ms` is how long the last whole frame took,
+// for comparison.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Latency {
+    pub samples: Vec<f64>,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    // Mean difference between consecutive round trips.
+    pub jitter_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_ms: Option<f64>,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub fn measure(panel: &mut dyn Panel, count: usize) -> Result<Latency> {
+    let count = count.clamp(1, MAX_COUNT);
+    println!("[latency] Pinging the panel {count} times...");
+    let samples = (0..count)
+        .map(|_| panel.ping().map(millis))
+        .collect::<Result<Vec<_>>>()?;
+    let mean_ms = samples.iter().sum::<f64>() / count as f64;
+    let jitter_ms = if count > 1 {
+        let diffs = samples.windows(2).map(|w| (w[1] - w[0]).abs());
+        diffs.sum::<f64>() / (count - 1) as f64
+    } else {
+        0.0
+    };
+    Ok(Latency {
+        min_ms: samples.iter().copied().fold(f64::INFINITY, f64::min),
+        max_ms: samples.iter().copied().fold(0.0, f64::max),
+        mean_ms,
+        jitter_ms,
+        frame_ms: panel.last_send().map(millis),
+        samples,
+    })
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{framebuffer::Framebuffer, text, ttf::Font};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VAlign {
+    Top,
+    #[default]
+    Middle,
+    Bottom,
+}
+
+// How to lay out a message of unknown length: word-wrapped, aligned, and in
+// the biggest size that still fits.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub align: Align,
+    pub valign: VAlign,
+    // Scalable font sizes to try, largest first, stepping by `step`. Below
+    // `min_size` the bitmap font takes over.
+    pub max_size: f32,
+    pub min_size: f32,
+    pub step: f32,
+    // Extra pixels between lines.
+    pub leading: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            align: Align::default(),
+            valign: VAlign::default(),
+            max_size: 32.0,
+            min_size: 12.0,
+            step: 2.0,
+            leading: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Face<'a> {
+    Bitmap,
+    Scaled(&'a Font, f32),
+}
+
+impl Face<'_> {
+    fn width(self, s: &str) -> usize {
+        match self {
+            // No trailing spacing column after the last glyph.
+            Face::Bitmap => (s.chars().count() * text::CELL_W).saturating_sub(1),
+            Face::Scaled(font, size) => font.measure(s, size).0,
+        }
+    }
+
+    fn height(self) -> usize {
+        match self {
+            Face::Bitmap => text::GLYPH_H,
+            Face::Scaled(font, size) => font.measure("", size).1,
+        }
+    }
+
+    fn draw(self, fb: &mut Framebuffer, x: isize, y: isize, s: &str) {
+        match self {
+            Face::Bitmap => text::draw_text(fb, x, y, s),
+            Face::Scaled(font, size) => font.draw(fb, x, y, s, size),
+        }
+    }
+}
+
+// Breaks `text` into lines no wider than `width`: at '\n', then between
+// words, and inside words only when one alone is too wide.
+fn wrap(face: Face, text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_owned()
+            } else {
+                format!("{line} {word}")
+            };
+            if face.width(&candidate) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Hard-break whatever of the word doesn't fit on a line alone.
+            for c in word.chars() {
+                line.push(c);
+                if face.width(&line) > width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+impl Layout {
+    // Renders `text` onto a blank frame. If even the bitmap font can't fit
+    // it all, the lines that don't fit are cut off at the bottom.
+    pub fn render(&self, text: &str, dims: (usize, usize), font: &Font) -> Vec<u8> {
+        let (w, h) = dims;
+        let mut faces = Vec::new();
+        let mut size = self.max_size;
+        while size >= self.min_size && self.step > 0.0 {
+            faces.push(Face::Scaled(font, size));
+            size -= self.step;
+        }
+        // The bitmap font is ASCII only; anything else would come out as
+        // question marks.
+        if text.is_ascii() {
+            faces.push(Face::Bitmap);
+        }
+
+        let fits = |face: Face, lines: &[String]| {
+            let total = (lines.len() * (face.height() + self.leading)).saturating_sub(self.leading);
+            total <= h && lines.iter().all(|line| face.width(line) <= w)
+        };
+        let (face, lines) = faces.iter()
+            .map(|&face| (face, wrap(face, text, w)))
+            .find(|(face, lines)| fits(*face, lines))
+            .unwrap_or_else(|| {
+                let smallest = *faces.last().unwrap_or(&Face::Bitmap);
+                (smallest, wrap(smallest, text, w))
+            });
+
+        let line_h = face.height() + self.leading;
+        let total = (lines.len() * line_h).saturating_sub(self.leading);
+        let top = match self.valign {
+            VAlign::Top => 0,
+            VAlign::Middle => h.saturating_sub(total) / 2,
+            VAlign::Bottom => h.saturating_sub(total),
+        };
+        let mut fb = Framebuffer::new(dims);
+        for (i, line) in lines.iter().enumerate() {
+            let spare = w.saturating_sub(face.width(line));
+            let x = match self.align {
+                Align::Left => 0,
+                Align::Center => spare / 2,
+                Align::Right => spare,
+            };
+            face.draw(&mut fb, x as isize, (top + (i * line_h)) as isize, line);
+        }
+        fb.into_pixels()
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{filter, ingest, picture, rawframe};
+
+// Kinds of file the library accepts: still PNGs, and raw frame streams (see
+// `rawframe`) played back as animations.
+pub const EXTENSIONS: &[&str] = &["png", "frames"];
+
+// Where thumbnails are kept, under `dir`. Being hidden, it's never listed.
+const THUMBS: &str = ".thumbs";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibraryConfig {
+    // Where uploaded images and animations are kept.
+    pub dir: PathBuf,
+    // Larger uploads are refused.
+    pub max_upload_kb: u64,
+    // How wide thumbnails are, in pixels; never wider than the panel.
+    pub thumb_px: usize,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self { dir: PathBuf::from("library"), max_upload_kb: 4096, thumb_px: 32 }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Item {
+    pub name: String,
+    pub bytes: u64,
+}
+
+// Stored files by name. Names are bare file names with one of the
+// `EXTENSIONS`, so a request can never reach outside `dir`.
+#[derive(Clone)]
+pub struct Library {
+    dir: PathBuf,
+    thumb_px: usize,
+}
+
+impl Library {
+    pub fn new(config: &LibraryConfig) -> Self {
+        Self { dir: config.dir.clone(), thumb_px: config.thumb_px.max(1) }
+    }
+
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let path = Path::new(name);
+        let extension = path.extension().and_then(|e| e.to_str());
+        if name.starts_with('.') || path.file_name() != Some(name.as_ref()) {
+            bail!("invalid name {name:?}");
+        }
+        if !extension.is_some_and(|e| EXTENSIONS.contains(&e)) {
+            bail!("{name:?} isn't one of {}", EXTENSIONS.join(", "));
+        }
+        Ok(self.dir.join(name))
+    }
+
+    // Like `path`, but the file has to exist.
+    pub fn existing(&self, name: &str) -> Result<PathBuf> {
+        let path = self.path(name)?;
+        if !path.is_file() {
+            bail!("no library item {name:?}");
+        }
+        Ok(path)
+    }
+
+    pub fn list(&self) -> Result<Vec<Item>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut items = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            if self.path(&name).is_ok() && entry.file_type()?.is_file() {
+                items.push(Item { name, bytes: entry.metadata()?.len() });
+            }
+        }
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(items)
+    }
+
+    pub fn read(&self, name: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(self.existing(name)?)?)
+    }
+
+    // Replaces any item of the same name. Only files the panel can show
+    // are accepted, so a bad upload fails here rather than on display.
+    pub fn write(&self, name: &str, data: &[u8], dims: (usize, usize)) -> Result<()> {
+        let path = self.path(name)?;
+        check(&path, data, dims).with_context(|| format!("invalid {name:?}"))?;
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating {}", self.dir.display()))?;
+        // Written aside and renamed, so a display never sees half a file.
+        let partial = self.dir.join(format!(".{name}.partial"));
+        fs::write(&partial, data)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        fs::remove_file(self.existing(name)?)?;
+        let _ = fs::remove_file(self.thumb_path(name));
+        Ok(())
+    }
+
+    fn thumb_path(&self, name: &str) -> PathBuf {
+        self.dir.join(THUMBS).join(format!("{name}.png"))
+    }
+
+    // A small PNG of the item, or of an animation's first frame. Each is
+    // made once and kept until the item is replaced.
+    pub fn thumbnail(&self, name: &str, dims: (usize, usize)) -> Result<Vec<u8>> {
+        let path = self.existing(name)?;
+        let thumb = self.thumb_path(name);
+        let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+        if let (Some(made), Some(changed)) = (modified(&thumb), modified(&path)) {
+            if made >= changed {
+                return Ok(fs::read(&thumb)?);
+            }
+        }
+        let frame = first_frame(&path, &fs::read(&path)?, dims)
+            .with_context(|| format!("invalid {name:?}"))?;
+        let w = self.thumb_px.min(dims.0);
+        let small = (w, (dims.1 * w / dims.0).max(1));
+        let png = picture::encode_png(&filter::downscale(&frame, dims, small), small)?;
+        // It can always be made again.
+        if let Err(e) = self.keep_thumb(name, &png) {
+            println!("[library] Not keeping the thumbnail of {name:?}: {e:#}");
+        }
+        Ok(png)
+    }
+
+    fn keep_thumb(&self, name: &str, png: &[u8]) -> Result<()> {
+        let dir = self.dir.join(THUMBS);
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let partial = dir.join(format!(".{name}.partial"));
+        fs::write(&partial, png)?;
+        fs::rename(&partial, self.thumb_path(name))?;
+        Ok(())
+    }
+}
+
+// What the panel shows first for the item at `path`.
+fn first_frame(path: &Path, data: &[u8], dims: (usize, usize)) -> Result<Vec<u8>> {
+    if path.extension().is_some_and(|e| e == "frames") {
+        return rawframe::read_frame(&mut Cursor::new(data), dims)?.context("no frames");
+    }
+    ingest::read_png_g(data, dims, picture::background())
+}
+
+fn check(path: &Path, data: &[u8], dims: (usize, usize)) -> Result<()> {
+    if path.extension().is_some_and(|e| e == "frames") {
+        let mut input = Cursor::new(data);
+        let mut frames = 0;
+        while rawframe::read_frame(&mut input, dims)?.is_some() {
+            frames += 1;
+        }
+        if frames == 0 {
+            bail!("no frames");
+        }
+        return Ok(());
+    }
+    ingest::read_png_g(data, dims, picture::background())?;
+    Ok(())
+}
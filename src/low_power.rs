@@ -0,0 +1,88 @@
+use std::{
+    sync::{Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::Sender;
+use serde::Deserialize;
+
+use crate::Update;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LowPowerConfig {
+    // Minutes with the mode not drawing (the screensaver doesn't count) and
+    // no updates or HTTP requests before the helmet goes low power; 0
+    // disables it.
+    pub idle_minutes: u64,
+    // How often the IMU is read while low power.
+    pub imu_poll_millis: u64,
+}
+
+impl Default for LowPowerConfig {
+    fn default() -> Self {
+        Self { idle_minutes: 15, imu_poll_millis: 1000 }
+    }
+}
+
+impl LowPowerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.imu_poll_millis == 0 {
+            bail!("low_power.imu_poll_millis: must be at least 1");
+        }
+        Ok(())
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_minutes > 0).then(|| Duration::from_secs(self.idle_minutes * 60))
+    }
+}
+
+// Only the mode manager goes in and out of low power; the pollers follow.
+static LOW_POWER: Mutex<bool> = Mutex::new(false);
+static CHANGED: Condvar = Condvar::new();
+
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub fn is_low_power() -> bool {
+    *LOW_POWER.lock().unwrap()
+}
+
+pub fn set(low_power: bool) {
+    *LOW_POWER.lock().unwrap() = low_power;
+    CHANGED.notify_all();
+}
+
+// Sleeps for `normal`, or for `low_power` while in low power, though that's
+// cut short when it ends.
+pub fn sleep(normal: Duration, low_power: Duration) {
+    let guard = LOW_POWER.lock().unwrap();
+    if !*guard {
+        drop(guard);
+        thread::sleep(normal);
+        return;
+    }
+    drop(CHANGED.wait_timeout_while(guard, low_power, |low_power| *low_power).unwrap());
+}
+
+pub fn wait_until_awake() {
+    let guard = LOW_POWER.lock().unwrap();
+    drop(CHANGED.wait_while(guard, |low_power| *low_power).unwrap());
+}
+
+// Counts an HTTP request as activity. Requests that don't send an update of
+// their own would otherwise go unnoticed by the mode manager, so this wakes
+// it up. It's only ever low power while waiting for an update, so the send
+// doesn't block for long.
+pub fn request(tx: &Sender<Update>) {
+    *LAST_REQUEST.lock().unwrap() = Some(Instant::now());
+    if is_low_power() {
+        let _ = tx.send(Update::Wake);
+    }
+}
+
+pub fn last_request() -> Option<Instant> {
+    *LAST_REQUEST.lock().unwrap()
+}
@@ -3,253 +3,2130 @@
 #![feature(exit_status_error)]
 
 use std::{
-    borrow::Cow,
-    convert::Infallible,
-    ffi::OsStr,
     fs::File,
-    io::Write,
+    io::{self, BufRead, Read, Write},
     net::SocketAddr,
+    os::unix::net::UnixStream,
     ops::DerefMut,
-    path::Path,
-    process::Command,
-    thread::{sleep, spawn},
-    time::Instant,
+    path::{Path, PathBuf},
+    panic,
+    sync::{Mutex, OnceLock, PoisonError, RwLock, TryLockError},
+    thread::{self, sleep, spawn, ThreadId},
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 use crossbeam_channel::{bounded, Sender, Receiver};
-use indicatif::ProgressBar;
 use lazy_static::lazy_static;
-use png::Decoder as PngDec;
-use serialport::SerialPort;
-use warp::Filter;
+use serde::{Deserialize, Serialize};
+use futures_util::SinkExt;
+use tokio::signal::unix::SignalKind;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter, Reply};
 
-const SERVER_ADDR: &'static str = "0.0.0.0:8080";
+mod ambient;
+mod battery;
+mod bot;
+mod capture;
+mod config;
+mod control;
+mod cors;
+mod events;
+mod eyes;
+mod fault;
+mod filter;
+mod flash;
+mod framebuffer;
+mod geo;
+mod gps;
+mod grpc;
+mod heart_rate;
+mod hexfont;
+mod hooks;
+mod hotplug;
+mod http_limits;
+mod imu;
+mod ingest;
+mod input;
+mod jobs;
+mod layout;
+mod latency;
+mod library;
+mod low_power;
+mod mask;
+mod mbtiles;
+mod mdns;
+mod mode;
+mod obd;
+mod openapi;
+mod overlay;
+mod picture;
+mod preview;
+mod progress;
+mod protocol;
+mod rawframe;
+mod route;
+mod safety;
+mod schedule;
+mod session;
+mod splash;
+mod sprite;
+mod state;
+mod text;
+mod track;
+mod transition;
+mod tween;
+mod ttf;
+mod waypoint;
+mod webhook;
+mod wiring;
 
-const MCU_SERIAL_PORT: &'static str = "/dev/ttyUSB0";
+use capture::Captured;
+use config::{Config, DEFAULT_CONFIG_PATH};
+use eyes::{Displays, LinkConfig};
+use filter::MapFilter;
+use mode::{
+    animation::AnimationMode,
+    audio::AudioMode,
+    calendar::CalendarMode,
+    calibrate::CalibrateMode,
+    camera::{CameraMode, CameraOpts},
+    clock::ClockMode,
+    headlines::HeadlinesMode,
+    heart_rate::HeartRateMode,
+    hud::HudMode,
+    life::LifeMode,
+    morse::MorseMode,
+    now_playing::NowPlayingMode,
+    pomodoro::PomodoroMode,
+    receive::ReceiveMode,
+    screen::{Region, ScreenMode, ScreenOpts, ScreenSource},
+    speedometer::SpeedometerMode,
+    split::SplitMode,
+    stats::StatsMode,
+    stdin::StdinMode,
+    test_pattern::{Pattern, TestPatternMode},
+    timer::TimerMode,
+    vehicle::VehicleMode,
+    vnc::VncMode,
+    weather::WeatherMode,
+    Mode,
+    ModeSettings,
+};
+#[cfg(feature = "scripting")]
+use mode::script::ScriptMode;
+use protocol::{Compression, FlowControl};
+
+const SERVER_ADDR: &str = "0.0.0.0:8080";
+
+const MCU_SERIAL_PORT: &str = "/dev/ttyUSB0";
+
+const MCU_BAUD: u32 = 115200;
+
+const MAP_IMAGE_FILENAME: &str = "_map.png";
+
+// Where `POST /api/v1/display-url` leaves the converted image for the image mode.
+const URL_IMAGE_FILENAME: &str = "_url.png";
+
+// And where the Telegram bot leaves pictures sent to it.
+const BOT_IMAGE_FILENAME: &str = "_bot.png";
 
-const MAP_IMAGE_FILENAME: &'static str = "_map.png";
+const PANEL_DIMS: (usize, usize) = (64, 64);
 
 const INVERT_IMAGE: bool = false;
 
-type UpdateT = String;
+// Work for the mode manager, from either HTTP or the control socket. The
+// serde shape is the control socket's wire format, e.g.
+// `{"cmd": "text", "text": "hello"}`, and how queued jobs are stored.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Update {
+    Coords { coords: String },
+    Text { text: String },
+    SendFile { path: PathBuf },
+    // A panel-sized frame to show in the image mode, e.g. one of a stream.
+    #[serde(skip)]
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    Frame { frame: Vec<u8> },
+    // `params` override the mode's config section for this start only.
+    Mode {
+        mode: String,
+        #[serde(default)]
+        params: Option<serde_json::Value>,
+    },
+    // Stops the display while `firmware` is flashed onto the MCU. The result
+    // goes back on `reply`, if given.
+    Flash {
+        firmware: PathBuf,
+        #[serde(skip)]
+        reply: Option<Sender<Result<(), String>>>,
+    },
+    // Normally from the MCU, but can be faked for testing.
+    Input { event: input::InputEvent },
+    // Only from HTTP; the result goes back on `reply`.
+    #[serde(skip)]
+    Latency {
+        count: usize,
+        reply: Sender<Result<latency::Latency, String>>,
+    },
+    // Ends low power, for activity that doesn't send an update of its own.
+    #[serde(skip)]
+    Wake,
+    // An MCU's serial adapter, `was`, is back after being unplugged, on
+    // `port`.
+    #[serde(skip)]
+    Reattach { was: String, port: String },
+}
+
+type UpdateT = Update;
+
+#[derive(Parser)]
+struct Cli {
+    /// TOML config file; defaults apply if it doesn't exist
+    #[arg(long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    config: PathBuf,
+    /// Forward raw frames from standard input to the panel
+    #[arg(long)]
+    stdin: bool,
+    /// No progress bars; progress goes to GET /api/v1/progress instead, as it
+    /// does whenever stdout isn't a terminal
+    #[arg(long, global = true)]
+    quiet: bool,
+    /// Write what would go to the MCU to FILE instead, exactly as sent, or
+    /// nowhere if no FILE is given (--dry-run=FILE)
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "/dev/null",
+    )]
+    dry_run: Option<PathBuf>,
+    /// Record every byte to and from the MCU, with timestamps, to FILE; the
+    /// dump subcommand prints it
+    #[arg(long, global = true, value_name = "FILE")]
+    capture: Option<PathBuf>,
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Serve the control page and display maps on request
+    Map {
+        /// Processing applied to each rendered map [default: edges]
+        #[arg(long, value_enum)]
+        filter: Option<MapFilter>,
+        /// Start out navigating this GPX or GeoJSON route
+        #[arg(long)]
+        route: Option<PathBuf>,
+    },
+    /// Play the Bad Apple frames (the default)
+    Touhou,
+    /// Show the local time
+    Clock,
+    /// Show the widgets from the HUD layout file
+    Hud,
+    /// Run Conway's Game of Life
+    Life,
+    /// Show a live spectrum of the audio input
+    Audio,
+    /// Show the current weather
+    Weather,
+    /// Show the next event from the calendar in mode.calendar
+    Calendar,
+    /// Scroll headlines from the feeds in mode.headlines
+    Headlines,
+    /// Show the track playing in MPD or an MPRIS player
+    NowPlaying,
+    /// Show CPU, temperature, memory and Wi-Fi gauges
+    Stats,
+    /// Show the speed from gpsd in big digits
+    Speedometer,
+    /// Show speed, RPM and coolant temperature from the OBD-II adapter in obd
+    Vehicle,
+    /// Show the heart rate from the Bluetooth strap in heart_rate
+    HeartRate,
+    /// Count down, then flash the panel
+    Timer {
+        /// How long for [default: mode.timer.seconds]
+        seconds: Option<u64>,
+    },
+    /// Count down work and break periods in turn
+    Pomodoro,
+    /// Flash a message in Morse code
+    Morse {
+        /// What to send [default: mode.morse.message]
+        message: Option<String>,
+        /// Words per minute [default: mode.morse.wpm]
+        #[arg(long)]
+        wpm: Option<u32>,
+    },
+    /// Show the two modes in mode.split, each on half the panel
+    Split,
+    /// Show frames sent over the network by something doing the rendering
+    Receive,
+    /// Stream the Pi camera to the panel
+    Camera {
+        /// libcamera capture program (rpicam-vid or libcamera-vid)
+        /// [default: rpicam-vid]
+        #[arg(long)]
+        program: Option<String>,
+        /// Mirror the image horizontally, rear-view style
+        #[arg(long)]
+        hflip: bool,
+    },
+    /// Mirror a region of the Pi's screen onto the panel
+    Screen {
+        #[arg(long, value_enum, default_value_t = ScreenSource::Fb)]
+        source: ScreenSource,
+        /// Capture rectangle as WxH+X+Y
+        #[arg(long, default_value = "256x256+0+0")]
+        region: Region,
+        /// Time between captures
+        #[arg(long, default_value_t = 500)]
+        interval_ms: u64,
+    },
+    /// Mirror a VNC server's desktop, as set in mode.vnc
+    Vnc,
+    /// Show a test pattern, for checking wiring and orientation
+    TestPattern {
+        #[arg(value_enum)]
+        pattern: Option<Pattern>,
+    },
+    /// Step a single lit pixel across the panel to build a stuck-pixel mask
+    Calibrate {
+        /// Mask file to extend and write, instead of mask.path
+        #[arg(long)]
+        mask: Option<PathBuf>,
+    },
+    /// Flash new MCU firmware, pausing the running server's display if any
+    FlashFirmware {
+        /// Firmware image, in whatever format the configured flasher takes
+        firmware: PathBuf,
+    },
+    /// Pack an animation's PNG frames into one raw frame file, which the
+    /// animation mode then plays without decoding anything
+    Convert {
+        /// Directory of panel-sized PNGs, played in name order
+        #[arg(default_value = "BadApple64x64")]
+        dir: PathBuf,
+        /// File to write [default: the directory's name plus .frames]
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Frames per second to play it at
+        #[arg(long, default_value_t = 2.0)]
+        fps: f64,
+    },
+    /// Send one PNG or JPEG to the panel and exit, scaled to fit
+    Send {
+        file: PathBuf,
+        /// Swap black and white
+        #[arg(long)]
+        invert: bool,
+        /// Degrees to turn the image clockwise: 0, 90, 180 or 270
+        #[arg(long, default_value_t = 0, value_parser = parse_quarter_turn)]
+        rotate: u16,
+    },
+    /// Turn every pixel off and exit
+    Clear,
+    /// Light every pixel with --on, or none without, and exit
+    Fill {
+        #[arg(long)]
+        on: bool,
+    },
+    /// List the serial ports, optionally probing which one is the helmet
+    Ports {
+        /// Ping each port, or say hello if pings are off, to see if the MCU
+        /// answers; other devices on them may not like it
+        #[arg(long)]
+        probe: bool,
+    },
+    /// List the modes the server can switch to at runtime
+    Modes,
+    /// Pretty-print a --capture file
+    Dump {
+        file: PathBuf,
+    },
+    /// Run a custom mode script, or list the available ones
+    #[cfg(feature = "scripting")]
+    Script {
+        /// Script name, without the .rhai extension
+        name: Option<String>,
+        /// Directory scripts are loaded from
+        #[arg(long, default_value = "scripts")]
+        dir: PathBuf,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // normal_mode().await
-    touhou_mode().await
+    let cli = Cli::parse();
+    if cli.stdin && cli.cmd.is_some() {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--stdin cannot be used with a mode")
+            .exit();
+    }
+    progress::init(cli.quiet);
+    if let Some(Cmd::Dump { file }) = &cli.cmd {
+        return capture::dump(file);
+    }
+    let config = Config::load(&cli.config)?;
+    if let Some(Cmd::FlashFirmware { firmware }) = &cli.cmd {
+        return flash_firmware(&config, firmware);
+    }
+    // Calibrating has to light pixels the old mask would hold back, and fill
+    // is for checking the hardware.
+    if !matches!(cli.cmd, Some(Cmd::Calibrate { .. } | Cmd::Fill { .. })) {
+        mask::init(&config.mask)?;
+    }
+    picture::init(&config.images);
+    hooks::init(&config.hooks);
+    fault::init(&config.faults);
+    safety::init(&config.safety);
+    if let Some(path) = &cli.capture {
+        capture::init(path)?;
+    }
+    if let Some(Cmd::Convert { dir, output, fps }) = &cli.cmd {
+        let output = output.clone().unwrap_or_else(|| dir.with_extension("frames"));
+        let frames = mode::animation::convert(dir, &output, *fps)?;
+        println!("[main] Wrote {frames} frames to {}.", output.display());
+        return Ok(());
+    }
+    if let Some(Cmd::Send { file, invert, rotate }) = &cli.cmd {
+        return send_image(&config, cli.dry_run.as_deref(), file, *invert, *rotate);
+    }
+    if let Some(Cmd::Ports { probe }) = &cli.cmd {
+        return list_ports(&config.protocol, &config.eyes, *probe);
+    }
+    if let Some(cmd @ (Cmd::Clear | Cmd::Fill { .. })) = &cli.cmd {
+        let level = if matches!(cmd, Cmd::Fill { on: true }) { 0xFF } else { 0 };
+        let frame = vec![level; PANEL_DIMS.0 * PANEL_DIMS.1];
+        return send_frame(&config, cli.dry_run.as_deref(), frame);
+    }
+    // Before the pollers, so a battery that's already low is reported.
+    webhook::spawn_notifier(&config.webhooks);
+    let mut settings = ModeSettings {
+        modes: config.mode.clone(),
+        screensaver_style: config.screensaver.style,
+        orientation: imu::spawn_poller(&config.imu, &config.low_power)?,
+        battery: battery::spawn_poller(&config.battery)?,
+        ambient: ambient::spawn_poller(&config.ambient)?,
+        gps: gps::spawn_poller(&config.gps)?,
+        heart_rate: heart_rate::spawn_monitor(&config.heart_rate)?,
+        obd: obd::spawn_poller(&config.obd)?,
+        font: ttf::Font::load(&config.font)?,
+    };
+    let initial: (&'static str, Box<dyn Mode>) = if cli.stdin {
+        (mode::stdin::NAME, Box::new(StdinMode::default()))
+    } else {
+        match cli.cmd.unwrap_or(Cmd::Touhou) {
+            Cmd::Map { filter, route } => {
+                if let Some(filter) = filter {
+                    settings.modes.map.filter = filter;
+                }
+                let saved = state::init(&config.state);
+                if saved.zoom.is_some() {
+                    settings.modes.map.zoom = saved.zoom;
+                }
+                let initial = if route.is_some() {
+                    settings.modes.navigate.route = route;
+                    mode::navigate::NAME
+                } else {
+                    // Back to whatever was on before the restart.
+                    saved.mode.as_deref()
+                        .and_then(mode::lookup)
+                        .map_or(mode::map::NAME, |info| info.name)
+                };
+                return normal_mode(settings, config, initial, cli.dry_run).await;
+            }
+            Cmd::Touhou => {
+                (mode::animation::NAME, Box::new(AnimationMode::default()))
+            }
+            Cmd::Clock => {
+                let clock = ClockMode::new(config.mode.clock, settings.font.clone());
+                (mode::clock::NAME, Box::new(clock))
+            }
+            Cmd::Hud => {
+                (mode::hud::NAME, Box::new(HudMode::new(&settings)))
+            }
+            Cmd::Life => (mode::life::NAME, Box::new(LifeMode::new(config.mode.life))),
+            Cmd::Audio => {
+                (mode::audio::NAME, Box::new(AudioMode::new(config.mode.audio)))
+            }
+            Cmd::Weather => {
+                (mode::weather::NAME, Box::new(WeatherMode::new(config.mode.weather)))
+            }
+            Cmd::Stats => (mode::stats::NAME, Box::new(StatsMode::default())),
+            Cmd::Speedometer => (
+                mode::speedometer::NAME,
+                Box::new(SpeedometerMode::new(
+                    config.mode.speedometer,
+                    settings.gps.clone(),
+                    settings.font.clone(),
+                )),
+            ),
+            Cmd::Vehicle => (
+                mode::vehicle::NAME,
+                Box::new(VehicleMode::new(
+                    config.mode.vehicle,
+                    settings.obd.clone(),
+                    settings.font.clone(),
+                )),
+            ),
+            Cmd::HeartRate => (
+                mode::heart_rate::NAME,
+                Box::new(HeartRateMode::new(settings.heart_rate.clone(), settings.font.clone())),
+            ),
+            Cmd::Split => (mode::split::NAME, Box::new(SplitMode::new(&settings))),
+            Cmd::Receive => {
+                (mode::receive::NAME, Box::new(ReceiveMode::new(config.mode.receive)))
+            }
+            Cmd::Timer { seconds } => {
+                let mut timer = config.mode.timer;
+                if let Some(seconds) = seconds {
+                    timer.seconds = seconds;
+                    timer.validate()?;
+                }
+                (mode::timer::NAME, Box::new(TimerMode::new(timer, settings.font.clone())))
+            }
+            Cmd::Pomodoro => {
+                let pomodoro = PomodoroMode::new(config.mode.pomodoro, settings.font.clone());
+                (mode::pomodoro::NAME, Box::new(pomodoro))
+            }
+            Cmd::Morse { message, wpm } => {
+                let mut morse = config.mode.morse;
+                morse.message = message.unwrap_or(morse.message);
+                morse.wpm = wpm.unwrap_or(morse.wpm);
+                morse.validate()?;
+                (mode::morse::NAME, Box::new(MorseMode::new(morse)))
+            }
+            Cmd::Calendar => {
+                (mode::calendar::NAME, Box::new(CalendarMode::new(config.mode.calendar)))
+            }
+            Cmd::Headlines => {
+                (mode::headlines::NAME, Box::new(HeadlinesMode::new(config.mode.headlines)))
+            }
+            Cmd::NowPlaying => (
+                mode::now_playing::NAME,
+                Box::new(NowPlayingMode::new(config.mode.now_playing)),
+            ),
+            Cmd::Camera { program, hflip } => {
+                let defaults = config.mode.camera;
+                let opts = CameraOpts {
+                    program: program.unwrap_or(defaults.program),
+                    hflip: hflip || defaults.hflip,
+                };
+                (mode::camera::NAME, Box::new(CameraMode::new(opts)))
+            }
+            Cmd::Screen { source, region, interval_ms } => (
+                mode::screen::NAME,
+                Box::new(ScreenMode::new(ScreenOpts {
+                    source,
+                    region,
+                    interval: Duration::from_millis(interval_ms),
+                })),
+            ),
+            Cmd::Vnc => (mode::vnc::NAME, Box::new(VncMode::new(config.mode.vnc))),
+            Cmd::TestPattern { pattern } => {
+                let mut opts = config.mode.test_pattern;
+                if let Some(pattern) = pattern {
+                    opts.pattern = pattern;
+                }
+                (mode::test_pattern::NAME, Box::new(TestPatternMode::new(opts)))
+            }
+            Cmd::Calibrate { mask } => {
+                let path = mask
+                    .or(config.mask.path)
+                    .unwrap_or_else(|| PathBuf::from(mask::DEFAULT_PATH));
+                (mode::calibrate::NAME, Box::new(CalibrateMode::new(path)?))
+            }
+            Cmd::FlashFirmware { .. }
+            | Cmd::Convert { .. }
+            | Cmd::Dump { .. }
+            | Cmd::Send { .. }
+            | Cmd::Clear
+            | Cmd::Fill { .. }
+            | Cmd::Ports { .. } => unreachable!(),
+            Cmd::Modes => {
+                for info in mode::REGISTRY {
+                    println!("{:<12} {}", info.name, info.about);
+                }
+                return Ok(());
+            }
+            #[cfg(feature = "scripting")]
+            Cmd::Script { name: Some(name), dir } => {
+                (mode::script::NAME, Box::new(ScriptMode::new(dir, &name)?))
+            }
+            #[cfg(feature = "scripting")]
+            Cmd::Script { name: None, dir } => {
+                for name in mode::script::list_scripts(dir)? {
+                    println!("{name}");
+                }
+                return Ok(());
+            }
+        }
+    };
+    let mut displays = connect(
+        &config.eyes,
+        &config.wiring,
+        &config.protocol,
+        &config.splash,
+        cli.dry_run.as_deref(),
+    )?;
+    mode::run(&mut displays, settings, initial, None, None)
 }
 
-async fn touhou_mode() -> Result<()> {
-    let mut mcu = HelmetMcu::new(MCU_SERIAL_PORT)?;
-    let start = Instant::now();
-    let mut last_frame_sent = -1;
-    loop {
-        let time = start.elapsed().as_millis() as i64;
-        let frame = (time / 500) + 1;
-        if frame != last_frame_sent {
-            let filename = format!("BadApple64x64/frame_{frame:03?}.png");
-            println!("{filename:?}");
-            mcu.send_png_g(filename)?;
-            last_frame_sent = frame;
+fn parse_quarter_turn(s: &str) -> Result<u16, String> {
+    match s.parse() {
+        Ok(degrees @ (0 | 90 | 180 | 270)) => Ok(degrees),
+        _ => Err(format!("expected 0, 90, 180 or 270, got {s:?}")),
+    }
+}
+
+// For `send`: shows `file` and exits.
+fn send_image(
+    config: &Config,
+    dry_run: Option<&Path>,
+    file: &Path,
+    invert: bool,
+    rotate: u16,
+) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+    let format = picture::sniff(&data)
+        .with_context(|| format!("{} isn't a PNG or JPEG", file.display()))?;
+    let (image, dims) = picture::decode(&data, format, picture::background())?;
+    let mut frame = picture::fit(&image, dims, PANEL_DIMS);
+    if rotate != 0 {
+        frame = filter::rotate(&frame, PANEL_DIMS, rotate.into(), PANEL_DIMS);
+    }
+    if invert {
+        frame.iter_mut().for_each(|p| *p = !*p);
+    }
+    println!("[main] Sending {}...", file.display());
+    send_frame(config, dry_run, frame)
+}
+
+// For the subcommands that show one frame and exit, with no splash or
+// shutdown screen.
+fn send_frame(config: &Config, dry_run: Option<&Path>, frame: Vec<u8>) -> Result<()> {
+    let links = links(&config.eyes, dry_run);
+    let content = config.eyes.content;
+    open_mcu(&links, content, &config.wiring, &config.protocol, dry_run.is_some())?.mirror(frame)
+}
+
+fn list_ports(protocol: &protocol::Protocol, eyes: &eyes::EyesConfig, probe: bool) -> Result<()> {
+    if probe && protocol.ping.is_empty() && protocol.hello.is_empty() {
+        anyhow::bail!("can't probe: protocol.ping and protocol.hello are both empty");
+    }
+    let mut ports = serialport::available_ports()?;
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    if ports.is_empty() {
+        println!("No serial ports found.");
+    }
+    for port in ports {
+        let mut line = port.port_name.clone();
+        if let serialport::SerialPortType::UsbPort(usb) = &port.port_type {
+            line += &format!("  USB {:04x}:{:04x}", usb.vid, usb.pid);
+            let details = [&usb.manufacturer, &usb.product, &usb.serial_number];
+            for detail in details.into_iter().flatten() {
+                line += &format!("  {detail}");
+            }
+        }
+        if eyes.links().iter().any(|link| link.port == port.port_name) {
+            line += "  (used)";
+        }
+        if probe {
+            let result = HelmetMcu::builder()
+                .protocol(protocol.clone())
+                .port(&port.port_name)
+                .build()
+                .and_then(|mut mcu| {
+                    if !mcu.protocol.ping.is_empty() {
+                        return mcu.ping();
+                    }
+                    let start = Instant::now();
+                    mcu.hello()?;
+                    Ok(start.elapsed())
+                });
+            line += &match result {
+                Ok(took) => format!("  -> the helmet, answering in {took:.1?}"),
+                Err(e) => format!("  -> no helmet: {e:#}"),
+            };
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
+
+// Asks a running server to flash, so it lets go of the serial port and
+// picks it up again afterwards, or flashes directly if none is running.
+fn flash_firmware(config: &Config, firmware: &Path) -> Result<()> {
+    let firmware = firmware.canonicalize()?;
+    let Ok(mut stream) = UnixStream::connect(control::socket_path()) else {
+        for link in config.eyes.links() {
+            flash::flash(&config.flash, &link.port, &firmware)?;
+        }
+        return Ok(());
+    };
+    println!("[main] Handing {} to the running server...", firmware.display());
+    let mut request = serde_json::to_vec(&serde_json::json!({
+        "cmd": "flash",
+        "firmware": firmware,
+    }))?;
+    request.push(b'\n');
+    stream.write_all(&request)?;
+    let mut line = String::new();
+    io::BufReader::new(stream).read_line(&mut line)?;
+    let reply: control::Reply = serde_json::from_str(&line)?;
+    match reply.error {
+        Some(error) => anyhow::bail!("flashing failed: {error}"),
+        None => {
+            println!("[main] Flashed.");
+            Ok(())
         }
     }
 }
 
-async fn normal_mode() -> Result<()> {
+async fn normal_mode(
+    settings: ModeSettings,
+    config: Config,
+    initial: &'static str,
+    dry_run: Option<PathBuf>,
+) -> Result<()> {
     lazy_static! {
         static ref UP_CHAN: (Sender<UpdateT>, Receiver<UpdateT>) = bounded(0);
         static ref UP_TX: &'static Sender<UpdateT> = &UP_CHAN.0;
         static ref UP_RX: &'static Receiver<UpdateT> = &UP_CHAN.1;
     }
-    println!("[main] Connecting to microcontroller...");
-    let mut mcu = HelmetMcu::new(MCU_SERIAL_PORT)?;
-    println!("[main] Spawning update handler thread...");
+    let battery = settings.battery.clone();
+    let ambient = settings.ambient.clone();
+    let modes = config.mode.clone();
+    let library = library::Library::new(&config.library);
+    let max_upload = config.library.max_upload_kb * 1024;
+    let display_url = config.display_url.clone();
+    let cors = config.cors.clone();
+    http_limits::init(&config.http_limits);
+    session::init(&config.session);
+    let max_body = config.http_limits.max_body();
+    let mdns = config.mdns.clone();
+    #[cfg(feature = "grpc")]
+    let grpc = config.grpc.clone();
+    // Losing the log shouldn't keep the helmet from working.
+    if let Err(e) = track::init(&config.track) {
+        println!("[main] Not recording track: {e:#}");
+    }
+    waypoint::init(&config.waypoints, state::get().waypoints);
+    // Without the queue, requests wait for the manager as they used to.
+    match jobs::init(&config.jobs) {
+        Ok(()) => jobs::spawn_feeder(*UP_TX),
+        Err(e) => println!("[main] Not queueing jobs: {e:#}"),
+    }
+    bot::spawn_bot(&config.bot, &config.display_url, *UP_TX);
+    let mut displays = connect(
+        &config.eyes,
+        &config.wiring,
+        &config.protocol,
+        &config.splash,
+        dry_run.as_deref(),
+    )?;
+    for mcu in displays.links() {
+        if dry_run.is_none() {
+            hotplug::spawn_watcher(&config.hotplug, &mcu.port, *UP_TX);
+        }
+        let inputs = mcu.inputs();
+        spawn(move || {
+            for event in inputs {
+                UP_TX.send(Update::Input { event }).unwrap();
+            }
+        });
+    }
+    println!("[main] Spawning mode manager thread...");
     spawn(move || {
-        spawn(move || -> Result<Infallible> {
-            println!("[update handler] Listening on rendevous channel...");
-            for coords in *UP_RX {
-                println!("[update handler] Loading map at {coords}...");
-                load_map(coords)?;
-                println!("[update handler] Sending map...");
-                let start = Instant::now();
-                mcu.send_map()?;
-                let elapsed = start.elapsed().as_millis();
-                println!("[update handler] Sent map in {elapsed:.2?}ms.")
-            }
-            unreachable!()
-        }).join().unwrap().unwrap();
+        let manager = spawn(move || -> Result<()> {
+            println!("[mode manager] Listening on rendevous channel...");
+            let info = mode::lookup(initial).unwrap();
+            let initial = (info.build)(&settings);
+            mode::run(
+                &mut displays,
+                settings,
+                (info.name, initial),
+                Some(*UP_RX),
+                Some(&config),
+            )
+        });
+        // Modes' own errors are got past, so this is the panel or the
+        // channel gone, and a server taking work nobody will do is no use.
+        match manager.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                println!("[main] Mode manager stopped: {e:#}");
+                events::error(format!("mode manager stopped: {e:#}"));
+                std::process::exit(1);
+            }
+            Err(_) => {
+                println!("[main] Mode manager panicked.");
+                std::process::exit(1);
+            }
+        }
+    });
+    println!("[main] Spawning control socket...");
+    tokio::spawn(async {
+        if let Err(e) = control::serve_control_socket(control::socket_path(), *UP_TX).await {
+            println!("[control socket] Stopped: {e}");
+        }
     });
+    #[cfg(feature = "grpc")]
+    if grpc.listen.is_some() {
+        println!("[main] Spawning gRPC server...");
+        let (modes, images) = (modes.clone(), display_url.clone());
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(&grpc, modes, images, *UP_TX).await {
+                println!("[grpc] Stopped: {e}");
+            }
+        });
+    }
     println!("[main] Setting up warp...");
     let html = warp::any().map(move || {
         println!("[warp filter] [GET] Serving index.html...");
         warp::reply::html(include_str!("index.html"))
     });
-    let data = warp::path!("coords" / String)
-        .then(|coords| async {
-            println!("[warp filter] [POST /coords] Rendezvousing...");
-            UP_TX.send(coords).unwrap();
-            "ok"
+    // From before /api/v1, for clients that haven't moved yet.
+    let legacy_coords = warp::path!("coords" / String)
+        .and(warp::post())
+        .map(|coords| {
+            println!("[warp filter] [POST /coords] Deprecated; queueing...");
+            let body = match jobs::send(*UP_TX, Update::Coords { coords }, Default::default()) {
+                Ok(()) => "ok".to_owned(),
+                Err(e) => format!("{e:#}"),
+            };
+            warp::reply::with_header(body, "deprecation", "true")
+        });
+    let coords = warp::path!("coords")
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .and(warp::body::content_length_limit(max_body))
+        .and(warp::body::json())
+        .map(|job: JobQuery, body: CoordsBody| {
+            println!("[warp filter] [POST /api/v1/coords] Queueing...");
+            let result = body.coords.parse::<geo::LatLon>()
+                .and_then(|_| {
+                    jobs::send(*UP_TX, Update::Coords { coords: body.coords }, job.priority)
+                });
+            reply(result)
+        });
+    let list_modes = modes.clone();
+    let list = warp::path!("modes")
+        .and(warp::get())
+        .map(move || {
+            let modes: Vec<_> = mode::REGISTRY.iter()
+                .map(|info| ModeEntry {
+                    name: info.name,
+                    about: info.about,
+                    params: list_modes.params(info.name)
+                        .unwrap_or_else(|| serde_json::json!({})),
+                })
+                .collect();
+            reply_data(Ok(modes))
+        });
+    let pattern_modes = modes.clone();
+    let test_pattern = warp::path!("test-pattern" / String)
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .map(move |pattern: String, job: JobQuery| {
+            println!("[warp filter] [POST /api/v1/test-pattern/{pattern}] Queueing...");
+            let params = serde_json::json!({ "pattern": pattern }).to_string();
+            let name = mode::test_pattern::NAME.to_owned();
+            reply(start_mode(&pattern_modes, name, params.as_bytes())
+                .and_then(|update| jobs::send(*UP_TX, update, job.priority)))
+        });
+    let timer_modes = modes.clone();
+    let timer = warp::path!("timer" / u64)
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .map(move |seconds: u64, job: JobQuery| {
+            println!("[warp filter] [POST /api/v1/timer/{seconds}] Queueing...");
+            let params = serde_json::json!({ "seconds": seconds }).to_string();
+            let name = mode::timer::NAME.to_owned();
+            reply(start_mode(&timer_modes, name, params.as_bytes())
+                .and_then(|update| jobs::send(*UP_TX, update, job.priority)))
+        });
+    let morse_modes = modes.clone();
+    let morse = warp::path!("morse")
+        .and(warp::post())
+        .and(warp::query::<MorseQuery>())
+        .and(warp::query::<JobQuery>())
+        .and(http_limits::body_limit(max_body))
+        .and(warp::body::bytes())
+        .map(move |query: MorseQuery, job: JobQuery, body: warp::hyper::body::Bytes| {
+            println!("[warp filter] [POST /api/v1/morse] Queueing...");
+            let mut params = serde_json::json!({ "message": String::from_utf8_lossy(&body) });
+            if let Some(wpm) = query.wpm {
+                params["wpm"] = wpm.into();
+            }
+            let name = mode::morse::NAME.to_owned();
+            reply(start_mode(&morse_modes, name, params.to_string().as_bytes())
+                .and_then(|update| jobs::send(*UP_TX, update, job.priority)))
+        });
+    let start = warp::path!("modes" / String)
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .and(http_limits::body_limit(max_body))
+        .and(warp::body::bytes())
+        .map(move |name: String, job: JobQuery, body: warp::hyper::body::Bytes| {
+            println!("[warp filter] [POST /api/v1/modes/{name}] Queueing...");
+            reply(start_mode(&modes, name, &body)
+                .and_then(|update| jobs::send(*UP_TX, update, job.priority)))
+        });
+    let lib = library.clone();
+    let library_list = warp::path!("library")
+        .and(warp::get())
+        .map(move || reply_data(lib.list()));
+    let lib = library.clone();
+    let library_get = warp::path!("library" / String)
+        .and(warp::get())
+        .map(move |name: String| match lib.read(&name) {
+            Ok(data) => {
+                let content_type = if name.ends_with(".png") {
+                    "image/png"
+                } else {
+                    "application/octet-stream"
+                };
+                warp::reply::with_header(data, "content-type", content_type).into_response()
+            }
+            Err(e) => reply(Err(e)).into_response(),
+        });
+    let lib = library.clone();
+    let library_thumb = warp::path!("library" / String / "thumb.png")
+        .and(warp::get())
+        .map(move |name: String| match lib.thumbnail(&name, PANEL_DIMS) {
+            Ok(png) => warp::reply::with_header(png, "content-type", "image/png").into_response(),
+            Err(e) => reply(Err(e)).into_response(),
+        });
+    let lib = library.clone();
+    let library_put = warp::path!("library" / String)
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_upload))
+        .and(warp::body::bytes())
+        .map(move |name: String, body: warp::hyper::body::Bytes| {
+            println!("[warp filter] [POST /api/v1/library/{name}] Storing {} bytes...", body.len());
+            reply(lib.write(&name, &body, PANEL_DIMS))
+        });
+    let lib = library.clone();
+    let library_show = warp::path!("library" / String / "show")
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .map(move |name: String, job: JobQuery| {
+            println!("[warp filter] [POST /api/v1/library/{name}/show] Queueing...");
+            reply(lib.existing(&name)
+                .and_then(|path| jobs::send(*UP_TX, Update::SendFile { path }, job.priority)))
+        });
+    let library_delete = warp::path!("library" / String)
+        .and(warp::delete())
+        .map(move |name: String| {
+            println!("[warp filter] [DELETE /api/v1/library/{name}] Deleting...");
+            reply(library.delete(&name))
+        });
+    let display = warp::path!("display-url")
+        .and(warp::post())
+        .and(warp::query::<JobQuery>())
+        .and(warp::body::content_length_limit(max_body))
+        .and(warp::body::json())
+        .then(move |job: JobQuery, body: DisplayUrl| {
+            let config = display_url.clone();
+            async move {
+                // ureq and the decoders block, so keep them off the runtime.
+                let result = tokio::task::spawn_blocking(move || {
+                    display_from_url(&body.url, &config)
+                })
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|r| r);
+                reply(result.and_then(|update| jobs::send(*UP_TX, update, job.priority)))
+            }
+        });
+    let progress = warp::path!("progress")
+        .and(warp::get())
+        .map(|| {
+            let events = BroadcastStream::new(progress::subscribe())
+                // A listener that fell behind just misses some steps.
+                .filter_map(|event| event.ok())
+                .map(|event| warp::sse::Event::default().json_data(event));
+            warp::sse::reply(warp::sse::keep_alive().stream(events))
+        });
+    let events = warp::path!("events")
+        .and(warp::get())
+        .and(warp::ws())
+        .map(|ws: warp::ws::Ws| ws.on_upgrade(send_events));
+    let latency = warp::path!("latency")
+        .and(warp::get())
+        .and(warp::query::<LatencyQuery>())
+        .then(|query: LatencyQuery| async move {
+            println!("[warp filter] [GET /api/v1/latency] Rendezvousing...");
+            let count = query.count.unwrap_or(latency::DEFAULT_COUNT);
+            let (reply, result) = bounded(1);
+            // Both ends block on the mode manager.
+            let result = tokio::task::spawn_blocking(move || {
+                UP_TX.send(Update::Latency { count, reply }).unwrap();
+                result.recv().unwrap()
+            }).await.unwrap();
+            reply_data(result.map_err(anyhow::Error::msg))
+        });
+    let track = warp::path!("track.gpx")
+        .and(warp::get())
+        .then(|| async {
+            println!("[warp filter] [GET /api/v1/track.gpx] Serving track...");
+            match tokio::task::spawn_blocking(track::contents).await.unwrap() {
+                Ok(Some(gpx)) => {
+                    warp::reply::with_header(gpx, "content-type", "application/gpx+xml")
+                        .into_response()
+                }
+                Ok(None) => reply_err("not recording a track".into(), StatusCode::NOT_FOUND)
+                    .into_response(),
+                Err(e) => reply(Err(e)).into_response(),
+            }
         });
-    let routes = warp::get().and(html)
-        .or(warp::post().and(data));
+    let frame = warp::path!("frame.png")
+        .and(warp::get())
+        .map(|| match preview::png() {
+            Ok(Some(png)) => warp::reply::with_header(png, "content-type", "image/png")
+                .into_response(),
+            Ok(None) => reply_err("nothing shown yet".into(), StatusCode::NOT_FOUND)
+                .into_response(),
+            Err(e) => reply(Err(e)).into_response(),
+        });
+    let waypoint_list = warp::path!("waypoints")
+        .and(warp::get())
+        .map(|| reply_data(Ok(waypoint::list())));
+    let waypoint_add = warp::path!("waypoints")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(max_body))
+        .and(warp::body::json())
+        .map(|waypoint: waypoint::Waypoint| {
+            println!("[warp filter] [POST /api/v1/waypoints] Adding {:?}...", waypoint.name);
+            reply(waypoint::add(waypoint))
+        });
+    let queue_list = warp::path!("queue")
+        .and(warp::get())
+        .map(|| reply_data(jobs::list()));
+    let queue_remove = warp::path!("queue" / i64)
+        .and(warp::delete())
+        .map(|id: i64| {
+            println!("[warp filter] [DELETE /api/v1/queue/{id}] Removing job...");
+            reply(jobs::remove(id))
+        });
+    let session_get = warp::path!("session")
+        .and(warp::get())
+        .map(|| reply_data(Ok(session::info())));
+    let session_acquire = warp::path!("session" / "acquire")
+        .and(warp::post())
+        .and(warp::query::<SessionQuery>())
+        .and(warp::addr::remote())
+        .and(warp::header::optional::<String>(session::HEADER))
+        .map(|query: SessionQuery, addr: Option<SocketAddr>, token: Option<String>| {
+            println!("[warp filter] [POST /api/v1/session/acquire] Taking control...");
+            let address = addr.map(|addr| addr.ip());
+            reply_data(session::acquire(query.owner, address, token.as_deref()))
+        });
+    let session_release = warp::path!("session" / "release")
+        .and(warp::post())
+        .and(warp::header::optional::<String>(session::HEADER))
+        .map(|token: Option<String>| {
+            println!("[warp filter] [POST /api/v1/session/release] Letting go...");
+            reply(session::release(token.as_deref()))
+        });
+    let waypoint_remove = warp::path!("waypoints" / String)
+        .and(warp::delete())
+        .map(|name: String| {
+            println!("[warp filter] [DELETE /api/v1/waypoints/{name}] Removing waypoint...");
+            reply(waypoint::remove(&name))
+        });
+    let status_battery = battery.clone();
+    let status = warp::path!("status")
+        .and(warp::get())
+        .map(move || {
+            let battery = status_battery.as_ref().map(|rx| *rx.borrow());
+            let ambient = ambient.as_ref().and_then(|rx| *rx.borrow());
+            reply_data(Ok(Status { battery, ambient }))
+        });
+    // Prometheus' text format rather than JSON, for scraping.
+    let metrics = warp::path!("metrics")
+        .and(warp::get())
+        .map(move || {
+            let mut out = String::new();
+            if let Some(rx) = &battery {
+                let reading = *rx.borrow();
+                out.push_str("# TYPE fett_helmet_battery_percent gauge\n");
+                out.push_str(&format!("fett_helmet_battery_percent {}\n", reading.percent));
+                if let Some(volts) = reading.volts {
+                    out.push_str("# TYPE fett_helmet_battery_volts gauge\n");
+                    out.push_str(&format!("fett_helmet_battery_volts {volts}\n"));
+                }
+            }
+            out
+        });
+    // Each route checks its method after its path, so a wrong method is told
+    // apart from a path that doesn't exist.
+    let api = status.or(metrics).or(progress).or(events).or(latency).or(track).or(frame)
+        .or(waypoint_list).or(waypoint_add).or(waypoint_remove).or(queue_list).or(queue_remove)
+        .or(session_get).or(session_acquire).or(session_release)
+        .or(list).or(start).or(test_pattern).or(timer).or(morse).or(coords).or(display)
+        .or(library_list).or(library_get).or(library_thumb).or(library_show).or(library_put)
+        .or(library_delete)
+        .recover(api_rejection)
+        // Or the types nest too deeply for the compiler.
+        .boxed();
+    let openapi = openapi::document();
+    let openapi_json = warp::path!("api" / "openapi.json")
+        .and(warp::get())
+        .map(move || {
+            warp::reply::with_header(openapi.clone(), "content-type", "application/json")
+        });
+    // Swagger UI, for trying the API out from a browser.
+    let docs = warp::path!("api" / "docs")
+        .and(warp::get())
+        .map(|| warp::reply::html(include_str!("swagger.html")));
+    // Anything else under /api/v1 gets an error in the same shape, rather
+    // than the control page.
+    let routes = warp::path("api").and(warp::path("v1")).and(api)
+        .or(openapi_json)
+        .or(docs)
+        .or(legacy_coords)
+        .or(warp::get().and(html));
+    // Before any route, so a script hammering one gets 429s rather than
+    // renders and serial traffic.
+    let routes = http_limits::check().and(routes).recover(http_limits::rejection);
+    // While a client has control, everyone else's changes get 409s.
+    let routes = session::check().and(routes).recover(session::rejection);
+    // Pages from the allowed origins may call the API, and others can't read
+    // what comes back.
+    let preflight_cors = cors.clone();
+    let preflight = warp::options()
+        .and(warp::header::<String>("origin"))
+        .map(move |origin| cors::preflight(&preflight_cors, origin));
+    let routes = preflight
+        .or(warp::header::optional::<String>("origin").and(routes)
+            .map(move |origin, reply| cors::allow(&cors, origin, reply)));
+    let routes = warp::any().map(|| low_power::request(*UP_TX)).untuple_one().and(routes);
     println!("[main] Serving via warp...");
     let socket_addr: SocketAddr = SERVER_ADDR.parse()?;
+    // Apps can still be pointed at the address by hand.
+    let _mdns = mdns::advertise(&mdns, socket_addr.port())
+        .inspect_err(|e| println!("[mdns] Not advertising: {e:#}"));
     warp::serve(routes).run(socket_addr).await;
     unreachable!()
 }
 
-fn load_map(coords: impl AsRef<OsStr>) -> Result<()> {
-    Command::new("./loadmap.sh")
-        .arg(coords)
-        .spawn()?
-        .wait()?
-        .exit_ok()?;
-    Ok(())
+// Keeps a WebSocket client up to date until it goes away. Whatever it sends
+// is ignored.
+async fn send_events(socket: warp::ws::WebSocket) {
+    let (mut tx, mut rx) = futures_util::StreamExt::split(socket);
+    let (current, updates) = events::subscribe();
+    let mut updates = BroadcastStream::new(updates);
+    let message = |event: &events::HelmetEvent| {
+        warp::ws::Message::text(serde_json::to_string(event).unwrap())
+    };
+    if tx.send(message(&current)).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            update = updates.next() => match update {
+                Some(Ok(event)) => {
+                    if tx.send(message(&event)).await.is_err() {
+                        break;
+                    }
+                }
+                // A client that fell behind just misses some events.
+                Some(Err(_)) => continue,
+                None => break,
+            },
+            incoming = rx.next() => match incoming {
+                Some(Ok(_)) => continue,
+                _ => break,
+            },
+        }
+    }
+}
+
+// Every /api/v1 JSON reply: `{"ok": true}`, with `data` if there's
+// anything to return, or `{"ok": false, "error": "..."}` with a 4xx status.
+#[derive(Serialize)]
+struct Envelope<T: Serialize> {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn reply(result: Result<()>) -> warp::reply::WithStatus<warp::reply::Json> {
+    match result {
+        Ok(()) => {
+            let reply = Envelope::<()> { ok: true, data: None, error: None };
+            warp::reply::with_status(warp::reply::json(&reply), StatusCode::OK)
+        }
+        Err(e) => reply_err(format!("{e:#}"), error_status(&e)),
+    }
+}
+
+fn reply_data<T: Serialize>(result: Result<T>) -> warp::reply::WithStatus<warp::reply::Json> {
+    match result {
+        Ok(data) => {
+            let reply = Envelope { ok: true, data: Some(data), error: None };
+            warp::reply::with_status(warp::reply::json(&reply), StatusCode::OK)
+        }
+        Err(e) => reply_err(format!("{e:#}"), error_status(&e)),
+    }
+}
+
+// A 409 when another client has control; anything else was a bad request.
+fn error_status(e: &anyhow::Error) -> StatusCode {
+    if e.is::<session::Busy>() {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+fn reply_err(error: String, status: StatusCode) -> warp::reply::WithStatus<warp::reply::Json> {
+    let reply = Envelope::<()> { ok: false, data: None, error: Some(error) };
+    warp::reply::with_status(warp::reply::json(&reply), status)
+}
+
+// What warp turned a request down for, in an envelope.
+async fn api_rejection(
+    rejection: warp::Rejection,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, std::convert::Infallible> {
+    use warp::{filters::body::BodyDeserializeError, reject};
+    let known = |e: &dyn std::fmt::Display, status| Ok(reply_err(e.to_string(), status));
+    if rejection.is_not_found() {
+        return Ok(reply_err("no such endpoint".into(), StatusCode::NOT_FOUND));
+    }
+    if let Some(e) = rejection.find::<BodyDeserializeError>() {
+        return known(e, StatusCode::BAD_REQUEST);
+    }
+    if let Some(e) = rejection.find::<reject::InvalidQuery>() {
+        return known(e, StatusCode::BAD_REQUEST);
+    }
+    if let Some(e) = rejection.find::<reject::PayloadTooLarge>() {
+        return known(e, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    if let Some(e) = rejection.find::<reject::LengthRequired>() {
+        return known(e, StatusCode::LENGTH_REQUIRED);
+    }
+    match rejection.find::<http_limits::BadBody>() {
+        Some(e @ http_limits::BadBody::TooLarge(_)) => {
+            return known(e, StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        Some(e @ http_limits::BadBody::NoLength) => return known(e, StatusCode::LENGTH_REQUIRED),
+        None => {}
+    }
+    if let Some(e) = rejection.find::<reject::UnsupportedMediaType>() {
+        return known(e, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+    if let Some(e) = rejection.find::<reject::MethodNotAllowed>() {
+        return known(e, StatusCode::METHOD_NOT_ALLOWED);
+    }
+    Ok(reply_err(format!("{rejection:?}"), StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+#[derive(Deserialize)]
+struct LatencyQuery {
+    count: Option<usize>,
+}
+
+// `?priority=` on requests to show something: "background", "normal", the
+// default, or "alert".
+#[derive(Deserialize)]
+struct JobQuery {
+    #[serde(default)]
+    priority: jobs::Priority,
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    // Who's taking control, as others are told; its address if not given.
+    owner: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MorseQuery {
+    wpm: Option<u32>,
 }
 
-struct HelmetMcu<S: DerefMut<Target = T>, T: Write + ?Sized> {
-    serial: S,
+// Checks a `POST /api/v1/modes/<name>` request before it reaches the manager, so
+// mistakes are reported to the caller rather than only logged.
+fn start_mode(modes: &config::ModesConfig, name: String, body: &[u8]) -> Result<Update> {
+    let Some(info) = mode::lookup(&name) else {
+        anyhow::bail!("unknown mode {name:?}");
+    };
+    let params: Option<serde_json::Value> = if body.iter().all(u8::is_ascii_whitespace) {
+        None
+    } else {
+        Some(serde_json::from_slice(body)?)
+    };
+    if let Some(params) = &params {
+        modes.with_params(info.name, params)?;
+    }
+    Ok(Update::Mode { mode: name, params })
+}
+
+// Body of `POST /api/v1/coords`: `{"coords": "lat,lon"}`.
+#[derive(Deserialize, ToSchema)]
+pub struct CoordsBody {
+    coords: String,
+}
+
+// Body of `POST /api/v1/display-url`: `{"url": "..."}`.
+#[derive(Deserialize, ToSchema)]
+pub struct DisplayUrl {
+    url: String,
+}
+
+fn display_from_url(url: &str, config: &picture::DisplayUrlConfig) -> Result<Update> {
+    println!("[warp filter] [POST /api/v1/display-url] Downloading {url}...");
+    let (data, format) = picture::download(url, config)?;
+    let (image, dims) = picture::decode(&data, format, picture::background())?;
+    let frame = picture::fit(&image, dims, PANEL_DIMS);
+    picture::save_png(&frame, PANEL_DIMS, URL_IMAGE_FILENAME)?;
+    Ok(Update::SendFile { path: PathBuf::from(URL_IMAGE_FILENAME) })
+}
+
+// One entry of `GET /api/v1/modes`.
+#[derive(Serialize, ToSchema)]
+pub struct ModeEntry {
+    name: &'static str,
+    about: &'static str,
+    // The mode's config section; a POST to /api/v1/modes/<name> may override
+    // any of these.
+    #[schema(value_type = Object)]
+    params: serde_json::Value,
+}
+
+// Data of `GET /api/v1/status`.
+#[derive(Serialize, ToSchema)]
+pub struct Status {
+    battery: Option<battery::Reading>,
+    ambient: Option<ambient::Ambient>,
+}
+
+// Kept from frame to frame, so once they've grown to size sending allocates
+// nothing.
+#[derive(Default)]
+struct SendBuffers {
+    rotated: Vec<u8>,
+    // The frame part of the way round, between quarter turns.
+    turning: Vec<u8>,
+    ordered: Vec<u8>,
+    packed: Vec<u8>,
+    compressed: Vec<u8>,
+    // Each row of `packed` in turn, escaped for XON/XOFF, or each chunk of
+    // it with its header.
+    stuffed: Vec<u8>,
+}
+
+struct HelmetMcu<S: DerefMut<Target = T>, T: Read + Write + ?Sized> {
+    // `None` while the port is lent out, e.g. to a firmware flasher.
+    serial: Option<S>,
+    port: String,
+    // Opens the port for writing, along with a handle for `reader` if it
+    // can be read.
+    open: Opener<S>,
+    // Whatever the MCU sends: input messages go to `inputs`, flow control to
+    // `flow`, other bytes to `echoes`.
+    reader: Option<input::Reader>,
+    inputs: (Sender<input::InputEvent>, Receiver<input::InputEvent>),
+    flow: (Sender<input::Flow>, Receiver<input::Flow>),
+    // Whether the MCU's last word with XON/XOFF was XOFF.
+    xoff: bool,
+    // Whether the firmware said it can decompress frames.
+    compress: bool,
+    echoes: (Sender<u8>, Receiver<u8>),
     dims: (usize, usize),
+    invert: bool,
+    // Degrees clockwise, in quarter turns.
+    rotation: u16,
+    wiring: wiring::Wiring,
+    protocol: protocol::Protocol,
+    // The most recent frame handed to `send_rotated`, pre-rotation.
+    last_frame: Option<Vec<u8>>,
+    // How long sending it took.
+    last_send: Option<Duration>,
+    buffers: SendBuffers,
+    // Distinguishes each ping's echo from a late one to an earlier ping.
+    ping_nonce: u8,
+    // Whether the port went away mid-send. Frames are only kept until it's
+    // reattached.
+    detached: bool,
+    safety: safety::Limiter,
+}
+
+// Longest wait for the MCU to echo a ping.
+const PING_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Longest wait for the MCU to report free buffer space, to send XON after
+// XOFF, or to acknowledge a chunk.
+const FLOW_TIMEOUT: Duration = Duration::from_millis(500);
+
+// How long the MCU gets to come back after flashing, e.g. for its USB serial
+// port to reappear.
+const REOPEN_ATTEMPTS: u32 = 10;
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+// Whatever frames are written to: the MCU's serial port, or a file.
+trait Sink: Read + Write + Send {}
+
+impl<T: Read + Write + Send + ?Sized> Sink for T {}
+
+// Opens the panel's serial port, with a second handle for reading input.
+type Opener<S> = fn(&str) -> Result<(S, Option<input::ReadHandle>)>;
+
+type Mcu = HelmetMcu<Box<dyn Sink>, dyn Sink>;
+
+fn open_serial(port: &str) -> Result<(Box<dyn Sink>, Option<input::ReadHandle>)> {
+    let serial = serialport::new(port, MCU_BAUD).timeout(PING_TIMEOUT).open()?;
+    let reader = Captured(serial.try_clone()?);
+    keep_spare_port(port, Box::new(Captured(serial.try_clone()?)));
+    Ok((fault::wrap(Captured(serial)), Some(Box::new(reader))))
+}
+
+// Takes the wire bytes for --dry-run. Nothing comes back, so there's no
+// input and pings go unanswered.
+fn open_file(path: &str) -> Result<(Box<dyn Sink>, Option<input::ReadHandle>)> {
+    let file = File::create(path).with_context(|| format!("creating {path}"))?;
+    keep_spare_port(path, Box::new(Captured(file.try_clone()?)));
+    Ok((fault::wrap(Captured(file)), None))
 }
 
-const RESET_SEQ: [u8; 11] = [b'#'; 11];
+// A second handle on each port `open_serial` or `open_file` opened, so the
+// shutdown screen can be sent while the mode manager holds the first.
+static SPARE_PORTS: Mutex<Vec<(String, Box<dyn Sink>)>> = Mutex::new(Vec::new());
 
-impl HelmetMcu<Box<dyn SerialPort>, dyn SerialPort> {
-    fn new<'a>(serial_port_path: impl Into<Cow<'a, str>>) -> Result<Self> {
-        Ok(
-            Self {
-                serial: serialport::new(
-                    serial_port_path, 115200,
-                ).open()?,
-                dims: (64, 64),
+// Both eyes of a dry run can write to the same device, so a port can have
+// more than one.
+fn keep_spare_port(port: &str, spare: Box<dyn Sink>) {
+    SPARE_PORTS.lock().unwrap().push((port.to_owned(), spare));
+}
+
+fn drop_spare_port(port: &str) -> Option<Box<dyn Sink>> {
+    let mut spares = SPARE_PORTS.lock().unwrap();
+    let i = spares.iter().position(|(name, _)| name == port)?;
+    Some(spares.remove(i).1)
+}
+
+// Read locked while a frame goes out. Shutting down write locks it once, to
+// wait for any frame on its way, so the shutdown screen can never interleave
+// with one. Frames to different eyes can.
+static SENDING: RwLock<()> = RwLock::new(());
+
+// Set once shutting down, to the thread sending the shutdown screen. Frames
+// from anywhere else are dropped from then on.
+static SHUTTING_DOWN: OnceLock<ThreadId> = OnceLock::new();
+
+fn take_spare_port(port: &str) -> Result<(Box<dyn Sink>, Option<input::ReadHandle>)> {
+    let spare = drop_spare_port(port);
+    Ok((spare.with_context(|| format!("{port} is lent out"))?, None))
+}
+
+// Shows `frame` on each of `links` through its spare port, putting the port
+// back after, so the shutdown screen can follow a panic's blank frame.
+fn show_on_spare_ports(
+    links: &[LinkConfig],
+    wiring: &wiring::WiringConfig,
+    protocol: &protocol::Protocol,
+    frame: &[u8],
+    what: &str,
+) {
+    for link in links {
+        let result = HelmetMcu::builder()
+            .link(link)
+            .wiring(wiring)
+            .protocol(protocol.clone())
+            .build_with(take_spare_port)
+            .and_then(|mut mcu| {
+                let sent = mcu.send_rotated(frame.to_vec());
+                if let Some(serial) = mcu.serial.take() {
+                    keep_spare_port(&link.port, serial);
+                }
+                sent
+            });
+        if let Err(e) = result {
+            println!("[main] Couldn't show {what} on {}: {e:#}", link.port);
+        }
+    }
+}
+
+// Longest a panic waits for a frame on its way before blanking the panel
+// regardless. The frame may be the panicking thread's own.
+const PANIC_SEND_WAIT: Duration = Duration::from_secs(1);
+
+// Blanks the panel on a panic in any thread, after the usual message.
+// Panics the mode manager catches are blanked again there, harmlessly; one
+// in a thread that carries on leaves the panel blank until the next frame.
+fn blank_on_panic(
+    links: Vec<LinkConfig>,
+    wiring: wiring::WiringConfig,
+    protocol: protocol::Protocol,
+) {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report(info);
+        println!("[main] Panicked, blanking the panel...");
+        let deadline = Instant::now() + PANIC_SEND_WAIT;
+        while let Err(TryLockError::WouldBlock) = SENDING.try_write().map(drop) {
+            if Instant::now() >= deadline {
+                break;
             }
-        )
+            sleep(Duration::from_millis(10));
+        }
+        let blank = vec![0; PANEL_DIMS.0 * PANEL_DIMS.1];
+        show_on_spare_ports(&links, &wiring, &protocol, &blank, "the blank frame");
+    }));
+}
+
+// Shows the shutdown screen on SIGINT or SIGTERM, then exits.
+async fn shut_down_on_signal(
+    links: Vec<LinkConfig>,
+    wiring: wiring::WiringConfig,
+    protocol: protocol::Protocol,
+    splash: splash::SplashConfig,
+) {
+    let mut term = tokio::signal::unix::signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
     }
+    println!("[main] Shutting down...");
+    tokio::task::spawn_blocking(move || {
+        let _ = SHUTTING_DOWN.set(thread::current().id());
+        let frame = splash.shutdown_frame();
+        // A frame already on its way is let through first.
+        drop(SENDING.write().unwrap_or_else(PoisonError::into_inner));
+        show_on_spare_ports(&links, &wiring, &protocol, &frame, "the shutdown screen");
+    }).await.unwrap();
+    std::process::exit(0);
 }
 
-const ROWS_BETWEEN_SLEEPS: u8 = 2;
-const SLEEP_TIME_MILLIS: u64 = 17;
+// The helmet's own panel, or with `dry_run` a file standing in for it,
+// showing the boot splash if there is one. From here on, SIGINT and SIGTERM
+// show the shutdown screen before exiting, and panics blank the panel.
+fn connect(
+    eyes: &eyes::EyesConfig,
+    wiring: &wiring::WiringConfig,
+    protocol: &protocol::Protocol,
+    splash: &splash::SplashConfig,
+    dry_run: Option<&Path>,
+) -> Result<Displays<Mcu>> {
+    let links = links(eyes, dry_run);
+    let mut displays = open_mcu(&links, eyes.content, wiring, protocol, dry_run.is_some())?;
+    match splash.boot_frame() {
+        Ok(Some(frame)) => {
+            println!("[main] Showing boot splash...");
+            displays.mirror(frame)?;
+        }
+        Ok(None) => {}
+        // Not worth keeping the helmet from starting over.
+        Err(e) => println!("[main] Couldn't read the boot splash: {e:#}"),
+    }
+    blank_on_panic(links.clone(), wiring.clone(), protocol.clone());
+    tokio::spawn(shut_down_on_signal(links, wiring.clone(), protocol.clone(), splash.clone()));
+    Ok(displays)
+}
 
-impl<S: DerefMut<Target = T>, T: Write + ?Sized> HelmetMcu<S, T> {
-    fn send_map(&mut self) -> Result<()> {
-        self.send_png(MAP_IMAGE_FILENAME)
+// The MCUs to open, left first. With `dry_run` their ports are files: the
+// one given, or for the right eye the same with ".right" on the end unless
+// it's a device like /dev/null.
+fn links(eyes: &eyes::EyesConfig, dry_run: Option<&Path>) -> Vec<LinkConfig> {
+    let mut links = eyes.links();
+    if let Some(path) = dry_run {
+        let device = path.metadata().is_ok_and(|meta| !meta.is_file());
+        for (i, link) in links.iter_mut().enumerate() {
+            link.port = path.to_string_lossy().into_owned();
+            if i > 0 && !device {
+                link.port += ".right";
+            }
+        }
     }
+    links
+}
 
-    fn send_png(&mut self, filename: impl AsRef<Path>) -> Result<()> {
-        let file = File::open(filename)?;
-        let data = read_png(file)?;
-        self.send_rotated(data)?;
+// The panels, or the files standing in for them, and nothing more.
+fn open_mcu(
+    links: &[LinkConfig],
+    content: eyes::Content,
+    wiring: &wiring::WiringConfig,
+    protocol: &protocol::Protocol,
+    dry_run: bool,
+) -> Result<Displays<Mcu>> {
+    let mcus = links.iter()
+        .map(|link| {
+            let builder = HelmetMcu::builder()
+                .dims(PANEL_DIMS)
+                .link(link)
+                .wiring(wiring)
+                .protocol(protocol.clone());
+            if dry_run {
+                println!("[main] Dry run, writing frames to {}...", link.port);
+                builder.build_with(open_file)
+            } else {
+                println!("[main] Connecting to microcontroller on {}...", link.port);
+                builder.build()
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok(Displays::new(mcus, content))
+}
+
+// Settings for a `HelmetMcu`, starting out as those of the helmet itself.
+struct HelmetMcuBuilder {
+    port: String,
+    dims: (usize, usize),
+    invert: bool,
+    rotation: u16,
+    wiring: wiring::WiringConfig,
+    protocol: protocol::Protocol,
+}
+
+impl HelmetMcuBuilder {
+    fn port(mut self, port: impl Into<String>) -> Self {
+        self.port = port.into();
+        self
+    }
+
+    // Before the 90° turn.
+    fn dims(mut self, dims: (usize, usize)) -> Self {
+        self.dims = dims;
+        self
+    }
+
+    // Lights the pixels frames leave dark and the other way round.
+    fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    // The port, rotation and inversion of one eye's MCU.
+    fn link(mut self, link: &LinkConfig) -> Self {
+        self.rotation = link.rotation;
+        self.port(link.port.clone()).invert(link.invert)
+    }
+
+    fn wiring(mut self, wiring: &wiring::WiringConfig) -> Self {
+        self.wiring = wiring.clone();
+        self
+    }
+
+    // Framing, and pacing: `rows_between_pauses` and `pause_millis`.
+    fn protocol(mut self, protocol: protocol::Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    // Opens the serial port.
+    fn build(self) -> Result<HelmetMcu<Box<dyn Sink>, dyn Sink>> {
+        self.build_with(open_serial)
+    }
+
+    // Frames go wherever `open` says instead, e.g. into a buffer. It's
+    // called with the port again after the port was lent out.
+    fn build_with<S: DerefMut<Target = T>, T: Read + Write + ?Sized>(
+        self,
+        open: Opener<S>,
+    ) -> Result<HelmetMcu<S, T>> {
+        // Packing happens after the turn.
+        let wiring = wiring::Wiring::new(&self.wiring, turned(self.dims, self.rotation))?;
+        let mut mcu = HelmetMcu {
+            serial: None,
+            port: self.port,
+            open,
+            reader: None,
+            inputs: bounded(64),
+            flow: bounded(64),
+            xoff: false,
+            compress: false,
+            echoes: bounded(64),
+            dims: self.dims,
+            invert: self.invert,
+            rotation: self.rotation,
+            wiring,
+            protocol: self.protocol,
+            last_frame: None,
+            last_send: None,
+            buffers: SendBuffers::default(),
+            ping_nonce: 0,
+            detached: false,
+            safety: Default::default(),
+        };
+        let (serial, reader) = open(&mcu.port)?;
+        mcu.attach(serial, reader);
+        Ok(mcu)
+    }
+}
+
+impl HelmetMcu<Box<dyn Sink>, dyn Sink> {
+    fn builder() -> HelmetMcuBuilder {
+        HelmetMcuBuilder {
+            port: MCU_SERIAL_PORT.to_owned(),
+            dims: PANEL_DIMS,
+            invert: INVERT_IMAGE,
+            rotation: 90,
+            wiring: Default::default(),
+            protocol: Default::default(),
+        }
+    }
+}
+
+impl<S: DerefMut<Target = T>, T: Read + Write + ?Sized> HelmetMcu<S, T> {
+    fn serial(&mut self) -> Result<&mut T> {
+        open_port(&mut self.serial)
+    }
+
+    fn attach(&mut self, serial: S, reader: Option<input::ReadHandle>) {
+        let parser = input::Parser::new(self.protocol.input_prefix, self.protocol.escape());
+        let inputs = self.inputs.0.clone();
+        let (flow, echoes) = (self.flow.0.clone(), self.echoes.0.clone());
+        self.reader = reader.map(|reader| {
+            input::Reader::spawn(reader, self.port.clone(), parser, inputs, flow, echoes)
+        });
+        self.serial = Some(serial);
+        // Nothing's been sent on the new port to be held off.
+        self.xoff = false;
+        self.negotiate();
+    }
+
+    // Asks the firmware whether it can decompress frames, again whenever the
+    // port opens as it might have been flashed meanwhile.
+    fn negotiate(&mut self) {
+        self.compress = false;
+        if self.protocol.compression == Compression::None || self.reader.is_none() {
+            return;
+        }
+        match self.hello() {
+            Ok(features) if features & protocol::HELLO_HEATSHRINK != 0 => {
+                println!("[serial] The firmware decompresses heatshrink; compressing frames...");
+                self.compress = true;
+            }
+            Ok(_) => println!("[serial] The firmware can't decompress frames."),
+            Err(e) => println!("[serial] Not compressing frames: {e:#}"),
+        }
+    }
+
+    fn hello(&mut self) -> Result<u16> {
+        let mut hello = Vec::new();
+        self.protocol.stuff(&self.protocol.hello, &mut hello);
+        while self.flow.1.try_recv().is_ok() {}
+        self.serial()?.write_all(&hello)?;
+        self.serial()?.flush()?;
+        let deadline = Instant::now() + FLOW_TIMEOUT;
+        loop {
+            match self.flow.1.recv_deadline(deadline) {
+                Ok(input::Flow::Hello(features)) => return Ok(features),
+                Ok(_) => continue,
+                Err(_) => anyhow::bail!("no answer to hello from the MCU within {FLOW_TIMEOUT:?}"),
+            }
+        }
+    }
+
+    // Input from the helmet's buttons and encoder, for as long as this lives.
+    fn inputs(&self) -> Receiver<input::InputEvent> {
+        self.inputs.1.clone()
+    }
+
+    // Closes the port for `user` to open by path, e.g. a firmware flasher,
+    // then opens it again whether or not `user` succeeded.
+    fn lend_port(&mut self, user: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        println!("[serial] Releasing {}...", self.port);
+        self.release();
+        let result = user(&self.port);
+        self.reopen()?;
+        println!("[serial] Reopened {}.", self.port);
+        self.last_frame = None;
+        result
+    }
+
+    // Picks the port up again as `port` after it was unplugged as `was`, and
+    // resends whatever was shown meanwhile. Another MCU's port is ignored.
+    fn reattach(&mut self, was: &str, port: &str) -> Result<()> {
+        if was != self.port {
+            return Ok(());
+        }
+        self.release();
+        self.port = port.to_owned();
+        self.take_back_port()?;
+        println!("[serial] Reattached to {port}.");
         Ok(())
     }
 
-    fn send_png_1bit(&mut self, filename: impl AsRef<Path>) -> Result<()> {
-        let file = File::open(filename)?;
-        let data = read_png_1bit(file)?;
-        self.send_rotated(data)?;
+    // Closes the port until `take_back_port`, keeping frames meanwhile as if
+    // it were unplugged.
+    fn release_port(&mut self) -> Result<String> {
+        println!("[serial] Releasing {}...", self.port);
+        self.release();
+        self.detached = true;
+        Ok(self.port.clone())
+    }
+
+    // Opens the port again and resends whatever was shown meanwhile.
+    fn take_back_port(&mut self) -> Result<()> {
+        self.reopen()?;
+        self.detached = false;
+        if self.last_frame.is_some() {
+            self.send_raw()?;
+        }
         Ok(())
     }
 
-    fn send_png_g(&mut self, filename: impl AsRef<Path> + Clone) -> Result<()> {
-        let file = File::open(filename.clone())?;
-        let mut data = read_png(file)?;
-        if data.len() != (64 * 64) {
-            assert!(data.len() == (64 * 64 / 8));
-            self.send_png_1bit(filename.clone())?;
-        } else {
-            self.send_rotated(data)?;
+    fn release(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.stop();
+        }
+        self.serial = None;
+        drop_spare_port(&self.port);
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            match (self.open)(&self.port) {
+                Ok((serial, reader)) => break self.attach(serial, reader),
+                Err(_) if attempt < REOPEN_ATTEMPTS => {
+                    attempt += 1;
+                    sleep(REOPEN_INTERVAL);
+                }
+                Err(e) => return Err(e.context(format!("reopening {}", self.port))),
+            }
         }
         Ok(())
     }
 
-    fn send_rotated(&mut self, data: Vec<u8>) -> Result<()> {
-        self.send_raw(Rot90::new(data, self.dims))
+    fn send_rotated(&mut self, mut data: Vec<u8>) -> Result<()> {
+        // What the panel will really show.
+        if let Some(mask) = mask::get() {
+            mask.apply(&mut data, self.dims.0);
+        }
+        if !self.safety.admit(&data) {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let buffers = &mut self.buffers;
+        buffers.rotated.clear();
+        buffers.rotated.extend_from_slice(&data);
+        let mut dims = self.dims;
+        for _ in 0..self.rotation / 90 {
+            buffers.turning.clear();
+            buffers.turning.extend(Rot90::new(&buffers.rotated, dims));
+            std::mem::swap(&mut buffers.rotated, &mut buffers.turning);
+            dims = (dims.1, dims.0);
+        }
+        self.wiring.apply(&buffers.rotated, &mut buffers.ordered);
+        self.last_frame = Some(data);
+        if self.detached {
+            return Ok(());
+        }
+        if let Err(e) = self.send_raw() {
+            // Anything else is still an error, but an unplugged adapter can
+            // come back.
+            if self.reader.is_none() || Path::new(&self.port).exists() {
+                return Err(e);
+            }
+            println!("[serial] {} is gone ({e:#}); waiting for it to come back...", self.port);
+            self.release();
+            self.detached = true;
+            return Ok(());
+        }
+        let elapsed = start.elapsed();
+        self.last_send = Some(elapsed);
+        hooks::fire(hooks::HookEvent::FrameSent, &[("millis", &elapsed.as_millis().to_string())]);
+        Ok(())
+    }
+
+    fn ping(&mut self) -> Result<Duration> {
+        if self.protocol.ping.is_empty() {
+            anyhow::bail!("this firmware can't be pinged; protocol.ping is empty");
+        }
+        self.ping_nonce = self.ping_nonce.wrapping_add(1);
+        // An echo of the prefix would read as the start of an input message.
+        if self.ping_nonce == self.protocol.input_prefix {
+            self.ping_nonce = self.ping_nonce.wrapping_add(1);
+        }
+        let start = Instant::now();
+        let nonce = self.ping_nonce;
+        let mut ping = Vec::new();
+        self.protocol.stuff(&self.protocol.ping, &mut ping);
+        self.protocol.stuff(&[nonce], &mut ping);
+        self.serial()?.write_all(&ping)?;
+        self.serial()?.flush()?;
+        // Anything else that comes back, like a stale echo, is skipped.
+        let deadline = start + PING_TIMEOUT;
+        while let Ok(byte) = self.echoes.1.recv_deadline(deadline) {
+            if byte == nonce {
+                return Ok(start.elapsed());
+            }
+        }
+        anyhow::bail!(
+            "no echo from the MCU within {PING_TIMEOUT:?}; does its firmware support ping?"
+        )
     }
 
-    fn send_raw(
-        &mut self,
-        data: impl Iterator<Item = u8>,
-    ) -> Result<()> {
+    // Sends what `send_rotated` left in `buffers.ordered`.
+    fn send_raw(&mut self) -> Result<()> {
+        let _sending = SENDING.read().unwrap_or_else(PoisonError::into_inner);
+        if SHUTTING_DOWN.get().is_some_and(|&id| id != thread::current().id()) {
+            return Ok(());
+        }
+        let Self {
+            serial, protocol, buffers, dims, invert, rotation, reader, flow, xoff, compress, ..
+        } = self;
+        let SendBuffers { ordered, packed, compressed, stuffed, .. } = buffers;
+        // Nothing comes back from a dry run's file, so it's paced instead,
+        // though chunks still show as sent.
+        let listening = reader.is_some();
+        let flow_control = match protocol.flow_control {
+            flow_control if listening => flow_control,
+            FlowControl::Chunked => FlowControl::Chunked,
+            _ => FlowControl::Pauses,
+        };
+        let serial = open_port(serial)?;
         println!("[send_raw] Sending reset sequence...");
-        self.serial.write_all(&RESET_SEQ)?;
-        self.serial.flush()?;
-        let mut rows_since_sleep = ROWS_BETWEEN_SLEEPS;
-        let mut index_within_row = -1;
-        let mut byte = 0x0_u8;
-        let mut index_within_byte = 0;
+        serial.write_all(&protocol.reset)?;
+        serial.flush()?;
+        // Rows are as wide as the frame after the turn.
+        let (row_w, _) = turned(*dims, *rotation);
+        packed.clear();
+        for row in ordered.chunks(row_w) {
+            let pixels = row.iter().map(|&p| (p > (u8::MAX / 2)) ^ *invert);
+            protocol.pack_row(pixels, packed);
+        }
+        if *compress {
+            let smaller = protocol.compress(packed, compressed);
+            if smaller {
+                std::mem::swap(packed, compressed);
+            }
+            packed.insert(0, smaller.into());
+        }
+        let row_len = protocol.row_header.len() + row_w.div_ceil(8) + protocol.row_footer.len();
+        let total = packed.len() + protocol.frame_footer.len();
         println!("[send_raw] Sending pixel data...");
-        let prog = ProgressBar::new(64 * 9);
-        for i in data {
-            if (i > (u8::MAX / 2)) ^ INVERT_IMAGE {
-                byte |= 1 << index_within_byte;
-            }
-            index_within_byte += 1;
-            if index_within_byte >= 8 {
-                index_within_byte = 0;
-                self.serial.write_all(&[byte])?;
-                prog.inc(1);
-                byte = 0x0_u8;
-                index_within_row += 1;
-                if index_within_row >= 8 {
-                    index_within_row = -1;
-                    self.serial.flush()?;
-                    if rows_since_sleep >= ROWS_BETWEEN_SLEEPS {
-                        sleep(std::time::Duration::from_millis(
-                            SLEEP_TIME_MILLIS
-                        ));
-                        rows_since_sleep = 0;
-                    } else {
-                        rows_since_sleep += 1;
-                    }
+        let mut prog = progress::Progress::new("send_frame", total as u64);
+        let chunked = flow_control == FlowControl::Chunked;
+        let mut aborted = false;
+        if chunked {
+            packed.extend_from_slice(&protocol.frame_footer);
+            let acks = listening.then_some(&flow.1);
+            aborted = !send_chunked(serial, packed, protocol, acks, stuffed, &mut prog)?;
+        }
+        // Everything from before the reset is unknown, so credit starts with
+        // a fresh report.
+        let mut credit = 0;
+        let mut write = |serial: &mut T, data: &[u8]| -> Result<()> {
+            match flow_control {
+                // Only the abort goes this way with chunks.
+                FlowControl::Pauses | FlowControl::Chunked => {
+                    serial.write_all(data)?;
+                    serial.flush()?;
+                }
+                FlowControl::Credit => write_credited(serial, data, &mut credit, &flow.1)?,
+                FlowControl::XonXoff => {
+                    stuffed.clear();
+                    protocol.stuff(data, stuffed);
+                    write_unless_xoff(serial, stuffed, xoff, &flow.1)?;
                 }
             }
-            if index_within_row == -1 {
-                self.serial.write_all(&[0x0])?;
-                prog.inc(1);
-                index_within_row += 1;
-                continue;
+            Ok(())
+        };
+        if !chunked {
+            let mut rows_since_pause = protocol.rows_between_pauses;
+            for row in packed.chunks(row_len) {
+                if !protocol.abort.is_empty() && jobs::preempting() {
+                    aborted = true;
+                    break;
+                }
+                write(serial, row)?;
+                prog.inc(row.len() as u64);
+                if flow_control != FlowControl::Pauses {
+                    continue;
+                }
+                if rows_since_pause >= protocol.rows_between_pauses {
+                    sleep(Duration::from_millis(protocol.pause_millis));
+                    rows_since_pause = 0;
+                } else {
+                    rows_since_pause += 1;
+                }
             }
         }
-        self.serial.flush()?;
+        if aborted {
+            println!("[send_raw] Aborting the frame for an alert...");
+            write(serial, &protocol.abort)?;
+            serial.flush()?;
+            return Ok(());
+        }
+        if !chunked {
+            write(serial, &protocol.frame_footer)?;
+            prog.inc(protocol.frame_footer.len() as u64);
+        }
+        serial.flush()?;
         prog.finish();
-        println!("[send_raw] All data sent and flushed.");
+        if chunked {
+            println!("[send_raw] All chunks sent.");
+        } else {
+            println!("[send_raw] All data sent and flushed.");
+        }
         Ok(())
     }
 }
 
-fn read_png_1bit(file: File) -> Result<Vec<u8>> {
-    let mut reader = PngDec::new(file).read_info()?;
-    let mut raw_buf = vec![0; reader.output_buffer_size()];
-    let mut buf = vec![0u8; raw_buf.len() * 8];
-    reader.next_frame(&mut raw_buf)?;
-    let mut index = 0;
-    for i in 0..(raw_buf.len()) {
-        for j in 0..8 {
-            if (raw_buf[i] & (1 << j)) > 0 {
-                buf[(i * 8) + j] = 0xFF;
+// Writes `data` no faster than the MCU has room for, spending `credit` and
+// waiting for a report of free space whenever it runs out.
+fn write_credited<T: Write + ?Sized>(
+    serial: &mut T,
+    mut data: &[u8],
+    credit: &mut usize,
+    reports: &Receiver<input::Flow>,
+) -> Result<()> {
+    while !data.is_empty() {
+        if *credit == 0 {
+            serial.flush()?;
+            // Reports from before what was just sent arrived would count it
+            // as free.
+            while reports.try_recv().is_ok() {}
+            let deadline = Instant::now() + FLOW_TIMEOUT;
+            loop {
+                match reports.recv_deadline(deadline) {
+                    Ok(input::Flow::Credit(free)) => break *credit = free.into(),
+                    Ok(_) => continue,
+                    Err(_) => anyhow::bail!(
+                        "no free space report from the MCU within {FLOW_TIMEOUT:?}; \
+                         does its firmware send them?"
+                    ),
+                }
             }
+            continue;
+        }
+        let n = data.len().min(*credit);
+        serial.write_all(&data[..n])?;
+        *credit -= n;
+        data = &data[n..];
+    }
+    serial.flush()?;
+    Ok(())
+}
+
+// Sends `frame` in chunks, sending each again until the MCU acknowledges it
+// on `acks`, or just once with nobody to acknowledge them. False if it
+// stopped short for an alert, leaving the abort to the caller.
+fn send_chunked<T: Write + ?Sized>(
+    serial: &mut T,
+    frame: &[u8],
+    protocol: &protocol::Protocol,
+    acks: Option<&Receiver<input::Flow>>,
+    chunk: &mut Vec<u8>,
+    prog: &mut progress::Progress,
+) -> Result<bool> {
+    // Indices start again with every frame, so old acknowledgements would
+    // pass for new ones.
+    if let Some(acks) = acks {
+        while acks.try_recv().is_ok() {}
+    }
+    for (index, data) in frame.chunks(protocol.chunk_bytes.into()).enumerate() {
+        if !protocol.abort.is_empty() && jobs::preempting() {
+            return Ok(false);
+        }
+        let index = index as u16;
+        chunk.clear();
+        protocol.chunk(index, data, chunk);
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            let result = serial.write_all(chunk)
+                .and_then(|()| serial.flush())
+                .map_err(|e| e.to_string())
+                .and_then(|()| acks.map_or(Ok(()), |acks| await_ack(acks, index)));
+            match result {
+                Ok(()) => break,
+                Err(e) if tries <= protocol.chunk_retries => {
+                    println!("[send_raw] Sending chunk {index} again: {e}");
+                }
+                Err(e) => anyhow::bail!("chunk {index} didn't get through in {tries} tries: {e}"),
+            }
+        }
+        prog.inc(data.len() as u64);
+    }
+    Ok(true)
+}
+
+fn await_ack(acks: &Receiver<input::Flow>, index: u16) -> Result<(), String> {
+    let deadline = Instant::now() + FLOW_TIMEOUT;
+    loop {
+        match acks.recv_deadline(deadline) {
+            Ok(input::Flow::Ack(acked)) if acked == index => return Ok(()),
+            Ok(input::Flow::Nak(missed)) if missed == index => {
+                return Err("the MCU didn't get it intact".into());
+            }
+            // Like a late acknowledgement of an earlier try.
+            Ok(_) => continue,
+            Err(_) => return Err(format!("no acknowledgement within {FLOW_TIMEOUT:?}")),
+        }
+    }
+}
+
+// Writes `data` once the MCU isn't holding sending off with XOFF, going by
+// everything it has said since last time.
+fn write_unless_xoff<T: Write + ?Sized>(
+    serial: &mut T,
+    data: &[u8],
+    xoff: &mut bool,
+    reports: &Receiver<input::Flow>,
+) -> Result<()> {
+    let deadline = Instant::now() + FLOW_TIMEOUT;
+    loop {
+        let report = match reports.try_recv() {
+            Ok(report) => report,
+            Err(_) if !*xoff => break,
+            Err(_) => reports.recv_deadline(deadline).map_err(|_| {
+                anyhow::anyhow!("the MCU sent XOFF and no XON within {FLOW_TIMEOUT:?}")
+            })?,
+        };
+        match report {
+            input::Flow::Xoff => *xoff = true,
+            input::Flow::Xon => *xoff = false,
+            _ => {}
         }
     }
-    Ok(buf)
+    serial.write_all(data)?;
+    serial.flush()?;
+    Ok(())
+}
+
+// Decodes a panel-sized PNG of any colour type.
+fn read_png_g(filename: impl AsRef<Path>) -> Result<Vec<u8>> {
+    ingest::read_png_g(&std::fs::read(filename)?, PANEL_DIMS, picture::background())
 }
 
-fn read_png(file: File) -> Result<Vec<u8>> {
-    let mut reader = PngDec::new(file).read_info()?;
-    let mut buf = vec![0; reader.output_buffer_size()];
-    reader.next_frame(&mut buf)?;
-    Ok(buf)
+// `None` while the port is lent out.
+fn open_port<S: DerefMut<Target = T>, T: ?Sized>(serial: &mut Option<S>) -> Result<&mut T> {
+    serial.as_deref_mut()
+        .ok_or_else(|| anyhow::anyhow!("the serial port is lent out"))
 }
 
-struct Rot90<T: Copy> {
-    orig: Vec<T>,
+// `dims` after turning `degrees` clockwise.
+fn turned(dims: (usize, usize), degrees: u16) -> (usize, usize) {
+    if degrees % 180 == 90 { (dims.1, dims.0) } else { dims }
+}
+
+struct Rot90<'a, T: Copy> {
+    orig: &'a [T],
     w: usize,
     h: usize,
     x: usize,
     y: usize,
 }
 
-impl<T: Copy> Rot90<T> {
-    fn new(orig: Vec<T>, dims: (usize, usize)) -> Self {
+impl<'a, T: Copy> Rot90<'a, T> {
+    fn new(orig: &'a [T], dims: (usize, usize)) -> Self {
         let (w, h) = dims;
         assert!(orig.len() == (w * h));
         Self {
@@ -259,7 +2136,7 @@ impl<T: Copy> Rot90<T> {
     }
 }
 
-impl<T: Copy> Rot90<T> {
+impl<T: Copy> Rot90<'_, T> {
     fn at_pre(&self, xt: usize, yt: usize) -> Option<<Self as Iterator>::Item> {
         let index = (yt * self.w) + xt;
         if index >= self.orig.len() {
@@ -269,14 +2146,19 @@ impl<T: Copy> Rot90<T> {
         }
     }
 
+    // Turned clockwise: each row out is a column of the original, read from
+    // the bottom up.
     fn internal_peek(&self) -> Option<<Self as Iterator>::Item> {
+        if self.y >= self.w {
+            return None;
+        }
         let xt = self.y;
-        let yt = self.w - self.x - 1;
+        let yt = self.h - self.x - 1;
         self.at_pre(xt, yt)
     }
 }
 
-impl<T: Copy> Iterator for Rot90<T> {
+impl<T: Copy> Iterator for Rot90<'_, T> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -290,5 +2172,504 @@ impl<T: Copy> Iterator for Rot90<T> {
         }
         ret
     }
-} 
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn frame() -> impl Strategy<Value = ((usize, usize), Vec<u8>)> {
+        (1usize..24, 1usize..24).prop_flat_map(|(w, h)| {
+            (Just((w, h)), proptest::collection::vec(any::<u8>(), w * h))
+        })
+    }
+
+    // What the MCU would make of a frame, going by `protocol`: `rows` rows of
+    // `row_len` pixels.
+    fn unpack(
+        bytes: &[u8],
+        protocol: &protocol::Protocol,
+        rows: usize,
+        row_len: usize,
+    ) -> Vec<bool> {
+        let mut rest = bytes.strip_prefix(protocol.reset.as_slice()).expect("reset");
+        let mut pixels = Vec::with_capacity(rows * row_len);
+        for _ in 0..rows {
+            rest = rest.strip_prefix(protocol.row_header.as_slice()).expect("row header");
+            let (packed, after) = rest.split_at(row_len.div_ceil(8));
+            for i in 0..row_len {
+                let bit = match protocol.bit_order {
+                    protocol::BitOrder::LsbFirst => 1 << (i % 8),
+                    protocol::BitOrder::MsbFirst => 0x80 >> (i % 8),
+                };
+                pixels.push(packed[i / 8] & bit != 0);
+            }
+            rest = after.strip_prefix(protocol.row_footer.as_slice()).expect("row footer");
+        }
+        assert_eq!(rest, protocol.frame_footer.as_slice());
+        pixels
+    }
+
+    // Stands in for the MCU's receive buffer: bytes arrive as soon as
+    // they're written and are taken out one per `per_byte`, and writing more
+    // than it holds fails.
+    struct SimulatedMcu {
+        capacity: usize,
+        per_byte: Duration,
+        level: usize,
+        // How far taking bytes out has got.
+        drained_to: Instant,
+    }
+
+    impl SimulatedMcu {
+        fn new(capacity: usize, per_byte: Duration) -> Self {
+            Self { capacity, per_byte, level: 0, drained_to: Instant::now() }
+        }
+
+        fn drain(&mut self) {
+            let now = Instant::now();
+            let done = (now.duration_since(self.drained_to).as_nanos()
+                / self.per_byte.as_nanos()) as usize;
+            if done >= self.level {
+                self.level = 0;
+                self.drained_to = now;
+            } else {
+                self.level -= done;
+                self.drained_to += self.per_byte * done as u32;
+            }
+        }
+    }
+
+    impl Read for SimulatedMcu {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for SimulatedMcu {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.drain();
+            if self.level + buf.len() > self.capacity {
+                return Err(io::Error::other(format!(
+                    "receive buffer overran: {} bytes into {}",
+                    self.level + buf.len(),
+                    self.capacity,
+                )));
+            }
+            self.level += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // A 64 byte buffer, as on most Arduinos, emptied at 400µs a byte while
+    // rows are shifted out to the panel.
+    fn send_to_simulated_mcu(protocol: protocol::Protocol) -> Result<()> {
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol)
+            .build_with(|_| {
+                Ok((Box::new(SimulatedMcu::new(64, Duration::from_micros(400))), None))
+            })?;
+        mcu.send_rotated(vec![0xFF; PANEL_DIMS.0 * PANEL_DIMS.1])
+    }
+
+    // Shares a simulated buffer between the port's two handles, saying what
+    // `report` makes of its free space on every read, about once a
+    // millisecond, as firmware with flow control would.
+    #[derive(Clone)]
+    struct ReportingMcu {
+        mcu: Arc<Mutex<SimulatedMcu>>,
+        report: fn(usize) -> Vec<u8>,
+    }
+
+    impl ReportingMcu {
+        fn open(
+            report: fn(usize) -> Vec<u8>,
+        ) -> Result<(Box<dyn Sink>, Option<input::ReadHandle>)> {
+            let mcu = SimulatedMcu::new(64, Duration::from_micros(400));
+            let mcu = ReportingMcu { mcu: Arc::new(Mutex::new(mcu)), report };
+            Ok((Box::new(mcu.clone()), Some(Box::new(mcu))))
+        }
+    }
+
+    impl Read for ReportingMcu {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            sleep(Duration::from_millis(1));
+            let mut mcu = self.mcu.lock().unwrap();
+            mcu.drain();
+            let report = (self.report)(mcu.capacity - mcu.level);
+            buf[..report.len()].copy_from_slice(&report);
+            Ok(report.len())
+        }
+    }
 
+    impl Write for ReportingMcu {
+        // Bytes take their time on the wire, about 87µs each at 115200 baud,
+        // before the MCU sees them.
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            sleep(Duration::from_micros(87) * buf.len() as u32);
+            self.mcu.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Takes frames in chunks as the MCU would, asking for any that arrive
+    // garbled again, and for `refuse` again anyway the first time. Keeps what
+    // it accepted.
+    #[derive(Clone, Default)]
+    struct ChunkingMcu(Arc<Mutex<ChunkLog>>);
+
+    #[derive(Default)]
+    struct ChunkLog {
+        refuse: Option<u16>,
+        written: Vec<u8>,
+        read_to: usize,
+        tries: Vec<u32>,
+        chunks: Vec<Option<Vec<u8>>>,
+    }
+
+    impl ChunkingMcu {
+        fn refusing(index: u16) -> Self {
+            Self(Arc::new(Mutex::new(ChunkLog { refuse: Some(index), ..Default::default() })))
+        }
+
+        fn accepted(&self) -> Vec<u8> {
+            let log = self.0.lock().unwrap();
+            log.chunks.iter()
+                .flat_map(|chunk| chunk.as_deref().expect("a missing chunk"))
+                .copied()
+                .collect()
+        }
+    }
+
+    impl Read for ChunkingMcu {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            sleep(Duration::from_millis(1));
+            let log = &mut *self.0.lock().unwrap();
+            // Past the reset, or anything garbled, to where a chunk starts.
+            while log.written.get(log.read_to).is_some_and(|&byte| byte != b'$') {
+                log.read_to += 1;
+            }
+            let Some(header) = log.written.get(log.read_to..log.read_to + 5) else {
+                return Ok(0);
+            };
+            let index = u16::from_le_bytes([header[1], header[2]]);
+            let len = u16::from_le_bytes([header[3], header[4]]) as usize;
+            if len > 256 {
+                log.read_to += 1;
+                return Ok(0);
+            }
+            let Some(body) = log.written.get(log.read_to + 1..log.read_to + 5 + len + 2) else {
+                return Ok(0);
+            };
+            let (body, crc) = body.split_at(4 + len);
+            let intact = protocol::crc16(body).to_le_bytes() == crc;
+            let data = body[4..].to_vec();
+            log.read_to += 5 + len + 2;
+            log.tries.resize(log.tries.len().max(index as usize + 1), 0);
+            log.tries[index as usize] += 1;
+            let refused = log.refuse == Some(index) && log.tries[index as usize] == 1;
+            let kind = if !intact || refused {
+                b'N'
+            } else {
+                log.chunks.resize(log.chunks.len().max(index as usize + 1), None);
+                log.chunks[index as usize] = Some(data);
+                b'A'
+            };
+            let [lo, hi] = index.to_le_bytes();
+            buf[..4].copy_from_slice(&[b'!', kind, lo, hi]);
+            Ok(4)
+        }
+    }
+
+    impl Write for ChunkingMcu {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Firmware that answers hello saying it decompresses heatshrink, keeping
+    // everything written to it.
+    #[derive(Clone, Default)]
+    struct DecompressingMcu(Arc<Mutex<(Vec<u8>, bool)>>);
+
+    impl Read for DecompressingMcu {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            sleep(Duration::from_millis(1));
+            let (written, answered) = &mut *self.0.lock().unwrap();
+            if *answered || written.is_empty() {
+                return Ok(0);
+            }
+            *answered = true;
+            let [lo, hi] = protocol::HELLO_HEATSHRINK.to_le_bytes();
+            buf[..4].copy_from_slice(&[b'!', b'H', lo, hi]);
+            Ok(4)
+        }
+    }
+
+    impl Write for DecompressingMcu {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn default_pacing_keeps_within_the_mcu_buffer() {
+        send_to_simulated_mcu(Default::default()).unwrap();
+    }
+
+    #[test]
+    fn sending_without_pauses_overruns_the_mcu_buffer() {
+        let protocol = protocol::Protocol {
+            rows_between_pauses: u32::MAX,
+            ..Default::default()
+        };
+        let error = send_to_simulated_mcu(protocol).unwrap_err();
+        assert!(error.to_string().contains("overran"), "{error}");
+    }
+
+    #[test]
+    fn pausing_too_briefly_overruns_the_mcu_buffer() {
+        let protocol = protocol::Protocol { pause_millis: 1, ..Default::default() };
+        let error = send_to_simulated_mcu(protocol).unwrap_err();
+        assert!(error.to_string().contains("overran"), "{error}");
+    }
+
+    #[test]
+    fn flow_control_keeps_within_the_mcu_buffer_without_pauses() {
+        let protocol = protocol::Protocol {
+            flow_control: FlowControl::Credit,
+            rows_between_pauses: u32::MAX,
+            ..Default::default()
+        };
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol)
+            .build_with(|_| {
+                ReportingMcu::open(|free| {
+                    let [lo, hi] = (free as u16).to_le_bytes();
+                    vec![b'!', b'C', lo, hi]
+                })
+            })
+            .unwrap();
+        mcu.send_rotated(vec![0xFF; PANEL_DIMS.0 * PANEL_DIMS.1]).unwrap();
+    }
+
+    #[test]
+    fn xon_xoff_keeps_within_the_mcu_buffer_without_pauses() {
+        let protocol = protocol::Protocol {
+            flow_control: FlowControl::XonXoff,
+            rows_between_pauses: u32::MAX,
+            ..Default::default()
+        };
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol)
+            .build_with(|_| {
+                ReportingMcu::open(|free| {
+                    vec![if free < 32 { protocol::XOFF } else { protocol::XON }]
+                })
+            })
+            .unwrap();
+        // Packs to XOFF, which needs escaping, all along every row. Three
+        // more turns undo the one before packing.
+        let mut frame: Vec<u8> = (0..PANEL_DIMS.0 * PANEL_DIMS.1)
+            .map(|i| if protocol::XOFF >> (i % 8) & 1 == 1 { 0xFF } else { 0 })
+            .collect();
+        for _ in 0..3 {
+            frame = Rot90::new(&frame, PANEL_DIMS).collect();
+        }
+        mcu.send_rotated(frame).unwrap();
+    }
+
+    #[test]
+    fn chunked_frames_resend_only_the_chunk_that_failed() {
+        let protocol = protocol::Protocol {
+            flow_control: FlowControl::Chunked,
+            ..Default::default()
+        };
+        // Openers can't capture anything to look at afterwards.
+        static LOG: OnceLock<ChunkingMcu> = OnceLock::new();
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol.clone())
+            .build_with(|_| {
+                let mcu = LOG.get_or_init(|| ChunkingMcu::refusing(1)).clone();
+                Ok((Box::new(mcu.clone()), Some(Box::new(mcu))))
+            })
+            .unwrap();
+        let data: Vec<u8> = (0..PANEL_DIMS.0 * PANEL_DIMS.1).map(|i| (i * 37) as u8).collect();
+        mcu.send_rotated(data.clone()).unwrap();
+        let log = LOG.get().unwrap();
+        // 64 rows of a header and 8 bytes, and the footer, in 256 byte chunks.
+        assert_eq!(log.0.lock().unwrap().tries, [1, 2, 1]);
+        let received = [protocol.reset.as_slice(), &log.accepted()].concat();
+        let expected: Vec<bool> = Rot90::new(&data, PANEL_DIMS)
+            .map(|p| p > (u8::MAX / 2))
+            .collect();
+        assert_eq!(unpack(&received, &protocol, PANEL_DIMS.0, PANEL_DIMS.1), expected);
+    }
+
+    #[test]
+    fn chunked_frames_get_through_a_faulty_link() {
+        let protocol = protocol::Protocol {
+            flow_control: FlowControl::Chunked,
+            chunk_bytes: 64,
+            chunk_retries: 10,
+            ..Default::default()
+        };
+        static LOG: OnceLock<ChunkingMcu> = OnceLock::new();
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol.clone())
+            .build_with(|_| {
+                let mcu = LOG.get_or_init(ChunkingMcu::default).clone();
+                let faults = fault::FaultConfig {
+                    drop: 0.003,
+                    duplicate: 0.003,
+                    corrupt: 0.003,
+                    seed: 7,
+                };
+                Ok((Box::new(fault::Faulty::new(mcu.clone(), &faults)), Some(Box::new(mcu))))
+            })
+            .unwrap();
+        let data = vec![0xFF; PANEL_DIMS.0 * PANEL_DIMS.1];
+        mcu.send_rotated(data.clone()).unwrap();
+        let log = LOG.get().unwrap();
+        assert!(log.0.lock().unwrap().tries.iter().any(|&tries| tries > 1), "nothing was resent");
+        let received = [protocol.reset.as_slice(), &log.accepted()].concat();
+        let expected: Vec<bool> = Rot90::new(&data, PANEL_DIMS)
+            .map(|p| p > (u8::MAX / 2))
+            .collect();
+        assert_eq!(unpack(&received, &protocol, PANEL_DIMS.0, PANEL_DIMS.1), expected);
+    }
+
+    #[test]
+    fn frames_are_compressed_only_when_that_makes_them_smaller() {
+        let protocol = protocol::Protocol {
+            hello: vec![b'%'],
+            compression: Compression::Heatshrink,
+            pause_millis: 0,
+            ..Default::default()
+        };
+        static LOG: OnceLock<DecompressingMcu> = OnceLock::new();
+        let mut mcu = HelmetMcu::builder()
+            .protocol(protocol.clone())
+            .build_with(|_| {
+                let mcu = LOG.get_or_init(DecompressingMcu::default).clone();
+                Ok((Box::new(mcu.clone()), Some(Box::new(mcu))))
+            })
+            .unwrap();
+        let written = || std::mem::take(&mut LOG.get().unwrap().0.lock().unwrap().0);
+        assert_eq!(written(), protocol.hello);
+        let config = heatshrink::Config::new(
+            protocol.heatshrink_window,
+            protocol.heatshrink_lookahead,
+        )
+            .unwrap();
+        let size = PANEL_DIMS.0 * PANEL_DIMS.1;
+        // A plain frame shrinks; noise doesn't.
+        let mut seed = 1u32;
+        let noise = (0..size)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        for (data, expect_compressed) in [(vec![0xFF; size], true), (noise, false)] {
+            mcu.send_rotated(data.clone()).unwrap();
+            let sent = written();
+            let (&tag, rest) = sent.strip_prefix(protocol.reset.as_slice())
+                .and_then(|sent| sent.split_first())
+                .unwrap();
+            assert_eq!(tag == 1, expect_compressed);
+            let rows = if expect_compressed {
+                let len = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+                let (compressed, footer) = rest[2..].split_at(len);
+                let mut rows = vec![0; size];
+                let rows = heatshrink::decode(compressed, &mut rows, &config).unwrap().to_vec();
+                [rows.as_slice(), footer].concat()
+            } else {
+                rest.to_vec()
+            };
+            let received = [protocol.reset.as_slice(), &rows].concat();
+            let expected: Vec<bool> = Rot90::new(&data, PANEL_DIMS)
+                .map(|p| p > (u8::MAX / 2))
+                .collect();
+            assert_eq!(unpack(&received, &protocol, PANEL_DIMS.0, PANEL_DIMS.1), expected);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn four_turns_are_identity(((w, h), data) in frame()) {
+            let mut turned = data.clone();
+            let mut dims = (w, h);
+            for _ in 0..4 {
+                turned = Rot90::new(&turned, dims).collect();
+                dims = (dims.1, dims.0);
+            }
+            prop_assert_eq!(turned, data);
+        }
+
+        #[test]
+        fn turning_keeps_every_pixel(((w, h), data) in frame()) {
+            let mut turned: Vec<u8> = Rot90::new(&data, (w, h)).collect();
+            let mut data = data;
+            turned.sort_unstable();
+            data.sort_unstable();
+            prop_assert_eq!(turned, data);
+        }
+
+        #[test]
+        fn sent_frames_unpack_to_the_turned_frame(
+            ((w, h), data) in frame(),
+            msb_first in any::<bool>(),
+            row_header in proptest::collection::vec(any::<u8>(), 0..3),
+            row_footer in proptest::collection::vec(any::<u8>(), 0..3),
+            frame_footer in proptest::collection::vec(any::<u8>(), 0..3),
+            invert in any::<bool>(),
+        ) {
+            let protocol = protocol::Protocol {
+                row_header,
+                row_footer,
+                frame_footer,
+                bit_order: if msb_first {
+                    protocol::BitOrder::MsbFirst
+                } else {
+                    protocol::BitOrder::LsbFirst
+                },
+                pause_millis: 0,
+                ..Default::default()
+            };
+            let mut mcu = HelmetMcu::builder()
+                .dims((w, h))
+                .invert(invert)
+                .protocol(protocol.clone())
+                .build_with(|_| Ok((Box::new(Cursor::new(Vec::new())), None)))
+                .unwrap();
+            mcu.send_rotated(data.clone()).unwrap();
+            let sent = mcu.serial.unwrap().into_inner();
+            let expected: Vec<bool> = Rot90::new(&data, (w, h))
+                .map(|p| (p > (u8::MAX / 2)) ^ invert)
+                .collect();
+            prop_assert_eq!(unpack(&sent, &protocol, w, h), expected);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+// Where `calibrate` writes when neither it nor the config names a file.
+pub const DEFAULT_PATH: &str = "mask.txt";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaskConfig {
+    // Stuck pixels of this particular panel, as written by `calibrate`.
+    pub path: Option<PathBuf>,
+}
+
+// Pixels that show the same thing whatever they're sent, keyed by (x, y) in
+// frame coordinates, with the value they're stuck at. The file has one
+// pixel per line:
+//
+//   # x y state
+//   12 40 off
+//   3 7 on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mask {
+    pub stuck: BTreeMap<(usize, usize), bool>,
+}
+
+static MASK: OnceLock<Mask> = OnceLock::new();
+
+impl Mask {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut stuck = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [x, y, state] = fields[..] else {
+                bail!("line {}: expected `x y on|off`", i + 1);
+            };
+            let on = match state {
+                "on" => true,
+                "off" => false,
+                _ => bail!("line {}: state must be on or off, not {state:?}", i + 1),
+            };
+            let at = (x.parse().context("bad x")?, y.parse().context("bad y")?);
+            stuck.insert(at, on);
+        }
+        Ok(Self { stuck })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid mask {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut text = String::from("# x y state\n");
+        for (&(x, y), &on) in &self.stuck {
+            text.push_str(&format!("{x} {y} {}\n", if on { "on" } else { "off" }));
+        }
+        Ok(fs::write(path, text)?)
+    }
+
+    // What pixel `i` of a `width`-wide frame is stuck at, if anything.
+    pub fn at(&self, i: usize, width: usize) -> Option<bool> {
+        self.stuck.get(&(i % width, i / width)).copied()
+    }
+
+    // Overwrites the stuck pixels of `frame` with what they'll really show.
+    pub fn apply(&self, frame: &mut [u8], width: usize) {
+        for (&(x, y), &on) in &self.stuck {
+            if x < width {
+                if let Some(p) = frame.get_mut((y * width) + x) {
+                    *p = if on { 0xFF } else { 0 };
+                }
+            }
+        }
+    }
+}
+
+// Loads the mask for the rest of the run, so every frame and every dither
+// takes it into account.
+pub fn init(config: &MaskConfig) -> Result<()> {
+    let Some(path) = &config.path else {
+        return Ok(());
+    };
+    let mask = Mask::load(path)?;
+    println!("[mask] {} stuck pixels.", mask.stuck.len());
+    let _ = MASK.set(mask);
+    Ok(())
+}
+
+pub fn get() -> Option<&'static Mask> {
+    MASK.get()
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    f64::consts::PI,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::{
+    geo::LatLon,
+    picture::{self, Format, Rgb},
+};
+
+// Raster MBTiles are laid out in web map tiles of this many pixels. Other
+// sizes are sampled down or up to it.
+const TILE: f64 = 256.0;
+
+// Web mercator stops short of the poles.
+const MAX_LAT: f64 = 85.051_128;
+
+// Where the file has no tile: the standard OSM style's land colour, so gaps
+// read as empty.
+const BLANK: Rgb = [0xF2, 0xEF, 0xE9];
+
+struct Decoded {
+    rgb: Vec<Rgb>,
+    size: usize,
+}
+
+// Renders `dims` pixels of map around `at` from an MBTiles file of PNG or
+// JPEG tiles, at the tiles' own scale. Deeper than the file goes, its
+// deepest tiles are blown up.
+pub fn render(path: &Path, at: LatLon, zoom: u8, dims: (usize, usize)) -> Result<Vec<Rgb>> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let max_zoom: Option<u8> =
+        db.query_row("SELECT MAX(zoom_level) FROM tiles", [], |row| row.get(0))?;
+    let Some(max_zoom) = max_zoom else {
+        bail!("{} has no tiles", path.display());
+    };
+    let source = zoom.min(max_zoom);
+    let blow_up = (1u32 << (zoom - source)) as f64;
+    let per_side = 1i64 << source;
+    let (cx, cy) = world_pixel(at, zoom);
+    let (w, h) = dims;
+    let mut tiles: HashMap<(i64, i64), Option<Decoded>> = HashMap::new();
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let wx = (cx + x as f64 - (w / 2) as f64) / blow_up;
+            let wy = (cy + y as f64 - (h / 2) as f64) / blow_up;
+            let (tx, ty) = ((wx / TILE).floor() as i64, (wy / TILE).floor() as i64);
+            if !(0..per_side).contains(&ty) {
+                out.push(BLANK);
+                continue;
+            }
+            // Round the back of the globe.
+            let tx = tx.rem_euclid(per_side);
+            let tile = match tiles.entry((tx, ty)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    fetch(&db, source, tx, ty, per_side)
+                        .with_context(|| format!("tile {source}/{tx}/{ty}"))?,
+                ),
+            };
+            let Some(tile) = tile else {
+                out.push(BLANK);
+                continue;
+            };
+            let scale = tile.size as f64 / TILE;
+            let px = ((wx.rem_euclid(TILE) * scale) as usize).min(tile.size - 1);
+            let py = ((wy.rem_euclid(TILE) * scale) as usize).min(tile.size - 1);
+            out.push(tile.rgb[(py * tile.size) + px]);
+        }
+    }
+    let missing = tiles.values().filter(|tile| tile.is_none()).count();
+    if missing > 0 {
+        println!("[mbtiles] {missing} tiles around {at} aren't in {}.", path.display());
+    }
+    Ok(out)
+}
+
+fn fetch(db: &Connection, zoom: u8, x: i64, y: i64, per_side: i64) -> Result<Option<Decoded>> {
+    // MBTiles count rows from the bottom.
+    let row = per_side - 1 - y;
+    let data: Option<Vec<u8>> = db
+        .query_row(
+            concat!(
+                "SELECT tile_data FROM tiles",
+                " WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            ),
+            (zoom, x, row),
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(data) = data else {
+        return Ok(None);
+    };
+    let format = match data.get(..4) {
+        Some([0x89, b'P', b'N', b'G']) => Format::Png,
+        Some([0xFF, 0xD8, ..]) => Format::Jpeg,
+        _ => bail!("only PNG and JPEG tiles can be drawn, not vector or WebP ones"),
+    };
+    let (rgb, (tw, th)) = picture::decode_rgb(&data, format, BLANK)?;
+    if tw != th || tw == 0 {
+        bail!("tile isn't square ({tw}x{th})");
+    }
+    Ok(Some(Decoded { rgb, size: tw }))
+}
+
+// Position in the whole web mercator map at `zoom`, in pixels from its
+// top-left corner.
+fn world_pixel(at: LatLon, zoom: u8) -> (f64, f64) {
+    let size = TILE * (1u64 << zoom) as f64;
+    let lat = at.lat.clamp(-MAX_LAT, MAX_LAT).to_radians();
+    let x = (at.lon + 180.0) / 360.0 * size;
+    let y = (1.0 - (lat.tan() + (1.0 / lat.cos())).ln() / PI) / 2.0 * size;
+    (x, y)
+}
@@ -0,0 +1,59 @@
+use std::fs;
+
+use anyhow::{bail, Result};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::Deserialize;
+
+const SERVICE_TYPE: &str = "_fetthelmet._tcp.local.";
+
+// Which HTTP API the server speaks, as under /api/<version>.
+const API_VERSION: &str = "v1";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MdnsConfig {
+    pub enabled: bool,
+    // What companion apps list the helmet as. Defaults to the hostname.
+    pub name: Option<String>,
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self { enabled: true, name: None }
+    }
+}
+
+impl MdnsConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.as_ref().is_some_and(|name| name.is_empty() || name.len() > 63) {
+            bail!("mdns.name: must be 1 to 63 bytes");
+        }
+        Ok(())
+    }
+}
+
+// Advertises the control server on `port` until the returned daemon is shut
+// down, answering for every address the Pi has as they come and go.
+pub fn advertise(config: &MdnsConfig, port: u16) -> Result<Option<ServiceDaemon>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_owned())
+        .unwrap_or_else(|_| "fett-helmet".to_owned());
+    let name = config.name.clone().unwrap_or_else(|| hostname.clone());
+    let properties = [("name", name.as_str()), ("api", API_VERSION)];
+    let info = ServiceInfo::new(
+        SERVICE_TYPE,
+        &name,
+        &format!("{hostname}.local."),
+        (),
+        port,
+        &properties[..],
+    )?
+        .enable_addr_auto();
+    let daemon = ServiceDaemon::new()?;
+    daemon.register(info)?;
+    println!("[mdns] Advertising the server as {name:?}...");
+    Ok(Some(daemon))
+}
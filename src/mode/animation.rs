@@ -0,0 +1,177 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{bounded, Receiver};
+
+use super::{Mode, Panel};
+use crate::{rawframe::{self, Record}, read_png_g, PANEL_DIMS};
+
+pub const NAME: &str = "animation";
+
+const FRAME_MILLIS: u64 = 500;
+
+const FRAME_DIR: &str = "BadApple64x64";
+
+// What `convert` makes of `FRAME_DIR`, played instead of the PNGs when it's
+// there.
+const CACHE: &str = "BadApple64x64.frames";
+
+// Frames decoded ahead of the one showing, so the serial link never waits on
+// PNG decoding. Turning and packing them stays with the panel, after the
+// overlays are drawn.
+const PREFETCH: usize = 3;
+
+type Decoded = (u64, Result<Vec<u8>>);
+
+fn frame_path(frame: u64) -> String {
+    format!("{FRAME_DIR}/frame_{frame:03?}.png")
+}
+
+// Decodes frames in order until one is missing or the mode stops listening.
+fn spawn_decoder() -> Receiver<Decoded> {
+    let (tx, rx) = bounded(PREFETCH);
+    spawn(move || {
+        for frame in 1.. {
+            let filename = frame_path(frame);
+            if !Path::new(&filename).exists() {
+                return;
+            }
+            if tx.send((frame, read_png_g(filename))).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+// Packs every PNG in `dir`, in name order, into one raw frame file that
+// shows each for `1 / fps` seconds. Returns how many frames went in.
+pub fn convert(dir: &Path, output: &Path, fps: f64) -> Result<usize> {
+    if !fps.is_finite() || fps <= 0.0 {
+        bail!("fps must be positive");
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|e| e == "png"));
+    paths.sort();
+    if paths.is_empty() {
+        bail!("no PNGs in {}", dir.display());
+    }
+    // Written aside and renamed, so a player never sees half a file.
+    let partial = output.with_extension("frames.partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    rawframe::write_timing(&mut out, Duration::from_secs_f64(1.0 / fps))?;
+    for path in &paths {
+        let frame = read_png_g(path).with_context(|| format!("{}", path.display()))?;
+        rawframe::write_1bit(&mut out, &frame, PANEL_DIMS)?;
+    }
+    out.flush()?;
+    drop(out);
+    fs::rename(&partial, output)?;
+    Ok(paths.len())
+}
+
+enum Source {
+    Pngs(Receiver<Decoded>),
+    // Preconverted by `convert`, with the number of frames read so far.
+    Cache(BufReader<File>, u64),
+}
+
+impl Source {
+    fn open() -> Result<Self> {
+        if Path::new(CACHE).exists() {
+            println!("[animation] Playing {CACHE}...");
+            return Ok(Source::Cache(BufReader::new(File::open(CACHE)?), 0));
+        }
+        Ok(Source::Pngs(spawn_decoder()))
+    }
+}
+
+pub struct AnimationMode {
+    start: Instant,
+    last_frame_sent: u64,
+    frame_time: Duration,
+    source: Option<Source>,
+}
+
+impl Default for AnimationMode {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            last_frame_sent: 0,
+            frame_time: Duration::from_millis(FRAME_MILLIS),
+            source: None,
+        }
+    }
+}
+
+impl AnimationMode {
+    // Frame number `frame`, counting from 1, or `None` past the end. Frames
+    // that fell behind are skipped.
+    fn next(&mut self, frame: u64, dims: (usize, usize)) -> Result<Option<Vec<u8>>> {
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => self.source.insert(Source::open()?),
+        };
+        match source {
+            Source::Pngs(frames) => loop {
+                match frames.recv() {
+                    Ok((n, _)) if n < frame => continue,
+                    Ok((_, data)) => return data.map(Some),
+                    Err(_) => return Ok(None),
+                }
+            },
+            Source::Cache(input, read) => loop {
+                match rawframe::read_record(input, dims)? {
+                    Some(Record::Timing(time)) => self.frame_time = time,
+                    Some(Record::Frame(data)) => {
+                        *read += 1;
+                        if *read >= frame {
+                            return Ok(Some(data));
+                        }
+                    }
+                    None => return Ok(None),
+                }
+            },
+        }
+    }
+}
+
+impl Mode for AnimationMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        *self = Self::default();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let frame = (self.start.elapsed().as_nanos() / self.frame_time.as_nanos()) as u64 + 1;
+        if frame != self.last_frame_sent {
+            let Some(data) = self.next(frame, panel.dims())? else {
+                println!("[animation] Animation finished.");
+                self.source = None;
+                return Ok(None);
+            };
+            if let Some(Source::Pngs(_)) = self.source {
+                println!("{:?}", frame_path(frame));
+            }
+            panel.show(data)?;
+            self.last_frame_sent = frame;
+        }
+        let next = self.frame_time * frame as u32;
+        Ok(Some(next.saturating_sub(self.start.elapsed())))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        // Lets the decoder finish.
+        self.source = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    thread::spawn,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::framebuffer::Framebuffer;
+
+pub const NAME: &str = "audio";
+
+// Samples per FFT window, and how many new samples arrive between frames.
+const WINDOW: usize = 1024;
+const HOP: usize = 512;
+
+// Lowest frequency given its own bar; bars are spaced logarithmically from
+// here up to Nyquist.
+const MIN_FREQ: f32 = 40.0;
+
+// Range shown on the bar display, in dB below the loudest recent peak.
+const RANGE_DB: f32 = 48.0;
+
+// How quickly the reference peak falls after something loud, per frame.
+const PEAK_DECAY_DB: f32 = 0.25;
+
+// Longest a tick waits for a frame before letting the manager see to
+// updates, as with the camera.
+const FRAME_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    #[default]
+    Bars,
+    Waveform,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    // ALSA capture device, as passed to `arecord -D`.
+    pub device: String,
+    pub sample_rate: u32,
+    pub style: Style,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            device: "default".to_owned(),
+            sample_rate: 22050,
+            style: Style::default(),
+        }
+    }
+}
+
+pub struct AudioMode {
+    config: AudioConfig,
+    child: Option<Child>,
+    frames: Option<Receiver<Vec<u8>>>,
+}
+
+impl AudioMode {
+    pub fn new(config: AudioConfig) -> Self {
+        Self { config, child: None, frames: None }
+    }
+}
+
+// Draws frames as fast as audio comes in, independently of the panel. Each
+// window overlaps the previous one by `WINDOW - HOP` samples.
+struct Renderer {
+    dims: (usize, usize),
+    style: Style,
+    sample_rate: f32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    peak_db: f32,
+}
+
+impl Renderer {
+    fn render(&mut self, samples: &[f32]) -> Vec<u8> {
+        let mut fb = Framebuffer::new(self.dims);
+        match self.style {
+            Style::Bars => self.bars(&mut fb, samples),
+            Style::Waveform => waveform(&mut fb, samples),
+        }
+        fb.into_pixels()
+    }
+
+    fn bars(&mut self, fb: &mut Framebuffer, samples: &[f32]) {
+        let (w, h) = self.dims;
+        let mut buf: Vec<Complex<f32>> = samples.iter()
+            .zip(&self.window)
+            .map(|(s, win)| Complex::new(s * win, 0.0))
+            .collect();
+        self.fft.process(&mut buf);
+        let spectrum: Vec<f32> = buf[..(WINDOW / 2)].iter().map(|c| c.norm()).collect();
+
+        let bin_hz = self.sample_rate / WINDOW as f32;
+        let max_freq = self.sample_rate / 2.0;
+        let bar_db: Vec<f32> = (0..w)
+            .map(|x| {
+                let f0 = MIN_FREQ * (max_freq / MIN_FREQ).powf(x as f32 / w as f32);
+                let f1 = MIN_FREQ * (max_freq / MIN_FREQ).powf((x + 1) as f32 / w as f32);
+                let b0 = ((f0 / bin_hz) as usize).min(spectrum.len() - 1);
+                let b1 = ((f1 / bin_hz) as usize).clamp(b0 + 1, spectrum.len());
+                let peak = spectrum[b0..b1].iter().cloned().fold(0.0, f32::max);
+                20.0 * peak.max(1e-6).log10()
+            })
+            .collect();
+
+        // Scale against a slowly falling peak, so quiet passages still show
+        // something without loud ones clipping for long.
+        let loudest = bar_db.iter().cloned().fold(f32::MIN, f32::max);
+        self.peak_db = loudest.max(self.peak_db - PEAK_DECAY_DB);
+        for (x, db) in bar_db.into_iter().enumerate() {
+            let level = ((db - (self.peak_db - RANGE_DB)) / RANGE_DB).clamp(0.0, 1.0);
+            let height = (level * h as f32) as isize;
+            if height > 0 {
+                fb.fill_rect(x as isize, h as isize - height, 1, height, true);
+            }
+        }
+    }
+}
+
+fn waveform(fb: &mut Framebuffer, samples: &[f32]) {
+    let (w, h) = fb.dims;
+    let mid = (h as f32 - 1.0) / 2.0;
+    let points: Vec<(isize, isize)> = (0..w)
+        .map(|x| {
+            let s = samples[x * samples.len() / w].clamp(-1.0, 1.0);
+            (x as isize, (mid - (s * mid)).round() as isize)
+        })
+        .collect();
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        fb.line(x0, y0, x1, y1, true);
+    }
+}
+
+impl Mode for AudioMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let mut cmd = Command::new("arecord");
+        cmd.args(["-q", "-D", &self.config.device])
+            .args(["-f", "S16_LE", "-c", "1", "-t", "raw"])
+            .args(["-r", &self.config.sample_rate.to_string()])
+            .stdout(Stdio::piped());
+        println!("[audio] Capturing from {}...", self.config.device);
+        let mut child = cmd.spawn()?;
+        let mut stdout = child.stdout.take()
+            .ok_or_else(|| anyhow!("arecord stdout not captured"))?;
+        let mut renderer = Renderer {
+            dims: panel.dims(),
+            style: self.config.style,
+            sample_rate: self.config.sample_rate as f32,
+            fft: FftPlanner::new().plan_fft_forward(WINDOW),
+            // Hann window, to keep leakage from smearing every bar together.
+            window: (0..WINDOW)
+                .map(|i| {
+                    let phase = std::f32::consts::TAU * i as f32 / (WINDOW - 1) as f32;
+                    0.5 - (0.5 * phase.cos())
+                })
+                .collect(),
+            peak_db: f32::MIN,
+        };
+        let (tx, rx) = unbounded();
+        spawn(move || -> Result<()> {
+            let mut samples = vec![0f32; WINDOW];
+            let mut raw = vec![0u8; HOP * 2];
+            loop {
+                stdout.read_exact(&mut raw)?;
+                samples.drain(..HOP);
+                samples.extend(raw.chunks_exact(2).map(|b| {
+                    i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32
+                }));
+                if tx.send(renderer.render(&samples)).is_err() {
+                    return Ok(());
+                }
+            }
+        });
+        self.child = Some(child);
+        self.frames = Some(rx);
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(frames) = &self.frames else {
+            return Ok(None);
+        };
+        let mut frame = match frames.recv_timeout(FRAME_WAIT) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(Duration::ZERO)),
+            // The last frame stays up, with nothing more to tick for.
+            Err(RecvTimeoutError::Disconnected) => {
+                self.frames = None;
+                match self.child.take().and_then(|mut child| child.wait().ok()) {
+                    Some(status) if !status.success() => {
+                        println!("[audio] Capture ended: arecord {status}.");
+                    }
+                    _ => println!("[audio] Capture ended."),
+                }
+                return Ok(None);
+            }
+        };
+        // Frames keep coming while one is on the wire; a slow link just means
+        // skipping to the newest.
+        while let Ok(newer) = frames.try_recv() {
+            frame = newer;
+        }
+        panel.show(frame)?;
+        Ok(Some(Duration::ZERO))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.frames = None;
+        if let Some(mut child) = self.child.take() {
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use base64::Engine;
+use chrono::{
+    DateTime,
+    Datelike,
+    Days,
+    Local,
+    Months,
+    NaiveDate,
+    NaiveDateTime,
+    TimeDelta,
+    TimeZone,
+    Utc,
+    Weekday,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "calendar";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How soon to try again after a failed fetch.
+const RETRY: Duration = Duration::from_secs(60);
+
+// Time per pixel of scrolling for titles too long to fit.
+const SCROLL_STEP: Duration = Duration::from_millis(150);
+
+// How far ahead recurring events are worked out, and the most occurrences
+// looked at per event getting there.
+const HORIZON_DAYS: u64 = 60;
+const MAX_OCCURRENCES: usize = 10_000;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    // An iCalendar (.ics) feed, like the secret address calendar apps give
+    // out, or a CalDAV calendar's export URL.
+    pub url: Option<String>,
+    // HTTP basic auth, for CalDAV servers that want it.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub refresh_minutes: u64,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        Self { url: None, username: None, password: None, refresh_minutes: 15 }
+    }
+}
+
+impl CalendarConfig {
+    pub fn validate(&self) -> Result<()> {
+        let url = self.url.as_deref().unwrap_or("https://");
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            bail!("mode.calendar.url: must be an http or https URL");
+        }
+        if self.password.is_some() && self.username.is_none() {
+            bail!("mode.calendar.username: needed with a password");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Event {
+    title: String,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    all_day: bool,
+}
+
+// A DTSTART, DTEND or EXDATE, as written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum When {
+    Date(NaiveDate),
+    Utc(NaiveDateTime),
+    // With a TZID or none, either of which is taken as the Pi's own zone.
+    Floating(NaiveDateTime),
+}
+
+impl When {
+    fn parse(value: &str) -> Option<Self> {
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+            return Some(When::Date(date));
+        }
+        let (value, utc) = match value.strip_suffix('Z') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+        Some(if utc { When::Utc(time) } else { When::Floating(time) })
+    }
+
+    fn naive(self) -> NaiveDateTime {
+        match self {
+            When::Date(date) => date.and_time(Default::default()),
+            When::Utc(time) | When::Floating(time) => time,
+        }
+    }
+
+    // The same kind of time, at `time`.
+    fn with(self, time: NaiveDateTime) -> Self {
+        match self {
+            When::Date(_) => When::Date(time.date()),
+            When::Utc(_) => When::Utc(time),
+            When::Floating(_) => When::Floating(time),
+        }
+    }
+
+    fn local(self) -> Option<DateTime<Local>> {
+        match self {
+            When::Utc(time) => Some(Utc.from_utc_datetime(&time).with_timezone(&Local)),
+            other => Local.from_local_datetime(&other.naive()).earliest(),
+        }
+    }
+}
+
+// A VEVENT's properties, before any recurrence is worked out.
+#[derive(Default)]
+struct Entry {
+    summary: String,
+    start: Option<When>,
+    end: Option<When>,
+    duration: Option<TimeDelta>,
+    rrule: Option<String>,
+    exdates: HashSet<NaiveDateTime>,
+    cancelled: bool,
+}
+
+// Lines with those folded onto the next joined back up.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => out.push(' '),
+                Some(c) => out.push(c),
+                None => {}
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// RFC 5545 durations like "PT1H30M" or "P1D". Negative ones aren't any use
+// for an event, so they aren't understood.
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let value = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total += match (unit, in_time) {
+                    ('W', false) => TimeDelta::weeks(n),
+                    ('D', false) => TimeDelta::days(n),
+                    ('H', true) => TimeDelta::hours(n),
+                    ('M', true) => TimeDelta::minutes(n),
+                    ('S', true) => TimeDelta::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    number.is_empty().then_some(total)
+}
+
+fn parse_entries(ics: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut entry: Option<Entry> = None;
+    for line in unfold(ics) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        match (name.to_ascii_uppercase().as_str(), &mut entry) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                entry = Some(Entry::default());
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                entries.extend(entry.take());
+            }
+            ("SUMMARY", Some(entry)) => entry.summary = unescape(value),
+            ("DTSTART", Some(entry)) => entry.start = When::parse(value),
+            ("DTEND", Some(entry)) => entry.end = When::parse(value),
+            ("DURATION", Some(entry)) => entry.duration = parse_duration(value),
+            ("RRULE", Some(entry)) => entry.rrule = Some(value.to_ascii_uppercase()),
+            ("EXDATE", Some(entry)) => {
+                let date_only = params.to_ascii_uppercase().contains("VALUE=DATE");
+                for exdate in value.split(',').filter_map(When::parse) {
+                    let exdate = if date_only { When::Date(exdate.naive().date()) } else { exdate };
+                    entry.exdates.insert(exdate.naive());
+                }
+            }
+            ("STATUS", Some(entry)) => entry.cancelled = value.eq_ignore_ascii_case("CANCELLED"),
+            _ => {}
+        }
+    }
+    entries
+}
+
+#[derive(Clone, Copy)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+// Start times of an event's occurrences, in order, as far as `until`. Only
+// FREQ, INTERVAL, COUNT, UNTIL and a weekly BYDAY are followed; anything
+// else in the rule is ignored.
+fn occurrences(
+    start: NaiveDateTime,
+    rrule: Option<&str>,
+    until: NaiveDateTime,
+) -> Vec<NaiveDateTime> {
+    let Some(rrule) = rrule else {
+        return vec![start];
+    };
+    let mut freq = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut last = until;
+    let mut by_day = Vec::new();
+    for part in rrule.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        match key {
+            "FREQ" => {
+                freq = match value {
+                    "DAILY" => Some(Freq::Daily),
+                    "WEEKLY" => Some(Freq::Weekly),
+                    "MONTHLY" => Some(Freq::Monthly),
+                    "YEARLY" => Some(Freq::Yearly),
+                    _ => None,
+                }
+            }
+            "INTERVAL" => interval = value.parse::<u32>().unwrap_or(1).max(1),
+            "COUNT" => count = value.parse::<usize>().ok(),
+            "UNTIL" => {
+                if let Some(when) = When::parse(value) {
+                    // Inclusive, and a date means all of that day.
+                    let end = match when {
+                        When::Date(date) => date.and_hms_opt(23, 59, 59).unwrap(),
+                        when => when.naive(),
+                    };
+                    last = last.min(end);
+                }
+            }
+            "BYDAY" => {
+                by_day = value.split(',')
+                    .filter_map(|day| day.get(day.len().saturating_sub(2)..)?.parse().ok())
+                    .collect::<Vec<Weekday>>();
+            }
+            _ => {}
+        }
+    }
+    let Some(freq) = freq else {
+        return vec![start];
+    };
+    let count = count.unwrap_or(MAX_OCCURRENCES).min(MAX_OCCURRENCES);
+    let mut out = Vec::new();
+    match freq {
+        // Day by day through every `interval`-th week from the start's.
+        Freq::Weekly if !by_day.is_empty() => {
+            let monday = start.weekday().num_days_from_monday();
+            let week_start = start.date() - Days::new(monday.into());
+            let mut day = start;
+            for _ in 0..MAX_OCCURRENCES * 7 {
+                if day > last || out.len() >= count {
+                    break;
+                }
+                let week = (day.date() - week_start).num_days() / 7;
+                if week % interval as i64 == 0 && by_day.contains(&day.weekday()) {
+                    out.push(day);
+                }
+                day += TimeDelta::days(1);
+            }
+        }
+        _ => {
+            for n in 0..count as u32 {
+                let step = n * interval;
+                let next = match freq {
+                    Freq::Daily => start.checked_add_days(Days::new(step.into())),
+                    Freq::Weekly => start.checked_add_days(Days::new(7 * step as u64)),
+                    Freq::Monthly => start.checked_add_months(Months::new(step)),
+                    Freq::Yearly => start.checked_add_months(Months::new(12 * step)),
+                };
+                match next {
+                    Some(next) if next <= last => out.push(next),
+                    _ => break,
+                }
+            }
+        }
+    }
+    out
+}
+
+// Events from `ics` that haven't ended by `now`, up to `HORIZON_DAYS`
+// ahead, soonest first.
+fn upcoming(ics: &str, now: DateTime<Local>) -> Vec<Event> {
+    let horizon = (now + Days::new(HORIZON_DAYS)).naive_local();
+    let mut events = Vec::new();
+    for entry in parse_entries(ics) {
+        let Some(start) = entry.start.filter(|_| !entry.cancelled) else {
+            continue;
+        };
+        let all_day = matches!(start, When::Date(_));
+        let length = match (entry.end, entry.duration) {
+            (Some(end), _) => end.naive() - start.naive(),
+            (None, Some(duration)) => duration,
+            (None, None) if all_day => TimeDelta::days(1),
+            (None, None) => TimeDelta::zero(),
+        };
+        let title = if entry.summary.is_empty() { "(untitled)".to_owned() } else { entry.summary };
+        for at in occurrences(start.naive(), entry.rrule.as_deref(), horizon) {
+            if entry.exdates.contains(&at) {
+                continue;
+            }
+            let (Some(start), Some(end)) = (start.with(at).local(), start.with(at + length).local())
+            else {
+                continue;
+            };
+            if end > now || (end == start && start >= now) {
+                events.push(Event { title: title.clone(), start, end, all_day });
+            }
+        }
+    }
+    events.sort_by_key(|event| event.start);
+    events
+}
+
+fn fetch(config: &CalendarConfig, url: &str) -> Result<String> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .new_agent();
+    let mut request = agent.get(url);
+    if let Some(username) = &config.username {
+        let password = config.password.as_deref().unwrap_or_default();
+        let credentials = base64::engine::general_purpose::STANDARD
+            .encode(format!("{username}:{password}"));
+        request = request.header("Authorization", format!("Basic {credentials}"));
+    }
+    let body = request.call()?.body_mut().read_to_string()?;
+    if !body.trim_start().starts_with("BEGIN:VCALENDAR") {
+        bail!("not an iCalendar feed");
+    }
+    Ok(body)
+}
+
+// "in 2d 3h", "in 1h 20m", "in 5m" or "now".
+fn time_until(from: DateTime<Local>, to: DateTime<Local>) -> String {
+    let minutes = (to - from).num_minutes();
+    match (minutes / (24 * 60), minutes / 60 % 24, minutes % 60) {
+        _ if minutes <= 0 => "now".to_owned(),
+        (0, 0, m) => format!("in {m}m"),
+        (0, h, m) => format!("in {h}h {m}m"),
+        (d, h, _) => format!("in {d}d {h}h"),
+    }
+}
+
+// When an event starts, short enough for one line: the time alone for
+// today, the weekday within a week, and just the date further out.
+fn starts(event: &Event, now: DateTime<Local>) -> String {
+    let days = (event.start.date_naive() - now.date_naive()).num_days();
+    let day = match days {
+        ..=0 if event.all_day => "Today".to_owned(),
+        ..=0 => String::new(),
+        1 => "Tmrw".to_owned(),
+        2..=6 => event.start.format("%a").to_string(),
+        _ => return event.start.format("%-d %b").to_string(),
+    };
+    match event.all_day {
+        true => day,
+        false => format!("{day} {}", event.start.format("%H:%M")).trim().to_owned(),
+    }
+}
+
+// The next event's start and title, with how long until it, and whether the
+// title needs scrolling. A mark in the corner means the feed couldn't be
+// fetched last time, so this may be out of date.
+fn draw(
+    event: &Event,
+    now: DateTime<Local>,
+    stale: bool,
+    scroll: usize,
+    dims: (usize, usize),
+) -> (Vec<u8>, bool) {
+    let mut fb = Framebuffer::new(dims);
+    let row = text::CELL_H as isize * 2;
+    text::draw_text(&mut fb, 0, 0, &starts(event, now));
+    let fits = text::scrolling_line(&mut fb, row, &event.title, scroll);
+    let until = if event.start <= now {
+        format!("{}m left", (event.end - now).num_minutes().max(1))
+    } else {
+        time_until(now, event.start)
+    };
+    text::draw_text(&mut fb, 0, row * 2, &until);
+    if stale {
+        let (x, y) = (dims.0 - text::GLYPH_W, dims.1 - text::GLYPH_H);
+        text::draw_char(&mut fb, x as isize, y as isize, '!');
+    }
+    (fb.into_pixels(), !fits)
+}
+
+pub struct CalendarMode {
+    config: CalendarConfig,
+    events: Vec<Event>,
+    // Whether the last fetch failed, leaving older events showing.
+    stale: bool,
+    next_fetch: Option<Instant>,
+    // The event on the panel, the minute it was drawn and whether it was
+    // stale then, so nothing is sent until one of those changes.
+    shown: Option<(Event, String, bool)>,
+    scroll: usize,
+}
+
+impl CalendarMode {
+    pub fn new(config: CalendarConfig) -> Self {
+        Self {
+            config,
+            events: Vec::new(),
+            stale: false,
+            next_fetch: None,
+            shown: None,
+            scroll: 0,
+        }
+    }
+
+    fn refresh(&mut self, url: &str) {
+        println!("[calendar] Fetching events...");
+        match fetch(&self.config, url) {
+            Ok(ics) => {
+                self.events = upcoming(&ics, Local::now());
+                self.stale = false;
+                println!("[calendar] {} events coming up.", self.events.len());
+                let refresh = Duration::from_secs(self.config.refresh_minutes.max(1) * 60);
+                self.next_fetch = Some(Instant::now() + refresh);
+            }
+            Err(e) => {
+                // What was fetched before is still better than nothing.
+                println!("[calendar] Fetch failed: {e:#}");
+                self.stale = true;
+                self.next_fetch = Some(Instant::now() + RETRY);
+            }
+        }
+    }
+}
+
+impl Mode for CalendarMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.next_fetch = None;
+        self.shown = None;
+        self.scroll = 0;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(url) = self.config.url.clone() else {
+            panel.show(text::render("No\ncalendar", panel.dims()))?;
+            return Ok(None);
+        };
+        if self.next_fetch.is_none_or(|at| Instant::now() >= at) {
+            self.refresh(&url);
+        }
+        let now = Local::now();
+        self.events.retain(|event| event.end > now || event.start >= now);
+        let Some(event) = self.events.first() else {
+            let message = if self.stale { "No events\n(offline)" } else { "No events" };
+            panel.show(text::render(message, panel.dims()))?;
+            self.shown = None;
+            return Ok(Some(Duration::from_secs(30)));
+        };
+        if self.shown.as_ref().is_none_or(|(shown, ..)| shown != event) {
+            self.scroll = 0;
+        }
+        let (frame, scrolling) = draw(event, now, self.stale, self.scroll, panel.dims());
+        let shown = (event.clone(), now.format("%H:%M").to_string(), self.stale);
+        if scrolling || self.shown.as_ref() != Some(&shown) {
+            panel.show(frame)?;
+        }
+        self.shown = Some(shown);
+        if scrolling {
+            self.scroll += 1;
+            Ok(Some(SCROLL_STEP))
+        } else {
+            Ok(Some(Duration::from_secs(1)))
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::mask::Mask;
+
+pub const NAME: &str = "calibrate";
+
+const HELP: &str = "enter: next pixel, b: back, g X Y: go to, \
+    off/on: mark stuck off/on, c: clear mark, w: write, q: write and quit";
+
+// Walks a single lit pixel across the panel, stepped from the terminal, so
+// dead and stuck pixels can be spotted and written to a mask file.
+pub struct CalibrateMode {
+    path: PathBuf,
+    mask: Mask,
+    at: usize,
+}
+
+impl CalibrateMode {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let mask = if path.exists() { Mask::load(&path)? } else { Mask::default() };
+        Ok(Self { path, mask, at: 0 })
+    }
+
+    fn save(&self) -> Result<()> {
+        self.mask.save(&self.path)?;
+        let count = self.mask.stuck.len();
+        println!("[calibrate] Wrote {count} stuck pixels to {}.", self.path.display());
+        Ok(())
+    }
+}
+
+impl Mode for CalibrateMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        println!("[calibrate] {HELP}");
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let (w, h) = panel.dims();
+        let mut frame = vec![0; w * h];
+        frame[self.at] = 0xFF;
+        panel.show(frame)?;
+        let (x, y) = (self.at % w, self.at / w);
+        let marked = match self.mask.stuck.get(&(x, y)) {
+            Some(true) => " (stuck on)",
+            Some(false) => " (stuck off)",
+            None => "",
+        };
+        print!("[calibrate] {x} {y}{marked}> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            self.save()?;
+            return Ok(None);
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            [] => self.at = (self.at + 1) % (w * h),
+            ["b"] => self.at = (self.at + (w * h) - 1) % (w * h),
+            ["g", x, y] => match (x.parse::<usize>(), y.parse::<usize>()) {
+                (Ok(x), Ok(y)) if x < w && y < h => self.at = (y * w) + x,
+                _ => println!("[calibrate] No pixel {x} {y}."),
+            },
+            ["off"] => {
+                self.mask.stuck.insert((x, y), false);
+            }
+            ["on"] => {
+                self.mask.stuck.insert((x, y), true);
+            }
+            ["c"] => {
+                self.mask.stuck.remove(&(x, y));
+            }
+            ["w"] => self.save()?,
+            ["q"] => {
+                self.save()?;
+                return Ok(None);
+            }
+            _ => println!("[calibrate] {HELP}"),
+        }
+        Ok(Some(Duration::ZERO))
+    }
+}
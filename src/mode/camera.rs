@@ -0,0 +1,116 @@
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    thread::spawn,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::filter;
+
+pub const NAME: &str = "camera";
+
+// Capture size requested from the camera. Larger than the panel so the box
+// downscale has something to average over.
+const CAPTURE_DIMS: (usize, usize) = (256, 256);
+
+// Longest a tick waits for a frame before letting the manager see to
+// updates, in case the camera stalls.
+const FRAME_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraOpts {
+    pub program: String,
+    pub hflip: bool,
+}
+
+impl Default for CameraOpts {
+    fn default() -> Self {
+        Self { program: "rpicam-vid".to_owned(), hflip: false }
+    }
+}
+
+pub struct CameraMode {
+    opts: CameraOpts,
+    child: Option<Child>,
+    frames: Option<Receiver<Vec<u8>>>,
+}
+
+impl CameraMode {
+    pub fn new(opts: CameraOpts) -> Self {
+        Self { opts, child: None, frames: None }
+    }
+}
+
+impl Mode for CameraMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        let (w, h) = CAPTURE_DIMS;
+        let mut cmd = Command::new(&self.opts.program);
+        cmd.args(["-t", "0", "-n", "--codec", "yuv420"])
+            .args(["--width", &w.to_string(), "--height", &h.to_string()])
+            .args(["-o", "-"])
+            .stdout(Stdio::piped());
+        if self.opts.hflip {
+            cmd.arg("--hflip");
+        }
+        println!("[camera] Starting {}...", self.opts.program);
+        let mut child = cmd.spawn()?;
+        let mut stdout = child.stdout.take()
+            .ok_or_else(|| anyhow!("camera stdout not captured"))?;
+        let (tx, rx) = unbounded();
+        spawn(move || -> Result<()> {
+            // yuv420: a full-resolution luma plane followed by two
+            // quarter-resolution chroma planes, which we throw away.
+            let mut frame = vec![0u8; w * h * 3 / 2];
+            loop {
+                stdout.read_exact(&mut frame)?;
+                if tx.send(frame[..(w * h)].to_vec()).is_err() {
+                    return Ok(());
+                }
+            }
+        });
+        self.child = Some(child);
+        self.frames = Some(rx);
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(frames) = &self.frames else {
+            return Ok(None);
+        };
+        let mut luma = match frames.recv_timeout(FRAME_WAIT) {
+            Ok(luma) => luma,
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(Duration::ZERO)),
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some(mut child) = self.child.take() {
+                    child.wait()?.exit_ok()?;
+                }
+                return Err(anyhow!("camera stream ended"));
+            }
+        };
+        // Only the newest frame matters; anything that queued up while the
+        // previous one was on the wire is already stale.
+        while let Ok(newer) = frames.try_recv() {
+            luma = newer;
+        }
+        let dims = panel.dims();
+        let small = filter::downscale(&luma, CAPTURE_DIMS, dims);
+        panel.show(filter::dither(&small, dims))?;
+        Ok(Some(Duration::ZERO))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        // Dropping the receiver lets the reader thread exit on its next frame.
+        self.frames = None;
+        if let Some(mut child) = self.child.take() {
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
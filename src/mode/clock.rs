@@ -0,0 +1,98 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+    Timelike,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text, ttf::Font};
+
+pub const NAME: &str = "clock";
+
+// Space between the time and the date under it.
+const GAP: usize = 4;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    // chrono strftime syntax, big and small.
+    pub format: String,
+    pub date_format: String,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self { format: "%H:%M".to_owned(), date_format: "%a %d".to_owned() }
+    }
+}
+
+impl ClockConfig {
+    pub fn validate(&self) -> Result<()> {
+        check_format(&self.format).map_err(|e| e.context("mode.clock.format"))?;
+        check_format(&self.date_format).map_err(|e| e.context("mode.clock.date_format"))
+    }
+}
+
+// chrono only notices a bad format when printing it, by panicking.
+pub fn check_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        bail!("invalid time format {format:?}");
+    }
+    Ok(())
+}
+
+pub struct ClockMode {
+    config: ClockConfig,
+    font: Arc<Font>,
+}
+
+impl ClockMode {
+    pub fn new(config: ClockConfig, font: Arc<Font>) -> Self {
+        Self { config, font }
+    }
+}
+
+impl Mode for ClockMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let now = Local::now();
+        let dims = panel.dims();
+        let time = now.format(&self.config.format).to_string();
+        let date = now.format(&self.config.date_format).to_string();
+        // Big digits for the time, as large as the configured size allows.
+        let size = self.font.fit(&time, dims.0, self.font.size);
+        let (time_w, time_h) = self.font.measure(&time, size);
+        let date_w = (date.len() * text::CELL_W) - 1;
+        let top = dims.1.saturating_sub(time_h + GAP + text::GLYPH_H) / 2;
+        let mut fb = Framebuffer::new(dims);
+        self.font.draw(
+            &mut fb,
+            (dims.0.saturating_sub(time_w) / 2) as isize,
+            top as isize,
+            &time,
+            size,
+        );
+        text::draw_text(
+            &mut fb,
+            (dims.0.saturating_sub(date_w) / 2) as isize,
+            (top + time_h + GAP) as isize,
+            &date,
+        );
+        panel.show(fb.into_pixels())?;
+        // Wake up right after the next minute boundary, or second if the
+        // format shows seconds.
+        let into_second = Duration::from_nanos((now.nanosecond() % 1_000_000_000) as u64);
+        if ["%S", "%T", "%X", "%r"].iter().any(|s| self.config.format.contains(s)) {
+            return Ok(Some(Duration::from_secs(1).saturating_sub(into_second)));
+        }
+        let into_minute = Duration::from_secs(now.second() as u64) + into_second;
+        Ok(Some(Duration::from_secs(60).saturating_sub(into_minute)))
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{text, MCU_BAUD};
+
+pub const NAME: &str = "console";
+
+// How often to check for a client, and how long a read of the MCU's output
+// waits before checking whether the client has gone.
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsoleConfig {
+    // Where `telnet` or `nc` can attach to the MCU's serial port. Anyone who
+    // can reach it can type at the firmware, so only this machine by
+    // default.
+    pub listen: String,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self { listen: "127.0.0.1:2323".into() }
+    }
+}
+
+// Bridges the MCU's serial port to one TCP client at a time, for reading
+// firmware debug output and typing commands. The bridge runs on its own
+// thread, so the manager carries on meanwhile, but frames are held back
+// while a client is attached, since they'd share the port.
+pub struct ConsoleMode {
+    config: ConsoleConfig,
+    listener: Option<TcpListener>,
+    stop: Arc<AtomicBool>,
+    bridge: Option<(SocketAddr, JoinHandle<Result<()>>)>,
+}
+
+impl ConsoleMode {
+    pub fn new(config: ConsoleConfig) -> Self {
+        Self { config, listener: None, stop: Arc::default(), bridge: None }
+    }
+
+    // Waits for the bridge to finish, then takes the port back.
+    fn detach(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let Some((addr, bridge)) = self.bridge.take() else {
+            return Ok(());
+        };
+        match bridge.join().unwrap() {
+            Ok(()) => println!("[console] {addr} detached."),
+            Err(e) => println!("[console] {addr} dropped: {e:#}"),
+        }
+        panel.take_back_port()
+    }
+}
+
+// Copies between `client` and the serial port at `port` until the client
+// disconnects or `stop` is set.
+fn bridge(port: &str, client: TcpStream, stop: &AtomicBool) -> Result<()> {
+    let mut serial = serialport::new(port, MCU_BAUD).timeout(POLL).open()?;
+    let mut to_mcu = serial.try_clone()?;
+    let mut from_client = client.try_clone()?;
+    let input = thread::spawn(move || io::copy(&mut from_client, &mut to_mcu));
+    let mut client = client;
+    let mut buf = [0u8; 256];
+    while !input.is_finished() && !stop.load(Ordering::Relaxed) {
+        let n = match serial.read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => Err(e)?,
+        };
+        if client.write_all(&buf[..n]).is_err() {
+            break;
+        }
+    }
+    // Unblocks the input thread if the client stopped reading but not
+    // writing, or the mode is stopping, so it lets go of its handle on the
+    // port.
+    let _ = client.shutdown(Shutdown::Both);
+    let _ = input.join();
+    Ok(())
+}
+
+impl Mode for ConsoleMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let listener = TcpListener::bind(&self.config.listen)?;
+        listener.set_nonblocking(true)?;
+        println!("[console] Listening on {}...", self.config.listen);
+        self.listener = Some(listener);
+        self.stop.store(false, Ordering::Relaxed);
+        panel.show(text::render("serial\nconsole", panel.dims()))
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        if let Some((_, bridge)) = &self.bridge {
+            if bridge.is_finished() {
+                self.detach(panel)?;
+                panel.show(text::render("serial\nconsole", panel.dims()))?;
+            }
+            return Ok(Some(POLL));
+        }
+        let Some(listener) = &self.listener else {
+            return Ok(None);
+        };
+        let (client, addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Some(POLL)),
+            Err(e) => Err(e)?,
+        };
+        client.set_nonblocking(false)?;
+        println!("[console] {addr} attached.");
+        let port = panel.release_port()?;
+        let stop = self.stop.clone();
+        self.bridge = Some((addr, thread::spawn(move || bridge(&port, client, &stop))));
+        Ok(Some(POLL))
+    }
+
+    fn stop(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        self.listener = None;
+        self.stop.store(true, Ordering::Relaxed);
+        self.detach(panel)
+    }
+}
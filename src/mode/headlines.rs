@@ -0,0 +1,283 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "headlines";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How soon to try again after every feed failed.
+const RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadlinesConfig {
+    // RSS or Atom feed URLs, whose items take turns in the order given.
+    pub feeds: Vec<String>,
+    pub refresh_minutes: u64,
+    // How many of each feed's newest items to show.
+    pub items_per_feed: usize,
+    // Pixels per second.
+    pub scroll_speed: u32,
+}
+
+impl Default for HeadlinesConfig {
+    fn default() -> Self {
+        Self { feeds: Vec::new(), refresh_minutes: 30, items_per_feed: 10, scroll_speed: 20 }
+    }
+}
+
+impl HeadlinesConfig {
+    pub fn validate(&self) -> Result<()> {
+        for feed in &self.feeds {
+            if !(feed.starts_with("http://") || feed.starts_with("https://")) {
+                bail!("mode.headlines.feeds: {feed:?} isn't an http or https URL");
+            }
+        }
+        if self.items_per_feed == 0 {
+            bail!("mode.headlines.items_per_feed: must be at least 1");
+        }
+        if !(1..=200).contains(&self.scroll_speed) {
+            bail!("mode.headlines.scroll_speed: must be from 1 to 200");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Feed {
+    title: String,
+    headlines: Vec<String>,
+}
+
+// Everything between `<tag ...>` and `</tag>`, for each `tag` element in
+// `xml`. Good enough for feeds, not XML in general: namespaced names and
+// nesting of the same tag aren't understood.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}"), format!("</{tag}>"));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(at) = rest.find(&open) {
+        rest = &rest[at + open.len()..];
+        if !rest.starts_with(['>', '/', ' ', '\t', '\r', '\n']) {
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        if rest[..end].ends_with('/') {
+            found.push("");
+            continue;
+        }
+        rest = &rest[end + 1..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                entity => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                    None => char::from_u32(entity.strip_prefix('#')?.parse().ok()?)?,
+                },
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+// An element's content as plain text on one line. Titles are sometimes HTML,
+// escaped or in CDATA, so tags are dropped after decoding too.
+fn plain_text(content: &str) -> String {
+    let mut text = String::new();
+    let mut rest = content;
+    loop {
+        let (before, cdata) = match rest.split_once("<![CDATA[") {
+            Some((before, after)) => (before, Some(after)),
+            None => (rest, None),
+        };
+        text.push_str(&decode_entities(&strip_tags(before)));
+        let Some(after) = cdata else {
+            break;
+        };
+        let (inside, after) = after.split_once("]]>").unwrap_or((after, ""));
+        text.push_str(inside);
+        rest = after;
+    }
+    strip_tags(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// RSS keeps items in `<item>`s and Atom in `<entry>`s; either way the feed's
+// own title comes before the first of them.
+fn parse_feed(xml: &str, limit: usize) -> Result<Feed> {
+    let items = match elements(xml, "item") {
+        items if items.is_empty() => elements(xml, "entry"),
+        items => items,
+    };
+    let head_end = ["<item", "<entry"].iter().filter_map(|tag| xml.find(tag)).min();
+    let head = &xml[..head_end.unwrap_or(xml.len())];
+    if items.is_empty() && !(head.contains("<rss") || head.contains("<feed")) {
+        bail!("not an RSS or Atom feed");
+    }
+    let title = elements(head, "title").first().map(|title| plain_text(title)).unwrap_or_default();
+    let headlines = items.iter()
+        .filter_map(|item| elements(item, "title").first().map(|title| plain_text(title)))
+        .filter(|headline| !headline.is_empty())
+        .take(limit)
+        .collect();
+    Ok(Feed { title, headlines })
+}
+
+fn fetch(url: &str, limit: usize) -> Result<Feed> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .new_agent();
+    let body = agent.get(url).call()?.body_mut().read_to_string()?;
+    parse_feed(&body, limit)
+}
+
+// Where the ticker is: which headline of which feed, scrolled how far.
+#[derive(Clone, Copy, Default)]
+struct Position {
+    feed: usize,
+    headline: usize,
+    offset: usize,
+}
+
+// Headlines from each feed in turn, each scrolling in from the right and all
+// the way off to the left before the next, under the name of its feed.
+pub struct HeadlinesMode {
+    config: HeadlinesConfig,
+    // One per configured feed, kept from the last fetch that worked.
+    feeds: Vec<Feed>,
+    next_fetch: Option<Instant>,
+    at: Position,
+}
+
+impl HeadlinesMode {
+    pub fn new(config: HeadlinesConfig) -> Self {
+        let feeds = vec![Feed::default(); config.feeds.len()];
+        Self { config, feeds, next_fetch: None, at: Position::default() }
+    }
+
+    fn refresh(&mut self) {
+        let mut fetched = 0;
+        for (url, feed) in self.config.feeds.iter().zip(&mut self.feeds) {
+            println!("[headlines] Fetching {url}...");
+            match fetch(url, self.config.items_per_feed) {
+                Ok(new) => {
+                    *feed = new;
+                    fetched += 1;
+                }
+                // The headlines from last time are still worth showing.
+                Err(e) => println!("[headlines] Fetch failed: {e:#}"),
+            }
+        }
+        let wait = if fetched > 0 {
+            Duration::from_secs(self.config.refresh_minutes.max(1) * 60)
+        } else {
+            RETRY
+        };
+        self.next_fetch = Some(Instant::now() + wait);
+    }
+
+    // Moves on from `at` to the first headline there is, wrapping around
+    // and skipping feeds with none. Returns whether there was one.
+    fn settle(&mut self) -> bool {
+        let at = &mut self.at;
+        for _ in 0..=self.feeds.len() {
+            at.feed %= self.feeds.len();
+            if at.headline < self.feeds[at.feed].headlines.len() {
+                return true;
+            }
+            *at = Position { feed: at.feed + 1, ..Position::default() };
+        }
+        false
+    }
+}
+
+impl Mode for HeadlinesMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.next_fetch = None;
+        self.at = Position::default();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let dims = panel.dims();
+        if self.feeds.is_empty() {
+            panel.show(text::render("No feeds", dims))?;
+            return Ok(None);
+        }
+        // Only between headlines, so none stops halfway across.
+        if self.at.offset == 0 && self.next_fetch.is_none_or(|at| Instant::now() >= at) {
+            self.refresh();
+        }
+        if !self.settle() {
+            panel.show(text::render("No\nheadlines", dims))?;
+            return Ok(Some(RETRY));
+        }
+        let feed = &self.feeds[self.at.feed];
+        let headline = &feed.headlines[self.at.headline];
+        let mut fb = Framebuffer::new(dims);
+        text::draw_text(&mut fb, 0, 0, &feed.title);
+        let width = headline.chars().count() * text::CELL_W;
+        let x = dims.0 as isize - self.at.offset as isize;
+        let y = (dims.1.saturating_sub(text::GLYPH_H) / 2) as isize;
+        text::draw_text(&mut fb, x, y, headline);
+        panel.show(fb.into_pixels())?;
+        self.at.offset += 1;
+        if self.at.offset > dims.0 + width {
+            self.at = Position { headline: self.at.headline + 1, offset: 0, ..self.at };
+        }
+        Ok(Some(Duration::from_secs(1) / self.config.scroll_speed))
+    }
+}
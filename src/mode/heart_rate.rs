@@ -0,0 +1,124 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::{
+    framebuffer::Framebuffer,
+    heart_rate::{HeartRate, HeartRateRx},
+    sprite::{self, Sprite},
+    text,
+    ttf::Font,
+};
+
+pub const NAME: &str = "heart_rate";
+
+// Space between the digits and the heart under them.
+const GAP: usize = 4;
+
+// How long the heart stays lit at the start of each beat, and how often the
+// mode looks at whether it should be.
+const BEAT: Duration = Duration::from_millis(150);
+const POLL: Duration = Duration::from_millis(50);
+
+const HEART: Sprite = &[
+    ".##...##.",
+    "####.####",
+    "#########",
+    "#########",
+    ".#######.",
+    "..#####..",
+    "...###...",
+    "....#....",
+];
+
+#[derive(PartialEq)]
+enum Shown {
+    Message(String),
+    // Beats per minute, or "--" before the first reading, and whether the
+    // heart is lit.
+    Reading(String, bool),
+}
+
+// Beats per minute from a Bluetooth heart rate strap in big digits, with a
+// heart under them flashing in time.
+pub struct HeartRateMode {
+    heart_rate: Option<HeartRateRx>,
+    font: Arc<Font>,
+    // What's on the panel, so unchanged readings aren't sent again.
+    drawn: Option<Shown>,
+}
+
+impl HeartRateMode {
+    pub fn new(heart_rate: Option<HeartRateRx>, font: Arc<Font>) -> Self {
+        Self { heart_rate, font, drawn: None }
+    }
+
+    fn draw(&self, bpm: &str, lit: bool, dims: (usize, usize)) -> Vec<u8> {
+        let (heart_w, heart_h) = sprite::size(HEART);
+        let room = dims.1.saturating_sub(GAP + heart_h);
+        let mut size = self.font.fit(bpm, dims.0, room as f32);
+        while size > 1.0 && self.font.measure(bpm, size).1 > room {
+            size -= 1.0;
+        }
+        let (bpm_w, bpm_h) = self.font.measure(bpm, size);
+        let y = dims.1.saturating_sub(bpm_h + GAP + heart_h) / 2;
+        let mut fb = Framebuffer::new(dims);
+        let x = dims.0.saturating_sub(bpm_w) / 2;
+        self.font.draw(&mut fb, x as isize, y as isize, bpm, size);
+        let label = "bpm";
+        let label_w = label.len() * text::CELL_W;
+        let x = dims.0.saturating_sub(heart_w + 2 + label_w) / 2;
+        let y = y + bpm_h + GAP;
+        if lit {
+            sprite::draw(&mut fb, x as isize, y as isize, HEART);
+        }
+        let label_y = y + heart_h.saturating_sub(text::GLYPH_H) / 2;
+        text::draw_text(&mut fb, (x + heart_w + 2) as isize, label_y as isize, label);
+        fb.into_pixels()
+    }
+}
+
+// Whether a beat started within the last `BEAT`, counting beats on from the
+// last reading at its rate.
+fn beating(bpm: u16, since: Instant) -> bool {
+    if bpm == 0 {
+        return false;
+    }
+    let period = Duration::from_secs(60).as_millis() / bpm as u128;
+    since.elapsed().as_millis() % period < BEAT.as_millis()
+}
+
+impl Mode for HeartRateMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.drawn = None;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(rx) = &self.heart_rate else {
+            panel.show(text::render("No heart\nrate strap", panel.dims()))?;
+            return Ok(None);
+        };
+        let shown = match &*rx.borrow() {
+            HeartRate::Searching => Shown::Message("Looking\nfor strap".to_owned()),
+            HeartRate::Connecting(name) => Shown::Message(format!("Connecting\n{name}")),
+            HeartRate::Connected { reading: None, .. } => Shown::Reading("--".to_owned(), false),
+            HeartRate::Connected { reading: Some((bpm, at)), .. } => {
+                Shown::Reading(bpm.to_string(), beating(*bpm, *at))
+            }
+        };
+        if self.drawn.as_ref() != Some(&shown) {
+            let frame = match &shown {
+                Shown::Message(message) => text::render(message, panel.dims()),
+                Shown::Reading(bpm, lit) => self.draw(bpm, *lit, panel.dims()),
+            };
+            panel.show(frame)?;
+            self.drawn = Some(shown);
+        }
+        Ok(Some(POLL))
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use super::{clock, map, Event, Mode, ModeSettings, Panel};
+use crate::{
+    ambient::AmbientRx,
+    battery::BatteryRx,
+    framebuffer::Framebuffer,
+    imu::OrientationRx,
+    ingest::{self, Format},
+    overlay::battery as battery_icon,
+    sprite,
+    text,
+    ttf::Font,
+};
+
+pub const NAME: &str = "hud";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HudConfig {
+    // TOML or JSON layout file, re-read whenever it changes.
+    pub path: PathBuf,
+}
+
+impl Default for HudConfig {
+    fn default() -> Self {
+        Self { path: PathBuf::from("hud.toml") }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HudLayout {
+    #[serde(default = "default_refresh_millis")]
+    refresh_millis: u64,
+    #[serde(default, rename = "widget")]
+    widgets: Vec<Widget>,
+    // What image widgets show, read along with the layout.
+    #[serde(skip)]
+    images: HashMap<PathBuf, Picture>,
+}
+
+// A PNG's lightness, and where it's opaque enough to draw.
+#[derive(Debug)]
+struct Picture {
+    gray: Vec<u8>,
+    mask: Vec<bool>,
+    dims: (usize, usize),
+}
+
+impl Picture {
+    fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let (rgba, dims) = ingest::decode_rgba(&data, Format::Png)
+            .with_context(|| format!("decoding {}", path.display()))?;
+        Ok(Self {
+            gray: rgba.iter().map(|&[r, g, b, _]| ingest::luma(r, g, b)).collect(),
+            mask: rgba.iter().map(|px| px[3] > (u8::MAX / 2)).collect(),
+            dims,
+        })
+    }
+}
+
+fn default_refresh_millis() -> u64 {
+    1000
+}
+
+fn default_clock_format() -> String {
+    "%H:%M".to_owned()
+}
+
+// Positions are the top-left corner. Text without a `size` uses the small
+// bitmap font; with one, the configured scalable font.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum Widget {
+    Clock {
+        x: isize,
+        y: isize,
+        // chrono strftime syntax
+        #[serde(default = "default_clock_format")]
+        format: String,
+        size: Option<f32>,
+    },
+    Battery {
+        x: isize,
+        y: isize,
+        // Print the percentage right of the icon.
+        #[serde(default)]
+        percent: bool,
+    },
+    // `text` may contain bindings, e.g. "{heading}", filled in on every
+    // redraw. See `Sources::bind`.
+    Text {
+        x: isize,
+        y: isize,
+        text: String,
+        size: Option<f32>,
+    },
+    MiniMap {
+        x: isize,
+        y: isize,
+        w: usize,
+        h: usize,
+    },
+    Sprite {
+        x: isize,
+        y: isize,
+        rows: Vec<String>,
+    },
+    // A PNG, drawn only where it's opaque.
+    Image {
+        x: isize,
+        y: isize,
+        path: PathBuf,
+    },
+}
+
+impl HudLayout {
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let mut layout: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&text)?,
+            _ => toml::from_str(&text)?,
+        };
+        for widget in &layout.widgets {
+            match widget {
+                Widget::Clock { format, .. } => clock::check_format(format)?,
+                Widget::Image { path, .. } => {
+                    if let Entry::Vacant(entry) = layout.images.entry(path.clone()) {
+                        entry.insert(Picture::load(path)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(layout)
+    }
+
+    fn has_map(&self) -> bool {
+        self.widgets.iter().any(|w| matches!(w, Widget::MiniMap { .. }))
+    }
+}
+
+// Everything a widget can show.
+struct Sources {
+    font: Arc<Font>,
+    map: map::MapConfig,
+    battery: Option<BatteryRx>,
+    orientation: Option<OrientationRx>,
+    ambient: Option<AmbientRx>,
+    // Last text event sent while the HUD was showing.
+    message: String,
+}
+
+impl Sources {
+    // Replaces each `{name}` in `text` with its current value, or "--" if
+    // there is none. Unknown names are left alone.
+    fn bind(&self, text: &str) -> String {
+        let now = Local::now();
+        let orientation = self.orientation.as_ref().and_then(|rx| *rx.borrow());
+        let ambient = self.ambient.as_ref().and_then(|rx| *rx.borrow());
+        let battery = self.battery.as_ref().map(|rx| *rx.borrow());
+        let or_none = |v: Option<String>| v.unwrap_or_else(|| "--".to_owned());
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let name = &rest[start + 1..start + len];
+            let value = match name {
+                "time" => now.format("%H:%M").to_string(),
+                "date" => now.format("%a %d").to_string(),
+                "battery" => or_none(battery.map(|b| format!("{:.0}%", b.percent))),
+                "heading" => or_none(orientation.map(|o| format!("{:.0}", o.heading))),
+                "pitch" => or_none(orientation.map(|o| format!("{:.0}", o.pitch))),
+                "lux" => or_none(ambient.map(|a| format!("{:.0}", a.lux))),
+                "message" => self.message.clone(),
+                _ => rest[start..=start + len].to_owned(),
+            };
+            out.push_str(&value);
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn draw_text(&self, fb: &mut Framebuffer, x: isize, y: isize, text: &str, size: Option<f32>) {
+        match size {
+            Some(size) => self.font.draw(fb, x, y, text, size),
+            None => text::draw_text(fb, x, y, text),
+        }
+    }
+}
+
+// Widgets placed by a layout file, so a HUD can be rearranged without a
+// rebuild.
+pub struct HudMode {
+    config: HudConfig,
+    sources: Sources,
+    layout: Option<HudLayout>,
+    // Modification time of the file `layout` was read from.
+    loaded: Option<SystemTime>,
+}
+
+impl HudMode {
+    pub fn new(settings: &ModeSettings) -> Self {
+        let sources = Sources {
+            font: settings.font.clone(),
+            map: settings.modes.map.clone(),
+            battery: settings.battery.clone(),
+            orientation: settings.orientation.clone(),
+            ambient: settings.ambient.clone(),
+            message: String::new(),
+        };
+        Self { config: settings.modes.hud.clone(), sources, layout: None, loaded: None }
+    }
+
+    // Re-reads the layout if the file changed. A broken edit keeps the
+    // previous layout on screen rather than blanking it.
+    fn reload(&mut self) {
+        let path = &self.config.path;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.loaded {
+            return;
+        }
+        self.loaded = modified;
+        match HudLayout::load(path) {
+            Ok(layout) => {
+                println!(
+                    "[hud] Loaded {} widgets from {}.",
+                    layout.widgets.len(),
+                    path.display(),
+                );
+                self.layout = Some(layout);
+            }
+            Err(e) => println!("[hud] Bad layout {}: {e:#}", path.display()),
+        }
+    }
+
+    fn draw(&self, layout: &HudLayout, dims: (usize, usize)) -> Result<Vec<u8>> {
+        let mut fb = Framebuffer::new(dims);
+        for widget in &layout.widgets {
+            match widget {
+                Widget::Clock { x, y, format, size } => {
+                    let time = Local::now().format(format).to_string();
+                    self.sources.draw_text(&mut fb, *x, *y, &time, *size);
+                }
+                Widget::Battery { x, y, percent } => {
+                    let Some(rx) = &self.sources.battery else {
+                        continue;
+                    };
+                    let reading = *rx.borrow();
+                    battery_icon::draw_icon(&mut fb, *x, *y, reading.percent);
+                    if *percent {
+                        let text_x = x + battery_icon::ICON_W + 3;
+                        // Centred on the icon.
+                        let text_y = y + (battery_icon::ICON_H - text::GLYPH_H as isize) / 2;
+                        let label = format!("{:.0}%", reading.percent);
+                        text::draw_text(&mut fb, text_x, text_y, &label);
+                    }
+                }
+                Widget::Text { x, y, text, size } => {
+                    let text = self.sources.bind(text);
+                    self.sources.draw_text(&mut fb, *x, *y, &text, *size);
+                }
+                Widget::MiniMap { x, y, w, h } => {
+                    if let Some(mini) = map::mini_map(self.sources.map.filter, dims, (*w, *h))? {
+                        fb.blit(*x, *y, &mini, (*w, *h));
+                    }
+                }
+                Widget::Sprite { x, y, rows } => sprite::draw(&mut fb, *x, *y, rows),
+                Widget::Image { x, y, path } => {
+                    let picture = &layout.images[path];
+                    fb.blit_masked(*x, *y, &picture.gray, &picture.mask, picture.dims);
+                }
+            }
+        }
+        Ok(fb.into_pixels())
+    }
+
+    fn show(&self, panel: &mut dyn Panel) -> Result<()> {
+        let frame = match &self.layout {
+            Some(layout) => self.draw(layout, panel.dims())?,
+            None => text::render("No HUD\nlayout", panel.dims()),
+        };
+        panel.show(frame)
+    }
+}
+
+impl Mode for HudMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.loaded = None;
+        self.reload();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        self.reload();
+        self.show(panel)?;
+        // Keep polling without a layout, so one can be dropped in.
+        let refresh = self.layout.as_ref().map_or(1000, |l| l.refresh_millis);
+        Ok(Some(Duration::from_millis(refresh.max(1))))
+    }
+
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        match event {
+            Event::Text(text) => self.sources.message = text,
+            Event::Coords(coords) => map::load_map(coords, &self.sources.map)?,
+            Event::File(_) | Event::Frame(_) | Event::Input(_) | Event::Zoom(_) => return Ok(()),
+        }
+        self.show(panel)
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        match event {
+            Event::Text(_) => true,
+            Event::Coords(_) => self.layout.as_ref().is_some_and(HudLayout::has_map),
+            Event::File(_) | Event::Frame(_) | Event::Input(_) | Event::Zoom(_) => false,
+        }
+    }
+}
+
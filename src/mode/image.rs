@@ -0,0 +1,92 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use super::{Event, Mode, Panel};
+use crate::{rawframe::{self, Record}, read_png_g};
+
+pub const NAME: &str = "image";
+
+// For raw frame files without a timing record.
+const FRAME: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct ImageMode {
+    path: Option<PathBuf>,
+    shown: bool,
+    // Open while a raw frame file is playing, with how long each frame
+    // shows for.
+    frames: Option<(BufReader<File>, Duration)>,
+}
+
+impl Mode for ImageMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.shown = false;
+        self.frames = None;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        if path.extension().is_some_and(|e| e == "frames") {
+            if !self.shown {
+                println!("[image] Playing {path:?}...");
+                self.frames = Some((BufReader::new(File::open(path)?), FRAME));
+                self.shown = true;
+            }
+            let Some((frames, frame_time)) = &mut self.frames else {
+                return Ok(None);
+            };
+            loop {
+                match rawframe::read_record(frames, panel.dims())? {
+                    Some(Record::Timing(time)) => *frame_time = time,
+                    Some(Record::Frame(frame)) => {
+                        panel.show(frame)?;
+                        return Ok(Some(*frame_time));
+                    }
+                    None => {
+                        // The last frame stays up.
+                        self.frames = None;
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        if !self.shown {
+            println!("[image] Sending {path:?}...");
+            panel.show(read_png_g(path)?)?;
+            self.shown = true;
+        }
+        Ok(None)
+    }
+
+    // Files are shown on the tick that follows.
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        match event {
+            Event::File(path) => {
+                self.path = Some(path);
+                self.shown = false;
+                self.frames = None;
+            }
+            // From a client streaming frames, so shown straight away.
+            Event::Frame(frame) => {
+                self.path = None;
+                self.frames = None;
+                panel.show(frame)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::{imu::OrientationRx, text};
+
+pub const NAME: &str = "imu";
+
+const REFRESH: Duration = Duration::from_millis(250);
+
+// Raw heading and pitch readout, mostly for checking the sensor is mounted
+// and offset correctly.
+pub struct ImuMode {
+    orientation: Option<OrientationRx>,
+}
+
+impl ImuMode {
+    pub fn new(orientation: Option<OrientationRx>) -> Self {
+        Self { orientation }
+    }
+}
+
+impl Mode for ImuMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(rx) = &self.orientation else {
+            panel.show(text::render("No IMU", panel.dims()))?;
+            return Ok(None);
+        };
+        let message = match *rx.borrow() {
+            Some(o) => format!("HDG {:.0}\nPIT {:.0}", o.heading, o.pitch),
+            None => "Waiting\nfor IMU".to_owned(),
+        };
+        panel.show(text::render(&message, panel.dims()))?;
+        Ok(Some(REFRESH))
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+
+pub const NAME: &str = "life";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Seed {
+    #[default]
+    Random,
+    // Start from whatever the panel was last showing
+    Screen,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LifeConfig {
+    // Each step goes out as a whole frame, as the firmware has no partial
+    // updates.
+    pub step_millis: u64,
+    pub seed: Seed,
+    // Fraction of cells alive after a random seed.
+    pub density: f64,
+}
+
+impl Default for LifeConfig {
+    fn default() -> Self {
+        Self { step_millis: 250, seed: Seed::default(), density: 0.3 }
+    }
+}
+
+impl LifeConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.step_millis < 10 {
+            bail!("mode.life.step_millis: must be at least 10");
+        }
+        if !(0.0..=1.0).contains(&self.density) {
+            bail!("mode.life.density: must be from 0 to 1");
+        }
+        Ok(())
+    }
+}
+
+pub struct LifeMode {
+    config: LifeConfig,
+    dims: (usize, usize),
+    cells: Vec<bool>,
+    // The two generations before `cells`, for spotting still lifes and
+    // blinkers so the board can be reseeded instead of stalling.
+    history: [Vec<bool>; 2],
+}
+
+impl LifeMode {
+    pub fn new(config: LifeConfig) -> Self {
+        Self {
+            config,
+            dims: (0, 0),
+            cells: Vec::new(),
+            history: [Vec::new(), Vec::new()],
+        }
+    }
+
+    fn randomize(&mut self) {
+        self.cells = (0..(self.dims.0 * self.dims.1))
+            .map(|_| rand::random_bool(self.config.density))
+            .collect();
+    }
+
+    // One generation on a torus, so gliders wrap instead of dying at the
+    // panel edge.
+    fn step(&self) -> Vec<bool> {
+        let (w, h) = self.dims;
+        let mut next = vec![false; self.cells.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let mut neighbours = 0;
+                for (dx, dy) in [
+                    (w - 1, h - 1), (0, h - 1), (1, h - 1),
+                    (w - 1, 0),                 (1, 0),
+                    (w - 1, 1),     (0, 1),     (1, 1),
+                ] {
+                    let (nx, ny) = ((x + dx) % w, (y + dy) % h);
+                    if self.cells[(ny * w) + nx] {
+                        neighbours += 1;
+                    }
+                }
+                let alive = self.cells[(y * w) + x];
+                next[(y * w) + x] = matches!((alive, neighbours), (true, 2) | (_, 3));
+            }
+        }
+        next
+    }
+}
+
+impl Mode for LifeMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        self.dims = panel.dims();
+        match (self.config.seed, panel.last_frame()) {
+            // A frame of another size, say from before a rotation, can't be.
+            (Seed::Screen, Some(frame)) if frame.len() == self.dims.0 * self.dims.1 => {
+                self.cells = frame.iter().map(|&p| p > (u8::MAX / 2)).collect();
+            }
+            _ => self.randomize(),
+        }
+        self.history = [Vec::new(), Vec::new()];
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let frame = self.cells.iter()
+            .map(|&alive| if alive { 0xFF } else { 0x00 })
+            .collect();
+        panel.show(frame)?;
+        let next = self.step();
+        if next == self.cells || self.history.contains(&next) {
+            println!("[life] Board settled, reseeding...");
+            self.randomize();
+            self.history = [Vec::new(), Vec::new()];
+        } else {
+            let prev = std::mem::replace(&mut self.cells, next);
+            self.history.swap(0, 1);
+            self.history[1] = prev;
+        }
+        Ok(Some(Duration::from_millis(self.config.step_millis)))
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Instant;
+
+use anyhow::Result;
+
+use super::{screensaver, Active, Mode, ModeSettings};
+use crate::{
+    events::{self, HelmetEvent},
+    low_power,
+    overlay::Compositor,
+};
+
+// What the manager is doing with the panel, besides which mode is active.
+// Only the screensaver, low power and the battery warning are states here:
+// notifications are overlays drawn over whichever the manager is in, and
+// switching modes, by request or schedule, is left to the manager.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    // Running the active mode.
+    Active,
+    // Running the screensaver, with the mode it interrupted saved.
+    Screensaver,
+    // The battery warning covering the panel, with the active mode held off
+    // ticking.
+    LowBattery,
+    // The panel blanked and nothing ticking until an update, noting whether
+    // the screensaver was running.
+    LowPower { screensaver: bool },
+}
+
+// What moves the manager between states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    // An update arrived: an HTTP command or a button on the MCU if `user`,
+    // or a latency measurement, flash or wake request if not.
+    Update { user: bool },
+    // The idle timeout passed without a user update.
+    Idle,
+    // The low power timeout passed without anything drawn or requested.
+    Quiet,
+    // The battery reading is under `battery.low_percent`, or isn't.
+    BatteryLow,
+    BatteryOk,
+}
+
+impl State {
+    // Where `trigger` takes the manager from here, or `None` if it stays.
+    pub fn next(self, trigger: Trigger) -> Option<State> {
+        use State::*;
+        use Trigger::*;
+        Some(match (self, trigger) {
+            (Active, Idle) => Screensaver,
+            (Active | Screensaver | LowBattery, Quiet) => {
+                LowPower { screensaver: self == Screensaver }
+            }
+            (Screensaver | LowPower { .. }, Update { user: true }) => Active,
+            (LowPower { screensaver: true }, Update { user: false }) => Screensaver,
+            (LowPower { screensaver: false }, Update { user: false }) => Active,
+            // Low power is easier on the battery than the warning, so it
+            // stays until an update.
+            (Active | Screensaver, BatteryLow) => LowBattery,
+            (LowBattery, BatteryOk) => Active,
+            _ => return None,
+        })
+    }
+
+    // Whether the active mode gets ticked.
+    pub fn ticks(self) -> bool {
+        matches!(self, State::Active | State::Screensaver)
+    }
+}
+
+// The manager's state, and what it keeps to get back out of it.
+pub struct Machine {
+    pub state: State,
+    // The mode the screensaver interrupted, kept intact for restoring.
+    saved: Option<(&'static str, Box<dyn Mode>)>,
+    // Whether the display was already off, while in low power.
+    was_blank: bool,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self { state: State::Active, saved: None, was_blank: false }
+    }
+
+    // Moves to wherever `trigger` leads, if anywhere, doing what leaving the
+    // old state and entering the new one takes. Returns whether it moved.
+    pub fn fire(
+        &mut self,
+        trigger: Trigger,
+        active: &mut Active,
+        panel: &mut Compositor,
+        settings: &ModeSettings,
+    ) -> Result<bool> {
+        let Some(to) = self.state.next(trigger) else {
+            return Ok(false);
+        };
+        let from = std::mem::replace(&mut self.state, to);
+        if let State::LowPower { .. } = from {
+            println!("[mode manager] Woken up from low power.");
+            low_power::set(false);
+            if !self.was_blank {
+                panel.set_blank(false)?;
+                events::publish(HelmetEvent::Display { on: true });
+            }
+        }
+        match to {
+            State::Screensaver if self.saved.is_none() => {
+                println!("[mode manager] Idle, starting screensaver...");
+                active.stop(panel);
+                let screensaver = screensaver::ScreensaverMode::new(settings.screensaver_style);
+                let interrupted = std::mem::replace(
+                    active,
+                    Active::start_or_fall_back(screensaver::NAME, Box::new(screensaver), panel),
+                );
+                self.saved = Some((interrupted.name, interrupted.mode));
+            }
+            State::Screensaver => {}
+            State::Active | State::LowBattery => {
+                if let Some((name, mode)) = self.saved.take() {
+                    println!("[mode manager] Woken up, restoring {name} mode...");
+                    active.stop(panel);
+                    *active = Active::start_or_fall_back(name, mode, panel);
+                }
+                if to == State::LowBattery {
+                    println!("[mode manager] Battery low, holding {} mode...", active.name);
+                } else if from == State::LowBattery {
+                    println!("[mode manager] Battery recovered, resuming {} mode...", active.name);
+                    active.next_tick = Some(Instant::now());
+                }
+            }
+            State::LowPower { .. } => {
+                println!("[mode manager] Nothing going on, going low power...");
+                self.was_blank = panel.is_blank();
+                if !self.was_blank {
+                    panel.set_blank(true)?;
+                    events::publish(HelmetEvent::Display { on: false });
+                }
+                low_power::set(true);
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use crossbeam_channel::bounded;
+use png::ColorType;
+use serde::{Deserialize, Serialize};
+
+use super::{Event, Mode, Panel};
+use crate::{
+    events,
+    filter::{self, MapFilter, MapImage},
+    framebuffer::Framebuffer,
+    geo::{self, Course, LatLon},
+    imu::OrientationRx,
+    mbtiles,
+    picture,
+    route::Route,
+    state,
+    waypoint,
+    MAP_IMAGE_FILENAME,
+    PANEL_DIMS,
+};
+
+pub const NAME: &str = "map";
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MapSource {
+    // Whatever loadmap.sh fetches.
+    #[default]
+    Loadmap,
+    // Tiles from a local MBTiles file, for riding without a connection.
+    Mbtiles(PathBuf),
+}
+
+// Web map zoom levels. Stepping starts from `DEFAULT_ZOOM` while `zoom` is
+// left to loadmap.sh.
+const ZOOM_RANGE: (u8, u8) = (1, 19);
+const DEFAULT_ZOOM: u8 = 16;
+
+// With heading_up, how often the heading is checked and how far it has to
+// swing before the map is turned again.
+const HEADING_CHECK: Duration = Duration::from_millis(250);
+const HEADING_STEP: f64 = 5.0;
+
+// How often a running loadmap.sh is checked on, and how many of the last
+// lines it wrote to stderr go into the error when it fails.
+const LOADMAP_POLL: Duration = Duration::from_millis(50);
+const LOADMAP_STDERR_LINES: usize = 3;
+
+// Held while a map is rendered, so two renders never write
+// `MAP_IMAGE_FILENAME` at once.
+static RENDERING: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapConfig {
+    pub source: MapSource,
+    pub filter: MapFilter,
+    // Passed on to loadmap.sh; its own default if left out.
+    pub zoom: Option<u8>,
+    // GPX or GeoJSON route or track drawn over the map.
+    pub track: Option<PathBuf>,
+    // Ground width of a map pixel, for placing the track. Worked out from the
+    // zoom if left out, which is right for maps cut from standard web map
+    // tiles at their own scale.
+    pub metres_per_pixel: Option<f64>,
+    // The high_contrast filter drops map labels when zoomed out further
+    // than this, where they'd just be noise.
+    pub labels_min_zoom: u8,
+    // Turns the map so the direction of travel is up, going by the IMU or
+    // else the GPS course.
+    pub heading_up: bool,
+    // loadmap.sh is killed if it takes longer than this.
+    pub loadmap_timeout_secs: u64,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self {
+            source: MapSource::Loadmap,
+            filter: MapFilter::Edges,
+            zoom: None,
+            track: None,
+            metres_per_pixel: None,
+            labels_min_zoom: 17,
+            heading_up: false,
+            loadmap_timeout_secs: 30,
+        }
+    }
+}
+
+impl MapConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.loadmap_timeout_secs == 0 {
+            bail!("mode.map.loadmap_timeout_secs: must be above 0");
+        }
+        let (min, max) = ZOOM_RANGE;
+        if self.zoom.is_some_and(|zoom| !(min..=max).contains(&zoom)) {
+            bail!("mode.map.zoom: must be from {min} to {max}");
+        }
+        Ok(())
+    }
+}
+
+pub struct MapMode {
+    config: MapConfig,
+    shown: bool,
+    // Where the last map was rendered, for rendering again on zooming.
+    coords: Option<String>,
+    track: Option<Route>,
+    orientation: Option<OrientationRx>,
+    course: Course,
+    // Which way up the map was last sent, for heading_up.
+    drawn_heading: Option<f64>,
+}
+
+impl MapMode {
+    pub fn new(config: MapConfig, orientation: Option<OrientationRx>) -> Self {
+        Self {
+            config,
+            shown: false,
+            coords: state::get().coords,
+            track: None,
+            orientation,
+            course: Course::default(),
+            drawn_heading: None,
+        }
+    }
+
+    // The IMU when there is one, otherwise the GPS course. `None` for north
+    // up.
+    fn heading(&self) -> Option<f64> {
+        if !self.config.heading_up {
+            return None;
+        }
+        let imu = self.orientation.as_ref().and_then(|rx| *rx.borrow());
+        imu.map(|o| o.heading).or(self.course.bearing)
+    }
+
+    fn send_map(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let dims = panel.dims();
+        let labels = self.config.zoom.unwrap_or(DEFAULT_ZOOM) >= self.config.labels_min_zoom;
+        let image = read_map()?;
+        let src_dims = image.dims;
+        let data = self.config.filter.apply(image, src_dims, labels);
+        // Turned after filtering, so the blank corners it leaves on a map
+        // only just big enough for the panel don't show up as edges.
+        let heading = self.heading();
+        let mut data = filter::rotate(&data, src_dims, -heading.unwrap_or(0.0), dims);
+        self.drawn_heading = heading;
+        let centre = self.coords.as_deref().and_then(|c| c.parse::<LatLon>().ok());
+        let waypoints = waypoint::list();
+        if let (true, Some(centre)) = (self.track.is_some() || !waypoints.is_empty(), centre) {
+            let mut fb = Framebuffer { dims, pixels: data };
+            let scale = self.config.metres_per_pixel.unwrap_or_else(|| {
+                geo::metres_per_pixel(centre.lat, self.config.zoom.unwrap_or(DEFAULT_ZOOM))
+            });
+            let project = Projection {
+                centre,
+                metres_per_pixel: scale,
+                heading: heading.unwrap_or(0.0),
+                dims,
+            };
+            if let Some(track) = &self.track {
+                draw_track(&mut fb, track, &project);
+            }
+            for point in &waypoints {
+                draw_waypoint(&mut fb, project.to_pixel(point.at()));
+            }
+            draw_position(&mut fb);
+            data = fb.into_pixels();
+        }
+        panel.show(data)?;
+        self.shown = true;
+        Ok(())
+    }
+}
+
+// Where places land on a map of `dims` pixels centred on `centre`, with
+// `heading` up.
+struct Projection {
+    centre: LatLon,
+    metres_per_pixel: f64,
+    heading: f64,
+    dims: (usize, usize),
+}
+
+impl Projection {
+    fn to_pixel(&self, p: LatLon) -> Point {
+        let (east, north) = p.local_xy(self.centre);
+        let (dx, dy) = (east / self.metres_per_pixel, -north / self.metres_per_pixel);
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        // In pixel indices, so the centre of an even-sized panel is between
+        // two of them.
+        let (cx, cy) = ((self.dims.0 as f64 - 1.0) / 2.0, (self.dims.1 as f64 - 1.0) / 2.0);
+        (cx + (cos * dx) + (sin * dy), cy - (sin * dx) + (cos * dy))
+    }
+}
+
+fn draw_track(fb: &mut Framebuffer, track: &Route, project: &Projection) {
+    let max = ((fb.dims.0 - 1) as f64, (fb.dims.1 - 1) as f64);
+    for leg in track.points.windows(2) {
+        if let Some((a, b)) = clip(project.to_pixel(leg[0]), project.to_pixel(leg[1]), max) {
+            let (a, b) = ((a.0.round(), a.1.round()), (b.0.round(), b.1.round()));
+            fb.line(a.0 as isize, a.1 as isize, b.0 as isize, b.1 as isize, true);
+        }
+    }
+}
+
+// A diamond, clear of whatever's underneath. Off-panel ones just get clipped.
+fn draw_waypoint(fb: &mut Framebuffer, at: Point) {
+    let (x, y) = (at.0.round() as isize, at.1.round() as isize);
+    for dy in -3isize..=3 {
+        let half = 3 - dy.abs();
+        fb.line(x - half, y + dy, x + half, y + dy, false);
+        fb.set(x - half, y + dy, true);
+        fb.set(x + half, y + dy, true);
+    }
+}
+
+// A dot in a box at the centre, for where the helmet is.
+fn draw_position(fb: &mut Framebuffer) {
+    let (cx, cy) = ((fb.dims.0 / 2) as isize, (fb.dims.1 / 2) as isize);
+    fb.fill_rect(cx - 3, cy - 3, 7, 7, false);
+    fb.rect(cx - 3, cy - 3, 7, 7, true);
+    fb.fill_rect(cx - 1, cy - 1, 3, 3, true);
+}
+
+type Point = (f64, f64);
+
+// The part of the segment from `a` to `b` inside (0, 0)..=`max`, if any
+// (Liang-Barsky), so legs running far off the panel keep their direction.
+fn clip(a: Point, b: Point, max: Point) -> Option<(Point, Point)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, a.0), (dx, max.0 - a.0), (-dy, a.1), (dy, max.1 - a.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then_some((
+        (a.0 + (t0 * dx), a.1 + (t0 * dy)),
+        (a.0 + (t1 * dx), a.1 + (t1 * dy)),
+    ))
+}
+
+// The last rendered map shrunk to `size`, for other modes to show in a
+// corner. `None` if no map has been rendered yet.
+pub fn mini_map(
+    filter: MapFilter,
+    dims: (usize, usize),
+    size: (usize, usize),
+) -> Result<Option<Vec<u8>>> {
+    if !Path::new(MAP_IMAGE_FILENAME).exists() {
+        return Ok(None);
+    }
+    let image = read_map()?;
+    let src_dims = image.dims;
+    // Labels would only smudge at this size.
+    let map = filter.apply(image, src_dims, false);
+    let map = filter::rotate(&map, src_dims, 0.0, dims);
+    let mini = filter::downscale(&map, dims, size);
+    // Downscaling washes thin edges out to grey, so keep anything that was
+    // touched at all.
+    Ok(Some(mini.into_iter().map(|p| if p > 0x20 { 0xFF } else { 0 }).collect()))
+}
+
+// The last map loadmap.sh rendered, whether it left it in grey or colour.
+fn read_map() -> Result<MapImage> {
+    let mut decoder = png::Decoder::new(File::open(MAP_IMAGE_FILENAME)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    let dims = (info.width as usize, info.height as usize);
+    let rgb: Vec<[u8; 3]> = match info.color_type {
+        ColorType::Grayscale => return Ok(MapImage { dims, gray: buf, rgb: None }),
+        ColorType::GrayscaleAlpha => {
+            let gray = buf.iter().step_by(2).copied().collect();
+            return Ok(MapImage { dims, gray, rgb: None });
+        }
+        ColorType::Rgb => buf.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+        ColorType::Rgba => buf.chunks_exact(4).map(|p| [p[0], p[1], p[2]]).collect(),
+        ColorType::Indexed => bail!("{MAP_IMAGE_FILENAME} wasn't expanded from its palette"),
+    };
+    let gray = rgb.iter().map(|&[r, g, b]| filter::luma(r, g, b)).collect();
+    Ok(MapImage { dims, gray, rgb: Some(rgb) })
+}
+
+pub fn load_map(coords: impl AsRef<str>, config: &MapConfig) -> Result<()> {
+    let coords = coords.as_ref();
+    let _rendering = RENDERING.lock().unwrap_or_else(|e| e.into_inner());
+    if let MapSource::Mbtiles(path) = &config.source {
+        let at: LatLon = coords.parse()?;
+        let zoom = config.zoom.unwrap_or(DEFAULT_ZOOM);
+        // Big enough to fill the panel at any angle, for heading_up.
+        let dims = if config.heading_up {
+            let diagonal = (PANEL_DIMS.0 as f64).hypot(PANEL_DIMS.1 as f64).ceil() as usize;
+            (diagonal, diagonal)
+        } else {
+            PANEL_DIMS
+        };
+        let map = mbtiles::render(path, at, zoom, dims)?;
+        return picture::save_rgb_png(&map, dims, MAP_IMAGE_FILENAME);
+    }
+    let mut cmd = Command::new("./loadmap.sh");
+    cmd.arg(coords);
+    if let Some(zoom) = config.zoom {
+        cmd.arg(zoom.to_string());
+    }
+    run_loadmap(cmd, Duration::from_secs(config.loadmap_timeout_secs))
+}
+
+// Runs loadmap.sh in a process group of its own, so whatever it started
+// can be killed along with it. Its stderr is echoed as it comes, and the
+// end of it goes into the error if it fails.
+fn run_loadmap(mut cmd: Command, timeout: Duration) -> Result<()> {
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| anyhow!("starting loadmap.sh: {e}"))?;
+    let stderr = BufReader::new(child.stderr.take().unwrap());
+    let (echoed_tx, echoed) = bounded(1);
+    thread::spawn(move || {
+        let mut last = Vec::new();
+        for line in stderr.lines() {
+            let Ok(line) = line else { break };
+            println!("[loadmap] {line}");
+            if last.len() == LOADMAP_STDERR_LINES {
+                last.remove(0);
+            }
+            last.push(line);
+        }
+        let _ = echoed_tx.send(last);
+    });
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            kill_group(&mut child);
+            break None;
+        }
+        sleep(LOADMAP_POLL);
+    };
+    // Something loadmap.sh left running can hold its stderr open after it
+    // exits, so that only gets until the timeout too. A process that left
+    // the group could hold it for good, so it's not waited on after that.
+    let stderr = echoed.recv_timeout(timeout.saturating_sub(start.elapsed()))
+        .unwrap_or_else(|_| {
+            kill_group(&mut child);
+            echoed.recv_timeout(LOADMAP_POLL * 20).unwrap_or_default()
+        });
+    let stderr = if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", stderr.join("; "))
+    };
+    match status {
+        None => bail!("loadmap.sh timed out after {}s{stderr}", timeout.as_secs()),
+        Some(status) if !status.success() => bail!("loadmap.sh failed ({status}){stderr}"),
+        Some(_) => Ok(()),
+    }
+}
+
+// loadmap.sh's downloads and conversions too, which would otherwise carry on
+// and could write the map under the next render.
+fn kill_group(child: &mut Child) {
+    let group = format!("-{}", child.id());
+    let killed = Command::new("kill")
+        .args(["-KILL", "--", &group])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        let _ = child.kill();
+    }
+    let _ = child.wait();
+}
+
+impl Mode for MapMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.shown = false;
+        // A bad track shouldn't keep the map itself from showing.
+        self.track = self.config.track.as_ref().and_then(|path| match Route::load(path) {
+            Ok(track) => Some(track),
+            Err(e) => {
+                println!("[map] Not drawing track: {e:#}");
+                None
+            }
+        });
+        Ok(())
+    }
+
+    // Re-shows the last rendered map when switched back to, unless an event
+    // has already put a fresh one up.
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        if !self.shown && Path::new(MAP_IMAGE_FILENAME).exists() {
+            println!("[map] Re-sending last map...");
+            self.send_map(panel)?;
+        }
+        if !self.config.heading_up {
+            return Ok(None);
+        }
+        let turned = match (self.heading(), self.drawn_heading) {
+            (Some(now), Some(drawn)) => geo::turn_angle(drawn, now).abs() >= HEADING_STEP,
+            (now, drawn) => now.is_some() != drawn.is_some(),
+        };
+        if self.shown && turned {
+            self.send_map(panel)?;
+        }
+        Ok(Some(HEADING_CHECK))
+    }
+
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        let coords = match event {
+            Event::Coords(coords) => {
+                if let Ok(fix) = coords.parse() {
+                    self.course.update(fix);
+                }
+                coords
+            }
+            Event::Zoom(step) => {
+                let zoom = self.config.zoom.unwrap_or(DEFAULT_ZOOM) as i16 + step as i16;
+                let zoom = zoom.clamp(ZOOM_RANGE.0 as i16, ZOOM_RANGE.1 as i16) as u8;
+                println!("[map] Zoom {zoom}.");
+                self.config.zoom = Some(zoom);
+                state::update(|state| state.zoom = Some(zoom));
+                match self.coords.clone() {
+                    Some(coords) => coords,
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        };
+        println!("[map] Loading map at {coords}...");
+        // The last map stays up rather than nothing.
+        if let Err(e) = load_map(&coords, &self.config) {
+            println!("[map] Couldn't load the map at {coords}: {e:#}");
+            events::error(format!("couldn't load the map at {coords}: {e:#}"));
+            return Ok(());
+        }
+        self.coords = Some(coords);
+        println!("[map] Sending map...");
+        let start = Instant::now();
+        self.send_map(panel)?;
+        let elapsed = start.elapsed().as_millis();
+        println!("[map] Sent map in {elapsed:.2?}ms.");
+        Ok(())
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        matches!(event, Event::Zoom(_))
+    }
+}
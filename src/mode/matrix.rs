@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::framebuffer::Framebuffer;
+
+pub const NAME: &str = "matrix";
+
+const FRAME: Duration = Duration::from_millis(100);
+
+struct Drop {
+    x: isize,
+    head: isize,
+    len: isize,
+    speed: isize,
+}
+
+// Falling trails of broken vertical lines, one possible drop per column.
+#[derive(Default)]
+pub struct MatrixMode {
+    drops: Vec<Drop>,
+}
+
+fn new_drop(x: isize, h: isize) -> Drop {
+    Drop {
+        x,
+        head: -(rand::random_range(0..h as i64) as isize),
+        len: rand::random_range(4..(h as i64 / 2).max(5)) as isize,
+        speed: rand::random_range(1..=2i64) as isize,
+    }
+}
+
+impl Mode for MatrixMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let (w, h) = panel.dims();
+        // Every other column, so neighbouring trails don't merge.
+        self.drops = (0..w as isize).step_by(2)
+            .map(|x| new_drop(x, h as isize))
+            .collect();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let start = Instant::now();
+        let dims = panel.dims();
+        let h = dims.1 as isize;
+        let mut fb = Framebuffer::new(dims);
+        for drop in &mut self.drops {
+            for i in 0..drop.len {
+                // The head is solid; the tail breaks up like flickering
+                // glyphs.
+                if i < 2 || rand::random_bool(0.6) {
+                    fb.set(drop.x, drop.head - i, true);
+                }
+            }
+            drop.head += drop.speed;
+            if drop.head - drop.len > h {
+                *drop = new_drop(drop.x, h);
+            }
+        }
+        panel.show(fb.into_pixels())?;
+        Ok(Some(FRAME.saturating_sub(start.elapsed())))
+    }
+}
@@ -0,0 +1,812 @@
+use std::{
+    io::{Read, Write},
+    ops::DerefMut,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::Local;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+
+use crate::{
+    ambient::AmbientRx,
+    battery::BatteryRx,
+    config::{Config, ModesConfig},
+    gps::GpsRx,
+    heart_rate::HeartRateRx,
+    obd::ObdRx,
+    events::{self, HelmetEvent},
+    hooks::{self, HookEvent},
+    flash,
+    input::{Action, InputEvent, InputMapper, Mapped},
+    imu::OrientationRx,
+    jobs,
+    latency,
+    low_power,
+    overlay::{self, Compositor},
+    session,
+    state,
+    track,
+    transition::Transitions,
+    ttf::Font,
+    HelmetMcu,
+    Update,
+};
+use machine::{Machine, State, Trigger};
+
+pub mod animation;
+pub mod audio;
+pub mod calendar;
+pub mod calibrate;
+pub mod camera;
+pub mod clock;
+pub mod console;
+pub mod headlines;
+pub mod heart_rate;
+pub mod hud;
+pub mod image;
+pub mod imu;
+pub mod life;
+mod machine;
+pub mod map;
+pub mod matrix;
+pub mod morse;
+pub mod navigate;
+pub mod now_playing;
+pub mod plasma;
+pub mod pomodoro;
+pub mod receive;
+pub mod screen;
+pub mod screensaver;
+pub mod speedometer;
+pub mod split;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod starfield;
+pub mod stats;
+pub mod stdin;
+pub mod test_pattern;
+pub mod text;
+pub mod timer;
+pub mod vehicle;
+pub mod vnc;
+pub mod weather;
+
+// Where modes put their frames. Frames are row-major grayscale at `dims()`.
+pub trait Panel {
+    fn dims(&self) -> (usize, usize);
+    fn show(&mut self, frame: Vec<u8>) -> Result<()>;
+    // Whatever was shown most recently, if anything.
+    fn last_frame(&self) -> Option<&[u8]>;
+
+    // One round trip to the panel's controller and back.
+    fn ping(&mut self) -> Result<Duration> {
+        bail!("this panel can't be pinged")
+    }
+
+    // How long showing the last frame took, if known.
+    fn last_send(&self) -> Option<Duration> {
+        None
+    }
+
+    // Hands the controller's serial port to `user` and takes it back
+    // afterwards. Nothing can be shown meanwhile.
+    fn lend_port(&mut self, user: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        let _ = user;
+        bail!("this panel has no serial port")
+    }
+
+    // Opens the controller's serial port again as `port`, after it was
+    // unplugged as `was`, and shows the last frame again.
+    fn reattach(&mut self, was: &str, port: &str) -> Result<()> {
+        let _ = (was, port);
+        bail!("this panel has no serial port")
+    }
+
+    // Like `lend_port`, but without waiting: closes the serial port and
+    // returns its path, for something on another thread to open until
+    // `take_back_port`. Frames shown meanwhile are kept, and the last goes
+    // out once the port is back.
+    fn release_port(&mut self) -> Result<String> {
+        bail!("this panel has no serial port")
+    }
+
+    fn take_back_port(&mut self) -> Result<()> {
+        bail!("this panel has no serial port")
+    }
+}
+
+impl<S: DerefMut<Target = T>, T: Read + Write + ?Sized> Panel for HelmetMcu<S, T> {
+    fn dims(&self) -> (usize, usize) {
+        self.dims
+    }
+
+    fn show(&mut self, frame: Vec<u8>) -> Result<()> {
+        self.send_rotated(frame)
+    }
+
+    fn last_frame(&self) -> Option<&[u8]> {
+        self.last_frame.as_deref()
+    }
+
+    fn ping(&mut self) -> Result<Duration> {
+        HelmetMcu::ping(self)
+    }
+
+    fn last_send(&self) -> Option<Duration> {
+        self.last_send
+    }
+
+    fn lend_port(&mut self, user: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        HelmetMcu::lend_port(self, user)
+    }
+
+    fn reattach(&mut self, was: &str, port: &str) -> Result<()> {
+        HelmetMcu::reattach(self, was, port)
+    }
+
+    fn release_port(&mut self) -> Result<String> {
+        HelmetMcu::release_port(self)
+    }
+
+    fn take_back_port(&mut self) -> Result<()> {
+        HelmetMcu::take_back_port(self)
+    }
+}
+
+// Inputs a mode may react to while it is active.
+pub enum Event {
+    Coords(String),
+    Text(String),
+    File(PathBuf),
+    // One panel-sized frame, shown as it is.
+    Frame(Vec<u8>),
+    // Helmet controls not bound to an action.
+    Input(InputEvent),
+    // Steps in (positive) or out, for modes with a scale.
+    Zoom(i8),
+}
+
+impl Event {
+    // The mode that knows how to display this event, or `None` for events
+    // only the active mode may want.
+    fn mode(&self) -> Option<&'static str> {
+        match self {
+            Event::Coords(_) => Some(map::NAME),
+            Event::Text(_) => Some(text::NAME),
+            Event::File(_) | Event::Frame(_) => Some(image::NAME),
+            Event::Input(_) | Event::Zoom(_) => None,
+        }
+    }
+}
+
+pub trait Mode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()>;
+
+    // Advances the mode and returns how long to wait before the next tick,
+    // or `None` if the mode has nothing more to do until an event arrives.
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>>;
+
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        let _ = (panel, event);
+        Ok(())
+    }
+
+    // Whether this mode wants `event` itself, rather than the manager
+    // switching to the event's usual mode.
+    fn accepts(&self, event: &Event) -> bool {
+        let _ = event;
+        false
+    }
+
+    fn stop(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let _ = panel;
+        Ok(())
+    }
+}
+
+// Settings the registry needs to construct modes by name.
+#[derive(Clone)]
+pub struct ModeSettings {
+    pub modes: ModesConfig,
+    pub screensaver_style: screensaver::Style,
+    // Set when an IMU is configured and found.
+    pub orientation: Option<OrientationRx>,
+    pub battery: Option<BatteryRx>,
+    pub ambient: Option<AmbientRx>,
+    pub gps: Option<GpsRx>,
+    pub heart_rate: Option<HeartRateRx>,
+    pub obd: Option<ObdRx>,
+    pub font: Arc<Font>,
+}
+
+pub struct ModeInfo {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub build: fn(&ModeSettings) -> Box<dyn Mode>,
+}
+
+pub const REGISTRY: &[ModeInfo] = &[
+    ModeInfo {
+        name: map::NAME,
+        about: "Rendered map around the last coordinates",
+        build: |s| {
+            Box::new(map::MapMode::new(s.modes.map.clone(), s.orientation.clone()))
+        },
+    },
+    ModeInfo {
+        name: navigate::NAME,
+        about: "Turn-by-turn arrows along a route",
+        build: |s| {
+            Box::new(navigate::NavigateMode::new(s.modes.navigate.clone(), s.modes.map.clone()))
+        },
+    },
+    ModeInfo {
+        name: clock::NAME,
+        about: "Local time",
+        build: |s| Box::new(clock::ClockMode::new(s.modes.clock.clone(), s.font.clone())),
+    },
+    ModeInfo {
+        name: hud::NAME,
+        about: "Widgets placed by a layout file",
+        build: |s| Box::new(hud::HudMode::new(s)),
+    },
+    ModeInfo {
+        name: animation::NAME,
+        about: "Bad Apple frame animation",
+        build: |_| Box::new(animation::AnimationMode::default()),
+    },
+    ModeInfo {
+        name: text::NAME,
+        about: "Last text message",
+        build: |s| Box::new(text::TextMode::new(s.modes.text, s.font.clone())),
+    },
+    ModeInfo {
+        name: image::NAME,
+        about: "Last image file sent",
+        build: |_| Box::new(image::ImageMode::default()),
+    },
+    ModeInfo {
+        name: life::NAME,
+        about: "Conway's Game of Life",
+        build: |s| Box::new(life::LifeMode::new(s.modes.life)),
+    },
+    ModeInfo {
+        name: audio::NAME,
+        about: "Live audio spectrum or waveform",
+        build: |s| Box::new(audio::AudioMode::new(s.modes.audio.clone())),
+    },
+    ModeInfo {
+        name: weather::NAME,
+        about: "Current weather and today's forecast",
+        build: |s| Box::new(weather::WeatherMode::new(s.modes.weather.clone())),
+    },
+    ModeInfo {
+        name: calendar::NAME,
+        about: "Next event from an iCalendar feed",
+        build: |s| Box::new(calendar::CalendarMode::new(s.modes.calendar.clone())),
+    },
+    ModeInfo {
+        name: headlines::NAME,
+        about: "Headlines from RSS or Atom feeds, scrolling past",
+        build: |s| Box::new(headlines::HeadlinesMode::new(s.modes.headlines.clone())),
+    },
+    ModeInfo {
+        name: now_playing::NAME,
+        about: "Track playing in MPD or an MPRIS player",
+        build: |s| Box::new(now_playing::NowPlayingMode::new(s.modes.now_playing.clone())),
+    },
+    ModeInfo {
+        name: matrix::NAME,
+        about: "Matrix-style digital rain",
+        build: |_| Box::new(matrix::MatrixMode::default()),
+    },
+    ModeInfo {
+        name: starfield::NAME,
+        about: "Flying through a starfield",
+        build: |_| Box::new(starfield::StarfieldMode::default()),
+    },
+    ModeInfo {
+        name: plasma::NAME,
+        about: "Dithered plasma effect",
+        build: |_| Box::new(plasma::PlasmaMode::default()),
+    },
+    ModeInfo {
+        name: speedometer::NAME,
+        about: "GPS speed in big digits, with the trip distance",
+        build: |s| {
+            Box::new(speedometer::SpeedometerMode::new(
+                s.modes.speedometer.clone(),
+                s.gps.clone(),
+                s.font.clone(),
+            ))
+        },
+    },
+    ModeInfo {
+        name: vehicle::NAME,
+        about: "Speed, RPM and coolant temperature over OBD-II",
+        build: |s| {
+            Box::new(vehicle::VehicleMode::new(
+                s.modes.vehicle.clone(),
+                s.obd.clone(),
+                s.font.clone(),
+            ))
+        },
+    },
+    ModeInfo {
+        name: heart_rate::NAME,
+        about: "Heart rate from a Bluetooth strap, with a beating heart",
+        build: |s| Box::new(heart_rate::HeartRateMode::new(s.heart_rate.clone(), s.font.clone())),
+    },
+    ModeInfo {
+        name: timer::NAME,
+        about: "Countdown in big digits with a ring that empties",
+        build: |s| Box::new(timer::TimerMode::new(s.modes.timer, s.font.clone())),
+    },
+    ModeInfo {
+        name: pomodoro::NAME,
+        about: "Work and break countdowns, one after the other",
+        build: |s| Box::new(pomodoro::PomodoroMode::new(s.modes.pomodoro, s.font.clone())),
+    },
+    ModeInfo {
+        name: morse::NAME,
+        about: "A message flashed in Morse code",
+        build: |s| Box::new(morse::MorseMode::new(s.modes.morse.clone())),
+    },
+    ModeInfo {
+        name: stats::NAME,
+        about: "CPU, temperature, memory and Wi-Fi gauges",
+        build: |_| Box::new(stats::StatsMode::default()),
+    },
+    ModeInfo {
+        name: imu::NAME,
+        about: "Heading and pitch from the IMU",
+        build: |s| Box::new(imu::ImuMode::new(s.orientation.clone())),
+    },
+    ModeInfo {
+        name: console::NAME,
+        about: "MCU serial port over TCP, for firmware debugging",
+        build: |s| Box::new(console::ConsoleMode::new(s.modes.console.clone())),
+    },
+    ModeInfo {
+        name: receive::NAME,
+        about: "Frames sent over TCP or UDP, rendered elsewhere",
+        build: |s| Box::new(receive::ReceiveMode::new(s.modes.receive.clone())),
+    },
+    ModeInfo {
+        name: split::NAME,
+        about: "Two modes at once, each on half the panel",
+        build: |s| Box::new(split::SplitMode::new(s)),
+    },
+    ModeInfo {
+        name: test_pattern::NAME,
+        about: "Checkerboard, bars, border or pixel walk for checking the panel",
+        build: |s| Box::new(test_pattern::TestPatternMode::new(s.modes.test_pattern)),
+    },
+    ModeInfo {
+        name: screensaver::NAME,
+        about: "Burn-in friendly idle animation",
+        build: |s| Box::new(screensaver::ScreensaverMode::new(s.screensaver_style)),
+    },
+    ModeInfo {
+        name: camera::NAME,
+        about: "Live Pi camera view",
+        build: |s| Box::new(camera::CameraMode::new(s.modes.camera.clone())),
+    },
+    ModeInfo {
+        name: screen::NAME,
+        about: "Mirror of the Pi's framebuffer",
+        build: |_| Box::new(screen::ScreenMode::new(Default::default())),
+    },
+    ModeInfo {
+        name: vnc::NAME,
+        about: "A VNC server's desktop",
+        build: |s| Box::new(vnc::VncMode::new(s.modes.vnc.clone())),
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static ModeInfo> {
+    REGISTRY.iter().find(|info| info.name == name)
+}
+
+struct Active {
+    name: &'static str,
+    mode: Box<dyn Mode>,
+    next_tick: Option<Instant>,
+}
+
+impl Active {
+    fn start(
+        name: &'static str,
+        mut mode: Box<dyn Mode>,
+        panel: &mut Compositor,
+    ) -> Result<Self> {
+        println!("[mode manager] Starting {name} mode...");
+        panel.transition_next();
+        mode.start(panel)?;
+        Ok(Self::started(name, mode))
+    }
+
+    fn started(name: &'static str, mode: Box<dyn Mode>) -> Self {
+        events::publish(HelmetEvent::Mode { mode: name });
+        hooks::fire(HookEvent::ModeChanged, &[("mode", name)]);
+        // The screensaver gives way to the saved mode anyway.
+        if name != screensaver::NAME && lookup(name).is_some() {
+            state::update(|state| state.mode = Some(name.to_string()));
+        }
+        Self { name, mode, next_tick: Some(Instant::now()) }
+    }
+
+    // Like `start`, but a mode that won't start gives way to the image mode
+    // with nothing to show, so the last frame stays up until something else
+    // is asked for.
+    fn start_or_fall_back(
+        name: &'static str,
+        mode: Box<dyn Mode>,
+        panel: &mut Compositor,
+    ) -> Self {
+        Self::start(name, mode, panel).unwrap_or_else(|e| {
+            failed(name, "start", &e);
+            Self::fallback()
+        })
+    }
+
+    fn fallback() -> Self {
+        println!("[mode manager] Falling back to {} mode...", image::NAME);
+        Self::started(image::NAME, Box::<image::ImageMode>::default())
+    }
+
+    // The mode is going either way, so failing to stop is only reported.
+    fn stop(&mut self, panel: &mut Compositor) {
+        if let Err(e) = self.mode.stop(panel) {
+            failed(self.name, "stop", &e);
+        }
+    }
+
+    fn switch(
+        &mut self,
+        info: &'static ModeInfo,
+        settings: &ModeSettings,
+        panel: &mut Compositor,
+    ) {
+        println!("[mode manager] Stopping {} mode...", self.name);
+        self.stop(panel);
+        *self = Self::start_or_fall_back(info.name, (info.build)(settings), panel);
+    }
+}
+
+// Reports a mode failing at `what`, which the manager carries on past.
+fn failed(name: &str, what: &str, e: &anyhow::Error) {
+    println!("[mode manager] {name} mode failed to {what}: {e:#}");
+    events::error(format!("{name} mode failed to {what}: {e:#}"));
+}
+
+// How often the manager re-evaluates the schedule.
+const SCHEDULE_CHECK: Duration = Duration::from_secs(15);
+
+// How often overlays are asked whether they need redrawing.
+const OVERLAY_CHECK: Duration = Duration::from_millis(100);
+
+// Drives `initial` and whatever modes `updates` or the schedule in `config`
+// switch to. Without an update source this returns once the mode runs out of
+// ticks, leaving the last frame up.
+pub fn run(
+    output: &mut dyn Panel,
+    settings: ModeSettings,
+    initial: (&'static str, Box<dyn Mode>),
+    updates: Option<&Receiver<Update>>,
+    config: Option<&Config>,
+) -> Result<()> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        manage(output, settings, initial, updates, config)
+    }));
+    if !matches!(result, Ok(Ok(()))) {
+        // Whatever was on the panel, perhaps half a frame, shouldn't stay in
+        // front of the wearer's eyes. Sending starts with the reset
+        // sequence, so a frame cut off by the panic doesn't matter.
+        println!("[mode manager] Stopped unexpectedly, blanking the panel...");
+        let (w, h) = output.dims();
+        if let Err(e) = output.show(vec![0; w * h]) {
+            println!("[mode manager] Couldn't blank the panel: {e:#}");
+        }
+    }
+    match result {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+fn manage(
+    output: &mut dyn Panel,
+    settings: ModeSettings,
+    initial: (&'static str, Box<dyn Mode>),
+    updates: Option<&Receiver<Update>>,
+    config: Option<&Config>,
+) -> Result<()> {
+    let schedule = config.map(|c| &c.schedule)
+        .filter(|schedule| !schedule.is_empty());
+    let idle_timeout = config.and_then(|c| c.screensaver.idle_timeout());
+    // Only updates can end low power, so there's none without them.
+    let low_power_timeout = config.and_then(|c| c.low_power.idle_timeout())
+        .filter(|_| updates.is_some());
+    let overlays = config.map(|c| overlay::from_config(c, &settings)).unwrap_or_default();
+    let transitions = config.map(|c| Transitions::new(&c.transition)).unwrap_or_default();
+    let panel = &mut Compositor::new(output, overlays, transitions);
+    let mut inputs = config.map(|c| InputMapper::new(c.input.clone()));
+    // Run once, with nothing to carry on with, a mode's error is the run's.
+    let mut active = match updates {
+        Some(_) => Active::start_or_fall_back(initial.0, initial.1, panel),
+        None => Active::start(initial.0, initial.1, panel)?,
+    };
+    // Manual changes hold off the schedule until this instant.
+    let mut override_until: Option<Instant> = None;
+    let mut last_update = Instant::now();
+    let mut machine = Machine::new();
+    // When a mode other than the screensaver last ticked.
+    let mut last_drawn = Instant::now();
+    loop {
+        let mut wake = active.next_tick.filter(|_| machine.state.ticks());
+        let mut wake_at = |at: Instant| {
+            wake = Some(wake.map_or(at, |w| w.min(at)));
+        };
+        if !panel.is_empty() {
+            panel.refresh()?;
+            wake_at(Instant::now() + OVERLAY_CHECK);
+            if let Some(at) = panel.next_refresh() {
+                wake_at(at);
+            }
+        }
+        // Checked whenever the loop comes round, which with the battery's
+        // overlay up is at least every `OVERLAY_CHECK`.
+        let battery = match (&settings.battery, config) {
+            (Some(rx), Some(config)) if rx.borrow().is_low(config.battery.low_percent) => {
+                Some(Trigger::BatteryLow)
+            }
+            (Some(_), Some(_)) => Some(Trigger::BatteryOk),
+            _ => None,
+        };
+        if let Some(trigger) = battery {
+            if machine.fire(trigger, &mut active, panel, &settings)? {
+                continue;
+            }
+        }
+        if let (Some(schedule), State::Active | State::LowBattery) = (schedule, machine.state) {
+            let now = Instant::now();
+            // Nor while a client has control.
+            if override_until.is_none_or(|until| now >= until) && !session::held() {
+                override_until = None;
+                let want = schedule.mode_at(Local::now().naive_local());
+                if let Some(info) = want.filter(|&name| name != active.name)
+                    .and_then(lookup)
+                {
+                    println!("[mode manager] Schedule wants {} mode.", info.name);
+                    active.switch(info, &settings, panel);
+                }
+            }
+            wake_at(now + SCHEDULE_CHECK);
+        }
+        if let (Some(timeout), State::Active) = (idle_timeout, machine.state) {
+            let idle_at = last_update + timeout;
+            if Instant::now() >= idle_at {
+                machine.fire(Trigger::Idle, &mut active, panel, &settings)?;
+                continue;
+            }
+            wake_at(idle_at);
+        }
+        let quiet = match machine.state {
+            State::Active => active.next_tick.is_none(),
+            State::Screensaver | State::LowBattery => true,
+            State::LowPower { .. } => false,
+        };
+        if let (Some(timeout), true) = (low_power_timeout, quiet) {
+            let idle_at = [Some(last_update), Some(last_drawn), low_power::last_request()]
+                .into_iter()
+                .flatten()
+                .max()
+                .unwrap()
+                + timeout;
+            if Instant::now() >= idle_at {
+                machine.fire(Trigger::Quiet, &mut active, panel, &settings)?;
+                continue;
+            }
+            wake_at(idle_at);
+        }
+        // Nothing goes to the panel until an update ends it.
+        if let State::LowPower { .. } = machine.state {
+            wake = None;
+        }
+        let update = match (wake, updates) {
+            (None, None) => {
+                active.mode.stop(panel)?;
+                return Ok(());
+            }
+            (Some(at), None) => {
+                sleep(at.saturating_duration_since(Instant::now()));
+                None
+            }
+            (None, Some(rx)) => Some(rx.recv()?),
+            (Some(at), Some(rx)) => match rx.recv_deadline(at) {
+                Ok(update) => Some(update),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(e) => Err(e)?,
+            },
+        };
+        let Some(update) = update else {
+            if machine.state.ticks() && active.next_tick.is_some_and(|at| at <= Instant::now()) {
+                active.next_tick = match active.mode.tick(panel) {
+                    Ok(delay) => delay.map(|delay| Instant::now() + delay),
+                    Err(e) if updates.is_none() => return Err(e),
+                    // Left idle until an event gives it something to do.
+                    Err(e) => {
+                        failed(active.name, "tick", &e);
+                        None
+                    }
+                };
+                if machine.state == State::Active {
+                    last_drawn = Instant::now();
+                }
+            }
+            continue;
+        };
+        jobs::taken();
+        if let State::LowPower { .. } = machine.state {
+            last_drawn = Instant::now();
+        }
+        // Measuring, flashing, waking and reattaching aren't user actions, so
+        // they don't count against idling or the schedule.
+        let user = !matches!(
+            update,
+            Update::Latency { .. } | Update::Flash { .. } | Update::Wake | Update::Reattach { .. }
+        );
+        machine.fire(Trigger::Update { user }, &mut active, panel, &settings)?;
+        if user {
+            last_update = Instant::now();
+            if let Some(schedule) = schedule {
+                override_until = Some(last_update + schedule.override_duration());
+            }
+        }
+        let event = match update {
+            Update::Latency { count, reply } => {
+                let result = latency::measure(panel, count).map_err(|e| format!("{e:#}"));
+                let _ = reply.send(result);
+                continue;
+            }
+            Update::Flash { firmware, reply } => {
+                let result = match config {
+                    Some(config) => {
+                        // Nothing reaches the port until the mode restarts.
+                        active.stop(panel);
+                        let result = panel.lend_port(&mut |port| {
+                            flash::flash(&config.flash, port, &firmware)
+                        });
+                        match active.mode.start(panel) {
+                            Ok(()) => active.next_tick = Some(Instant::now()),
+                            Err(e) => {
+                                failed(active.name, "start", &e);
+                                active = Active::fallback();
+                            }
+                        }
+                        result
+                    }
+                    None => Err(anyhow::anyhow!("flashing needs the server's config")),
+                };
+                if let Err(e) = &result {
+                    println!("[mode manager] Flashing failed: {e:#}");
+                    events::error(format!("flashing failed: {e:#}"));
+                }
+                if let Some(reply) = reply {
+                    let _ = reply.send(result.map_err(|e| format!("{e:#}")));
+                }
+                continue;
+            }
+            Update::Mode { mode, params } => {
+                let Some(info) = lookup(&mode) else {
+                    println!("[mode manager] Unknown mode {mode:?}.");
+                    events::error(format!("unknown mode {mode:?}"));
+                    continue;
+                };
+                let Some(params) = params else {
+                    active.switch(info, &settings, panel);
+                    continue;
+                };
+                match settings.modes.with_params(info.name, &params) {
+                    Ok(modes) => {
+                        let settings = ModeSettings { modes, ..settings.clone() };
+                        active.switch(info, &settings, panel);
+                    }
+                    Err(e) => {
+                        println!("[mode manager] Bad parameters for {mode}: {e:#}");
+                        events::error(format!("bad parameters for {mode}: {e:#}"));
+                    }
+                }
+                continue;
+            }
+            Update::Input { event } => {
+                events::publish(HelmetEvent::Input { event });
+                let mapped = match &mut inputs {
+                    Some(inputs) => inputs.map(event),
+                    None => Some(Mapped::Event(event)),
+                };
+                let Some(mapped) = mapped else {
+                    continue;
+                };
+                // Whatever it was, it only turns the display back on.
+                if panel.is_blank() {
+                    println!("[mode manager] Display on.");
+                    panel.set_blank(false)?;
+                    events::publish(HelmetEvent::Display { on: true });
+                    continue;
+                }
+                match mapped {
+                    Mapped::Action(Action::ZoomIn) => Event::Zoom(1),
+                    Mapped::Action(Action::ZoomOut) => Event::Zoom(-1),
+                    Mapped::Action(Action::DisplayOff) => {
+                        println!("[mode manager] Display off.");
+                        panel.set_blank(true)?;
+                        events::publish(HelmetEvent::Display { on: false });
+                        continue;
+                    }
+                    Mapped::Action(action) => {
+                        let backwards = action == Action::PreviousMode;
+                        let next = inputs.as_ref()
+                            .and_then(|inputs| inputs.config.step(active.name, backwards));
+                        // The cycle is validated with the config.
+                        if let Some(info) = next.and_then(lookup) {
+                            active.switch(info, &settings, panel);
+                        }
+                        continue;
+                    }
+                    Mapped::Event(event) => Event::Input(event),
+                }
+            }
+            Update::Coords { coords } => {
+                state::update(|state| state.coords = Some(coords.clone()));
+                if let Ok(at) = coords.parse() {
+                    track::record(at);
+                }
+                Event::Coords(coords)
+            }
+            Update::Text { text } => Event::Text(text),
+            Update::SendFile { path } => Event::File(path),
+            Update::Frame { frame } => Event::Frame(frame),
+            Update::Wake => continue,
+            Update::Reattach { was, port } => {
+                if let Err(e) = panel.reattach(&was, &port) {
+                    println!("[mode manager] Couldn't reattach to {port}: {e:#}");
+                    events::error(format!("couldn't reattach to {port}: {e:#}"));
+                }
+                continue;
+            }
+        };
+        panel.observe(&event);
+        // Streamed frames are content too, but a transition per frame would
+        // only slow them down.
+        if matches!(event, Event::Text(_) | Event::File(_)) {
+            panel.transition_next();
+        }
+        let accepted = active.mode.accepts(&event);
+        match event.mode() {
+            Some(name) if name != active.name && !accepted => {
+                let info = lookup(name).unwrap();
+                active.switch(info, &settings, panel);
+            }
+            None if !accepted => continue,
+            _ => {}
+        }
+        if let Err(e) = active.mode.handle_event(panel, event) {
+            failed(active.name, "handle an update", &e);
+        }
+        // An event can give an idle mode something to do again.
+        if active.next_tick.is_none() {
+            active.next_tick = Some(Instant::now());
+        }
+    }
+}
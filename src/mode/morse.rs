@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{screen::Region, Mode, Panel};
+use crate::framebuffer::Framebuffer;
+
+pub const NAME: &str = "morse";
+
+// Dots and dashes by character, as in ITU-R M.1677.
+const CODES: &[(char, &str)] = &[
+    ('A', ".-"), ('B', "-..."), ('C', "-.-."), ('D', "-.."), ('E', "."), ('F', "..-."),
+    ('G', "--."), ('H', "...."), ('I', ".."), ('J', ".---"), ('K', "-.-"), ('L', ".-.."),
+    ('M', "--"), ('N', "-."), ('O', "---"), ('P', ".--."), ('Q', "--.-"), ('R', ".-."),
+    ('S', "..."), ('T', "-"), ('U', "..-"), ('V', "...-"), ('W', ".--"), ('X', "-..-"),
+    ('Y', "-.--"), ('Z', "--.."),
+    ('0', "-----"), ('1', ".----"), ('2', "..---"), ('3', "...--"), ('4', "....-"),
+    ('5', "....."), ('6', "-...."), ('7', "--..."), ('8', "---.."), ('9', "----."),
+    ('.', ".-.-.-"), (',', "--..--"), ('?', "..--.."), ('\'', ".----."), ('!', "-.-.--"),
+    ('/', "-..-."), ('(', "-.--."), (')', "-.--.-"), ('&', ".-..."), (':', "---..."),
+    (';', "-.-.-."), ('=', "-...-"), ('+', ".-.-."), ('-', "-....-"), ('_', "..--.-"),
+    ('"', ".-..-."), ('$', "...-..-"), ('@', ".--.-."),
+];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MorseConfig {
+    // Also the body of `POST /api/v1/morse`.
+    pub message: String,
+    pub wpm: u32,
+    // Where to flash as `WxH+X+Y`, or everywhere if not given.
+    pub region: Option<Region>,
+    // Starts over after a word's gap; otherwise the panel goes dark once.
+    pub repeat: bool,
+}
+
+impl Default for MorseConfig {
+    fn default() -> Self {
+        Self { message: "SOS".to_owned(), wpm: 15, region: None, repeat: true }
+    }
+}
+
+impl MorseConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.message.trim().is_empty() {
+            bail!("mode.morse.message: must not be empty");
+        }
+        if let Some(c) = self.message.chars().find(|&c| !c.is_whitespace() && code(c).is_none()) {
+            bail!("mode.morse.message: {c:?} has no Morse code");
+        }
+        if !(5..=40).contains(&self.wpm) {
+            bail!("mode.morse.wpm: must be from 5 to 40");
+        }
+        Ok(())
+    }
+}
+
+fn code(c: char) -> Option<&'static str> {
+    let c = c.to_ascii_uppercase();
+    CODES.iter().find(|(key, _)| *key == c).map(|(_, code)| *code)
+}
+
+// The message as runs of the light on or off, in dots: one on for a dot,
+// three for a dash, one off between them, three between letters and seven
+// between words. It ends with the gap before starting again.
+fn keying(message: &str) -> Vec<(bool, u32)> {
+    let mut runs = Vec::new();
+    for word in message.split_whitespace() {
+        for letter in word.chars().filter_map(code) {
+            for symbol in letter.chars() {
+                runs.push((true, if symbol == '-' { 3 } else { 1 }));
+                runs.push((false, 1));
+            }
+            runs.last_mut().unwrap().1 = 3;
+        }
+        if let Some(last) = runs.last_mut() {
+            last.1 = 7;
+        }
+    }
+    runs
+}
+
+// Flashes a message in Morse on the whole panel, or part of it. Each change
+// is timed from the start, so slow frames don't add up to drift.
+pub struct MorseMode {
+    config: MorseConfig,
+    runs: Vec<(bool, u32)>,
+    dot: Duration,
+    started: Instant,
+    lit: Option<bool>,
+}
+
+impl MorseMode {
+    pub fn new(config: MorseConfig) -> Self {
+        let runs = keying(&config.message);
+        // A dot takes 1.2s at 1 wpm, "PARIS " being 50 of them.
+        let dot = Duration::from_millis(1200) / config.wpm.max(1);
+        Self { config, runs, dot, started: Instant::now(), lit: None }
+    }
+
+    fn draw(&self, lit: bool, dims: (usize, usize)) -> Vec<u8> {
+        let (w, h) = dims;
+        let Some(region) = self.config.region else {
+            return vec![if lit { 0xFF } else { 0 }; w * h];
+        };
+        let mut fb = Framebuffer::new(dims);
+        let (x, y) = (region.x as isize, region.y as isize);
+        fb.fill_rect(x, y, region.w as isize, region.h as isize, lit);
+        if !lit {
+            // So the region can be found in the dark.
+            fb.rect(x, y, region.w as isize, region.h as isize, true);
+        }
+        fb.into_pixels()
+    }
+}
+
+impl Mode for MorseMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        println!("[morse] Flashing {:?} at {} wpm...", self.config.message, self.config.wpm);
+        self.started = Instant::now();
+        self.lit = None;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let total: u32 = self.runs.iter().map(|&(_, units)| units).sum();
+        if total == 0 {
+            return Ok(None);
+        }
+        let mut elapsed = (self.started.elapsed().as_nanos() / self.dot.as_nanos()) as u32;
+        if elapsed >= total && !self.config.repeat {
+            if self.lit != Some(false) {
+                panel.show(self.draw(false, panel.dims()))?;
+                self.lit = Some(false);
+            }
+            println!("[morse] Done.");
+            return Ok(None);
+        }
+        let cycles = elapsed / total;
+        elapsed %= total;
+        let mut ends = cycles * total;
+        let mut lit = false;
+        for &(on, units) in &self.runs {
+            ends += units;
+            if elapsed < units {
+                lit = on;
+                break;
+            }
+            elapsed -= units;
+        }
+        if self.lit != Some(lit) {
+            panel.show(self.draw(lit, panel.dims()))?;
+            self.lit = Some(lit);
+        }
+        Ok(Some((self.started + self.dot * ends).saturating_duration_since(Instant::now())))
+    }
+}
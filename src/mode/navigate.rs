@@ -0,0 +1,212 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{map, Event, Mode, Panel};
+use crate::{
+    framebuffer::Framebuffer,
+    geo::LatLon,
+    route::{Route, Turn},
+    text,
+};
+
+pub const NAME: &str = "navigate";
+
+// Within this distance of the last point counts as having arrived.
+const ARRIVE_M: f64 = 20.0;
+
+// Further than this from every leg counts as being off the route.
+const OFF_ROUTE_M: f64 = 50.0;
+
+// Side of the square the turn arrow is drawn in, and of the mini-map.
+const ARROW_BOX: usize = 32;
+const MINI_MAP: usize = 32;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NavigationConfig {
+    // GPX or GeoJSON file to follow.
+    pub route: Option<PathBuf>,
+    // Direction changes gentler than this, in degrees, aren't announced.
+    pub turn_angle: f64,
+    // Keep rendering the map around the position under the arrow.
+    pub mini_map: bool,
+}
+
+impl Default for NavigationConfig {
+    fn default() -> Self {
+        Self { route: None, turn_angle: 30.0, mini_map: true }
+    }
+}
+
+struct Progress {
+    leg: usize,
+    t: f64,
+    off_route: bool,
+}
+
+pub struct NavigateMode {
+    config: NavigationConfig,
+    map: map::MapConfig,
+    route: Option<Route>,
+    turns: Vec<Turn>,
+    progress: Option<Progress>,
+}
+
+impl NavigateMode {
+    pub fn new(config: NavigationConfig, map: map::MapConfig) -> Self {
+        Self { config, map, route: None, turns: Vec::new(), progress: None }
+    }
+
+    fn draw(&self, dims: (usize, usize)) -> Result<Vec<u8>> {
+        let (Some(route), Some(progress)) = (&self.route, &self.progress) else {
+            let message = if self.route.is_none() { "No route" } else { "Waiting\nfor GPS" };
+            return Ok(text::render(message, dims));
+        };
+        let mut fb = Framebuffer::new(dims);
+        let mini = if self.config.mini_map {
+            map::mini_map(self.map.filter, dims, (MINI_MAP, MINI_MAP))?
+        } else {
+            None
+        };
+        if let Some(mini) = mini {
+            let x = (dims.0 - MINI_MAP) as isize;
+            let y = (dims.1 - MINI_MAP) as isize;
+            fb.blit(x, y, &mini, (MINI_MAP, MINI_MAP));
+        }
+
+        let last = route.points.len() - 1;
+        let to_end = route.distance_along(progress.leg, progress.t, last);
+        let next = self.turns.iter().find(|turn| turn.index > progress.leg);
+        let text_x = ARROW_BOX as isize + 2;
+        if to_end < ARRIVE_M {
+            draw_arrival(&mut fb);
+            text::draw_text(&mut fb, text_x, 12, "Here");
+        } else if progress.off_route {
+            text::draw_text(&mut fb, 0, 4, "Off");
+            text::draw_text(&mut fb, 0, 4 + text::CELL_H as isize, "route");
+        } else {
+            let (angle, index) = next.map_or((0.0, last), |turn| (turn.angle, turn.index));
+            draw_arrow(&mut fb, angle);
+            let to_turn = route.distance_along(progress.leg, progress.t, index);
+            text::draw_text(&mut fb, text_x, 12, &distance(to_turn));
+        }
+        // Left of the mini-map: what's left of the whole trip.
+        let y = (dims.1 - MINI_MAP) as isize + 8;
+        text::draw_text(&mut fb, 0, y, "Dest");
+        text::draw_text(&mut fb, 0, y + text::CELL_H as isize, &distance(to_end));
+        Ok(fb.into_pixels())
+    }
+}
+
+fn distance(metres: f64) -> String {
+    if metres < 1000.0 {
+        format!("{:.0}m", (metres / 10.0).round() * 10.0)
+    } else {
+        format!("{:.1}km", metres / 1000.0)
+    }
+}
+
+// A 3x3 brush, so arrows read at a glance.
+fn thick_line(fb: &mut Framebuffer, from: (f64, f64), to: (f64, f64)) {
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            fb.line(
+                from.0.round() as isize + dx,
+                from.1.round() as isize + dy,
+                to.0.round() as isize + dx,
+                to.1.round() as isize + dy,
+                true,
+            );
+        }
+    }
+}
+
+// Straight up for ahead, bending at the middle for a turn of `angle`
+// degrees (positive is right).
+fn draw_arrow(fb: &mut Framebuffer, angle: f64) {
+    let size = ARROW_BOX as f64;
+    let centre = (size / 2.0, size / 2.0);
+    let bottom = (centre.0, size - 3.0);
+    // Past about 150 degrees the head would land back on the shaft.
+    let angle = angle.clamp(-150.0, 150.0).to_radians();
+    let reach = size / 2.0 - 4.0;
+    let tip = (centre.0 + (reach * angle.sin()), centre.1 - (reach * angle.cos()));
+    thick_line(fb, bottom, centre);
+    thick_line(fb, centre, tip);
+    for side in [-1.0, 1.0] {
+        let back = angle + std::f64::consts::PI + (side * 0.6);
+        let end = (tip.0 + (7.0 * back.sin()), tip.1 - (7.0 * back.cos()));
+        thick_line(fb, tip, end);
+    }
+}
+
+// A target: the destination is right here.
+fn draw_arrival(fb: &mut Framebuffer) {
+    let size = ARROW_BOX as isize;
+    fb.rect(4, 4, size - 8, size - 8, true);
+    fb.rect(5, 5, size - 10, size - 10, true);
+    fb.fill_rect(12, 12, size - 24, size - 24, true);
+}
+
+impl Mode for NavigateMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.route = None;
+        self.turns.clear();
+        self.progress = None;
+        if let Some(path) = &self.config.route {
+            println!("[navigate] Loading route {}...", path.display());
+            let route = Route::load(path)?;
+            self.turns = route.turns(self.config.turn_angle);
+            println!(
+                "[navigate] {} points, {} turns.",
+                route.points.len(),
+                self.turns.len(),
+            );
+            self.route = Some(route);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        panel.show(self.draw(panel.dims())?)?;
+        Ok(None)
+    }
+
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        let Event::Coords(coords) = event else {
+            return Ok(());
+        };
+        // The control socket and the queue pass coordinates on unchecked.
+        let Ok(pos) = coords.parse::<LatLon>() else {
+            println!("[navigate] Skipping unreadable coordinates {coords:?}.");
+            return Ok(());
+        };
+        if let Some(route) = &self.route {
+            // Only search forwards, so a route that doubles back on itself
+            // doesn't snap to the wrong pass.
+            let from = self.progress.as_ref().map_or(0, |p| p.leg);
+            let (leg, t, off) = route.nearest_leg(pos, from);
+            let (leg, t, off) = if off > OFF_ROUTE_M && from > 0 {
+                route.nearest_leg(pos, 0)
+            } else {
+                (leg, t, off)
+            };
+            self.progress = Some(Progress { leg, t, off_route: off > OFF_ROUTE_M });
+        }
+        if self.config.mini_map {
+            map::load_map(coords, &self.map)?;
+        }
+        panel.show(self.draw(panel.dims())?)?;
+        Ok(())
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        matches!(event, Event::Coords(_))
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "now_playing";
+
+// Time per pixel of scrolling for lines too long to fit.
+const SCROLL_STEP: Duration = Duration::from_millis(150);
+
+const BAR_H: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    #[default]
+    Mpd,
+    // Any MPRIS player on the session bus, through `playerctl`
+    Mpris,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NowPlayingConfig {
+    pub source: Source,
+    pub mpd_addr: String,
+    // MPRIS player to follow, as passed to `playerctl -p`; any if left out.
+    pub player: Option<String>,
+}
+
+impl Default for NowPlayingConfig {
+    fn default() -> Self {
+        Self {
+            source: Source::default(),
+            mpd_addr: "localhost:6600".to_owned(),
+            player: None,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Track {
+    artist: String,
+    title: String,
+    playing: bool,
+    elapsed: Duration,
+    length: Option<Duration>,
+}
+
+// A track as last reported, and when, so the progress bar can move between
+// reports.
+struct Current {
+    track: Track,
+    at: Instant,
+}
+
+impl Current {
+    fn elapsed(&self) -> Duration {
+        let elapsed = if self.track.playing {
+            self.track.elapsed + self.at.elapsed()
+        } else {
+            self.track.elapsed
+        };
+        self.track.length.map_or(elapsed, |length| elapsed.min(length))
+    }
+}
+
+// One MPD command, returning its `key: value` lines.
+fn mpd_command(
+    stream: &mut TcpStream,
+    reader: &mut impl BufRead,
+    command: &str,
+) -> Result<Vec<(String, String)>> {
+    stream.write_all(format!("{command}\n").as_bytes())?;
+    let mut pairs = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("MPD closed the connection");
+        }
+        let line = line.trim_end();
+        if line == "OK" {
+            return Ok(pairs);
+        }
+        if let Some(e) = line.strip_prefix("ACK ") {
+            bail!("MPD: {e}");
+        }
+        if let Some((key, value)) = line.split_once(": ") {
+            pairs.push((key.to_owned(), value.to_owned()));
+        }
+    }
+}
+
+fn mpd_track(stream: &mut TcpStream, reader: &mut impl BufRead) -> Result<Track> {
+    let mut track = Track::default();
+    let secs = |value: &str| value.parse::<f64>().ok().map(Duration::from_secs_f64);
+    for (key, value) in mpd_command(stream, reader, "currentsong")? {
+        match key.as_str() {
+            "Artist" => track.artist = value,
+            "Title" => track.title = value,
+            // No tags at all, e.g. a bare stream URL
+            "file" if track.title.is_empty() => track.title = value,
+            _ => {}
+        }
+    }
+    for (key, value) in mpd_command(stream, reader, "status")? {
+        match key.as_str() {
+            "state" => track.playing = value == "play",
+            "elapsed" => track.elapsed = secs(&value).unwrap_or_default(),
+            "duration" => track.length = secs(&value),
+            _ => {}
+        }
+    }
+    Ok(track)
+}
+
+// Reports the current track, then blocks in MPD's `idle` until the player
+// state changes and reports it again.
+fn follow_mpd(addr: &str, tx: Sender<Option<Track>>) -> Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut greeting = String::new();
+    reader.read_line(&mut greeting)?;
+    if !greeting.starts_with("OK MPD") {
+        bail!("{addr} is not an MPD server");
+    }
+    loop {
+        let track = mpd_track(&mut stream, &mut reader)?;
+        let track = (!track.title.is_empty()).then_some(track);
+        if tx.send(track).is_err() {
+            return Ok(());
+        }
+        mpd_command(&mut stream, &mut reader, "idle player")?;
+    }
+}
+
+// `playerctl --follow` prints a line whenever the track or playback status
+// changes.
+const PLAYERCTL_FORMAT: &str =
+    "{{status}}\t{{position}}\t{{mpris:length}}\t{{artist}}\t{{title}}";
+
+fn parse_playerctl(line: &str) -> Option<Track> {
+    let mut fields = line.splitn(5, '\t');
+    let status = fields.next()?;
+    let micros = |s: &str| s.parse::<u64>().ok().map(Duration::from_micros);
+    let elapsed = micros(fields.next()?).unwrap_or_default();
+    let length = micros(fields.next()?);
+    let artist = fields.next()?.to_owned();
+    let title = fields.next()?.to_owned();
+    if title.is_empty() || status == "Stopped" {
+        return None;
+    }
+    Some(Track { artist, title, playing: status == "Playing", elapsed, length })
+}
+
+pub struct NowPlayingMode {
+    config: NowPlayingConfig,
+    child: Option<Child>,
+    tracks: Option<Receiver<Option<Track>>>,
+    current: Option<Current>,
+    scroll: usize,
+}
+
+impl NowPlayingMode {
+    pub fn new(config: NowPlayingConfig) -> Self {
+        Self { config, child: None, tracks: None, current: None, scroll: 0 }
+    }
+
+    fn draw(&self, dims: (usize, usize)) -> (Vec<u8>, bool) {
+        let (w, h) = dims;
+        let mut fb = Framebuffer::new(dims);
+        let Some(current) = &self.current else {
+            return (text::render("Nothing\nplaying", dims), false);
+        };
+        let track = &current.track;
+        let title_fits = text::scrolling_line(&mut fb, 4, &track.title, self.scroll);
+        let artist_fits = text::scrolling_line(&mut fb, 16, &track.artist, self.scroll);
+
+        let elapsed = current.elapsed();
+        let status = match (track.playing, track.length) {
+            (false, _) => "Paused".to_owned(),
+            (true, Some(length)) => {
+                format!("{}/{}", minutes(elapsed), minutes(length))
+            }
+            (true, None) => minutes(elapsed),
+        };
+        text::draw_text(&mut fb, 0, 32, &status);
+
+        let bar_y = (h - BAR_H) as isize;
+        fb.rect(0, bar_y, w as isize, BAR_H as isize, true);
+        if let Some(length) = track.length.filter(|l| !l.is_zero()) {
+            let inner = (w - 2) as f64;
+            let filled = (inner * elapsed.as_secs_f64() / length.as_secs_f64()) as isize;
+            fb.fill_rect(1, bar_y + 1, filled, BAR_H as isize - 2, true);
+        }
+        (fb.into_pixels(), !(title_fits && artist_fits))
+    }
+}
+
+fn minutes(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+impl Mode for NowPlayingMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        let (tx, rx) = unbounded();
+        match self.config.source {
+            Source::Mpd => {
+                let addr = self.config.mpd_addr.clone();
+                println!("[now playing] Following MPD at {addr}...");
+                spawn(move || {
+                    if let Err(e) = follow_mpd(&addr, tx) {
+                        println!("[now playing] MPD error: {e}");
+                    }
+                });
+            }
+            Source::Mpris => {
+                let mut cmd = Command::new("playerctl");
+                if let Some(player) = &self.config.player {
+                    cmd.args(["-p", player]);
+                }
+                cmd.args(["--follow", "metadata", "--format", PLAYERCTL_FORMAT])
+                    .stdout(Stdio::piped());
+                println!("[now playing] Following MPRIS players...");
+                let mut child = cmd.spawn()?;
+                let stdout = child.stdout.take()
+                    .ok_or_else(|| anyhow!("playerctl stdout not captured"))?;
+                spawn(move || -> Result<()> {
+                    for line in BufReader::new(stdout).lines() {
+                        if tx.send(parse_playerctl(&line?)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(())
+                });
+                self.child = Some(child);
+            }
+        }
+        self.tracks = Some(rx);
+        self.current = None;
+        self.scroll = 0;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        if let Some(tracks) = &self.tracks {
+            let mut changed = false;
+            while let Ok(track) = tracks.try_recv() {
+                self.current = track.map(|track| Current { track, at: Instant::now() });
+                changed = true;
+            }
+            if changed {
+                self.scroll = 0;
+            }
+        }
+        let (frame, scrolling) = self.draw(panel.dims());
+        panel.show(frame)?;
+        if scrolling {
+            self.scroll += 1;
+            Ok(Some(SCROLL_STEP))
+        } else {
+            // Often enough for the progress bar and for noticing a new track.
+            Ok(Some(Duration::from_secs(1)))
+        }
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        // The MPD thread notices on its next event that nobody is listening.
+        self.tracks = None;
+        if let Some(mut child) = self.child.take() {
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(())
+    }
+}
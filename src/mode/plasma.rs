@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::filter;
+
+pub const NAME: &str = "plasma";
+
+const FRAME: Duration = Duration::from_millis(100);
+
+// Classic sum-of-sines plasma, dithered down to 1 bit.
+pub struct PlasmaMode {
+    start: Instant,
+}
+
+impl Default for PlasmaMode {
+    fn default() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Mode for PlasmaMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.start = Instant::now();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let start = Instant::now();
+        let dims = panel.dims();
+        let (w, h) = dims;
+        let t = self.start.elapsed().as_secs_f32();
+        let mut gray = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let (fx, fy) = (x as f32 / w as f32, y as f32 / h as f32);
+                let (dx, dy) = (fx - 0.5 - (t * 0.3).sin() * 0.3, fy - 0.5);
+                let v = (fx * 10.0 + t).sin()
+                    + ((fy * 8.0) - (t * 1.3)).sin()
+                    + ((fx + fy) * 6.0 + t * 0.7).sin()
+                    + (((dx * dx) + (dy * dy)).sqrt() * 14.0 - t * 2.0).sin();
+                // v is in [-4, 4].
+                gray.push((((v + 4.0) / 8.0) * 255.0) as u8);
+            }
+        }
+        panel.show(filter::dither(&gray, dims))?;
+        Ok(Some(FRAME.saturating_sub(start.elapsed())))
+    }
+}
@@ -0,0 +1,78 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{timer::Countdown, Mode, Panel};
+use crate::ttf::Font;
+
+pub const NAME: &str = "pomodoro";
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PomodoroConfig {
+    pub work_minutes: u64,
+    pub break_minutes: u64,
+}
+
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        Self { work_minutes: 25, break_minutes: 5 }
+    }
+}
+
+impl PomodoroConfig {
+    pub fn validate(&self) -> Result<()> {
+        let periods = [("work_minutes", self.work_minutes), ("break_minutes", self.break_minutes)];
+        for (key, minutes) in periods {
+            if !(1..=24 * 60).contains(&minutes) {
+                bail!("mode.pomodoro.{key}: must be from 1 to 1440");
+            }
+        }
+        Ok(())
+    }
+}
+
+// Timers for work and breaks, one after the other until switched away from.
+pub struct PomodoroMode {
+    config: PomodoroConfig,
+    font: Arc<Font>,
+    working: bool,
+    countdown: Option<Countdown>,
+}
+
+impl PomodoroMode {
+    pub fn new(config: PomodoroConfig, font: Arc<Font>) -> Self {
+        Self { config, font, working: true, countdown: None }
+    }
+
+    fn next_period(&mut self) -> Countdown {
+        let (minutes, label) = if self.working {
+            (self.config.work_minutes, "work")
+        } else {
+            (self.config.break_minutes, "break")
+        };
+        println!("[pomodoro] Starting {minutes} minutes of {label}...");
+        Countdown::new(Duration::from_secs(minutes * 60), Some(label))
+    }
+}
+
+impl Mode for PomodoroMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.working = true;
+        self.countdown = Some(self.next_period());
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(countdown) = &mut self.countdown else {
+            return Ok(None);
+        };
+        if let Some(next) = countdown.tick(&self.font, panel)? {
+            return Ok(Some(next));
+        }
+        self.working = !self.working;
+        self.countdown = Some(self.next_period());
+        Ok(Some(Duration::ZERO))
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    io::{self, Read},
+    net::{TcpListener, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{rawframe::read_frame, text};
+
+pub const NAME: &str = "receive";
+
+// How long the receiving thread waits on the network before checking whether
+// the mode has stopped, and the longest a tick waits for a frame.
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    // One sender at a time, streaming frames back to back.
+    #[default]
+    Tcp,
+    // One frame to a datagram, from anyone.
+    Udp,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReceiveConfig {
+    pub listen: String,
+    pub transport: Transport,
+    // Frames arriving faster than this are skipped, keeping the newest.
+    pub max_fps: f64,
+}
+
+impl Default for ReceiveConfig {
+    fn default() -> Self {
+        Self { listen: "0.0.0.0:7777".into(), transport: Transport::Tcp, max_fps: 20.0 }
+    }
+}
+
+impl ReceiveConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.max_fps > 0.0 && self.max_fps <= 1000.0) {
+            bail!("mode.receive.max_fps: must be more than 0 and at most 1000");
+        }
+        Ok(())
+    }
+}
+
+// Shows frames sent over the network in the `rawframe` format, as `--stdin`
+// takes them, so something else can do the rendering.
+pub struct ReceiveMode {
+    config: ReceiveConfig,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    frames: Option<Receiver<Vec<u8>>>,
+    last_shown: Option<Instant>,
+}
+
+impl ReceiveMode {
+    pub fn new(config: ReceiveConfig) -> Self {
+        Self {
+            config,
+            stop: Arc::default(),
+            thread: None,
+            frames: None,
+            last_shown: None,
+        }
+    }
+}
+
+// Waits out read timeouts until `stop` is set, so a frame that's slow to
+// arrive isn't cut in half.
+struct UntilStopped<R> {
+    inner: R,
+    stop: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for UntilStopped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.inner.read(buf) {
+                Err(e) if is_timeout(&e) && !self.stop.load(Ordering::Relaxed) => continue,
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+fn receive_tcp(
+    listener: TcpListener,
+    dims: (usize, usize),
+    tx: Sender<Vec<u8>>,
+    stop: Arc<AtomicBool>,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        let (client, addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) if is_timeout(&e) => {
+                thread::sleep(POLL);
+                continue;
+            }
+            Err(e) => {
+                println!("[receive] Couldn't accept a sender: {e}");
+                thread::sleep(POLL);
+                continue;
+            }
+        };
+        println!("[receive] {addr} connected.");
+        let result = client.set_nonblocking(false)
+            .and_then(|()| client.set_read_timeout(Some(POLL)));
+        if let Err(e) = result {
+            println!("[receive] Dropping {addr}: {e}");
+            continue;
+        }
+        let mut input = UntilStopped { inner: client, stop: stop.clone() };
+        let mut frames = 0;
+        loop {
+            match read_frame(&mut input, dims) {
+                Ok(Some(frame)) => {
+                    frames += 1;
+                    let _ = tx.send(frame);
+                }
+                Ok(None) => {
+                    println!("[receive] {addr} disconnected after {frames} frames.");
+                    break;
+                }
+                // Past a bad frame there's no telling where the next starts.
+                Err(e) => {
+                    println!("[receive] Dropping {addr} after {frames} frames: {e:#}");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn receive_udp(
+    socket: UdpSocket,
+    dims: (usize, usize),
+    tx: Sender<Vec<u8>>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut buf = vec![0u8; 65536];
+    while !stop.load(Ordering::Relaxed) {
+        let (n, addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => {
+                println!("[receive] Couldn't receive: {e}");
+                thread::sleep(POLL);
+                continue;
+            }
+        };
+        match read_frame(&mut &buf[..n], dims) {
+            Ok(Some(frame)) => {
+                let _ = tx.send(frame);
+            }
+            Ok(None) => {}
+            Err(e) => println!("[receive] Skipping a datagram from {addr}: {e:#}"),
+        }
+    }
+}
+
+impl Mode for ReceiveMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let dims = panel.dims();
+        let (tx, rx) = unbounded();
+        self.stop = Arc::default();
+        let stop = self.stop.clone();
+        let (thread, transport) = match self.config.transport {
+            Transport::Tcp => {
+                let listener = TcpListener::bind(&self.config.listen)?;
+                listener.set_nonblocking(true)?;
+                (thread::spawn(move || receive_tcp(listener, dims, tx, stop)), "TCP")
+            }
+            Transport::Udp => {
+                let socket = UdpSocket::bind(&self.config.listen)?;
+                socket.set_read_timeout(Some(POLL))?;
+                (thread::spawn(move || receive_udp(socket, dims, tx, stop)), "UDP")
+            }
+        };
+        println!("[receive] Listening for frames over {transport} on {}...", self.config.listen);
+        self.thread = Some(thread);
+        self.frames = Some(rx);
+        self.last_shown = None;
+        panel.show(text::render("waiting\nfor\nframes", dims))
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(frames) = &self.frames else {
+            return Ok(None);
+        };
+        if let Some(last_shown) = self.last_shown {
+            let next = last_shown + Duration::from_secs_f64(1.0 / self.config.max_fps);
+            let now = Instant::now();
+            if now < next {
+                return Ok(Some(next - now));
+            }
+        }
+        let mut frame = match frames.recv_timeout(POLL) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(Duration::ZERO)),
+            Err(RecvTimeoutError::Disconnected) => bail!("the frame receiver stopped"),
+        };
+        // Only the newest frame matters; the rest are already stale.
+        while let Ok(newer) = frames.try_recv() {
+            frame = newer;
+        }
+        panel.show(frame)?;
+        self.last_shown = Some(Instant::now());
+        Ok(Some(Duration::ZERO))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.frames = None;
+        // So the port is free again by the time this mode can restart.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+}
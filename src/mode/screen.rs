@@ -0,0 +1,237 @@
+use std::{
+    fmt,
+    fs,
+    process::Command,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::filter;
+
+pub const NAME: &str = "screen";
+
+const FRAMEBUFFER_DEV: &str = "/dev/fb0";
+const FRAMEBUFFER_SYSFS: &str = "/sys/class/graphics/fb0";
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ScreenSource {
+    /// Read the Linux framebuffer device directly
+    Fb,
+    /// Capture a Wayland output with grim
+    Wayland,
+    /// Capture the X11 root window with ImageMagick's import
+    X11,
+}
+
+// A capture rectangle in X geometry syntax, `WxH+X+Y`. Also how other
+// modes' configs give part of the panel.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || anyhow!("expected WxH+X+Y, got {s:?}");
+        let (size, offset) = s.split_once('+').ok_or_else(bad)?;
+        let (w, h) = size.split_once('x').ok_or_else(bad)?;
+        let (x, y) = offset.split_once('+').ok_or_else(bad)?;
+        let region = Self {
+            x: x.parse()?,
+            y: y.parse()?,
+            w: w.parse()?,
+            h: h.parse()?,
+        };
+        if region.w == 0 || region.h == 0 {
+            bail!("region {s:?} is empty");
+        }
+        Ok(region)
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.to_string()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.w, self.h, self.x, self.y)
+    }
+}
+
+pub struct ScreenOpts {
+    pub source: ScreenSource,
+    pub region: Region,
+    pub interval: Duration,
+}
+
+impl Default for ScreenOpts {
+    fn default() -> Self {
+        Self {
+            source: ScreenSource::Fb,
+            region: Region { x: 0, y: 0, w: 256, h: 256 },
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+pub struct ScreenMode {
+    opts: ScreenOpts,
+}
+
+impl ScreenMode {
+    pub fn new(opts: ScreenOpts) -> Self {
+        Self { opts }
+    }
+}
+
+impl Mode for ScreenMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let start = Instant::now();
+        let r = self.opts.region;
+        let luma = match self.opts.source {
+            ScreenSource::Fb => grab_framebuffer(r)?,
+            ScreenSource::Wayland => grab_command(
+                Command::new("grim")
+                    .args(["-t", "ppm", "-g"])
+                    .arg(format!("{},{} {}x{}", r.x, r.y, r.w, r.h))
+                    .arg("-"),
+                r,
+            )?,
+            ScreenSource::X11 => grab_command(
+                Command::new("import")
+                    .args(["-window", "root", "-crop"])
+                    .arg(format!("{}x{}+{}+{}", r.w, r.h, r.x, r.y))
+                    .arg("ppm:-"),
+                r,
+            )?,
+        };
+        let dims = panel.dims();
+        let small = filter::downscale(&luma, (r.w, r.h), dims);
+        panel.show(filter::dither(&small, dims))?;
+        let elapsed = start.elapsed();
+        println!("[screen] Mirrored frame in {:.2?}ms.", elapsed.as_millis());
+        Ok(Some(self.opts.interval.saturating_sub(elapsed)))
+    }
+}
+
+fn grab_command(cmd: &mut Command, region: Region) -> Result<Vec<u8>> {
+    let output = cmd.output()?;
+    output.status.exit_ok()?;
+    let (dims, luma) = parse_ppm(&output.stdout)?;
+    if dims != (region.w, region.h) {
+        bail!(
+            "capture returned {}x{}, expected {}x{}",
+            dims.0, dims.1, region.w, region.h,
+        );
+    }
+    Ok(luma)
+}
+
+// Binary (P6) PPM with 8-bit samples, converted to luma.
+fn parse_ppm(data: &[u8]) -> Result<((usize, usize), Vec<u8>)> {
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if data.get(pos) == Some(&b'#') {
+            while pos < data.len() && data[pos] != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            bail!("truncated PPM header");
+        }
+        fields.push(std::str::from_utf8(&data[start..pos])?);
+    }
+    // Exactly one whitespace byte separates the header from the samples.
+    pos += 1;
+    if fields[0] != "P6" {
+        bail!("unsupported PPM magic {:?}", fields[0]);
+    }
+    let w: usize = fields[1].parse()?;
+    let h: usize = fields[2].parse()?;
+    if fields[3] != "255" {
+        bail!("unsupported PPM maxval {}", fields[3]);
+    }
+    let pixels = data.get(pos..(pos + (w * h * 3)))
+        .ok_or_else(|| anyhow!("truncated PPM data"))?;
+    let luma = pixels.chunks_exact(3)
+        .map(|px| filter::luma(px[0], px[1], px[2]))
+        .collect();
+    Ok(((w, h), luma))
+}
+
+fn read_sysfs(name: &str) -> Result<String> {
+    let path = format!("{FRAMEBUFFER_SYSFS}/{name}");
+    let value = fs::read_to_string(&path).with_context(|| path.clone())?;
+    Ok(value.trim().to_owned())
+}
+
+fn grab_framebuffer(region: Region) -> Result<Vec<u8>> {
+    let bpp: usize = read_sysfs("bits_per_pixel")?.parse()?;
+    let stride: usize = read_sysfs("stride")?.parse()?;
+    let size = read_sysfs("virtual_size")?;
+    let (fw, fh) = size.split_once(',')
+        .ok_or_else(|| anyhow!("bad virtual_size {size:?}"))?;
+    let (fw, fh): (usize, usize) = (fw.parse()?, fh.parse()?);
+    if region.x + region.w > fw || region.y + region.h > fh {
+        bail!("capture region is outside the {fw}x{fh} framebuffer");
+    }
+    let fb = fs::read(FRAMEBUFFER_DEV)?;
+    let mut luma = Vec::with_capacity(region.w * region.h);
+    for y in region.y..(region.y + region.h) {
+        let row = &fb[(y * stride)..];
+        for x in region.x..(region.x + region.w) {
+            luma.push(match bpp {
+                16 => {
+                    let px = u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]);
+                    filter::luma(
+                        ((px >> 11) << 3) as u8,
+                        (((px >> 5) & 0x3F) << 2) as u8,
+                        ((px & 0x1F) << 3) as u8,
+                    )
+                }
+                24 | 32 => {
+                    // Little-endian XRGB/RGB: blue sits at the lowest address.
+                    let px = &row[(x * bpp / 8)..];
+                    filter::luma(px[2], px[1], px[0])
+                }
+                _ => bail!("unsupported framebuffer depth {bpp}"),
+            });
+        }
+    }
+    Ok(luma)
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "screensaver";
+
+const LOGO: &str = "FETT";
+
+// Slow on purpose: the point is to keep any one pixel from staying lit, not
+// to be watched. The panel firmware has no brightness command, so there's no
+// dimming besides lighting fewer pixels.
+const STEP: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    // A single lit pixel wandering around the panel
+    #[default]
+    Pixel,
+    // A small word bouncing off the edges
+    Logo,
+    // Everything off
+    Blank,
+}
+
+pub struct ScreensaverMode {
+    style: Style,
+    pos: (isize, isize),
+    vel: (isize, isize),
+}
+
+impl ScreensaverMode {
+    pub fn new(style: Style) -> Self {
+        Self { style, pos: (0, 0), vel: (1, 1) }
+    }
+}
+
+impl Mode for ScreensaverMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let (w, h) = panel.dims();
+        self.pos = (
+            rand::random_range(0..w as i64) as isize,
+            rand::random_range(0..h as i64) as isize,
+        );
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let dims = panel.dims();
+        let (w, h) = (dims.0 as isize, dims.1 as isize);
+        let mut fb = Framebuffer::new(dims);
+        match self.style {
+            Style::Blank => {
+                panel.show(fb.into_pixels())?;
+                return Ok(None);
+            }
+            Style::Pixel => {
+                let (x, y) = self.pos;
+                self.pos = (
+                    (x + rand::random_range(-1..=1i64) as isize).clamp(0, w - 1),
+                    (y + rand::random_range(-1..=1i64) as isize).clamp(0, h - 1),
+                );
+                fb.set(self.pos.0, self.pos.1, true);
+            }
+            Style::Logo => {
+                let logo_w = (LOGO.len() * text::CELL_W) as isize - 1;
+                let logo_h = text::GLYPH_H as isize;
+                let max = (w - logo_w, h - logo_h);
+                let (mut x, mut y) = (self.pos.0 + self.vel.0, self.pos.1 + self.vel.1);
+                if x <= 0 || x >= max.0 {
+                    self.vel.0 = -self.vel.0;
+                    x = x.clamp(0, max.0.max(0));
+                }
+                if y <= 0 || y >= max.1 {
+                    self.vel.1 = -self.vel.1;
+                    y = y.clamp(0, max.1.max(0));
+                }
+                self.pos = (x, y);
+                text::draw_text(&mut fb, x, y, LOGO);
+            }
+        }
+        panel.show(fb.into_pixels())?;
+        Ok(Some(STEP))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreensaverConfig {
+    // Minutes without updates before the screensaver starts; 0 disables it.
+    pub idle_minutes: u64,
+    pub style: Style,
+}
+
+impl Default for ScreensaverConfig {
+    fn default() -> Self {
+        Self { idle_minutes: 10, style: Style::default() }
+    }
+}
+
+impl ScreensaverConfig {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_minutes > 0).then(|| Duration::from_secs(self.idle_minutes * 60))
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "script";
+
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+const DEFAULT_TICK_MILLIS: i64 = 500;
+
+// Upper bound on work per `tick` call, so a runaway loop in a script errors
+// out instead of hanging the display.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn list_scripts(dir: impl AsRef<Path>) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION) {
+            if let Some(stem) = path.file_stem() {
+                names.push(stem.to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// Registers the host API. Every drawing call goes to `fb`; `interval(ms)`
+// changes how often the host calls the script's `tick`.
+fn build_engine(
+    fb: Rc<RefCell<Framebuffer>>,
+    tick_millis: Rc<Cell<i64>>,
+    start: Instant,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let (w, h) = fb.borrow().dims;
+    engine.register_fn("width", move || w as i64);
+    engine.register_fn("height", move || h as i64);
+    let f = fb.clone();
+    engine.register_fn("clear", move || f.borrow_mut().clear());
+    let f = fb.clone();
+    engine.register_fn("set", move |x: i64, y: i64, on: bool| {
+        f.borrow_mut().set(x as isize, y as isize, on)
+    });
+    let f = fb.clone();
+    engine.register_fn("line", move |x0: i64, y0: i64, x1: i64, y1: i64| {
+        f.borrow_mut().line(x0 as isize, y0 as isize, x1 as isize, y1 as isize, true)
+    });
+    let f = fb.clone();
+    engine.register_fn("rect", move |x: i64, y: i64, w: i64, h: i64| {
+        f.borrow_mut().rect(x as isize, y as isize, w as isize, h as isize, true)
+    });
+    let f = fb.clone();
+    engine.register_fn("fill_rect", move |x: i64, y: i64, w: i64, h: i64| {
+        f.borrow_mut().fill_rect(x as isize, y as isize, w as isize, h as isize, true)
+    });
+    let f = fb;
+    engine.register_fn("text", move |x: i64, y: i64, s: &str| {
+        text::draw_text(&mut f.borrow_mut(), x as isize, y as isize, s)
+    });
+    engine.register_fn("millis", move || start.elapsed().as_millis() as i64);
+    engine.register_fn("interval", move |ms: i64| tick_millis.set(ms.max(1)));
+    engine.register_fn(
+        "http_get",
+        |url: &str| -> Result<String, Box<EvalAltResult>> {
+            let agent = ureq::Agent::config_builder()
+                .timeout_global(Some(HTTP_TIMEOUT))
+                .build()
+                .new_agent();
+            agent.get(url).call()
+                .and_then(|mut resp| resp.body_mut().read_to_string())
+                .map_err(|e| e.to_string().into())
+        },
+    );
+    engine
+}
+
+// Calls a script function with `this` bound to the script's state map,
+// which is how scripts keep values between ticks.
+fn call(
+    engine: &Engine,
+    scope: &mut Scope,
+    ast: &AST,
+    state: &mut Dynamic,
+    name: &str,
+) -> Result<()> {
+    let options = CallFnOptions::new()
+        .eval_ast(false)
+        .bind_this_ptr(state);
+    engine.call_fn_with_options::<()>(options, scope, ast, name, ())
+        .map_err(|e| anyhow!("script {name} failed: {e}"))
+}
+
+struct Loaded {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    fb: Rc<RefCell<Framebuffer>>,
+    tick_millis: Rc<Cell<i64>>,
+    last_sent: Option<Vec<u8>>,
+}
+
+pub struct ScriptMode {
+    path: PathBuf,
+    loaded: Option<Loaded>,
+}
+
+impl ScriptMode {
+    pub fn new(dir: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let path = dir.as_ref().join(name).with_extension(SCRIPT_EXTENSION);
+        if !path.exists() {
+            bail!("no script at {path:?}");
+        }
+        Ok(Self { path, loaded: None })
+    }
+}
+
+impl Mode for ScriptMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let fb = Rc::new(RefCell::new(Framebuffer::new(panel.dims())));
+        let tick_millis = Rc::new(Cell::new(DEFAULT_TICK_MILLIS));
+        let engine = build_engine(fb.clone(), tick_millis.clone(), Instant::now());
+        println!("[script] Loading {:?}...", self.path);
+        let ast = engine.compile_file(self.path.clone())
+            .map_err(|e| anyhow!("script failed to compile: {e}"))?;
+        let mut scope = Scope::new();
+        let mut state = Dynamic::from_map(Map::new());
+        if ast.iter_functions().any(|f| f.name == "init") {
+            call(&engine, &mut scope, &ast, &mut state, "init")?;
+        }
+        self.loaded = Some(Loaded {
+            engine, ast, scope, state, fb, tick_millis,
+            last_sent: None,
+        });
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(s) = &mut self.loaded else {
+            return Ok(None);
+        };
+        let start = Instant::now();
+        call(&s.engine, &mut s.scope, &s.ast, &mut s.state, "tick")?;
+        let pixels = s.fb.borrow().pixels.clone();
+        if s.last_sent.as_ref() != Some(&pixels) {
+            panel.show(pixels.clone())?;
+            s.last_sent = Some(pixels);
+        }
+        let interval = Duration::from_millis(s.tick_millis.get() as u64);
+        Ok(Some(interval.saturating_sub(start.elapsed())))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.loaded = None;
+        Ok(())
+    }
+}
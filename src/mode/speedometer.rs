@@ -0,0 +1,137 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, gps::GpsRx, text, ttf::Font};
+
+pub const NAME: &str = "speedometer";
+
+// Space between the speed and the unit and trip readout under it.
+const GAP: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedUnit {
+    Kmh,
+    Mph,
+    Knots,
+    // Metres per second, with the trip in kilometres.
+    Ms,
+}
+
+impl SpeedUnit {
+    // Per metre per second, and per metre of trip, with their labels.
+    fn speed(self) -> (f64, &'static str) {
+        match self {
+            SpeedUnit::Kmh => (3.6, "km/h"),
+            SpeedUnit::Mph => (2.236_936, "mph"),
+            SpeedUnit::Knots => (1.943_844, "kn"),
+            SpeedUnit::Ms => (1.0, "m/s"),
+        }
+    }
+
+    fn distance(self) -> (f64, &'static str) {
+        match self {
+            SpeedUnit::Kmh | SpeedUnit::Ms => (1.0 / 1000.0, "km"),
+            SpeedUnit::Mph => (1.0 / 1609.344, "mi"),
+            SpeedUnit::Knots => (1.0 / 1852.0, "nm"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedometerConfig {
+    pub units: SpeedUnit,
+    pub refresh_millis: u64,
+}
+
+impl Default for SpeedometerConfig {
+    fn default() -> Self {
+        Self { units: SpeedUnit::Kmh, refresh_millis: 250 }
+    }
+}
+
+#[derive(PartialEq)]
+enum Shown {
+    Waiting,
+    // Speed, its unit and the trip so far.
+    Reading(String, &'static str, String),
+}
+
+// Current speed from gpsd in big digits, and the trip so far under it.
+pub struct SpeedometerMode {
+    config: SpeedometerConfig,
+    gps: Option<GpsRx>,
+    font: Arc<Font>,
+    // What's on the panel, so unchanged readings aren't sent again.
+    drawn: Option<Shown>,
+}
+
+impl SpeedometerMode {
+    pub fn new(config: SpeedometerConfig, gps: Option<GpsRx>, font: Arc<Font>) -> Self {
+        Self { config, gps, font, drawn: None }
+    }
+
+    fn draw(&self, speed: &str, small: [&str; 2], dims: (usize, usize)) -> Vec<u8> {
+        // As big as fits above the small lines, whatever the font size.
+        let room = dims.1.saturating_sub(GAP + text::CELL_H + text::GLYPH_H);
+        let mut size = self.font.fit(speed, dims.0, room as f32);
+        while size > 1.0 && self.font.measure(speed, size).1 > room {
+            size -= 1.0;
+        }
+        let (speed_w, speed_h) = self.font.measure(speed, size);
+        let mut y = dims.1.saturating_sub(speed_h + GAP + text::CELL_H + text::GLYPH_H) / 2;
+        let mut fb = Framebuffer::new(dims);
+        let x = dims.0.saturating_sub(speed_w) / 2;
+        self.font.draw(&mut fb, x as isize, y as isize, speed, size);
+        y += speed_h + GAP;
+        for line in small {
+            let x = dims.0.saturating_sub((line.len() * text::CELL_W).saturating_sub(1)) / 2;
+            text::draw_text(&mut fb, x as isize, y as isize, line);
+            y += text::CELL_H;
+        }
+        fb.into_pixels()
+    }
+}
+
+impl Mode for SpeedometerMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.drawn = None;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(rx) = &self.gps else {
+            panel.show(text::render("No GPS", panel.dims()))?;
+            return Ok(None);
+        };
+        let fix = *rx.borrow();
+        let shown = match fix {
+            Some(fix) => {
+                let (per_ms, unit) = self.config.units.speed();
+                let (per_m, distance_unit) = self.config.units.distance();
+                let speed = match fix.speed {
+                    Some(speed) => format!("{:.0}", speed * per_ms),
+                    None => "--".to_owned(),
+                };
+                let trip = format!("{:.1} {distance_unit}", fix.trip_m * per_m);
+                Shown::Reading(speed, unit, trip)
+            }
+            None => Shown::Waiting,
+        };
+        // The panel only takes whole frames, so the saving is in not
+        // sending ones that wouldn't change anything.
+        if self.drawn.as_ref() != Some(&shown) {
+            let frame = match &shown {
+                Shown::Waiting => text::render("Waiting\nfor GPS", panel.dims()),
+                Shown::Reading(speed, unit, trip) => self.draw(speed, [unit, trip], panel.dims()),
+            };
+            panel.show(frame)?;
+            self.drawn = Some(shown);
+        }
+        Ok(Some(Duration::from_millis(self.config.refresh_millis.max(50))))
+    }
+}
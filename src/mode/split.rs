@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{lookup, Event, Mode, ModeSettings, Panel};
+
+pub const NAME: &str = "split";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arrangement {
+    // The first pane on top.
+    #[default]
+    TopBottom,
+    // The first pane on the left.
+    LeftRight,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplitConfig {
+    // Two modes from the registry, each drawing into its half as if it were
+    // the whole panel.
+    pub panes: Vec<String>,
+    pub arrangement: Arrangement,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        Self {
+            panes: vec!["map".to_owned(), "speedometer".to_owned()],
+            arrangement: Arrangement::TopBottom,
+        }
+    }
+}
+
+impl SplitConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.panes.len() != 2 {
+            bail!("mode.split.panes: must name two modes");
+        }
+        for name in &self.panes {
+            if name == NAME || lookup(name).is_none() {
+                bail!("mode.split.panes: {name:?} isn't a mode that can go in a pane");
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Pane {
+    name: &'static str,
+    mode: Box<dyn Mode>,
+    // Where the pane's frames go on the panel, and their size.
+    origin: (usize, usize),
+    dims: (usize, usize),
+    next_tick: Option<Instant>,
+    frame: Option<Vec<u8>>,
+}
+
+// The panel as a pane's mode sees it: just its half. Frames are kept for
+// merging rather than sent straight away.
+struct View<'a> {
+    panel: &'a mut dyn Panel,
+    dims: (usize, usize),
+    frame: &'a mut Option<Vec<u8>>,
+    shown: &'a mut bool,
+}
+
+impl Panel for View<'_> {
+    fn dims(&self) -> (usize, usize) {
+        self.dims
+    }
+
+    fn show(&mut self, frame: Vec<u8>) -> Result<()> {
+        if frame.len() != self.dims.0 * self.dims.1 {
+            bail!("frame is {} bytes, not {}x{}", frame.len(), self.dims.0, self.dims.1);
+        }
+        *self.frame = Some(frame);
+        *self.shown = true;
+        Ok(())
+    }
+
+    fn last_frame(&self) -> Option<&[u8]> {
+        self.frame.as_deref()
+    }
+
+    fn ping(&mut self) -> Result<Duration> {
+        self.panel.ping()
+    }
+
+    fn last_send(&self) -> Option<Duration> {
+        self.panel.last_send()
+    }
+}
+
+impl Pane {
+    // Calls `f` with the pane's mode and its view of `panel`, setting `shown`
+    // if it draws.
+    fn with_view<R>(
+        &mut self,
+        panel: &mut dyn Panel,
+        shown: &mut bool,
+        f: impl FnOnce(&mut dyn Mode, &mut dyn Panel) -> Result<R>,
+    ) -> Result<R> {
+        let mut view = View { panel, dims: self.dims, frame: &mut self.frame, shown };
+        f(self.mode.as_mut(), &mut view)
+    }
+}
+
+// Two modes side by side or one above the other, each ticking on its own
+// schedule. Whenever either draws, both halves go to the panel as one frame.
+pub struct SplitMode {
+    arrangement: Arrangement,
+    panes: Vec<Pane>,
+}
+
+impl SplitMode {
+    pub fn new(settings: &ModeSettings) -> Self {
+        let config = &settings.modes.split;
+        // The config is validated, so every pane is in the registry.
+        let panes = config.panes.iter()
+            .filter_map(|name| lookup(name))
+            .map(|info| Pane {
+                name: info.name,
+                mode: (info.build)(settings),
+                origin: (0, 0),
+                dims: (0, 0),
+                next_tick: None,
+                frame: None,
+            })
+            .collect();
+        Self { arrangement: config.arrangement, panes }
+    }
+
+    // Both halves as one frame, with any that hasn't drawn yet left blank.
+    fn show(&self, panel: &mut dyn Panel) -> Result<()> {
+        let (w, h) = panel.dims();
+        let mut merged = vec![0; w * h];
+        for pane in &self.panes {
+            let Some(frame) = &pane.frame else {
+                continue;
+            };
+            let (x, y) = pane.origin;
+            let pane_w = pane.dims.0;
+            for (row, line) in frame.chunks_exact(pane_w).enumerate() {
+                let start = (y + row) * w + x;
+                merged[start..start + pane_w].copy_from_slice(line);
+            }
+        }
+        panel.show(merged)
+    }
+}
+
+impl Mode for SplitMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let (w, h) = panel.dims();
+        let halves = match self.arrangement {
+            Arrangement::TopBottom => [((0, 0), (w, h / 2)), ((0, h / 2), (w, h - h / 2))],
+            Arrangement::LeftRight => [((0, 0), (w / 2, h)), ((w / 2, 0), (w - w / 2, h))],
+        };
+        let names: Vec<_> = self.panes.iter().map(|pane| pane.name).collect();
+        println!("[split] Showing {}...", names.join(" and "));
+        let mut shown = false;
+        for (pane, (origin, dims)) in self.panes.iter_mut().zip(halves) {
+            pane.origin = origin;
+            pane.dims = dims;
+            pane.frame = None;
+            pane.with_view(panel, &mut shown, |mode, view| mode.start(view))?;
+            pane.next_tick = Some(Instant::now());
+        }
+        if shown {
+            self.show(panel)?;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let mut shown = false;
+        for pane in &mut self.panes {
+            if pane.next_tick.is_some_and(|at| at <= Instant::now()) {
+                let next = pane.with_view(panel, &mut shown, |mode, view| mode.tick(view))?;
+                pane.next_tick = next.map(|delay| Instant::now() + delay);
+            }
+        }
+        if shown {
+            self.show(panel)?;
+        }
+        let now = Instant::now();
+        let next = self.panes.iter().filter_map(|pane| pane.next_tick).min();
+        Ok(next.map(|at| at.saturating_duration_since(now)))
+    }
+
+    fn handle_event(&mut self, panel: &mut dyn Panel, event: Event) -> Result<()> {
+        let mut shown = false;
+        let wanted = event.mode();
+        let pane = self.panes.iter_mut()
+            .find(|pane| wanted == Some(pane.name) || pane.mode.accepts(&event));
+        if let Some(pane) = pane {
+            pane.with_view(panel, &mut shown, |mode, view| mode.handle_event(view, event))?;
+            // An event can give an idle mode something to do again.
+            if pane.next_tick.is_none() {
+                pane.next_tick = Some(Instant::now());
+            }
+        }
+        if shown {
+            self.show(panel)?;
+        }
+        Ok(())
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        let wanted = event.mode();
+        self.panes.iter().any(|pane| wanted == Some(pane.name) || pane.mode.accepts(event))
+    }
+
+    fn stop(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        let mut shown = false;
+        for pane in &mut self.panes {
+            pane.with_view(panel, &mut shown, |mode, view| mode.stop(view))?;
+        }
+        Ok(())
+    }
+}
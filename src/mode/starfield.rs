@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::framebuffer::Framebuffer;
+
+pub const NAME: &str = "starfield";
+
+const FRAME: Duration = Duration::from_millis(100);
+
+const STARS: usize = 48;
+
+// Depth at which new stars appear, and how far they move per frame.
+const FAR: f32 = 32.0;
+const SPEED: f32 = 1.0;
+
+struct Star {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+fn new_star(z: f32) -> Star {
+    Star {
+        x: rand::random_range(-1.0..1.0f32),
+        y: rand::random_range(-1.0..1.0f32),
+        z,
+    }
+}
+
+// Flying forward through a field of stars, drawn as streaks once they get
+// close.
+#[derive(Default)]
+pub struct StarfieldMode {
+    stars: Vec<Star>,
+}
+
+impl Mode for StarfieldMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.stars = (0..STARS)
+            .map(|_| new_star(rand::random_range(1.0..FAR)))
+            .collect();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let start = Instant::now();
+        let dims = panel.dims();
+        let (cx, cy) = (dims.0 as f32 / 2.0, dims.1 as f32 / 2.0);
+        let project = |x: f32, y: f32, z: f32| -> (isize, isize) {
+            let scale = FAR / z;
+            ((cx + (x * cx * scale / 4.0)) as isize, (cy + (y * cy * scale / 4.0)) as isize)
+        };
+        let mut fb = Framebuffer::new(dims);
+        for star in &mut self.stars {
+            let (x0, y0) = project(star.x, star.y, star.z);
+            star.z -= SPEED;
+            if star.z <= 0.5 {
+                *star = new_star(FAR);
+                continue;
+            }
+            let (x1, y1) = project(star.x, star.y, star.z);
+            if x1 < 0 || y1 < 0 || x1 >= dims.0 as isize || y1 >= dims.1 as isize {
+                *star = new_star(FAR);
+                continue;
+            }
+            fb.line(x0, y0, x1, y1, true);
+        }
+        panel.show(fb.into_pixels())?;
+        Ok(Some(FRAME.saturating_sub(start.elapsed())))
+    }
+}
@@ -0,0 +1,125 @@
+use std::{fs, time::Duration};
+
+use anyhow::{anyhow, Result};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text};
+
+pub const NAME: &str = "stats";
+
+const REFRESH: Duration = Duration::from_secs(3);
+
+const THERMAL_ZONE: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+// Temperatures shown as an empty and a full gauge. The Pi starts throttling
+// at 80C.
+const TEMP_RANGE: (f64, f64) = (30.0, 85.0);
+
+// Wi-Fi signal levels shown as an empty and a full gauge, in dBm.
+const SIGNAL_RANGE: (f64, f64) = (-90.0, -30.0);
+
+// Rows of label, value and gauge, one per stat.
+const ROW_H: isize = 16;
+const BAR_H: isize = 4;
+
+// Busy and total jiffies summed over all CPUs, from the first line of
+// /proc/stat.
+fn cpu_times() -> Result<(u64, u64)> {
+    let stat = fs::read_to_string("/proc/stat")?;
+    let line = stat.lines().next().ok_or_else(|| anyhow!("empty /proc/stat"))?;
+    let fields: Vec<u64> = line.split_whitespace()
+        .skip(1)
+        .filter_map(|f| f.parse().ok())
+        .collect();
+    let total = fields.iter().sum();
+    // idle and iowait
+    let idle = fields.get(3).copied().unwrap_or(0) + fields.get(4).copied().unwrap_or(0);
+    Ok((total - idle, total))
+}
+
+fn temperature() -> Option<f64> {
+    let millis: f64 = fs::read_to_string(THERMAL_ZONE).ok()?.trim().parse().ok()?;
+    Some(millis / 1000.0)
+}
+
+// Fraction of memory in use, counting reclaimable cache as free.
+fn memory() -> Option<f64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| -> Option<f64> {
+        let line = meminfo.lines().find(|l| l.starts_with(name))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    };
+    let (total, available) = (field("MemTotal:")?, field("MemAvailable:")?);
+    Some(1.0 - (available / total))
+}
+
+// Signal level of the first wireless interface, in dBm.
+fn wifi_signal() -> Option<f64> {
+    let wireless = fs::read_to_string("/proc/net/wireless").ok()?;
+    // Two header lines, then "iface: status link level noise ...".
+    let line = wireless.lines().nth(2)?;
+    let level = line.split_whitespace().nth(3)?;
+    level.trim_end_matches('.').parse().ok()
+}
+
+fn fraction(value: f64, range: (f64, f64)) -> f64 {
+    ((value - range.0) / (range.1 - range.0)).clamp(0.0, 1.0)
+}
+
+fn gauge(fb: &mut Framebuffer, row: isize, label: &str, value: Option<(String, f64)>) {
+    let w = fb.dims.0 as isize;
+    let y = row * ROW_H;
+    text::draw_text(fb, 0, y, label);
+    let Some((shown, level)) = value else {
+        text::draw_text(fb, w - (2 * text::CELL_W as isize), y, "--");
+        return;
+    };
+    let x = w - (shown.len() * text::CELL_W) as isize + 1;
+    text::draw_text(fb, x, y, &shown);
+    let bar_y = y + text::CELL_H as isize + 1;
+    fb.rect(0, bar_y, w, BAR_H, true);
+    fb.fill_rect(1, bar_y + 1, ((w - 2) as f64 * level).round() as isize, BAR_H - 2, true);
+}
+
+// CPU, temperature, memory and Wi-Fi at a glance, for keeping an eye on
+// thermal throttling inside the helmet.
+#[derive(Default)]
+pub struct StatsMode {
+    last_cpu: Option<(u64, u64)>,
+}
+
+impl Mode for StatsMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.last_cpu = cpu_times().ok();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let cpu = cpu_times().ok();
+        let load = match (self.last_cpu, cpu) {
+            (Some((busy0, total0)), Some((busy1, total1))) if total1 > total0 => {
+                Some((busy1 - busy0) as f64 / (total1 - total0) as f64)
+            }
+            _ => None,
+        };
+        self.last_cpu = cpu;
+
+        let mut fb = Framebuffer::new(panel.dims());
+        gauge(&mut fb, 0, "CPU", load.map(|l| (format!("{:.0}%", l * 100.0), l)));
+        gauge(
+            &mut fb,
+            1,
+            "TEMP",
+            temperature().map(|t| (format!("{t:.0}C"), fraction(t, TEMP_RANGE))),
+        );
+        gauge(&mut fb, 2, "MEM", memory().map(|m| (format!("{:.0}%", m * 100.0), m)));
+        gauge(
+            &mut fb,
+            3,
+            "WIFI",
+            wifi_signal().map(|s| (format!("{s:.0}"), fraction(s, SIGNAL_RANGE))),
+        );
+        panel.show(fb.into_pixels())?;
+        Ok(Some(REFRESH))
+    }
+}
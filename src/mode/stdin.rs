@@ -0,0 +1,35 @@
+use std::{io, time::Duration};
+
+use anyhow::Result;
+
+use super::{Mode, Panel};
+use crate::rawframe::read_frame;
+
+pub const NAME: &str = "stdin";
+
+// Forwards frames in the `rawframe` format from standard input.
+#[derive(Default)]
+pub struct StdinMode {
+    frames: usize,
+}
+
+impl Mode for StdinMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.frames = 0;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        match read_frame(&mut io::stdin().lock(), panel.dims())? {
+            Some(frame) => {
+                panel.show(frame)?;
+                self.frames += 1;
+                Ok(Some(Duration::ZERO))
+            }
+            None => {
+                println!("[stdin] End of input after {} frames.", self.frames);
+                Ok(None)
+            }
+        }
+    }
+}
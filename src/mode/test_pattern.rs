@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{Event, Mode, Panel};
+use crate::{filter, framebuffer::Framebuffer, input::InputEvent};
+
+pub const NAME: &str = "test_pattern";
+
+// Distinct grey levels in the gradient pattern.
+const BARS: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// Alternating squares of `cell` pixels
+    #[default]
+    Checkerboard,
+    /// Dithered bars from black to white, left to right
+    Gradient,
+    /// Outline, centre cross and a block in the top-left corner
+    Border,
+    /// One pixel stepping through the panel row by row
+    PixelWalk,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TestPatternConfig {
+    pub pattern: Pattern,
+    // Checkerboard square size.
+    pub cell: usize,
+    // Time per pixel of the pixel walk.
+    pub step_millis: u64,
+}
+
+impl Default for TestPatternConfig {
+    fn default() -> Self {
+        Self { pattern: Pattern::default(), cell: 8, step_millis: 20 }
+    }
+}
+
+// Procedural patterns for checking wiring, orientation and the row padding
+// after hardware changes.
+pub struct TestPatternMode {
+    config: TestPatternConfig,
+    // Next pixel of the walk.
+    step: usize,
+}
+
+impl TestPatternMode {
+    pub fn new(config: TestPatternConfig) -> Self {
+        Self { config, step: 0 }
+    }
+}
+
+fn checkerboard(dims: (usize, usize), cell: usize) -> Vec<u8> {
+    let cell = cell.max(1);
+    let (w, h) = dims;
+    (0..w * h)
+        .map(|i| if ((i % w / cell) + (i / w / cell)).is_multiple_of(2) { 0xFF } else { 0 })
+        .collect()
+}
+
+fn gradient(dims: (usize, usize)) -> Vec<u8> {
+    let (w, h) = dims;
+    let gray: Vec<u8> = (0..w * h)
+        .map(|i| ((i % w * BARS / w) * 255 / (BARS - 1)) as u8)
+        .collect();
+    filter::dither(&gray, dims)
+}
+
+fn border(dims: (usize, usize)) -> Vec<u8> {
+    let mut fb = Framebuffer::new(dims);
+    let (w, h) = (dims.0 as isize, dims.1 as isize);
+    fb.rect(0, 0, w, h, true);
+    fb.line(w / 2, 0, w / 2, h - 1, true);
+    fb.line(0, h / 2, w - 1, h / 2, true);
+    // Only one corner is marked, so a flipped or rotated panel shows.
+    fb.fill_rect(2, 2, 4, 4, true);
+    fb.into_pixels()
+}
+
+impl Mode for TestPatternMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        println!("[test pattern] Showing {:?}...", self.config.pattern);
+        self.step = 0;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let dims = panel.dims();
+        let frame = match self.config.pattern {
+            Pattern::Checkerboard => checkerboard(dims, self.config.cell),
+            Pattern::Gradient => gradient(dims),
+            Pattern::Border => border(dims),
+            Pattern::PixelWalk => {
+                let mut frame = vec![0; dims.0 * dims.1];
+                frame[self.step] = 0xFF;
+                self.step = (self.step + 1) % frame.len();
+                panel.show(frame)?;
+                return Ok(Some(Duration::from_millis(self.config.step_millis)));
+            }
+        };
+        panel.show(frame)?;
+        Ok(None)
+    }
+
+    // Any button moves on to the next pattern, for checking a panel with
+    // nothing but the helmet at hand.
+    fn handle_event(&mut self, _panel: &mut dyn Panel, event: Event) -> Result<()> {
+        if let Event::Input(InputEvent::Press { .. }) = event {
+            let patterns = Pattern::value_variants();
+            let at = patterns.iter().position(|&p| p == self.config.pattern).unwrap_or(0);
+            self.config.pattern = patterns[(at + 1) % patterns.len()];
+            self.step = 0;
+            println!("[test pattern] Showing {:?}...", self.config.pattern);
+        }
+        Ok(())
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        matches!(event, Event::Input(InputEvent::Press { .. }))
+    }
+}
@@ -0,0 +1,50 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+
+use super::{Event, Mode, Panel};
+use crate::{layout::Layout, ttf::Font};
+
+pub const NAME: &str = "text";
+
+pub struct TextMode {
+    layout: Layout,
+    font: Arc<Font>,
+    text: String,
+    shown: bool,
+}
+
+impl TextMode {
+    pub fn new(layout: Layout, font: Arc<Font>) -> Self {
+        Self { layout, font, text: String::new(), shown: false }
+    }
+}
+
+impl Mode for TextMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.shown = false;
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        if !self.shown {
+            panel.show(self.layout.render(&self.text, panel.dims(), &self.font))?;
+            self.shown = true;
+        }
+        Ok(None)
+    }
+
+    fn handle_event(
+        &mut self,
+        panel: &mut dyn Panel,
+        event: Event,
+    ) -> Result<()> {
+        if let Event::Text(text) = event {
+            println!("[text] Sending text...");
+            self.text = text;
+            self.shown = false;
+            self.tick(panel)?;
+        }
+        Ok(())
+    }
+}
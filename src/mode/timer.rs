@@ -0,0 +1,175 @@
+use std::{
+    f64::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{framebuffer::Framebuffer, text, ttf::Font};
+
+pub const NAME: &str = "timer";
+
+// The ring's width in pixels, and the gap between it and the digits.
+const RING: f64 = 3.0;
+const GAP: f64 = 3.0;
+
+// On expiry the panel flashes this many times, each on and off for
+// `FLASH`.
+const FLASHES: u32 = 6;
+const FLASH: Duration = Duration::from_millis(250);
+
+// Longest a countdown can be; more won't fit the digits.
+const MAX_SECONDS: u64 = 100 * 60 * 60 - 1;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimerConfig {
+    // Also `POST /api/v1/timer/<seconds>`.
+    pub seconds: u64,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        Self { seconds: 300 }
+    }
+}
+
+impl TimerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_SECONDS).contains(&self.seconds) {
+            bail!("mode.timer.seconds: must be from 1 to {MAX_SECONDS}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq)]
+enum Shown {
+    SecondsLeft(u64),
+    Flash(bool),
+}
+
+// Big digits counting down inside a ring that empties clockwise, then a few
+// flashes of the whole panel.
+pub struct Countdown {
+    total: Duration,
+    ends: Instant,
+    // Under the digits, e.g. what the time is for.
+    label: Option<&'static str>,
+    // What's on the panel, so nothing is sent until that changes.
+    shown: Option<Shown>,
+}
+
+impl Countdown {
+    pub fn new(total: Duration, label: Option<&'static str>) -> Self {
+        Self { total, ends: Instant::now() + total, label, shown: None }
+    }
+
+    // Shows whatever is due, returning when to call again, or `None` once
+    // the time is up and the flashing done.
+    pub fn tick(&mut self, font: &Font, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        let (shown, next) = if now < self.ends {
+            let left = self.ends - now;
+            let seconds = left.as_secs_f64().ceil() as u64;
+            (Shown::SecondsLeft(seconds), Some(left - Duration::from_secs(seconds - 1)))
+        } else {
+            let flash = ((now - self.ends).as_millis() / FLASH.as_millis()) as u32;
+            if flash < FLASHES * 2 {
+                let next = self.ends + (FLASH * (flash + 1)) - now;
+                (Shown::Flash(flash.is_multiple_of(2)), Some(next))
+            } else {
+                (Shown::SecondsLeft(0), None)
+            }
+        };
+        if self.shown.as_ref() != Some(&shown) {
+            let dims = panel.dims();
+            let frame = match shown {
+                Shown::SecondsLeft(seconds) => self.draw(font, seconds, dims),
+                Shown::Flash(true) => vec![0xFF; dims.0 * dims.1],
+                Shown::Flash(false) => self.draw(font, 0, dims),
+            };
+            panel.show(frame)?;
+            self.shown = Some(shown);
+        }
+        Ok(next)
+    }
+
+    fn draw(&self, font: &Font, seconds: u64, dims: (usize, usize)) -> Vec<u8> {
+        let (w, h) = dims;
+        let mut fb = Framebuffer::new(dims);
+        let total = self.total.as_secs().max(1);
+        let left = seconds as f64 / total as f64;
+        let (cx, cy) = (w as f64 / 2.0, h as f64 / 2.0);
+        let outer = cx.min(cy);
+        for y in 0..h {
+            for x in 0..w {
+                let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                let r = dx.hypot(dy);
+                // Clockwise from twelve o'clock, with what's gone first.
+                let angle = dx.atan2(-dy).rem_euclid(TAU) / TAU;
+                if r <= outer && r > outer - RING && angle >= 1.0 - left {
+                    fb.set(x as isize, y as isize, true);
+                }
+            }
+        }
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        let digits = if hours > 0 {
+            format!("{hours}:{minutes:02}:{seconds:02}")
+        } else {
+            format!("{minutes:02}:{seconds:02}")
+        };
+        // The biggest square inside the ring.
+        let inside = ((outer - RING - GAP) * std::f64::consts::SQRT_2) as usize;
+        let label_h = if self.label.is_some() { text::CELL_H } else { 0 };
+        let room = inside.saturating_sub(label_h) as f32;
+        let mut size = font.fit(&digits, inside, room);
+        while size > 1.0 && font.measure(&digits, size).1 > room as usize {
+            size -= 1.0;
+        }
+        let (digits_w, digits_h) = font.measure(&digits, size);
+        let y = h.saturating_sub(digits_h + label_h) / 2;
+        font.draw(&mut fb, (w.saturating_sub(digits_w) / 2) as isize, y as isize, &digits, size);
+        if let Some(label) = self.label {
+            let label_w = (label.len() * text::CELL_W).saturating_sub(1);
+            let x = w.saturating_sub(label_w) / 2;
+            text::draw_text(&mut fb, x as isize, (y + digits_h + 1) as isize, label);
+        }
+        fb.into_pixels()
+    }
+}
+
+pub struct TimerMode {
+    config: TimerConfig,
+    font: Arc<Font>,
+    countdown: Option<Countdown>,
+}
+
+impl TimerMode {
+    pub fn new(config: TimerConfig, font: Arc<Font>) -> Self {
+        Self { config, font, countdown: None }
+    }
+}
+
+impl Mode for TimerMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        println!("[timer] Counting down {}s...", self.config.seconds);
+        self.countdown = Some(Countdown::new(Duration::from_secs(self.config.seconds), None));
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(countdown) = &mut self.countdown else {
+            return Ok(None);
+        };
+        let next = countdown.tick(&self.font, panel)?;
+        if next.is_none() {
+            println!("[timer] Time's up.");
+            self.countdown = None;
+        }
+        Ok(next)
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{weather::Units, Mode, Panel};
+use crate::{
+    framebuffer::Framebuffer,
+    obd::{ObdRx, Vehicle},
+    text,
+    ttf::Font,
+    tween::{self, Easing, Tween},
+};
+
+pub const NAME: &str = "vehicle";
+
+// The rev bar's height, and the space under it and above the bottom line.
+const BAR_H: usize = 6;
+const GAP: usize = 2;
+
+// How long the rev bar takes to sweep to a new reading.
+const SWEEP: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VehicleConfig {
+    // "metric" for km/h and °C, "imperial" for mph and °F.
+    pub units: Units,
+    // Where the rev bar fills up; past it, the bar flashes.
+    pub redline_rpm: u32,
+    pub refresh_millis: u64,
+}
+
+impl Default for VehicleConfig {
+    fn default() -> Self {
+        Self { units: Units::Metric, redline_rpm: 9000, refresh_millis: 250 }
+    }
+}
+
+impl VehicleConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(1000..=30000).contains(&self.redline_rpm) {
+            bail!("mode.vehicle.redline_rpm: must be from 1000 to 30000");
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq)]
+enum Shown {
+    Waiting,
+    // Speed, the rev bar's width, RPM and coolant temperature as drawn.
+    Reading { speed: String, bar: usize, rpm: String, coolant: String },
+}
+
+// Speed from an ELM327 OBD-II adapter in big digits, between a rev bar with
+// the RPM and the speed's unit with the coolant temperature.
+pub struct VehicleMode {
+    config: VehicleConfig,
+    obd: Option<ObdRx>,
+    font: Arc<Font>,
+    // What's on the panel, so unchanged readings aren't sent again.
+    drawn: Option<Shown>,
+    // When the mode started, which over the redline the bar flashes in time
+    // with, off every other refresh.
+    started: Instant,
+    // The rev bar's width, easing between readings.
+    bar: Tween,
+}
+
+impl VehicleMode {
+    pub fn new(config: VehicleConfig, obd: Option<ObdRx>, font: Arc<Font>) -> Self {
+        let bar = Tween::new(0.0, SWEEP, Easing::Out);
+        Self { config, obd, font, drawn: None, started: Instant::now(), bar }
+    }
+
+    fn refresh(&self) -> Duration {
+        Duration::from_millis(self.config.refresh_millis.max(50))
+    }
+
+    fn shown(&mut self, vehicle: Vehicle, width: usize) -> Shown {
+        let (per_kmh, coolant) = match self.config.units {
+            Units::Metric => (1.0, vehicle.coolant_c.map(|c| format!("{c}C"))),
+            Units::Imperial => {
+                (1.0 / 1.609_344, vehicle.coolant_c.map(|c| format!("{}F", c * 9 / 5 + 32)))
+            }
+        };
+        let redline = f64::from(self.config.redline_rpm);
+        let rpm = vehicle.rpm.unwrap_or(0.0);
+        self.bar.to((rpm / redline).min(1.0) * width as f64);
+        let refreshes = self.started.elapsed().as_millis() / self.refresh().as_millis();
+        let flash = rpm >= redline && refreshes % 2 == 1;
+        let bar = if flash { 0 } else { self.bar.value().round() as usize };
+        Shown::Reading {
+            speed: vehicle.speed_kmh
+                .map_or("--".to_owned(), |kmh| format!("{:.0}", f64::from(kmh) * per_kmh)),
+            bar,
+            rpm: vehicle.rpm.map_or("-- rpm".to_owned(), |rpm| format!("{rpm:.0} rpm")),
+            coolant: coolant.unwrap_or_default(),
+        }
+    }
+
+    fn draw(&self, shown: &Shown, dims: (usize, usize)) -> Vec<u8> {
+        let Shown::Reading { speed, bar, rpm, coolant } = shown else {
+            return text::render("Waiting\nfor OBD", dims);
+        };
+        let (w, h) = dims;
+        let mut fb = Framebuffer::new(dims);
+        for y in 0..BAR_H {
+            for x in 0..*bar {
+                fb.set(x as isize, y as isize, true);
+            }
+        }
+        let rpm_y = BAR_H + GAP;
+        text::draw_text(&mut fb, 0, rpm_y as isize, rpm);
+        let bottom_y = h.saturating_sub(text::GLYPH_H);
+        let unit = match self.config.units {
+            Units::Metric => "km/h",
+            Units::Imperial => "mph",
+        };
+        text::draw_text(&mut fb, 0, bottom_y as isize, unit);
+        let coolant_w = (coolant.len() * text::CELL_W).saturating_sub(1);
+        text::draw_text(&mut fb, w.saturating_sub(coolant_w) as isize, bottom_y as isize, coolant);
+        // As big as fits between the RPM and the bottom line.
+        let top = rpm_y + text::CELL_H + GAP;
+        let room = bottom_y.saturating_sub(top + GAP);
+        let mut size = self.font.fit(speed, w, room as f32);
+        while size > 1.0 && self.font.measure(speed, size).1 > room {
+            size -= 1.0;
+        }
+        let (speed_w, speed_h) = self.font.measure(speed, size);
+        let x = w.saturating_sub(speed_w) / 2;
+        let y = top + room.saturating_sub(speed_h) / 2;
+        self.font.draw(&mut fb, x as isize, y as isize, speed, size);
+        fb.into_pixels()
+    }
+}
+
+impl Mode for VehicleMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        self.drawn = None;
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(rx) = &self.obd else {
+            panel.show(text::render("No OBD\nadapter", panel.dims()))?;
+            return Ok(None);
+        };
+        let vehicle = *rx.borrow();
+        let shown = match vehicle {
+            Some(vehicle) => self.shown(vehicle, panel.dims().0),
+            None => Shown::Waiting,
+        };
+        if self.drawn.as_ref() != Some(&shown) {
+            panel.show(self.draw(&shown, panel.dims()))?;
+            self.drawn = Some(shown);
+        }
+        let refresh = self.refresh();
+        Ok(Some(if self.bar.is_moving() { tween::FRAME.min(refresh) } else { refresh }))
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    io::{BufReader, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use des::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Des,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{ingest::luma, picture, text};
+
+pub const NAME: &str = "vnc";
+
+// For connecting and the handshake; after that the server may well have
+// nothing to send for a long time.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long a tick waits for a frame.
+const POLL: Duration = Duration::from_millis(100);
+
+// 8-bit true colour, 3 bits of red and green and 2 of blue from the bottom
+// up, which is plenty for the panel and a quarter of the traffic of 32-bit.
+const PIXEL_FORMAT: [u8; 16] = [8, 8, 0, 1, 0, 7, 0, 7, 0, 3, 0, 3, 6, 0, 0, 0];
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_COPY_RECT: i32 = 1;
+
+const SECURITY_NONE: u8 = 1;
+const SECURITY_VNC_AUTH: u8 = 2;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VncConfig {
+    // The server's address and port; display :0 is port 5900, :1 5901 and so
+    // on.
+    pub server: String,
+    // For servers that use VNC authentication.
+    pub password: Option<String>,
+    pub refresh_millis: u64,
+}
+
+impl Default for VncConfig {
+    fn default() -> Self {
+        Self { server: "localhost:5900".into(), password: None, refresh_millis: 500 }
+    }
+}
+
+// Mirrors a VNC server's desktop, scaled to fit the panel. Only raw and
+// CopyRect updates are asked for, which every server can send.
+pub struct VncMode {
+    config: VncConfig,
+    stream: Option<TcpStream>,
+    frames: Option<Receiver<Result<Vec<u8>>>>,
+}
+
+impl VncMode {
+    pub fn new(config: VncConfig) -> Self {
+        Self { config, stream: None, frames: None }
+    }
+}
+
+struct Session {
+    input: BufReader<TcpStream>,
+    output: TcpStream,
+    dims: (usize, usize),
+    // The desktop as luma, kept up to date by the server's updates.
+    desktop: Vec<u8>,
+    palette: [u8; 256],
+}
+
+fn read_u8(input: &mut impl Read) -> Result<u8> {
+    let mut buf = [0];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(input: &mut impl Read) -> Result<u16> {
+    let mut buf = [0; 2];
+    input.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_string(input: &mut impl Read) -> Result<String> {
+    let len = read_u32(input)?;
+    let mut buf = vec![0; len.min(4096) as usize];
+    input.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn skip(input: &mut impl Read, len: u64) -> Result<()> {
+    std::io::copy(&mut input.take(len), &mut std::io::sink())?;
+    Ok(())
+}
+
+// VNC authentication DES-encrypts the challenge with the password as the
+// key, each byte's bits reversed.
+fn answer_challenge(password: &str, challenge: &mut [u8; 16]) {
+    let mut key = [0u8; 8];
+    for (k, p) in key.iter_mut().zip(password.bytes()) {
+        *k = p.reverse_bits();
+    }
+    let cipher = Des::new(&key.into());
+    for block in challenge.chunks_exact_mut(8) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+}
+
+impl Session {
+    fn connect(config: &VncConfig) -> Result<Self> {
+        let addr = config.server.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("{} has no address", config.server))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let mut output = stream.try_clone()?;
+        let mut input = BufReader::new(stream);
+
+        let mut version = [0u8; 12];
+        input.read_exact(&mut version)?;
+        let minor = match &version {
+            b"RFB 003.003\n" => 3,
+            b"RFB 003.007\n" => 7,
+            v if v.starts_with(b"RFB 003.") => 8,
+            _ => bail!("not a VNC server"),
+        };
+        output.write_all(format!("RFB 003.{minor:03}\n").as_bytes())?;
+
+        let offered = if minor == 3 {
+            vec![read_u32(&mut input)? as u8]
+        } else {
+            let count = read_u8(&mut input)?;
+            let mut types = vec![0; count as usize];
+            input.read_exact(&mut types)?;
+            types
+        };
+        if offered.is_empty() || offered == [0] {
+            bail!("the server refused: {}", read_string(&mut input)?);
+        }
+        let security = if offered.contains(&SECURITY_NONE) && config.password.is_none() {
+            SECURITY_NONE
+        } else if offered.contains(&SECURITY_VNC_AUTH) {
+            SECURITY_VNC_AUTH
+        } else if offered.contains(&SECURITY_NONE) {
+            SECURITY_NONE
+        } else {
+            bail!("the server only offers security types {offered:?}, and 1 or 2 are needed");
+        };
+        if minor != 3 {
+            output.write_all(&[security])?;
+        }
+        if security == SECURITY_VNC_AUTH {
+            let password = config.password.as_deref()
+                .context("the server wants a password; set mode.vnc.password")?;
+            let mut challenge = [0; 16];
+            input.read_exact(&mut challenge)?;
+            answer_challenge(password, &mut challenge);
+            output.write_all(&challenge)?;
+        }
+        // 3.8 reports on every security type; older versions only on
+        // passwords.
+        if (minor == 8 || security == SECURITY_VNC_AUTH) && read_u32(&mut input)? != 0 {
+            let reason = if minor == 8 {
+                read_string(&mut input)?
+            } else {
+                "authentication failed".into()
+            };
+            bail!("the server refused: {reason}");
+        }
+
+        // Shared, so other viewers stay connected.
+        output.write_all(&[1])?;
+        let w = read_u16(&mut input)? as usize;
+        let h = read_u16(&mut input)? as usize;
+        skip(&mut input, 16)?;
+        let name = read_string(&mut input)?;
+        if w == 0 || h == 0 {
+            bail!("the server's desktop is empty");
+        }
+        println!("[vnc] Connected to {name:?}, {w}x{h}.");
+
+        let mut set_format = vec![0, 0, 0, 0];
+        set_format.extend_from_slice(&PIXEL_FORMAT);
+        output.write_all(&set_format)?;
+        let mut set_encodings = vec![2, 0, 0, 2];
+        set_encodings.extend_from_slice(&ENCODING_COPY_RECT.to_be_bytes());
+        set_encodings.extend_from_slice(&ENCODING_RAW.to_be_bytes());
+        output.write_all(&set_encodings)?;
+
+        let palette = std::array::from_fn(|p| {
+            let scale = |value: usize, max: usize| (value * 255 / max) as u8;
+            luma(scale(p & 7, 7), scale((p >> 3) & 7, 7), scale(p >> 6, 3))
+        });
+        input.get_ref().set_read_timeout(None)?;
+        Ok(Self { input, output, dims: (w, h), desktop: vec![0; w * h], palette })
+    }
+
+    fn request_update(&mut self, incremental: bool) -> Result<()> {
+        let [w_hi, w_lo] = (self.dims.0 as u16).to_be_bytes();
+        let [h_hi, h_lo] = (self.dims.1 as u16).to_be_bytes();
+        self.output.write_all(&[3, incremental as u8, 0, 0, 0, 0, w_hi, w_lo, h_hi, h_lo])?;
+        Ok(())
+    }
+
+    // Reads messages until the next framebuffer update has been applied.
+    fn await_update(&mut self) -> Result<()> {
+        loop {
+            match read_u8(&mut self.input)? {
+                0 => return self.apply_update(),
+                // Colour map entries, which don't apply to true colour.
+                1 => {
+                    skip(&mut self.input, 3)?;
+                    let count = read_u16(&mut self.input)?;
+                    skip(&mut self.input, count as u64 * 6)?;
+                }
+                // Bell.
+                2 => {}
+                // Cut text.
+                3 => {
+                    skip(&mut self.input, 3)?;
+                    let len = read_u32(&mut self.input)?;
+                    skip(&mut self.input, len.into())?;
+                }
+                other => bail!("unknown message type {other} from the server"),
+            }
+        }
+    }
+
+    fn apply_update(&mut self) -> Result<()> {
+        let (dw, dh) = self.dims;
+        skip(&mut self.input, 1)?;
+        let rects = read_u16(&mut self.input)?;
+        let mut row = Vec::new();
+        for _ in 0..rects {
+            let x = read_u16(&mut self.input)? as usize;
+            let y = read_u16(&mut self.input)? as usize;
+            let w = read_u16(&mut self.input)? as usize;
+            let h = read_u16(&mut self.input)? as usize;
+            let encoding = read_u32(&mut self.input)? as i32;
+            if x + w > dw || y + h > dh {
+                bail!("the server sent a rectangle off the desktop");
+            }
+            match encoding {
+                ENCODING_RAW => {
+                    row.resize(w, 0);
+                    for ry in y..y + h {
+                        self.input.read_exact(&mut row)?;
+                        let out = &mut self.desktop[ry * dw + x..][..w];
+                        for (o, &p) in out.iter_mut().zip(&row) {
+                            *o = self.palette[p as usize];
+                        }
+                    }
+                }
+                ENCODING_COPY_RECT => {
+                    let sx = read_u16(&mut self.input)? as usize;
+                    let sy = read_u16(&mut self.input)? as usize;
+                    if sx + w > dw || sy + h > dh {
+                        bail!("the server copied from off the desktop");
+                    }
+                    let copied: Vec<u8> = (sy..sy + h)
+                        .flat_map(|ry| self.desktop[ry * dw + sx..][..w].to_vec())
+                        .collect();
+                    for (ry, src) in (y..y + h).zip(copied.chunks_exact(w.max(1))) {
+                        self.desktop[ry * dw + x..][..w].copy_from_slice(src);
+                    }
+                }
+                other => bail!("the server sent encoding {other}, which wasn't asked for"),
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the desktop, fitted to `panel_dims`, each time it changes, but
+    // no more often than every `interval`.
+    fn run(mut self, panel_dims: (usize, usize), interval: Duration, tx: &Sender<Result<Vec<u8>>>) {
+        let mut incremental = false;
+        loop {
+            let start = Instant::now();
+            let result = self.request_update(incremental).and_then(|()| self.await_update());
+            if let Err(e) = result {
+                let _ = tx.send(Err(e));
+                return;
+            }
+            incremental = true;
+            let frame = picture::fit(&self.desktop, self.dims, panel_dims);
+            if tx.send(Ok(frame)).is_err() {
+                return;
+            }
+            sleep(interval.saturating_sub(start.elapsed()));
+        }
+    }
+}
+
+impl Mode for VncMode {
+    fn start(&mut self, panel: &mut dyn Panel) -> Result<()> {
+        println!("[vnc] Connecting to {}...", self.config.server);
+        panel.show(text::render("vnc\nconnecting", panel.dims()))?;
+        let session = Session::connect(&self.config)
+            .with_context(|| format!("connecting to {}", self.config.server))?;
+        let (tx, rx) = unbounded();
+        let dims = panel.dims();
+        let interval = Duration::from_millis(self.config.refresh_millis);
+        self.stream = Some(session.output.try_clone()?);
+        spawn(move || session.run(dims, interval, &tx));
+        self.frames = Some(rx);
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        let Some(frames) = &self.frames else {
+            return Ok(None);
+        };
+        let mut frame = match frames.recv_timeout(POLL) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(Duration::ZERO)),
+            Err(RecvTimeoutError::Disconnected) => bail!("the VNC session ended"),
+        };
+        while let Ok(newer) = frames.try_recv() {
+            frame = newer;
+        }
+        panel.show(frame.context("VNC session")?)?;
+        Ok(Some(Duration::ZERO))
+    }
+
+    fn stop(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        // Ends the session thread's read, and with it the thread.
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.frames = None;
+        Ok(())
+    }
+}
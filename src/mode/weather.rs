@@ -0,0 +1,449 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Mode, Panel};
+use crate::{
+    framebuffer::Framebuffer,
+    sprite::{self, Sprite},
+    text,
+};
+
+pub const NAME: &str = "weather";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How soon to try again after a failed fetch.
+const RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    // Needs no API key
+    #[default]
+    OpenMeteo,
+    OpenWeatherMap,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub provider: Provider,
+    // Only needed for OpenWeatherMap.
+    pub api_key: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub units: Units,
+    pub refresh_minutes: u64,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            provider: Provider::default(),
+            api_key: None,
+            latitude: 0.0,
+            longitude: 0.0,
+            units: Units::default(),
+            refresh_minutes: 15,
+        }
+    }
+}
+
+impl WeatherConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.provider == Provider::OpenWeatherMap && self.api_key.is_none() {
+            bail!("mode.weather.api_key: needed for provider open_weather_map");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sky {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Fog,
+    Rain,
+    Snow,
+    Storm,
+}
+
+impl Sky {
+    // WMO weather interpretation codes, as used by Open-Meteo.
+    fn from_wmo(code: u32) -> Self {
+        match code {
+            0 => Sky::Clear,
+            1 | 2 => Sky::PartlyCloudy,
+            3 => Sky::Cloudy,
+            45 | 48 => Sky::Fog,
+            71..=77 | 85 | 86 => Sky::Snow,
+            95..=99 => Sky::Storm,
+            _ => Sky::Rain,
+        }
+    }
+
+    // OpenWeatherMap condition ids.
+    fn from_owm(id: u32) -> Self {
+        match id {
+            200..=299 => Sky::Storm,
+            600..=699 => Sky::Snow,
+            700..=799 => Sky::Fog,
+            800 => Sky::Clear,
+            801 | 802 => Sky::PartlyCloudy,
+            803..=899 => Sky::Cloudy,
+            _ => Sky::Rain,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Sky::Clear => "Clear",
+            Sky::PartlyCloudy => "Cloudy-ish",
+            Sky::Cloudy => "Cloudy",
+            Sky::Fog => "Fog",
+            Sky::Rain => "Rain",
+            Sky::Snow => "Snow",
+            Sky::Storm => "Storm",
+        }
+    }
+
+    fn icon(self) -> Sprite {
+        match self {
+            Sky::Clear => SUN,
+            Sky::PartlyCloudy => SUN_CLOUD,
+            Sky::Cloudy => CLOUD,
+            Sky::Fog => FOG,
+            Sky::Rain => RAIN,
+            Sky::Snow => SNOW,
+            Sky::Storm => STORM,
+        }
+    }
+}
+
+// Conditions now plus the rest of today.
+struct Report {
+    temp: f64,
+    sky: Sky,
+    high: f64,
+    low: f64,
+    // Percent.
+    precip_chance: u32,
+}
+
+#[derive(Deserialize)]
+struct MeteoResponse {
+    current: MeteoCurrent,
+    daily: MeteoDaily,
+}
+
+#[derive(Deserialize)]
+struct MeteoCurrent {
+    temperature_2m: f64,
+    weather_code: u32,
+}
+
+#[derive(Deserialize)]
+struct MeteoDaily {
+    temperature_2m_max: Vec<f64>,
+    temperature_2m_min: Vec<f64>,
+    precipitation_probability_max: Vec<Option<u32>>,
+}
+
+#[derive(Deserialize)]
+struct OwmCurrent {
+    main: OwmMain,
+    weather: Vec<OwmCondition>,
+}
+
+#[derive(Deserialize)]
+struct OwmMain {
+    temp: f64,
+}
+
+#[derive(Deserialize)]
+struct OwmCondition {
+    id: u32,
+}
+
+#[derive(Deserialize)]
+struct OwmForecast {
+    list: Vec<OwmSlot>,
+}
+
+#[derive(Deserialize)]
+struct OwmSlot {
+    main: OwmMain,
+    // Probability of precipitation, 0 to 1.
+    pop: f64,
+}
+
+fn get_json<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(HTTP_TIMEOUT))
+        .build()
+        .new_agent();
+    let body = agent.get(url).call()?.body_mut().read_to_string()?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+fn fetch(config: &WeatherConfig) -> Result<Report> {
+    let (lat, lon) = (config.latitude, config.longitude);
+    match config.provider {
+        Provider::OpenMeteo => {
+            let units = match config.units {
+                Units::Metric => "celsius",
+                Units::Imperial => "fahrenheit",
+            };
+            let r: MeteoResponse = get_json(&format!(
+                "https://api.open-meteo.com/v1/forecast?latitude={lat}&longitude={lon}\
+                 &current=temperature_2m,weather_code\
+                 &daily=temperature_2m_max,temperature_2m_min,precipitation_probability_max\
+                 &forecast_days=1&timezone=auto&temperature_unit={units}"
+            ))?;
+            let first = |v: &[f64]| v.first().copied().ok_or_else(|| anyhow!("empty forecast"));
+            Ok(Report {
+                temp: r.current.temperature_2m,
+                sky: Sky::from_wmo(r.current.weather_code),
+                high: first(&r.daily.temperature_2m_max)?,
+                low: first(&r.daily.temperature_2m_min)?,
+                precip_chance: r.daily.precipitation_probability_max.first()
+                    .copied()
+                    .flatten()
+                    .unwrap_or(0),
+            })
+        }
+        Provider::OpenWeatherMap => {
+            let key = config.api_key.as_deref().unwrap_or_default();
+            let units = match config.units {
+                Units::Metric => "metric",
+                Units::Imperial => "imperial",
+            };
+            let query = format!("lat={lat}&lon={lon}&units={units}&appid={key}");
+            let now: OwmCurrent = get_json(&format!(
+                "https://api.openweathermap.org/data/2.5/weather?{query}"
+            ))?;
+            // Eight 3-hour slots make up the next day.
+            let forecast: OwmForecast = get_json(&format!(
+                "https://api.openweathermap.org/data/2.5/forecast?{query}&cnt=8"
+            ))?;
+            let temps = forecast.list.iter().map(|slot| slot.main.temp);
+            Ok(Report {
+                temp: now.main.temp,
+                sky: Sky::from_owm(now.weather.first().map_or(800, |c| c.id)),
+                high: temps.clone().fold(now.main.temp, f64::max),
+                low: temps.fold(now.main.temp, f64::min),
+                precip_chance: forecast.list.iter()
+                    .map(|slot| (slot.pop * 100.0).round() as u32)
+                    .max()
+                    .unwrap_or(0),
+            })
+        }
+    }
+}
+
+pub struct WeatherMode {
+    config: WeatherConfig,
+}
+
+impl WeatherMode {
+    pub fn new(config: WeatherConfig) -> Self {
+        Self { config }
+    }
+}
+
+fn draw(report: &Report, units: Units, dims: (usize, usize)) -> Vec<u8> {
+    let mut fb = Framebuffer::new(dims);
+    let icon = report.sky.icon();
+    sprite::draw(&mut fb, 0, 0, icon);
+    let unit = match units {
+        Units::Metric => 'C',
+        Units::Imperial => 'F',
+    };
+    let (icon_w, icon_h) = sprite::size(icon);
+    let temp_y = (icon_h - text::GLYPH_H) as isize / 2;
+    text::draw_text(
+        &mut fb,
+        icon_w as isize + 2,
+        temp_y,
+        &format!("{:.0}{unit}", report.temp),
+    );
+    let mut y = icon_h as isize + 2;
+    for line in [
+        report.sky.label().to_owned(),
+        format!("H{:.0} L{:.0}", report.high, report.low),
+        format!("Precip {}%", report.precip_chance),
+    ] {
+        text::draw_text(&mut fb, 0, y, &line);
+        y += text::CELL_H as isize;
+    }
+    fb.into_pixels()
+}
+
+impl Mode for WeatherMode {
+    fn start(&mut self, _panel: &mut dyn Panel) -> Result<()> {
+        Ok(())
+    }
+
+    fn tick(&mut self, panel: &mut dyn Panel) -> Result<Option<Duration>> {
+        println!("[weather] Fetching conditions...");
+        match fetch(&self.config) {
+            Ok(report) => {
+                panel.show(draw(&report, self.config.units, panel.dims()))?;
+                Ok(Some(Duration::from_secs(self.config.refresh_minutes.max(1) * 60)))
+            }
+            Err(e) => {
+                // Keep whatever was last shown rather than blanking the panel
+                // over a flaky connection.
+                println!("[weather] Fetch failed: {e}");
+                if panel.last_frame().is_none() {
+                    panel.show(text::render("No weather", panel.dims()))?;
+                }
+                Ok(Some(RETRY))
+            }
+        }
+    }
+}
+
+const SUN: Sprite = &[
+    ".......#........",
+    "..#....#....#...",
+    "...#.......#....",
+    "......###.......",
+    ".....#####......",
+    "....#######.....",
+    "##..#######..##.",
+    "....#######.....",
+    ".....#####......",
+    "......###.......",
+    "...#.......#....",
+    "..#....#....#...",
+    ".......#........",
+    "................",
+    "................",
+    "................",
+];
+
+const SUN_CLOUD: Sprite = &[
+    "....#...........",
+    "#...#...#.......",
+    ".#.....#........",
+    "....###.........",
+    "...#####........",
+    "##.####..###....",
+    "...###.##...#...",
+    "....##.#.....##.",
+    ".....#.#.......#",
+    "....##.........#",
+    "...#...........#",
+    "...#..........#.",
+    "....##########..",
+    "................",
+    "................",
+    "................",
+];
+
+const CLOUD: Sprite = &[
+    "................",
+    "................",
+    "................",
+    "......###.......",
+    ".....#...#......",
+    "..###.....##....",
+    ".#..........#...",
+    "#............#..",
+    "#.............#.",
+    "#.............#.",
+    ".#...........#..",
+    "..###########...",
+    "................",
+    "................",
+    "................",
+    "................",
+];
+
+const FOG: Sprite = &[
+    "................",
+    "................",
+    "................",
+    "##############..",
+    "................",
+    "..############..",
+    "................",
+    "##############..",
+    "................",
+    "..############..",
+    "................",
+    "##############..",
+    "................",
+    "................",
+    "................",
+    "................",
+];
+
+const RAIN: Sprite = &[
+    "......###.......",
+    ".....#...#......",
+    "..###.....##....",
+    ".#..........#...",
+    "#............#..",
+    "#.............#.",
+    ".#...........#..",
+    "..###########...",
+    "................",
+    "...#...#...#....",
+    "..#...#...#.....",
+    "................",
+    ".#...#...#......",
+    "#...#...#.......",
+    "................",
+    "................",
+];
+
+const SNOW: Sprite = &[
+    "......###.......",
+    ".....#...#......",
+    "..###.....##....",
+    ".#..........#...",
+    "#............#..",
+    "#.............#.",
+    ".#...........#..",
+    "..###########...",
+    "................",
+    "..#...#...#.....",
+    ".###.###.###....",
+    "..#...#...#.....",
+    "................",
+    "....#...#...#...",
+    "...###.###.###..",
+    "....#...#...#...",
+];
+
+const STORM: Sprite = &[
+    "......###.......",
+    ".....#...#......",
+    "..###.....##....",
+    ".#..........#...",
+    "#............#..",
+    "#.............#.",
+    ".#...........#..",
+    "..#####..####...",
+    ".......##.......",
+    "......##........",
+    ".....######.....",
+    "........##......",
+    ".......##.......",
+    "......#.........",
+    "................",
+    "................",
+];
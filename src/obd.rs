@@ -0,0 +1,168 @@
+use std::{
+    io,
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serialport::SerialPort;
+use tokio::sync::watch;
+
+const RECONNECT: Duration = Duration::from_secs(5);
+
+// Each read gives up after `READ_TIMEOUT`, and waiting for the adapter's
+// prompt after `RESPONSE_TIMEOUT`. The first query after a reset can take
+// several seconds while it works out the vehicle's protocol.
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Mode 01 parameters: vehicle speed, engine speed and coolant temperature.
+const SPEED: u8 = 0x0D;
+const RPM: u8 = 0x0C;
+const COOLANT: u8 = 0x05;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObdConfig {
+    // The ELM327's serial port, e.g. "/dev/ttyUSB0", or "/dev/rfcomm0" for a
+    // Bluetooth one bound with `rfcomm bind`. None means no OBD-II.
+    pub port: Option<String>,
+    pub baud: u32,
+    pub poll_millis: u64,
+}
+
+impl Default for ObdConfig {
+    fn default() -> Self {
+        Self { port: None, baud: 38400, poll_millis: 250 }
+    }
+}
+
+// The latest readings. Any the vehicle doesn't report stay `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vehicle {
+    pub speed_kmh: Option<u8>,
+    pub rpm: Option<f64>,
+    pub coolant_c: Option<i16>,
+}
+
+// Latest readings, or `None` while the adapter or the vehicle isn't
+// answering.
+pub type ObdRx = watch::Receiver<Option<Vehicle>>;
+
+// Polls the adapter on its own thread. It's often plugged in, or the
+// ignition turned on, after we start, so it's retried rather than failing.
+pub fn spawn_poller(config: &ObdConfig) -> Result<Option<ObdRx>> {
+    let Some(port) = config.port.clone() else {
+        return Ok(None);
+    };
+    let (tx, rx) = watch::channel(None);
+    let config = config.clone();
+    spawn(move || {
+        // Only logged once while it keeps failing the same way.
+        let mut last_error = None;
+        loop {
+            let result = poll_adapter(&port, &config, &tx);
+            if tx.is_closed() {
+                return;
+            }
+            tx.send_replace(None);
+            if let Err(e) = result {
+                let error = format!("{e:#}");
+                if last_error.as_ref() != Some(&error) {
+                    println!("[obd] {port}: {error}");
+                    last_error = Some(error);
+                }
+            }
+            sleep(RECONNECT);
+        }
+    });
+    Ok(Some(rx))
+}
+
+fn poll_adapter(
+    port: &str,
+    config: &ObdConfig,
+    tx: &watch::Sender<Option<Vehicle>>,
+) -> Result<()> {
+    let mut serial = serialport::new(port, config.baud).timeout(READ_TIMEOUT).open()?;
+    // Reset, then no echo, line feeds, spaces or headers, so a response is
+    // just the hex, and let it find the vehicle's protocol by itself.
+    for command in ["ATZ", "ATE0", "ATL0", "ATS0", "ATH0", "ATSP0"] {
+        command_response(&mut *serial, command)?;
+    }
+    println!("[obd] Polling the ELM327 on {port}...");
+    // Whether the vehicle went quiet, so that's only logged once.
+    let mut quiet = false;
+    loop {
+        let started = Instant::now();
+        let vehicle = Vehicle {
+            speed_kmh: query(&mut *serial, SPEED)?.map(|data| data[0]),
+            rpm: query(&mut *serial, RPM)?
+                .filter(|data| data.len() >= 2)
+                .map(|data| f64::from(u16::from_be_bytes([data[0], data[1]])) / 4.0),
+            coolant_c: query(&mut *serial, COOLANT)?.map(|data| i16::from(data[0]) - 40),
+        };
+        // With the ignition off the adapter answers but the vehicle doesn't.
+        let answered = vehicle != Vehicle::default();
+        if !answered && !quiet {
+            println!("[obd] No answer from the vehicle; is the ignition on?");
+        }
+        quiet = !answered;
+        tx.send_replace(answered.then_some(vehicle));
+        if tx.is_closed() {
+            return Ok(());
+        }
+        let period = Duration::from_millis(config.poll_millis);
+        sleep(period.saturating_sub(started.elapsed()));
+    }
+}
+
+// Sends `command` and returns everything up to the next prompt.
+fn command_response(serial: &mut dyn SerialPort, command: &str) -> Result<String> {
+    serial.write_all(format!("{command}\r").as_bytes())?;
+    let deadline = Instant::now() + RESPONSE_TIMEOUT;
+    let mut response = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match serial.read(&mut buf) {
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(end) = response.iter().position(|&b| b == b'>') {
+            response.truncate(end);
+            return Ok(String::from_utf8_lossy(&response).into_owned());
+        }
+        if Instant::now() >= deadline {
+            bail!("no prompt after {command}");
+        }
+    }
+}
+
+// The data bytes of a mode 01 response for `pid`, or `None` if the vehicle
+// has nothing for it.
+fn query(serial: &mut dyn SerialPort, pid: u8) -> Result<Option<Vec<u8>>> {
+    let response = command_response(serial, &format!("01{pid:02X}"))?;
+    Ok(parse_response(&response, pid))
+}
+
+// Responses can come with "SEARCHING..." first, or from more than one
+// control unit, so the first line that's an answer to `pid` is taken.
+fn parse_response(response: &str, pid: u8) -> Option<Vec<u8>> {
+    let answer = format!("41{pid:02X}");
+    response.split(['\r', '\n'])
+        .map(|line| line.replace(' ', ""))
+        .find_map(|line| {
+            let hex = line.strip_prefix(&answer)?;
+            // Line noise can leave replacement characters, which slicing
+            // by bytes would split.
+            if !hex.is_ascii() {
+                return None;
+            }
+            let data = (0..hex.len() / 2)
+                .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            (!data.is_empty()).then_some(data)
+        })
+}
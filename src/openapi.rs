@@ -0,0 +1,208 @@
+use utoipa::openapi::{
+    path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn},
+    request_body::RequestBodyBuilder,
+    schema::{ArrayBuilder, ComponentsBuilder, ObjectBuilder, Type},
+    ContentBuilder, Deprecated, Info, OpenApiBuilder, Paths, Ref, RefOr, ResponseBuilder, Schema,
+};
+
+use crate::{
+    ambient, battery, jobs, latency, library, session, waypoint, CoordsBody, DisplayUrl, ModeEntry,
+    Status,
+};
+
+// What a route takes in.
+enum Body {
+    None,
+    Json(&'static str),
+    // Anything else, by media type.
+    Raw(&'static str),
+}
+
+// What a route sends back on success.
+enum Returns {
+    // `{"ok": true}`.
+    Ok,
+    // `{"ok": true, "data": ...}`, with one of the named schema or a list
+    // of them.
+    Data(&'static str),
+    List(&'static str),
+    // Not JSON at all, by media type.
+    Raw(&'static str),
+}
+
+struct Route {
+    method: HttpMethod,
+    path: &'static str,
+    summary: &'static str,
+    body: Body,
+    returns: Returns,
+}
+
+fn route(
+    method: HttpMethod,
+    path: &'static str,
+    summary: &'static str,
+    body: Body,
+    returns: Returns,
+) -> Route {
+    Route { method, path, summary, body, returns }
+}
+
+fn named(name: &str) -> RefOr<Schema> {
+    Ref::from_schema_name(name).into()
+}
+
+fn list_of(name: &str) -> RefOr<Schema> {
+    ArrayBuilder::new().items(named(name)).into()
+}
+
+// Kept in step with the filters in `main`, which can't describe themselves.
+fn routes() -> Vec<Route> {
+    use Body as B;
+    use HttpMethod::*;
+    use Returns as R;
+    vec![
+        route(Get, "/api/v1/status", "Battery and ambient light readings",
+            B::None, R::Data("Status")),
+        route(Get, "/api/v1/metrics", "Readings in Prometheus' text format",
+            B::None, R::Raw("text/plain")),
+        route(Get, "/api/v1/progress", "Progress of long jobs, as server-sent events",
+            B::None, R::Raw("text/event-stream")),
+        route(Get, "/api/v1/events", "Mode, display, input and error events over a WebSocket",
+            B::None, R::Raw("application/json")),
+        route(Get, "/api/v1/latency", "Time round trips to the MCU; `?count=` sets how many",
+            B::None, R::Data("Latency")),
+        route(Get, "/api/v1/track.gpx", "The track being recorded",
+            B::None, R::Raw("application/gpx+xml")),
+        route(Get, "/api/v1/frame.png", "What the panel is showing, overlays and all",
+            B::None, R::Raw("image/png")),
+        route(Get, "/api/v1/waypoints", "The waypoints, in order",
+            B::None, R::List("Waypoint")),
+        route(Post, "/api/v1/waypoints", "Add a waypoint, or replace the one of the same name",
+            B::Json("Waypoint"), R::Ok),
+        route(Delete, "/api/v1/waypoints/{name}", "Remove a waypoint",
+            B::None, R::Ok),
+        route(Get, "/api/v1/queue", "Jobs waiting to be shown, in the order they'll go",
+            B::None, R::List("Queued")),
+        route(Delete, "/api/v1/queue/{id}", "Remove a waiting job",
+            B::None, R::Ok),
+        route(Get, "/api/v1/session", "Who has control, if anyone",
+            B::None, R::Data("SessionInfo")),
+        route(Post, "/api/v1/session/acquire",
+            "Take control, or keep it with `x-session`; `?owner=` says who's taking it",
+            B::None, R::Data("Acquired")),
+        route(Post, "/api/v1/session/release", "Give up control, sending `x-session`",
+            B::None, R::Ok),
+        route(Get, "/api/v1/modes", "The modes and their config",
+            B::None, R::List("ModeEntry")),
+        route(Post, "/api/v1/modes/{name}", "Switch modes, overriding any of its config",
+            B::Raw("application/json"), R::Ok),
+        route(Post, "/api/v1/test-pattern/{pattern}", "Show a test pattern",
+            B::None, R::Ok),
+        route(Post, "/api/v1/timer/{seconds}", "Count down, then flash the panel",
+            B::None, R::Ok),
+        route(Post, "/api/v1/morse", "Flash the body in Morse code; `?wpm=` sets the speed",
+            B::Raw("text/plain"), R::Ok),
+        route(Post, "/api/v1/coords", "Show a map of coordinates",
+            B::Json("CoordsBody"), R::Ok),
+        route(Post, "/api/v1/display-url", "Download a PNG or JPEG and show it",
+            B::Json("DisplayUrl"), R::Ok),
+        route(Get, "/api/v1/library", "The stored frames and animations",
+            B::None, R::List("Item")),
+        route(Get, "/api/v1/library/{name}", "Download a stored file",
+            B::None, R::Raw("application/octet-stream")),
+        route(Get, "/api/v1/library/{name}/thumb.png",
+            "A small preview of a stored file, or an animation's first frame",
+            B::None, R::Raw("image/png")),
+        route(Post, "/api/v1/library/{name}", "Store a file",
+            B::Raw("application/octet-stream"), R::Ok),
+        route(Post, "/api/v1/library/{name}/show", "Show a stored file",
+            B::None, R::Ok),
+        route(Delete, "/api/v1/library/{name}", "Delete a stored file",
+            B::None, R::Ok),
+    ]
+}
+
+// The envelope every JSON reply comes in, with `data` if there is any.
+fn envelope(data: Option<RefOr<Schema>>) -> ObjectBuilder {
+    let object = ObjectBuilder::new()
+        .property("ok", ObjectBuilder::new().schema_type(Type::Boolean))
+        .required("ok");
+    match data {
+        Some(data) => object.property("data", data),
+        None => object.property("error", ObjectBuilder::new().schema_type(Type::String)),
+    }
+}
+
+// The OpenAPI document served at /api/openapi.json.
+pub fn document() -> String {
+    let mut paths = Paths::new();
+    for route in routes() {
+        let mut operation = OperationBuilder::new().summary(Some(route.summary));
+        for segment in route.path.split('/') {
+            if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                operation = operation.parameter(ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(ParameterIn::Path)
+                    .schema(Some(ObjectBuilder::new().schema_type(Type::String))));
+            }
+        }
+        let body = match route.body {
+            Body::None => None,
+            Body::Json(name) => Some(("application/json", named(name))),
+            Body::Raw(media) => Some((media, ObjectBuilder::new().into())),
+        };
+        if let Some((media, schema)) = body {
+            operation = operation.request_body(Some(RequestBodyBuilder::new()
+                .content(media, ContentBuilder::new().schema(Some(schema)).build())
+                .build()));
+        }
+        let (media, schema): (_, RefOr<Schema>) = match route.returns {
+            Returns::Ok => ("application/json", envelope(None).into()),
+            Returns::Data(name) => ("application/json", envelope(Some(named(name))).into()),
+            Returns::List(name) => ("application/json", envelope(Some(list_of(name))).into()),
+            Returns::Raw(media) => (media, ObjectBuilder::new().into()),
+        };
+        let ok = ResponseBuilder::new()
+            .description("Done")
+            .content(media, ContentBuilder::new().schema(Some(schema)).build());
+        let failed = ResponseBuilder::new()
+            .description("Couldn't; `error` says why")
+            .content("application/json", ContentBuilder::new()
+                .schema(Some(envelope(None)))
+                .build());
+        operation = operation.response("200", ok.build()).response("4XX", failed.build());
+        paths.add_path_operation(route.path, vec![route.method], operation.build());
+    }
+    paths.add_path_operation("/coords/{coords}", vec![HttpMethod::Post], OperationBuilder::new()
+        .summary(Some("Show a map of coordinates, from before /api/v1"))
+        .deprecated(Some(Deprecated::True))
+        .parameter(ParameterBuilder::new()
+            .name("coords")
+            .parameter_in(ParameterIn::Path)
+            .schema(Some(ObjectBuilder::new().schema_type(Type::String))))
+        .response("200", ResponseBuilder::new().description("Always `ok`").build())
+        .build());
+    let components = ComponentsBuilder::new()
+        .schema_from::<Status>()
+        .schema_from::<battery::Reading>()
+        .schema_from::<ambient::Ambient>()
+        .schema_from::<latency::Latency>()
+        .schema_from::<waypoint::Waypoint>()
+        .schema_from::<ModeEntry>()
+        .schema_from::<CoordsBody>()
+        .schema_from::<DisplayUrl>()
+        .schema_from::<library::Item>()
+        .schema_from::<jobs::Queued>()
+        .schema_from::<jobs::Priority>()
+        .schema_from::<session::SessionInfo>()
+        .schema_from::<session::Acquired>()
+        .build();
+    OpenApiBuilder::new()
+        .info(Info::new("fett-helmet-pi", env!("CARGO_PKG_VERSION")))
+        .paths(paths)
+        .components(Some(components))
+        .build()
+        .to_json()
+        .unwrap()
+}
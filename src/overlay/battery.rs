@@ -0,0 +1,77 @@
+use super::Overlay;
+use crate::{
+    battery::{BatteryConfig, BatteryRx, Reading},
+    framebuffer::Framebuffer,
+    text,
+};
+
+// Body of the corner icon, not counting the nub on its right.
+pub const ICON_W: isize = 9;
+pub const ICON_H: isize = 5;
+
+// A small charge gauge in the bottom-right corner, and a full-screen warning
+// once the battery gets low.
+pub struct Battery {
+    rx: BatteryRx,
+    icon: bool,
+    low_percent: f64,
+    drawn: Option<(i64, bool)>,
+}
+
+impl Battery {
+    pub fn new(config: &BatteryConfig, rx: BatteryRx) -> Self {
+        Self { rx, icon: config.icon, low_percent: config.low_percent, drawn: None }
+    }
+
+    fn is_low(&self, reading: &Reading) -> bool {
+        reading.is_low(self.low_percent)
+    }
+
+    // What a redraw depends on: the icon's fill level and the warning.
+    fn state(&self) -> (i64, bool) {
+        let reading = *self.rx.borrow();
+        let level = if self.is_low(&reading) {
+            reading.percent.round() as i64
+        } else {
+            (reading.percent / 100.0 * (ICON_W - 2) as f64).round() as i64
+        };
+        (level, self.is_low(&reading))
+    }
+}
+
+// The icon with its body's top-left corner at (x, y), `ICON_W + 1` wide
+// with the nub.
+pub fn draw_icon(fb: &mut Framebuffer, x: isize, y: isize, percent: f64) {
+    // A blank margin so the icon reads over busy content.
+    fb.fill_rect(x - 1, y - 1, ICON_W + 3, ICON_H + 2, false);
+    fb.rect(x, y, ICON_W, ICON_H, true);
+    fb.fill_rect(x + ICON_W, y + 1, 1, ICON_H - 2, true);
+    let fill = (percent / 100.0 * (ICON_W - 2) as f64).round() as isize;
+    fb.fill_rect(x + 1, y + 1, fill, ICON_H - 2, true);
+}
+
+impl Overlay for Battery {
+    fn draw(&mut self, fb: &mut Framebuffer) {
+        let reading = *self.rx.borrow();
+        self.drawn = Some(self.state());
+        if self.is_low(&reading) {
+            fb.clear();
+            let message = format!("LOW\nBATTERY\n\n{:.0}%", reading.percent);
+            let y = (fb.dims.1 - (4 * text::CELL_H)) as isize / 2;
+            for (i, line) in message.lines().enumerate() {
+                let x = (fb.dims.0 - (line.len() * text::CELL_W)) as isize / 2;
+                text::draw_text(fb, x, y + (i * text::CELL_H) as isize, line);
+            }
+            return;
+        }
+        if !self.icon {
+            return;
+        }
+        let (w, h) = (fb.dims.0 as isize, fb.dims.1 as isize);
+        draw_icon(fb, w - ICON_W - 2, h - ICON_H - 1, reading.percent);
+    }
+
+    fn changed(&self) -> bool {
+        self.drawn != Some(self.state())
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+use super::Overlay;
+use crate::{
+    framebuffer::Framebuffer,
+    geo::{self, Course, LatLon},
+    imu::OrientationRx,
+    mode::Event,
+    text,
+};
+
+// Rows taken up by the ribbon, not counting the centre marker under it.
+const RIBBON_H: isize = 9;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompassConfig {
+    pub enabled: bool,
+    // Degrees of heading visible across the width of the panel.
+    pub span_degrees: f64,
+}
+
+impl Default for CompassConfig {
+    fn default() -> Self {
+        Self { enabled: false, span_degrees: 120.0 }
+    }
+}
+
+// A strip along the top edge with N/E/S/W and tick marks scrolling past, the
+// current heading under the centre.
+pub struct Compass {
+    span: f64,
+    orientation: Option<OrientationRx>,
+    course: Course,
+    // Heading last drawn, in whole degrees.
+    drawn: Option<i64>,
+}
+
+impl Compass {
+    pub fn new(config: &CompassConfig, orientation: Option<OrientationRx>) -> Self {
+        Self {
+            span: config.span_degrees.clamp(10.0, 360.0),
+            orientation,
+            course: Course::default(),
+            drawn: None,
+        }
+    }
+
+    // The IMU when there is one, otherwise the course between the last two
+    // GPS fixes.
+    fn heading(&self) -> Option<f64> {
+        let imu = self.orientation.as_ref().and_then(|rx| *rx.borrow());
+        imu.map(|o| o.heading).or(self.course.bearing)
+    }
+}
+
+impl Overlay for Compass {
+    fn draw(&mut self, fb: &mut Framebuffer) {
+        let heading = self.heading();
+        self.drawn = heading.map(|h| h.round() as i64);
+        let Some(heading) = heading else {
+            return;
+        };
+        let w = fb.dims.0 as isize;
+        let cx = w / 2;
+        let px_per_degree = w as f64 / self.span;
+        fb.fill_rect(0, 0, w, RIBBON_H, false);
+        fb.line(0, RIBBON_H - 1, w - 1, RIBBON_H - 1, true);
+        for deg in (0..360).step_by(15) {
+            let offset = geo::turn_angle(heading, deg as f64);
+            if offset.abs() > (self.span / 2.0) + 3.0 {
+                continue;
+            }
+            let x = cx + (offset * px_per_degree).round() as isize;
+            match deg {
+                0 | 90 | 180 | 270 => {
+                    let label = ['N', 'E', 'S', 'W'][deg / 90];
+                    text::draw_char(fb, x - (text::GLYPH_W as isize / 2), 0, label);
+                }
+                45 | 135 | 225 | 315 => fb.line(x, RIBBON_H - 4, x, RIBBON_H - 2, true),
+                _ => fb.set(x, RIBBON_H - 2, true),
+            }
+        }
+        // A caret under the ribbon pointing at the current heading.
+        fb.fill_rect(cx - 1, RIBBON_H, 3, 1, true);
+        fb.set(cx, RIBBON_H + 1, true);
+    }
+
+    fn changed(&self) -> bool {
+        self.heading().map(|h| h.round() as i64) != self.drawn
+    }
+
+    fn observe(&mut self, event: &Event) {
+        let Event::Coords(coords) = event else {
+            return;
+        };
+        if let Ok(fix) = coords.parse::<LatLon>() {
+            self.course.update(fix);
+        }
+    }
+}
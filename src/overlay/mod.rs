@@ -0,0 +1,203 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::{
+    config::Config,
+    framebuffer::Framebuffer,
+    mode::{Event, ModeSettings, Panel},
+    transition::Transitions,
+};
+
+pub mod battery;
+pub mod compass;
+pub mod temporal;
+pub mod threshold;
+pub mod waypoint;
+
+// Something drawn on top of whatever mode is active.
+pub trait Overlay {
+    fn draw(&mut self, fb: &mut Framebuffer);
+
+    // Whether a redraw now would look different from the last one, so the
+    // panel can be refreshed without the mode sending anything.
+    fn changed(&self) -> bool {
+        false
+    }
+
+    // When `changed` will next turn true by itself, for overlays that have
+    // to keep to a pace; the rest are polled.
+    fn next_change(&self) -> Option<Instant> {
+        None
+    }
+
+    // Sees every event that reaches the mode manager.
+    fn observe(&mut self, event: &Event) {
+        let _ = event;
+    }
+}
+
+pub fn from_config(config: &Config, settings: &ModeSettings) -> Vec<Box<dyn Overlay>> {
+    let mut overlays: Vec<Box<dyn Overlay>> = Vec::new();
+    // First, so they only see the mode's own pixels. Dithering leaves no grey
+    // for the threshold to cut.
+    if config.temporal_dither.enabled {
+        overlays.push(Box::new(temporal::TemporalDither::new(&config.temporal_dither)));
+    }
+    if let (true, Some(rx)) = (config.ambient.adjust_threshold, &settings.ambient) {
+        overlays.push(Box::new(threshold::AmbientThreshold::new(&config.ambient, rx.clone())));
+    }
+    if config.compass.enabled {
+        overlays.push(Box::new(compass::Compass::new(
+            &config.compass,
+            settings.orientation.clone(),
+        )));
+    }
+    if config.waypoints.alert {
+        overlays.push(Box::new(waypoint::WaypointAlert::new(&config.waypoints)));
+    }
+    // Last, so the low-battery warning covers everything else.
+    if let Some(rx) = &settings.battery {
+        overlays.push(Box::new(battery::Battery::new(&config.battery, rx.clone())));
+    }
+    overlays
+}
+
+// Sits between the modes and the real panel, drawing the overlays onto every
+// frame. The panel protocol only takes whole frames, so an overlay change
+// means resending everything.
+pub struct Compositor<'a> {
+    panel: &'a mut dyn Panel,
+    overlays: Vec<Box<dyn Overlay>>,
+    // The active mode's last frame, before any overlays. Also kept while
+    // blank, for when the display comes back on.
+    base: Option<Vec<u8>>,
+    blank: bool,
+    transitions: Transitions,
+}
+
+impl<'a> Compositor<'a> {
+    pub fn new(
+        panel: &'a mut dyn Panel,
+        overlays: Vec<Box<dyn Overlay>>,
+        transitions: Transitions,
+    ) -> Self {
+        Self { panel, overlays, base: None, blank: false, transitions }
+    }
+
+    // Eases into the next frame that differs from what's showing, for a new
+    // mode or new content.
+    pub fn transition_next(&mut self) {
+        self.transitions.arm();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.blank
+    }
+
+    // Turns the display off, meaning all black as the panel has no power
+    // control, or back on with whatever the mode last showed.
+    pub fn set_blank(&mut self, blank: bool) -> Result<()> {
+        if blank == self.blank {
+            return Ok(());
+        }
+        if blank {
+            if self.base.is_none() {
+                self.base = self.panel.last_frame().map(<[u8]>::to_vec);
+            }
+            let (w, h) = self.panel.dims();
+            self.panel.show(vec![0; w * h])?;
+        }
+        self.blank = blank;
+        if !blank {
+            self.present()?;
+            // Without overlays, frames go straight through again.
+            if self.overlays.is_empty() {
+                self.base = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn observe(&mut self, event: &Event) {
+        for overlay in &mut self.overlays {
+            overlay.observe(event);
+        }
+    }
+
+    // Re-sends the last frame if any overlay has changed since.
+    pub fn refresh(&mut self) -> Result<()> {
+        if !self.blank && self.base.is_some() && self.overlays.iter().any(|o| o.changed()) {
+            self.present()?;
+        }
+        Ok(())
+    }
+
+    pub fn next_refresh(&self) -> Option<Instant> {
+        if self.blank || self.base.is_none() {
+            return None;
+        }
+        self.overlays.iter().filter_map(|o| o.next_change()).min()
+    }
+
+    fn present(&mut self) -> Result<()> {
+        let Some(base) = &self.base else {
+            return Ok(());
+        };
+        let mut fb = Framebuffer { dims: self.panel.dims(), pixels: base.clone() };
+        for overlay in &mut self.overlays {
+            overlay.draw(&mut fb);
+        }
+        self.transitions.show(self.panel, fb.into_pixels())
+    }
+}
+
+impl Panel for Compositor<'_> {
+    fn dims(&self) -> (usize, usize) {
+        self.panel.dims()
+    }
+
+    fn show(&mut self, frame: Vec<u8>) -> Result<()> {
+        if self.blank {
+            self.base = Some(frame);
+            return Ok(());
+        }
+        if self.overlays.is_empty() {
+            return self.transitions.show(self.panel, frame);
+        }
+        self.base = Some(frame);
+        self.present()
+    }
+
+    fn last_frame(&self) -> Option<&[u8]> {
+        self.base.as_deref().or_else(|| self.panel.last_frame())
+    }
+
+    fn ping(&mut self) -> Result<Duration> {
+        self.panel.ping()
+    }
+
+    fn last_send(&self) -> Option<Duration> {
+        self.panel.last_send()
+    }
+
+    fn lend_port(&mut self, user: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        self.panel.lend_port(user)
+    }
+
+    fn reattach(&mut self, was: &str, port: &str) -> Result<()> {
+        self.panel.reattach(was, port)
+    }
+
+    fn release_port(&mut self) -> Result<String> {
+        self.panel.release_port()
+    }
+
+    fn take_back_port(&mut self) -> Result<()> {
+        self.panel.take_back_port()
+    }
+}
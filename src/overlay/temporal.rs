@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use super::Overlay;
+use crate::framebuffer::Framebuffer;
+
+// A frame takes about 50ms over the serial link, so it can't go much faster
+// than this anyway.
+const MAX_HZ: f64 = 20.0;
+
+// Spreads each pixel's lit phases over its neighbours', so a patch of grey
+// shimmers rather than blinking as one.
+pub const BAYER: [[usize; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemporalDitherConfig {
+    pub enabled: bool,
+    // Frames in one cycle. A pixel is lit on 0 to this many of them, so
+    // there's one more level than this, black and white included.
+    pub levels: usize,
+    // How often the pattern may change, which the matching flicker is
+    // `max_hz / levels` at its slowest.
+    pub max_hz: f64,
+}
+
+impl Default for TemporalDitherConfig {
+    fn default() -> Self {
+        Self { enabled: false, levels: 3, max_hz: 10.0 }
+    }
+}
+
+impl TemporalDitherConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(2..=8).contains(&self.levels) {
+            bail!("temporal_dither.levels: must be 2 to 8");
+        }
+        if !(self.max_hz > 0.0 && self.max_hz <= MAX_HZ) {
+            bail!("temporal_dither.max_hz: must be above 0 and at most {MAX_HZ}");
+        }
+        Ok(())
+    }
+}
+
+// Not really something on top either: fakes grey levels by lighting each
+// grey pixel on a share of consecutive frames. Frames that are already
+// black and white go through as they are, and aren't resent.
+pub struct TemporalDither {
+    levels: usize,
+    period: Duration,
+    phase: usize,
+    // When `phase` last moved on, and whether the frame it was drawn on had
+    // any grey in it.
+    stepped: Instant,
+    grey: bool,
+}
+
+impl TemporalDither {
+    pub fn new(config: &TemporalDitherConfig) -> Self {
+        Self {
+            levels: config.levels,
+            period: Duration::from_secs_f64(1.0 / config.max_hz),
+            phase: 0,
+            stepped: Instant::now(),
+            grey: false,
+        }
+    }
+}
+
+impl Overlay for TemporalDither {
+    fn draw(&mut self, fb: &mut Framebuffer) {
+        if self.stepped.elapsed() >= self.period {
+            self.phase = (self.phase + 1) % self.levels;
+            self.stepped = Instant::now();
+        }
+        self.grey = fb.pixels.iter().any(|&p| p != 0x00 && p != 0xFF);
+        if !self.grey {
+            return;
+        }
+        let w = fb.dims.0;
+        for (i, p) in fb.pixels.iter_mut().enumerate() {
+            // Lit on `duty` of every `levels` frames.
+            let duty = ((*p as usize * self.levels) + 127) / 255;
+            let offset = BAYER[(i / w) % 4][(i % w) % 4];
+            let lit = (self.phase + offset) % self.levels < duty;
+            *p = if lit { 0xFF } else { 0x00 };
+        }
+    }
+
+    fn changed(&self) -> bool {
+        self.grey && self.stepped.elapsed() >= self.period
+    }
+
+    fn next_change(&self) -> Option<Instant> {
+        self.grey.then_some(self.stepped + self.period)
+    }
+}
@@ -0,0 +1,38 @@
+use super::Overlay;
+use crate::{
+    ambient::{AmbientConfig, AmbientRx},
+    framebuffer::Framebuffer,
+};
+
+// Not really something on top: cuts grey frames at a threshold that follows
+// the ambient light level, before anything else is drawn over them.
+pub struct AmbientThreshold {
+    config: AmbientConfig,
+    rx: AmbientRx,
+    drawn: Option<u8>,
+}
+
+impl AmbientThreshold {
+    pub fn new(config: &AmbientConfig, rx: AmbientRx) -> Self {
+        Self { config: config.clone(), rx, drawn: None }
+    }
+
+    fn threshold(&self) -> Option<u8> {
+        self.rx.borrow().map(|ambient| self.config.threshold(ambient.level))
+    }
+}
+
+impl Overlay for AmbientThreshold {
+    fn draw(&mut self, fb: &mut Framebuffer) {
+        self.drawn = self.threshold();
+        if let Some(threshold) = self.drawn {
+            for p in &mut fb.pixels {
+                *p = if *p > threshold { 0xFF } else { 0x00 };
+            }
+        }
+    }
+
+    fn changed(&self) -> bool {
+        self.threshold() != self.drawn
+    }
+}
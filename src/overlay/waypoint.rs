@@ -0,0 +1,102 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use super::Overlay;
+use crate::{
+    framebuffer::Framebuffer,
+    geo::LatLon,
+    mode::Event,
+    text,
+    tween::{Easing, Tween},
+    waypoint::{self, WaypointConfig},
+};
+
+// How long the banner takes to slide up into view, or back down.
+const SLIDE: Duration = Duration::from_millis(250);
+
+// A banner sliding up from the bottom edge with the name of a waypoint just
+// reached.
+pub struct WaypointAlert {
+    radius_m: f64,
+    duration: Duration,
+    // Waypoints the last fix was within range of, so each alerts once per
+    // visit.
+    inside: HashSet<String>,
+    alert: Option<(String, Instant)>,
+    drawn: Option<String>,
+    // How much of the banner is up, from 0 to 1, and the name on it, kept
+    // while it slides away.
+    slide: Tween,
+    banner: Option<String>,
+}
+
+impl WaypointAlert {
+    pub fn new(config: &WaypointConfig) -> Self {
+        Self {
+            radius_m: config.radius_m,
+            duration: Duration::from_secs(config.alert_secs),
+            inside: HashSet::new(),
+            alert: None,
+            drawn: None,
+            slide: Tween::new(0.0, SLIDE, Easing::Out),
+            banner: None,
+        }
+    }
+
+    fn showing(&self) -> Option<&str> {
+        self.alert.as_ref()
+            .filter(|(_, since)| since.elapsed() < self.duration)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl Overlay for WaypointAlert {
+    fn draw(&mut self, fb: &mut Framebuffer) {
+        self.drawn = self.showing().map(String::from);
+        if self.drawn.is_some() {
+            self.banner.clone_from(&self.drawn);
+        }
+        self.slide.to(if self.drawn.is_some() { 1.0 } else { 0.0 });
+        let up = self.slide.value();
+        let Some(name) = self.banner.as_ref().filter(|_| up > 0.0) else {
+            return;
+        };
+        let (w, h) = (fb.dims.0 as isize, fb.dims.1 as isize);
+        let cols = (fb.dims.0 - 2) / text::CELL_W;
+        let name: String = name.chars().take(cols).collect();
+        let height = text::CELL_H as f64 + 3.0;
+        let top = h - (height * up).round() as isize;
+        fb.fill_rect(0, top, w, h - top, false);
+        fb.line(0, top, w - 1, top, true);
+        let x = (w - (name.chars().count() * text::CELL_W) as isize) / 2;
+        text::draw_text(fb, x, top + 2, &name);
+    }
+
+    fn changed(&self) -> bool {
+        self.showing() != self.drawn.as_deref() || self.slide.is_moving()
+    }
+
+    fn next_change(&self) -> Option<Instant> {
+        self.slide.next_frame()
+    }
+
+    fn observe(&mut self, event: &Event) {
+        let Event::Coords(coords) = event else {
+            return;
+        };
+        let Ok(fix) = coords.parse::<LatLon>() else {
+            return;
+        };
+        for point in waypoint::list() {
+            let radius = point.radius_m.unwrap_or(self.radius_m);
+            if fix.distance(point.at()) > radius {
+                self.inside.remove(&point.name);
+            } else if self.inside.insert(point.name.clone()) {
+                println!("[waypoint] Reached {}.", point.name);
+                self.alert = Some((point.name, Instant::now()));
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use png::ColorType;
+use serde::Deserialize;
+
+pub use crate::ingest::{decode, decode_rgb, Format, Rgb};
+use crate::filter;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+    #[default]
+    Black,
+    White,
+}
+
+impl Background {
+    pub fn rgb(self) -> Rgb {
+        match self {
+            Background::Black => [0x00; 3],
+            Background::White => [0xFF; 3],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    // What shows through transparent parts of PNGs, whether from the library
+    // or `POST /api/v1/display-url`.
+    pub background: Background,
+}
+
+static BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+pub fn init(config: &ImagesConfig) {
+    let _ = BACKGROUND.set(config.background);
+}
+
+pub fn background() -> Rgb {
+    BACKGROUND.get().copied().unwrap_or_default().rgb()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayUrlConfig {
+    // Downloads bigger than this are cut off and refused.
+    pub max_kb: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for DisplayUrlConfig {
+    fn default() -> Self {
+        Self { max_kb: 8192, timeout_secs: 10 }
+    }
+}
+
+// Fetches an image for `POST /api/v1/display-url`. Only PNG and JPEG are accepted,
+// going by the Content-Type the server sends.
+pub fn download(url: &str, config: &DisplayUrlConfig) -> Result<(Vec<u8>, Format)> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        bail!("only http and https URLs can be displayed");
+    }
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(config.timeout_secs)))
+        .build()
+        .new_agent();
+    let mut response = agent.get(url).call()?;
+    let body = response.body_mut();
+    let format = match body.mime_type() {
+        Some("image/png") => Format::Png,
+        Some("image/jpeg") => Format::Jpeg,
+        other => bail!("not a PNG or JPEG (Content-Type {})", other.unwrap_or("missing")),
+    };
+    let data = body.with_config().limit(config.max_kb * 1024).read_to_vec()?;
+    Ok((data, format))
+}
+
+// What `data` is by its first bytes, for images that come without a type.
+pub fn sniff(data: &[u8]) -> Option<Format> {
+    match data.get(..4) {
+        Some([0x89, b'P', b'N', b'G']) => Some(Format::Png),
+        Some([0xFF, 0xD8, ..]) => Some(Format::Jpeg),
+        _ => None,
+    }
+}
+
+// Scales `image` to fit `dims` without distorting it, centred on black, and
+// dithers it down to 1 bit.
+pub fn fit(image: &[u8], src_dims: (usize, usize), dims: (usize, usize)) -> Vec<u8> {
+    let (sw, sh) = src_dims;
+    let scale = f64::min(dims.0 as f64 / sw as f64, dims.1 as f64 / sh as f64);
+    let w = ((sw as f64 * scale).round() as usize).clamp(1, dims.0);
+    let h = ((sh as f64 * scale).round() as usize).clamp(1, dims.1);
+    let small = filter::downscale(image, src_dims, (w, h));
+    let (left, top) = ((dims.0 - w) / 2, (dims.1 - h) / 2);
+    let mut out = vec![0u8; dims.0 * dims.1];
+    for (y, row) in small.chunks_exact(w).enumerate() {
+        let start = ((top + y) * dims.0) + left;
+        out[start..start + w].copy_from_slice(row);
+    }
+    filter::dither(&out, dims)
+}
+
+// Writes a panel frame as an 8-bit grayscale PNG, for modes that display
+// files.
+pub fn save_png(frame: &[u8], dims: (usize, usize), path: impl AsRef<Path>) -> Result<()> {
+    fs::write(path, encode_png(frame, dims)?)?;
+    Ok(())
+}
+
+pub fn encode_png(frame: &[u8], dims: (usize, usize)) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, dims.0 as u32, dims.1 as u32);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.write_header()?.write_image_data(frame)?;
+    Ok(out)
+}
+
+pub fn save_rgb_png(
+    pixels: &[Rgb],
+    dims: (usize, usize),
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(File::create(path)?, dims.0 as u32, dims.1 as u32);
+    encoder.set_color(ColorType::Rgb);
+    encoder.write_header()?.write_image_data(pixels.as_flattened())?;
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::picture;
+
+#[derive(Clone)]
+struct Frame {
+    pixels: Vec<u8>,
+    dims: (usize, usize),
+}
+
+// The last whole frame sent to the panels, overlays and all, for the web UI
+// to start drawings from.
+static LAST: Mutex<Option<Frame>> = Mutex::new(None);
+
+pub fn set(frame: &[u8], dims: (usize, usize)) {
+    *LAST.lock().unwrap() = Some(Frame { pixels: frame.to_vec(), dims });
+}
+
+// As a PNG, or `None` before anything's been shown.
+pub fn png() -> Result<Option<Vec<u8>>> {
+    let last = LAST.lock().unwrap().clone();
+    last.map(|frame| picture::encode_png(&frame.pixels, frame.dims)).transpose()
+}
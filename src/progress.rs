@@ -0,0 +1,90 @@
+use std::{
+    io::{stderr, IsTerminal},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// How often a running task publishes an event, besides at its start and end.
+const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+// One step of a task, as sent to `GET /api/v1/progress` listeners.
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgressEvent {
+    pub task: &'static str,
+    pub done: u64,
+    pub total: u64,
+    pub elapsed_ms: u64,
+    pub finished: bool,
+}
+
+lazy_static! {
+    static ref EVENTS: broadcast::Sender<ProgressEvent> = broadcast::channel(64).0;
+}
+
+static BARS: OnceLock<bool> = OnceLock::new();
+
+// Picks how progress is shown for the rest of the run: bars for a person
+// watching a terminal, events otherwise, so a service's log isn't full of
+// control characters. The bars go to stderr, so that's the one that counts.
+pub fn init(quiet: bool) {
+    let _ = BARS.set(!quiet && stderr().is_terminal());
+}
+
+pub fn subscribe() -> broadcast::Receiver<ProgressEvent> {
+    EVENTS.subscribe()
+}
+
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    task: &'static str,
+    done: u64,
+    total: u64,
+    started: Instant,
+    last_event: Instant,
+}
+
+impl Progress {
+    pub fn new(task: &'static str, total: u64) -> Self {
+        let bar = BARS.get().copied().unwrap_or(true).then(|| ProgressBar::new(total));
+        let now = Instant::now();
+        let this = Self { bar, task, done: 0, total, started: now, last_event: now };
+        this.publish(false);
+        this
+    }
+
+    pub fn inc(&mut self, n: u64) {
+        self.done += n;
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+        } else if self.last_event.elapsed() >= EVENT_INTERVAL {
+            self.last_event = Instant::now();
+            self.publish(false);
+        }
+    }
+
+    pub fn finish(self) {
+        match &self.bar {
+            Some(bar) => bar.finish(),
+            None => self.publish(true),
+        }
+    }
+
+    fn publish(&self, finished: bool) {
+        if self.bar.is_some() {
+            return;
+        }
+        // Nobody listening is fine.
+        let _ = EVENTS.send(ProgressEvent {
+            task: self.task,
+            done: self.done,
+            total: self.total,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            finished,
+        });
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitOrder {
+    // The first pixel of each group of eight is the lowest bit.
+    #[default]
+    LsbFirst,
+    MsbFirst,
+}
+
+// Software flow control characters, as a terminal would use them.
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+
+// What keeps frames from overrunning the MCU's receive buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowControl {
+    // Guesswork: resting for `pause_millis` every `rows_between_pauses`
+    // rows.
+    #[default]
+    Pauses,
+    // The MCU reports its free space as an `input_prefix` message of kind C,
+    // and frames go out only as fast as there's room.
+    Credit,
+    // The MCU sends XOFF when it's nearly full and XON once it has room.
+    // Both directions then escape those and `escape` itself.
+    XonXoff,
+    // Frames go in numbered chunks, each acknowledged by the MCU with an
+    // `input_prefix` message of kind A, or asked for again with kind N, either
+    // followed by the chunk's index as a little-endian u16. Only a chunk that
+    // doesn't get through is sent again, not the whole frame.
+    Chunked,
+}
+
+// What frames may be compressed with, if the firmware says it can
+// decompress them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Heatshrink,
+}
+
+// Feature bits in the firmware's answer to `hello`.
+pub const HELLO_HEATSHRINK: u16 = 1 << 0;
+
+// How frames go over the wire, for firmware other than the helmet's own.
+// The defaults are what that firmware expects.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Protocol {
+    // Sent before every frame, for the MCU to sync on.
+    pub reset: Vec<u8>,
+    // Sent before and after the packed pixels of each row; pauses come after
+    // the footer.
+    pub row_header: Vec<u8>,
+    pub row_footer: Vec<u8>,
+    // Sent after the last row's footer.
+    pub frame_footer: Vec<u8>,
+    pub bit_order: BitOrder,
+    // After a pause, this many rows go without one. The MCU's receive buffer
+    // is small, so it needs time to shift rows out to the panel.
+    pub rows_between_pauses: u32,
+    pub pause_millis: u64,
+    pub flow_control: FlowControl,
+    // With XON/XOFF, comes before any byte that would read as a control
+    // character, which is then sent XORed with 0x20. The reset is never
+    // escaped, so the MCU can always sync on it.
+    pub escape: u8,
+    // With chunks: each is `chunk_start`, its index and length as
+    // little-endian u16s, up to `chunk_bytes` of the frame, and the CRC of
+    // everything after `chunk_start`, so a garbled header is caught too.
+    // One that isn't acknowledged is sent up to `chunk_retries` more times.
+    pub chunk_start: Vec<u8>,
+    pub chunk_bytes: u16,
+    pub chunk_retries: u32,
+    // Sent whenever the port opens, asking what the firmware can do. It
+    // answers with an `input_prefix` message of kind H and a little-endian
+    // u16 of feature bits. Empty for firmware that doesn't.
+    pub hello: Vec<u8>,
+    // Only used if the firmware says it can decompress it. Each frame then
+    // has a tag after the reset: 0 for rows as usual, or 1 for compressed
+    // rows, after their length as a little-endian u16, whichever is smaller.
+    // The footer follows either way.
+    pub compression: Compression,
+    // As log2 of bytes; the firmware must decompress with the same.
+    pub heatshrink_window: u8,
+    pub heatshrink_lookahead: u8,
+    // Makes the MCU echo the one byte that follows, for measuring latency.
+    // Empty for firmware that can't.
+    pub ping: Vec<u8>,
+    // Makes the MCU drop what it has of a frame and wait for the next
+    // reset, so an alert needn't wait for the rest. Empty for firmware that
+    // can't, which then always gets whole frames.
+    pub abort: Vec<u8>,
+    // Starts each three-byte message the MCU sends about its buttons and
+    // encoder: the prefix, a kind (P press, R release, E encoder, S status)
+    // and a value.
+    pub input_prefix: u8,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self {
+            reset: vec![b'#'; 11],
+            row_header: vec![0x00],
+            row_footer: vec![],
+            frame_footer: vec![0x00],
+            bit_order: BitOrder::LsbFirst,
+            rows_between_pauses: 2,
+            pause_millis: 17,
+            flow_control: FlowControl::Pauses,
+            escape: 0x7D,
+            chunk_start: vec![b'$'],
+            chunk_bytes: 256,
+            chunk_retries: 3,
+            hello: vec![],
+            compression: Compression::None,
+            heatshrink_window: 8,
+            heatshrink_lookahead: 4,
+            ping: vec![b'?'; 11],
+            abort: vec![],
+            input_prefix: b'!',
+        }
+    }
+}
+
+impl Protocol {
+    pub fn validate(&self) -> Result<()> {
+        if self.reset.is_empty() {
+            bail!("protocol.reset: must not be empty");
+        }
+        if self.flow_control == FlowControl::XonXoff {
+            if self.escape == XON || self.escape == XOFF {
+                bail!("protocol.escape: must not be XON (0x11) or XOFF (0x13)");
+            }
+            let special = [XON, XOFF, self.escape];
+            if special.contains(&self.input_prefix) {
+                bail!("protocol.input_prefix: must not be XON, XOFF or protocol.escape");
+            }
+            if self.reset.iter().any(|b| special.contains(b)) {
+                bail!("protocol.reset: must not contain XON, XOFF or protocol.escape");
+            }
+            if self.abort.iter().any(|b| special.contains(b)) {
+                bail!("protocol.abort: must not contain XON, XOFF or protocol.escape");
+            }
+        }
+        if self.flow_control == FlowControl::Chunked && self.chunk_bytes == 0 {
+            bail!("protocol.chunk_bytes: must be at least 1");
+        }
+        if self.compression != Compression::None && self.hello.is_empty() {
+            bail!("protocol.compression: needs protocol.hello to ask the firmware first");
+        }
+        if !(1..=16).contains(&self.heatshrink_window) {
+            bail!("protocol.heatshrink_window: must be 1 to 16");
+        }
+        if !(1..self.heatshrink_window).contains(&self.heatshrink_lookahead) {
+            bail!("protocol.heatshrink_lookahead: must be at least 1 and below the window");
+        }
+        Ok(())
+    }
+
+    // Puts `rows`, heatshrink-compressed, in `out` after their length, if
+    // that's smaller than they are.
+    pub fn compress(&self, rows: &[u8], out: &mut Vec<u8>) -> bool {
+        let Ok(config) = heatshrink::Config::new(self.heatshrink_window, self.heatshrink_lookahead)
+        else {
+            return false;
+        };
+        out.clear();
+        out.resize(rows.len().max(2), 0);
+        let len = match heatshrink::encode(rows, &mut out[2..], &config) {
+            Ok(compressed) if compressed.len() <= u16::MAX.into() => compressed.len(),
+            // Didn't fit in as much room as the rows take.
+            _ => return false,
+        };
+        if len + 2 >= rows.len() {
+            return false;
+        }
+        out[..2].copy_from_slice(&(len as u16).to_le_bytes());
+        out.truncate(len + 2);
+        true
+    }
+
+    // Appends chunk `index` of a frame, carrying `data`, to `out`.
+    pub fn chunk(&self, index: u16, data: &[u8], out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.chunk_start);
+        let start = out.len();
+        out.extend_from_slice(&index.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(data);
+        let crc = crc16(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    // What the reader has to unescape, if anything.
+    pub fn escape(&self) -> Option<u8> {
+        (self.flow_control == FlowControl::XonXoff).then_some(self.escape)
+    }
+
+    // Appends `data` to `out`, escaped if the flow control needs it.
+    pub fn stuff(&self, data: &[u8], out: &mut Vec<u8>) {
+        let Some(escape) = self.escape() else {
+            out.extend_from_slice(data);
+            return;
+        };
+        for &byte in data {
+            if byte == XON || byte == XOFF || byte == escape {
+                out.extend_from_slice(&[escape, byte ^ 0x20]);
+            } else {
+                out.push(byte);
+            }
+        }
+    }
+
+    // Appends one row of thresholded pixels to `out`, header and footer
+    // included.
+    pub fn pack_row(&self, pixels: impl Iterator<Item = bool>, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.row_header);
+        let (mut byte, mut bit) = (0u8, 0);
+        for on in pixels {
+            if on {
+                byte |= match self.bit_order {
+                    BitOrder::LsbFirst => 1 << bit,
+                    BitOrder::MsbFirst => 0x80 >> bit,
+                };
+            }
+            bit += 1;
+            if bit == 8 {
+                out.push(byte);
+                (byte, bit) = (0, 0);
+            }
+        }
+        if bit > 0 {
+            out.push(byte);
+        }
+        out.extend_from_slice(&self.row_footer);
+    }
+}
+
+// CRC-16/CCITT-FALSE, bit by bit as small firmware would have it.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+
+use crate::ingest::unpack_1bit;
+
+// Every frame starts with a four byte header:
+//
+//   b'F', kind, width, height
+//
+// where kind is b'G' for one grayscale byte per pixel or b'1' for packed
+// 1-bit pixels (LSB first, row-major, as in `unpack_1bit`). The header is
+// followed by exactly the number of pixel bytes the kind and size imply.
+//
+// Between frames there may also be a timing record, four bytes on its own:
+//
+//   b'T', millis (u16, little-endian), 0
+//
+// giving how long each following frame shows for, when played from a file.
+const FRAME_MAGIC: u8 = b'F';
+const TIMING_MAGIC: u8 = b'T';
+const KIND_GRAY: u8 = b'G';
+const KIND_1BIT: u8 = b'1';
+
+pub enum Record {
+    Frame(Vec<u8>),
+    Timing(Duration),
+}
+
+// Reads one frame and returns it as grayscale, or `None` on a clean end of
+// stream between frames. Timing records are skipped.
+pub fn read_frame(
+    input: &mut impl Read,
+    dims: (usize, usize),
+) -> Result<Option<Vec<u8>>> {
+    loop {
+        match read_record(input, dims)? {
+            Some(Record::Frame(frame)) => return Ok(Some(frame)),
+            Some(Record::Timing(_)) => {}
+            None => return Ok(None),
+        }
+    }
+}
+
+// Like `read_frame`, but with timing records too.
+pub fn read_record(input: &mut impl Read, dims: (usize, usize)) -> Result<Option<Record>> {
+    let mut header = [0u8; 4];
+    match input.read_exact(&mut header[..1]) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        r => r?,
+    }
+    input.read_exact(&mut header[1..])?;
+    let [magic, kind, w, h] = header;
+    if magic == TIMING_MAGIC {
+        let millis = u16::from_le_bytes([kind, w]);
+        if millis == 0 || h != 0 {
+            bail!("bad timing record");
+        }
+        return Ok(Some(Record::Timing(Duration::from_millis(millis as u64))));
+    }
+    if magic != FRAME_MAGIC {
+        bail!("bad frame magic {magic:#04x}");
+    }
+    let (w, h) = (w as usize, h as usize);
+    if (w, h) != dims {
+        bail!("frame is {w}x{h}, panel is {}x{}", dims.0, dims.1);
+    }
+    let frame = match kind {
+        KIND_GRAY => {
+            let mut buf = vec![0u8; w * h];
+            input.read_exact(&mut buf)?;
+            buf
+        }
+        KIND_1BIT => {
+            let mut buf = vec![0u8; (w * h).div_ceil(8)];
+            input.read_exact(&mut buf)?;
+            let mut frame = unpack_1bit(&buf);
+            frame.truncate(w * h);
+            frame
+        }
+        _ => bail!("unknown frame kind {kind:#04x}"),
+    };
+    Ok(Some(Record::Frame(frame)))
+}
+
+pub fn write_timing(output: &mut impl Write, per_frame: Duration) -> Result<()> {
+    let millis = u16::try_from(per_frame.as_millis()).ok().filter(|&ms| ms > 0);
+    let Some(millis) = millis else {
+        bail!("frames must show for 1ms to about a minute each");
+    };
+    let [lo, hi] = millis.to_le_bytes();
+    output.write_all(&[TIMING_MAGIC, lo, hi, 0])?;
+    Ok(())
+}
+
+// Writes a grayscale frame as packed 1-bit, cut at the same threshold the
+// panel uses.
+pub fn write_1bit(output: &mut impl Write, frame: &[u8], dims: (usize, usize)) -> Result<()> {
+    let (w, h) = dims;
+    assert!(frame.len() == (w * h));
+    let (Ok(w8), Ok(h8)) = (u8::try_from(w), u8::try_from(h)) else {
+        bail!("{w}x{h} is too big for a raw frame");
+    };
+    let mut packed = vec![0u8; (w * h).div_ceil(8)];
+    for (i, &p) in frame.iter().enumerate() {
+        if p > (u8::MAX / 2) {
+            packed[i / 8] |= 1 << (i % 8);
+        }
+    }
+    output.write_all(&[FRAME_MAGIC, KIND_1BIT, w8, h8])?;
+    output.write_all(&packed)?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+
+use crate::geo::{self, LatLon};
+
+// Legs shorter than this are merged into their neighbours before looking for
+// turns, so GPS jitter in a recorded track doesn't read as a zig-zag.
+const MIN_LEG_M: f64 = 10.0;
+
+pub struct Route {
+    pub points: Vec<LatLon>,
+}
+
+// A change of direction at `points[index]`, in degrees; positive is right.
+#[derive(Clone, Copy, Debug)]
+pub struct Turn {
+    pub index: usize,
+    pub angle: f64,
+}
+
+impl Route {
+    // GPX (track or route points) or GeoJSON (the first LineString found).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading route {}", path.display()))?;
+        let points = match path.extension().and_then(|e| e.to_str()) {
+            Some("gpx") => parse_gpx(&text)?,
+            Some("geojson" | "json") => parse_geojson(&text)?,
+            _ => bail!("unknown route format {}", path.display()),
+        };
+        // After merging, so a file of one point over and over isn't a route.
+        let points = simplify(points);
+        if points.len() < 2 {
+            bail!("route {} has fewer than two distinct points", path.display());
+        }
+        Ok(Self { points })
+    }
+
+    // Direction changes sharper than `min_angle` degrees.
+    pub fn turns(&self, min_angle: f64) -> Vec<Turn> {
+        self.points.windows(3)
+            .enumerate()
+            .filter_map(|(i, w)| {
+                let angle = geo::turn_angle(w[0].bearing(w[1]), w[1].bearing(w[2]));
+                (angle.abs() >= min_angle).then_some(Turn { index: i + 1, angle })
+            })
+            .collect()
+    }
+
+    // The leg (`points[i]` to `points[i + 1]`) nearest `pos`, searching from
+    // `from` onwards, how far along it `pos` is (0 to 1), and how far off it
+    // in metres.
+    pub fn nearest_leg(&self, pos: LatLon, from: usize) -> (usize, f64, f64) {
+        let mut best = (from, 0.0, f64::MAX);
+        for i in from..(self.points.len() - 1) {
+            let (ax, ay) = self.points[i].local_xy(pos);
+            let (bx, by) = self.points[i + 1].local_xy(pos);
+            let (dx, dy) = (bx - ax, by - ay);
+            let len2 = (dx * dx) + (dy * dy);
+            let t = if len2 == 0.0 {
+                0.0
+            } else {
+                (-(ax * dx) - (ay * dy)) / len2
+            }.clamp(0.0, 1.0);
+            let (px, py) = (ax + (t * dx), ay + (t * dy));
+            let dist = ((px * px) + (py * py)).sqrt();
+            if dist < best.2 {
+                best = (i, t, dist);
+            }
+        }
+        best
+    }
+
+    // Distance along the route from `t` of the way along leg `leg` to
+    // `points[to]`.
+    pub fn distance_along(&self, leg: usize, t: f64, to: usize) -> f64 {
+        let first = self.points[leg].distance(self.points[leg + 1]) * (1.0 - t);
+        first + self.points[(leg + 1)..=to.max(leg + 1)]
+            .windows(2)
+            .map(|w| w[0].distance(w[1]))
+            .sum::<f64>()
+    }
+}
+
+fn simplify(points: Vec<LatLon>) -> Vec<LatLon> {
+    let Some(&last) = points.last() else {
+        return points;
+    };
+    let mut out = vec![points[0]];
+    for p in points.into_iter().skip(1) {
+        if out.last().unwrap().distance(p) >= MIN_LEG_M {
+            out.push(p);
+        }
+    }
+    if *out.last().unwrap() != last {
+        out.push(last);
+    }
+    out
+}
+
+// No XML crate for the sake of two attributes: finds every `<trkpt>` and
+// `<rtept>` tag and reads its `lat` and `lon`.
+fn parse_gpx(text: &str) -> Result<Vec<LatLon>> {
+    let attr = |tag: &str, name: &str| -> Result<f64> {
+        let key = format!(" {name}=");
+        let start = tag.find(&key)
+            .ok_or_else(|| anyhow!("GPX point without {name}"))? + key.len();
+        let quote = tag[start..].chars().next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| anyhow!("bad GPX {name} attribute"))?;
+        let value = &tag[(start + 1)..];
+        let end = value.find(quote).ok_or_else(|| anyhow!("bad GPX {name} attribute"))?;
+        Ok(value[..end].parse()?)
+    };
+    let mut points = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[(start + 1)..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        if tag.starts_with("trkpt ") || tag.starts_with("rtept ") {
+            points.push(LatLon { lat: attr(tag, "lat")?, lon: attr(tag, "lon")? });
+        }
+        rest = &rest[end..];
+    }
+    Ok(points)
+}
+
+fn parse_geojson(text: &str) -> Result<Vec<LatLon>> {
+    fn find_line(v: &Value) -> Option<&Vec<Value>> {
+        match v.get("type")?.as_str()? {
+            "LineString" => v.get("coordinates")?.as_array(),
+            "MultiLineString" => v.get("coordinates")?.as_array()?.first()?.as_array(),
+            "Feature" => find_line(v.get("geometry")?),
+            "FeatureCollection" => v.get("features")?.as_array()?.iter().find_map(find_line),
+            _ => None,
+        }
+    }
+    let json: Value = serde_json::from_str(text)?;
+    let line = find_line(&json).ok_or_else(|| anyhow!("no LineString in GeoJSON"))?;
+    // GeoJSON positions are [lon, lat].
+    line.iter()
+        .map(|p| {
+            let lon = p.get(0).and_then(Value::as_f64);
+            let lat = p.get(1).and_then(Value::as_f64);
+            match (lat, lon) {
+                (Some(lat), Some(lon)) => Ok(LatLon { lat, lon }),
+                _ => Err(anyhow!("bad GeoJSON position {p}")),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_of_one_point_repeated_are_refused() {
+        let path = std::env::temp_dir().join("fett-helmet-same-point.gpx");
+        let point = r#"<trkpt lat="59.4384" lon="24.7426"></trkpt>"#;
+        fs::write(&path, format!("<gpx><trk><trkseg>{}</trkseg></trk></gpx>", point.repeat(5)))
+            .unwrap();
+        let result = Route::load(&path);
+        let _ = fs::remove_file(&path);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::VecDeque,
+    sync::OnceLock,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+// Flashes are counted over this long.
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    // Holds frames back until they can go without flashing too fast.
+    #[default]
+    Throttle,
+    // Drops them, leaving what's on the panel.
+    Refuse,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConfig {
+    pub enabled: bool,
+    // Flashes a second allowed, each a swing one way and back.
+    pub max_flash_hz: f64,
+    // How much of the panel's full brightness a frame has to change by on
+    // average to count as a swing.
+    pub min_change_percent: u8,
+    pub action: Action,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self { enabled: true, max_flash_hz: 3.0, min_change_percent: 20, action: Action::Throttle }
+    }
+}
+
+impl SafetyConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.5..=25.0).contains(&self.max_flash_hz) {
+            bail!("safety.max_flash_hz: must be from 0.5 to 25");
+        }
+        if !(1..=100).contains(&self.min_change_percent) {
+            bail!("safety.min_change_percent: must be from 1 to 100");
+        }
+        Ok(())
+    }
+
+    // Swings allowed in `WINDOW`.
+    fn max_swings(&self) -> usize {
+        (self.max_flash_hz * 2.0 * WINDOW.as_secs_f64()).floor() as usize
+    }
+}
+
+static SAFETY: OnceLock<SafetyConfig> = OnceLock::new();
+
+pub fn init(config: &SafetyConfig) {
+    if config.enabled {
+        let _ = SAFETY.set(*config);
+    } else {
+        println!("[safety] Not limiting flashing.");
+    }
+}
+
+// Watches the frames going to one panel for the whole of it strobing, and
+// slows or stops them when it does.
+#[derive(Default)]
+pub struct Limiter {
+    // The average brightness, from 0 to 1, of the last frame let through.
+    level: Option<f64>,
+    // When frames let through swung the brightness, oldest first.
+    swings: VecDeque<Instant>,
+    // When it last held back or dropped a frame, until a whole window goes
+    // by without it needing to.
+    intervened: Option<Instant>,
+}
+
+impl Limiter {
+    // Whether `frame` may go to the panel, once any wait for it is over.
+    pub fn admit(&mut self, frame: &[u8]) -> bool {
+        let Some(config) = SAFETY.get() else {
+            return true;
+        };
+        let level = frame.iter().map(|&p| f64::from(p)).sum::<f64>()
+            / (frame.len().max(1) as f64 * 255.0);
+        let swing = self.level
+            .is_some_and(|was| (level - was).abs() * 100.0 >= f64::from(config.min_change_percent));
+        if swing {
+            let now = Instant::now();
+            while self.swings.front().is_some_and(|&at| now - at >= WINDOW) {
+                self.swings.pop_front();
+            }
+            if self.swings.len() >= config.max_swings() {
+                if self.intervened.is_none() {
+                    let doing = match config.action {
+                        Action::Throttle => "slowing it down",
+                        Action::Refuse => "dropping frames",
+                    };
+                    println!("[safety] Flashing over {} Hz; {doing}...", config.max_flash_hz);
+                }
+                self.intervened = Some(now);
+                if config.action == Action::Refuse {
+                    return false;
+                }
+                let oldest = self.swings.pop_front().unwrap();
+                sleep((oldest + WINDOW).saturating_duration_since(now));
+            } else if self.intervened.is_some_and(|at| now - at >= WINDOW) {
+                println!("[safety] Flashing back under {} Hz.", config.max_flash_hz);
+                self.intervened = None;
+            }
+            self.swings.push_back(Instant::now());
+        }
+        self.level = Some(level);
+        true
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+
+use crate::mode;
+
+// Time-of-day rules choosing which mode the manager should be showing, e.g.
+//
+//   [schedule]
+//   default = "animation"
+//
+//   [[schedule.rule]]
+//   mode = "clock"
+//   from = "22:00"
+//   to = "07:00"
+//
+// Rules are checked in order and the first match wins. A rule whose `to` is
+// earlier than its `from` runs past midnight.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedule {
+    pub default: Option<String>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+    // How long a manual mode change holds off the schedule.
+    pub override_minutes: u64,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            default: None,
+            rules: Vec::new(),
+            override_minutes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub mode: String,
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+    // Days the rule starts on; every day if left out.
+    #[serde(default)]
+    pub days: Option<Vec<Weekday>>,
+}
+
+impl Rule {
+    fn matches(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let (in_window, start_day) = if self.from <= self.to {
+            (time >= self.from && time < self.to, now.weekday())
+        } else if time >= self.from {
+            (true, now.weekday())
+        } else {
+            // After midnight, the window belongs to the day it started on.
+            (time < self.to, now.weekday().pred())
+        };
+        in_window && self.days.as_ref().is_none_or(|days| days.contains(&start_day))
+    }
+}
+
+impl Schedule {
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.rules.is_empty()
+    }
+
+    pub fn override_duration(&self) -> Duration {
+        Duration::from_secs(self.override_minutes * 60)
+    }
+
+    // The mode the schedule wants at `now`, if any.
+    pub fn mode_at(&self, now: NaiveDateTime) -> Option<&str> {
+        self.rules.iter()
+            .find(|rule| rule.matches(now))
+            .map(|rule| rule.mode.as_str())
+            .or(self.default.as_deref())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(name) = &self.default {
+            if mode::lookup(name).is_none() {
+                bail!("schedule.default: unknown mode {name:?}");
+            }
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            if mode::lookup(&rule.mode).is_none() {
+                bail!("schedule.rule[{i}].mode: unknown mode {:?}", rule.mode);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    net::IpAddr,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::{
+    http::{Method, StatusCode},
+    path::FullPath,
+    reject::Reject,
+    Filter, Rejection, Reply,
+};
+
+use crate::http_limits;
+
+// The header a session's holder sends its token in.
+pub const HEADER: &str = "x-session";
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    // Lets a client claim the helmet with `POST /api/v1/session/acquire`,
+    // after which other clients' changes are turned down and the schedule
+    // is held off until it lets go.
+    pub enabled: bool,
+    // How long a session lasts after the holder's last request, in case it
+    // goes away without letting go.
+    pub timeout_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self { enabled: true, timeout_secs: 120 }
+    }
+}
+
+struct Holder {
+    owner: String,
+    address: Option<IpAddr>,
+    token: String,
+    expires: Instant,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.owner)?;
+        match self.address.filter(|address| address.to_string() != self.owner) {
+            Some(address) => write!(f, " ({address})"),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Session {
+    timeout: Duration,
+    holder: Mutex<Option<Holder>>,
+}
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+pub fn init(config: &SessionConfig) {
+    if !config.enabled {
+        return;
+    }
+    let timeout = Duration::from_secs(config.timeout_secs.max(1));
+    let _ = SESSION.set(Session { timeout, holder: Mutex::new(None) });
+}
+
+// Someone else has control.
+#[derive(Debug)]
+pub struct Busy(String);
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Busy {}
+
+impl Reject for Busy {}
+
+impl Busy {
+    fn held_by(holder: &Holder) -> Self {
+        let left = holder.expires.saturating_duration_since(Instant::now()).as_secs();
+        Busy(format!("{holder} has control for up to another {left}s"))
+    }
+}
+
+// Who has control, as `GET /api/v1/session` tells anyone.
+#[derive(Serialize, ToSchema)]
+pub struct SessionInfo {
+    pub owner: String,
+    pub address: Option<String>,
+    pub expires_in_secs: u64,
+}
+
+// What the holder gets on acquiring, to send in `x-session` from then on.
+#[derive(Serialize, ToSchema)]
+pub struct Acquired {
+    pub token: String,
+    pub expires_in_secs: u64,
+}
+
+// The holder, unless its session has run out.
+fn current(holder: &mut Option<Holder>) -> Option<&mut Holder> {
+    if holder.as_ref().is_some_and(|holder| Instant::now() >= holder.expires) {
+        println!("[session] {}'s session timed out.", holder.take().unwrap());
+    }
+    holder.as_mut()
+}
+
+// Whether anyone has control, so the schedule stays out of the way.
+pub fn held() -> bool {
+    SESSION.get().is_some_and(|session| current(&mut session.holder.lock().unwrap()).is_some())
+}
+
+pub fn info() -> Option<SessionInfo> {
+    let session = SESSION.get()?;
+    let mut holder = session.holder.lock().unwrap();
+    let holder = current(&mut holder)?;
+    Some(SessionInfo {
+        owner: holder.owner.clone(),
+        address: holder.address.map(|address| address.to_string()),
+        expires_in_secs: holder.expires.saturating_duration_since(Instant::now()).as_secs(),
+    })
+}
+
+// Gives control to `owner`, going by its address if it doesn't say, or
+// extends the session of a holder that sends its `token`. Fails with `Busy`
+// if someone else has it.
+pub fn acquire(
+    owner: Option<String>,
+    address: Option<IpAddr>,
+    token: Option<&str>,
+) -> Result<Acquired> {
+    let Some(session) = SESSION.get() else {
+        bail!("sessions are turned off in session.enabled");
+    };
+    let mut holder = session.holder.lock().unwrap();
+    let expires = Instant::now() + session.timeout;
+    if let Some(current) = current(&mut holder) {
+        if token != Some(current.token.as_str()) {
+            return Err(Busy::held_by(current).into());
+        }
+        current.expires = expires;
+        let token = current.token.clone();
+        return Ok(Acquired { token, expires_in_secs: session.timeout.as_secs() });
+    }
+    let owner = owner.or(address.map(|address| address.to_string()))
+        .unwrap_or_else(|| "unknown".to_owned());
+    let token = format!("{:032x}", rand::random::<u128>());
+    let new = Holder { owner, address, token: token.clone(), expires };
+    println!("[session] {new} has control.");
+    *holder = Some(new);
+    Ok(Acquired { token, expires_in_secs: session.timeout.as_secs() })
+}
+
+// Gives up control, if `token` is the holder's.
+pub fn release(token: Option<&str>) -> Result<()> {
+    let Some(session) = SESSION.get() else {
+        return Ok(());
+    };
+    let mut holder = session.holder.lock().unwrap();
+    match current(&mut holder) {
+        Some(current) if token != Some(current.token.as_str()) => {
+            Err(Busy::held_by(current).into())
+        }
+        Some(_) => {
+            println!("[session] {} let go.", holder.take().unwrap());
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+// For changes from the control socket, the bot and gRPC, which have no token
+// to show, so are turned down while anyone has control.
+pub fn ensure_free() -> Result<()> {
+    let Some(session) = SESSION.get() else {
+        return Ok(());
+    };
+    match current(&mut session.holder.lock().unwrap()) {
+        Some(current) => Err(Busy::held_by(current).into()),
+        None => Ok(()),
+    }
+}
+
+// Turns down requests that change something while another client has
+// control, and extends the holder's session with each of its own. Claiming
+// and letting go are left to their routes.
+pub fn check() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>(HEADER))
+        .and_then(|method: Method, path: FullPath, token: Option<String>| async move {
+            let Some(session) = SESSION.get() else {
+                return Ok(());
+            };
+            if !http_limits::mutating(&method) || path.as_str().starts_with("/api/v1/session/") {
+                return Ok(());
+            }
+            let mut holder = session.holder.lock().unwrap();
+            match current(&mut holder) {
+                Some(current) if token.as_deref() != Some(current.token.as_str()) => {
+                    Err(warp::reject::custom(Busy::held_by(current)))
+                }
+                Some(current) => {
+                    current.expires = Instant::now() + session.timeout;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        })
+        .untuple_one()
+}
+
+// A 409 in the API's envelope for a request `check` turned down, saying who
+// has control. Anything else is passed on.
+pub async fn rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    match rejection.find::<Busy>() {
+        Some(busy) => Ok(crate::reply_err(busy.to_string(), StatusCode::CONFLICT)),
+        None => Err(rejection),
+    }
+}
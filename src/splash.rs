@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{read_png_g, PANEL_DIMS};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplashConfig {
+    // Panel-sized PNGs. Without `boot` the panel shows whatever it had until
+    // the first mode draws; without `shutdown` it's left blank.
+    pub boot: Option<PathBuf>,
+    pub shutdown: Option<PathBuf>,
+}
+
+impl SplashConfig {
+    pub fn boot_frame(&self) -> Result<Option<Vec<u8>>> {
+        self.boot.as_ref().map(read_png_g).transpose()
+    }
+
+    // Falls back to blank if the image can't be read, as there's no
+    // later chance to show anything.
+    pub fn shutdown_frame(&self) -> Vec<u8> {
+        let blank = || vec![0; PANEL_DIMS.0 * PANEL_DIMS.1];
+        let Some(path) = &self.shutdown else {
+            return blank();
+        };
+        read_png_g(path).unwrap_or_else(|e| {
+            println!("[splash] Couldn't read {}: {e:#}", path.display());
+            blank()
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use crate::framebuffer::Framebuffer;
+
+// Small 1-bit pictures written out as rows of text, `#` for a lit pixel and
+// anything else for an unlit one, e.g.
+//
+//   const ARROW: Sprite = &[
+//       "..#..",
+//       ".###.",
+//       "#####",
+//   ];
+pub type Sprite = &'static [&'static str];
+
+pub fn size(sprite: &[impl AsRef<str>]) -> (usize, usize) {
+    let w = sprite.iter().map(|row| row.as_ref().len()).max().unwrap_or(0);
+    (w, sprite.len())
+}
+
+// Only lit pixels are drawn, so a sprite can go on top of other content.
+// Takes owned rows too, for sprites loaded at runtime.
+pub fn draw(fb: &mut Framebuffer, x: isize, y: isize, sprite: &[impl AsRef<str>]) {
+    for (dy, row) in sprite.iter().enumerate() {
+        for (dx, c) in row.as_ref().bytes().enumerate() {
+            if c == b'#' {
+                fb.set(x + dx as isize, y + dy as isize, true);
+            }
+        }
+    }
+}
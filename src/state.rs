@@ -0,0 +1,93 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::waypoint::Waypoint;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateConfig {
+    // Whether the server remembers what it was showing across restarts.
+    pub persist: bool,
+    pub path: PathBuf,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self { persist: true, path: PathBuf::from("state.json") }
+    }
+}
+
+// What the server picks up again after a restart. Inversion is config only,
+// and the panel has no brightness setting, so neither is here.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct State {
+    // The last mode started by hand or by the schedule.
+    pub mode: Option<String>,
+    // The last coordinates sent, and the map zoom last stepped to.
+    pub coords: Option<String>,
+    pub zoom: Option<u8>,
+    // Waypoints added over HTTP.
+    pub waypoints: Vec<Waypoint>,
+}
+
+static STATE: OnceLock<(PathBuf, Mutex<State>)> = OnceLock::new();
+
+// Loads the saved state and keeps saving changes to it for the rest of the
+// run. A missing or unreadable file just means starting afresh.
+pub fn init(config: &StateConfig) -> State {
+    if !config.persist {
+        return State::default();
+    }
+    let state = match load(&config.path) {
+        Ok(state) => state,
+        Err(e) => {
+            println!("[state] Not restoring {}: {e:#}", config.path.display());
+            State::default()
+        }
+    };
+    let _ = STATE.set((config.path.clone(), Mutex::new(state.clone())));
+    state
+}
+
+fn load(path: &Path) -> Result<State> {
+    if !path.exists() {
+        return Ok(State::default());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+pub fn get() -> State {
+    STATE.get().map(|(_, state)| state.lock().unwrap().clone()).unwrap_or_default()
+}
+
+// Changes the saved state, writing it out if that made a difference. Does
+// nothing unless `init` persisted it.
+pub fn update(change: impl FnOnce(&mut State)) {
+    let Some((path, state)) = STATE.get() else {
+        return;
+    };
+    let mut state = state.lock().unwrap();
+    let before = state.clone();
+    change(&mut state);
+    if *state != before {
+        if let Err(e) = save(path, &state) {
+            println!("[state] Saving {} failed: {e:#}", path.display());
+        }
+    }
+}
+
+fn save(path: &Path, state: &State) -> Result<()> {
+    // Written aside and renamed, so losing power mid-write can't corrupt it.
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, serde_json::to_vec_pretty(state)?)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use serde::Deserialize;
+
+use crate::geo::LatLon;
+
+const HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<gpx version=\"1.1\" creator=\"fett-helmet-pi\"",
+    " xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    "<trk>\n<trkseg>\n",
+);
+const FOOTER: &str = "</trkseg>\n</trk>\n</gpx>\n";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackConfig {
+    // Whether coordinates sent to the server are logged to `path`.
+    pub record: bool,
+    pub path: PathBuf,
+    // Coordinates sent sooner than this after the last logged point are
+    // left out.
+    pub interval_secs: u64,
+    // Once a track has this many points it's moved aside as
+    // `<name>.1.gpx`, and older ones are numbered up, down to `keep`.
+    pub max_points: usize,
+    pub keep: usize,
+}
+
+impl Default for TrackConfig {
+    fn default() -> Self {
+        Self {
+            record: false,
+            path: PathBuf::from("track.gpx"),
+            interval_secs: 5,
+            max_points: 20_000,
+            keep: 5,
+        }
+    }
+}
+
+// Appends points to a GPX file, keeping the closing tags at the end so the
+// file is complete between writes.
+struct Recorder {
+    config: TrackConfig,
+    file: File,
+    points: usize,
+    last: Option<Instant>,
+}
+
+static RECORDER: OnceLock<Mutex<Recorder>> = OnceLock::new();
+
+// Starts recording for the rest of the run, if configured to. Carries on
+// with the track left by the last run.
+pub fn init(config: &TrackConfig) -> Result<()> {
+    if !config.record {
+        return Ok(());
+    }
+    let recorder = Recorder::open(config.clone())?;
+    println!("[track] Recording to {}...", config.path.display());
+    let _ = RECORDER.set(Mutex::new(recorder));
+    Ok(())
+}
+
+// Logs a position, unless not recording or it's too soon after the last.
+pub fn record(at: LatLon) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    if let Err(e) = recorder.lock().unwrap().append(at) {
+        println!("[track] Recording {at} failed: {e:#}");
+    }
+}
+
+// The track recorded so far, or `None` if not recording.
+pub fn contents() -> Result<Option<Vec<u8>>> {
+    let Some(recorder) = RECORDER.get() else {
+        return Ok(None);
+    };
+    // Holding the lock keeps a point from being half-written meanwhile.
+    let recorder = recorder.lock().unwrap();
+    Ok(Some(fs::read(&recorder.config.path)?))
+}
+
+impl Recorder {
+    fn open(config: TrackConfig) -> Result<Self> {
+        let (file, points) = match start(&config.path)? {
+            Some(started) => started,
+            None => {
+                // Cut off part way through a point, e.g. by losing power.
+                println!("[track] {} is incomplete, moving it aside.", config.path.display());
+                rotate(&config)?;
+                start(&config.path)?.unwrap()
+            }
+        };
+        Ok(Self { config, file, points, last: None })
+    }
+
+    fn append(&mut self, at: LatLon) -> Result<()> {
+        let interval = Duration::from_secs(self.config.interval_secs);
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let point = format!(
+            "<trkpt lat=\"{}\" lon=\"{}\"><time>{time}</time></trkpt>\n",
+            at.lat, at.lon,
+        );
+        self.file.seek(SeekFrom::End(-(FOOTER.len() as i64)))?;
+        self.file.write_all(format!("{point}{FOOTER}").as_bytes())?;
+        self.file.flush()?;
+        self.points += 1;
+        if self.points >= self.config.max_points {
+            println!("[track] {} is full, starting another.", self.config.path.display());
+            rotate(&self.config)?;
+            (self.file, self.points) = start(&self.config.path)?.unwrap();
+        }
+        Ok(())
+    }
+}
+
+// Opens the track at `path` for appending, creating it if needed, and counts
+// its points. `None` if it doesn't end where a point can be added.
+fn start(path: &Path) -> Result<Option<(File, usize)>> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let mut existing = String::new();
+    file.read_to_string(&mut existing)?;
+    if existing.is_empty() {
+        file.write_all(format!("{HEADER}{FOOTER}").as_bytes())?;
+        return Ok(Some((file, 0)));
+    }
+    if !existing.ends_with(FOOTER) {
+        return Ok(None);
+    }
+    Ok(Some((file, existing.matches("<trkpt ").count())))
+}
+
+// Moves the track aside as the newest of the older ones, dropping the oldest.
+fn rotate(config: &TrackConfig) -> Result<()> {
+    let path = &config.path;
+    if config.keep == 0 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    let numbered = |n: usize| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{stem}.{n}.{}", ext.to_string_lossy()),
+            None => format!("{stem}.{n}"),
+        };
+        path.with_file_name(name)
+    };
+    for n in (1..config.keep).rev() {
+        if numbered(n).exists() {
+            fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(1))?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use crate::{
+    mode::Panel,
+    overlay::temporal::BAYER,
+    tween::{Easing, FRAME},
+};
+
+// How long after a change the first frame counts as showing it. Modes that
+// fetch something first can take a while.
+const ARMED_FOR: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    // Hard cuts.
+    #[default]
+    None,
+    // The new frame uncovered from left to right.
+    Wipe,
+    // The new frame sliding down from the top, pushing the old one off.
+    Roll,
+    // Pixels changing over in an ordered dither pattern, so the new frame
+    // shows through evenly.
+    Dissolve,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransitionConfig {
+    pub style: Style,
+    pub duration_millis: u64,
+    pub easing: Easing,
+}
+
+impl Default for TransitionConfig {
+    fn default() -> Self {
+        Self { style: Style::None, duration_millis: 300, easing: Easing::InOut }
+    }
+}
+
+impl TransitionConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(100..=2000).contains(&self.duration_millis) {
+            bail!("transition.duration_millis: must be from 100 to 2000");
+        }
+        Ok(())
+    }
+}
+
+// Plays a short sequence of frames between what's on the panel and the next
+// frame, once armed by a change of mode or content. Everything else goes
+// straight through.
+#[derive(Default)]
+pub struct Transitions {
+    config: TransitionConfig,
+    armed: Option<Instant>,
+}
+
+impl Transitions {
+    pub fn new(config: &TransitionConfig) -> Self {
+        Self { config: config.clone(), armed: None }
+    }
+
+    pub fn arm(&mut self) {
+        if self.config.style != Style::None {
+            self.armed = Some(Instant::now());
+        }
+    }
+
+    pub fn show(&mut self, panel: &mut dyn Panel, frame: Vec<u8>) -> Result<()> {
+        let Some(armed) = self.armed else {
+            return panel.show(frame);
+        };
+        let from = match panel.last_frame() {
+            // Resending what's there isn't the change, so it stays armed.
+            Some(from) if from == frame => return panel.show(frame),
+            Some(from) if armed.elapsed() < ARMED_FOR => from.to_vec(),
+            _ => {
+                self.armed = None;
+                return panel.show(frame);
+            }
+        };
+        self.armed = None;
+        // One step per frame the serial link can manage in the time.
+        let steps = (self.config.duration_millis / FRAME.as_millis() as u64).max(2) as u32;
+        let dims = panel.dims();
+        let started = Instant::now();
+        for step in 1..steps {
+            let progress = self.config.easing.apply(f64::from(step) / f64::from(steps));
+            panel.show(blend(self.config.style, &from, &frame, dims, progress))?;
+            sleep((started + FRAME * step).saturating_duration_since(Instant::now()));
+        }
+        panel.show(frame)
+    }
+}
+
+// The frame `progress` of the way from `from` to `to`.
+fn blend(style: Style, from: &[u8], to: &[u8], dims: (usize, usize), progress: f64) -> Vec<u8> {
+    let (w, h) = dims;
+    let mut out = vec![0; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            out[y * w + x] = match style {
+                Style::None => to[y * w + x],
+                Style::Wipe if (x as f64) < progress * w as f64 => to[y * w + x],
+                Style::Wipe => from[y * w + x],
+                Style::Roll => {
+                    let shift = (progress * h as f64) as usize;
+                    if y < shift {
+                        to[(y + h - shift) * w + x]
+                    } else {
+                        from[(y - shift) * w + x]
+                    }
+                }
+                Style::Dissolve => {
+                    let threshold = (BAYER[y % 4][x % 4] as f64 + 0.5) / 16.0;
+                    if threshold < progress { to[y * w + x] } else { from[y * w + x] }
+                }
+            };
+        }
+    }
+    out
+}
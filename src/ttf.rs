@@ -0,0 +1,208 @@
+use std::{fs, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use fontdue::FontSettings;
+use serde::Deserialize;
+
+use crate::{framebuffer::Framebuffer, hexfont::{self, HexFont}};
+
+// Hack Regular (MIT / Bitstream Vera licence, see fonts/Hack-Regular.txt),
+// so there's always something to draw big digits with.
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/Hack-Regular.ttf");
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontConfig {
+    // TTF or OTF file; the embedded font if left out.
+    pub path: Option<PathBuf>,
+    // Pixel height modes use for large text, unless they pick their own.
+    pub size: f32,
+    // Glyph coverage above which a pixel is lit.
+    pub threshold: u8,
+    // More scalable fonts, tried in order for characters the main one
+    // doesn't have (e.g. a CJK font).
+    pub fallbacks: Vec<PathBuf>,
+    // A GNU Unifont style .hex bitmap font, tried last.
+    pub unifont: Option<PathBuf>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            size: 24.0,
+            threshold: 0x80,
+            fallbacks: Vec::new(),
+            unifont: None,
+        }
+    }
+}
+
+// Where one character's glyph comes from.
+#[derive(Clone, Copy)]
+enum Source<'a> {
+    Scalable(&'a fontdue::Font),
+    Hex(&'a hexfont::Glyph),
+}
+
+// A scalable font, rasterized a glyph at a time and cut down to 1 bit. Each
+// character comes from the first font that has it.
+pub struct Font {
+    font: fontdue::Font,
+    fallbacks: Vec<fontdue::Font>,
+    unifont: Option<HexFont>,
+    pub size: f32,
+    threshold: u8,
+}
+
+fn parse_font(data: Vec<u8>, name: &str) -> Result<fontdue::Font> {
+    fontdue::Font::from_bytes(data, FontSettings::default())
+        .map_err(|e| anyhow!("invalid font {name}: {e}"))
+}
+
+impl Font {
+    pub fn load(config: &FontConfig) -> Result<Arc<Self>> {
+        let data = match &config.path {
+            Some(path) => fs::read(path)
+                .with_context(|| format!("reading font {}", path.display()))?,
+            None => DEFAULT_FONT.to_vec(),
+        };
+        let font = parse_font(data, "")?;
+        let fallbacks = config.fallbacks.iter()
+            .map(|path| {
+                let data = fs::read(path)
+                    .with_context(|| format!("reading font {}", path.display()))?;
+                parse_font(data, &path.display().to_string())
+            })
+            .collect::<Result<_>>()?;
+        let unifont = config.unifont.as_ref().map(HexFont::load).transpose()?;
+        Ok(Arc::new(Self {
+            font,
+            fallbacks,
+            unifont,
+            size: config.size,
+            threshold: config.threshold,
+        }))
+    }
+
+    fn source(&self, c: char) -> Source<'_> {
+        if let Some(font) = std::iter::once(&self.font)
+            .chain(&self.fallbacks)
+            .find(|font| font.has_glyph(c))
+        {
+            return Source::Scalable(font);
+        }
+        match self.unifont.as_ref().and_then(|hex| hex.glyph(c)) {
+            Some(glyph) => Source::Hex(glyph),
+            // The main font's missing-glyph box.
+            None => Source::Scalable(&self.font),
+        }
+    }
+
+    // Kerning only makes sense within one font.
+    fn kern(&self, prev: Option<char>, c: char, size: f32) -> f32 {
+        let Some(prev) = prev else {
+            return 0.0;
+        };
+        match (self.source(prev), self.source(c)) {
+            (Source::Scalable(a), Source::Scalable(b)) if std::ptr::eq(a, b) => {
+                a.horizontal_kern(prev, c, size).unwrap_or(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn advance(&self, c: char, size: f32) -> f32 {
+        match self.source(c) {
+            Source::Scalable(font) => font.metrics(c, size).advance_width,
+            Source::Hex(glyph) => glyph.width as f32 * hex_scale(size),
+        }
+    }
+
+    // Distance from the top of a line to its baseline, and the full line
+    // height, at `size` pixels.
+    fn line_metrics(&self, size: f32) -> (f32, f32) {
+        match self.font.horizontal_line_metrics(size) {
+            Some(m) => (m.ascent, m.ascent - m.descent),
+            None => (size, size),
+        }
+    }
+
+    // Width and height `text` takes up on one line.
+    pub fn measure(&self, text: &str, size: f32) -> (usize, usize) {
+        let mut width = 0.0;
+        let mut prev = None;
+        for c in text.chars() {
+            width += self.kern(prev, c, size) + self.advance(c, size);
+            prev = Some(c);
+        }
+        (width.ceil() as usize, self.line_metrics(size).1.ceil() as usize)
+    }
+
+    // Draws one line of `text` with its top-left corner at (x, y). Only lit
+    // pixels are drawn.
+    pub fn draw(&self, fb: &mut Framebuffer, x: isize, y: isize, text: &str, size: f32) {
+        let baseline = y as f32 + self.line_metrics(size).0;
+        let mut pen = x as f32;
+        let mut prev = None;
+        for c in text.chars() {
+            pen += self.kern(prev, c, size);
+            match self.source(c) {
+                Source::Scalable(font) => {
+                    let (metrics, coverage) = font.rasterize(c, size);
+                    let left = pen.round() as isize + metrics.xmin as isize;
+                    let top = (baseline - metrics.height as f32 - metrics.ymin as f32)
+                        .round() as isize;
+                    for (i, &alpha) in coverage.iter().enumerate() {
+                        if alpha > self.threshold {
+                            let (gx, gy) = (i % metrics.width, i / metrics.width);
+                            fb.set(left + gx as isize, top + gy as isize, true);
+                        }
+                    }
+                }
+                Source::Hex(glyph) => draw_hex(fb, pen, baseline, glyph, size),
+            }
+            pen += self.advance(c, size);
+            prev = Some(c);
+        }
+    }
+
+    // The largest size, no bigger than `max`, at which `text` fits in
+    // `width` pixels.
+    pub fn fit(&self, text: &str, width: usize, max: f32) -> f32 {
+        let (w, _) = self.measure(text, max);
+        if w <= width || w == 0 {
+            return max;
+        }
+        // Proportional first, then down a pixel at a time for whatever
+        // hinting and kerning didn't scale with it.
+        let mut size = (max * width as f32 / w as f32).floor();
+        while size > 1.0 && self.measure(text, size).0 > width {
+            size -= 1.0;
+        }
+        size
+    }
+}
+
+// Bitmap glyphs are drawn at 16 pixels to the em, like a scalable font at
+// size 16.
+fn hex_scale(size: f32) -> f32 {
+    size / hexfont::HEIGHT as f32
+}
+
+// Nearest-neighbour scaled, so it's pixel-exact at size 16.
+fn draw_hex(fb: &mut Framebuffer, pen: f32, baseline: f32, glyph: &hexfont::Glyph, size: f32) {
+    let scale = hex_scale(size);
+    let left = pen.round() as isize;
+    let top = (baseline - (hexfont::ASCENT as f32 * scale)).round() as isize;
+    let w = (glyph.width as f32 * scale).round() as usize;
+    let h = (hexfont::HEIGHT as f32 * scale).round() as usize;
+    for y in 0..h {
+        for x in 0..w {
+            let (gx, gy) = ((x as f32 / scale) as usize, (y as f32 / scale) as usize);
+            if glyph.is_set(gx.min(glyph.width - 1), gy.min(hexfont::HEIGHT - 1)) {
+                fb.set(left + x as isize, top + y as isize, true);
+            }
+        }
+    }
+}
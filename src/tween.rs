@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+// How often something moving wants redrawing: about as fast as frames get
+// over the serial link.
+pub const FRAME: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    // Starting slowly.
+    In,
+    // Slowing to a stop, which suits most things arriving somewhere.
+    Out,
+    InOut,
+}
+
+impl Easing {
+    // How far along the way something is, from 0 to 1, after `t` of its time.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::In => t * t * t,
+            Easing::Out => 1.0 - (1.0 - t).powi(3),
+            Easing::InOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::InOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+        }
+    }
+}
+
+// A number easing towards a target over a set time, for positions and
+// values that shouldn't jump: a banner sliding in, a gauge sweeping round.
+// Whoever draws it asks for ticks every `FRAME` while it's moving.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween {
+    from: f64,
+    to: f64,
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Tween {
+    // At rest at `value`.
+    pub fn new(value: f64, duration: Duration, easing: Easing) -> Self {
+        Self { from: value, to: value, start: Instant::now(), duration, easing }
+    }
+
+    // Heads for `target` from wherever it is now, taking the full duration
+    // again. Aiming at the same target doesn't restart anything.
+    pub fn to(&mut self, target: f64) {
+        if target == self.to {
+            return;
+        }
+        let now = Instant::now();
+        self.from = self.value_at(now);
+        self.to = target;
+        self.start = now;
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value_at(Instant::now())
+    }
+
+    pub fn value_at(&self, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return self.to;
+        }
+        let t = now.saturating_duration_since(self.start).as_secs_f64()
+            / self.duration.as_secs_f64();
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.from != self.to && self.start.elapsed() < self.duration
+    }
+
+    // When to draw it next, or `None` once it's at rest.
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.is_moving().then(|| Instant::now() + FRAME)
+    }
+}
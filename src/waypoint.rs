@@ -0,0 +1,120 @@
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{geo::LatLon, state};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Waypoint {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    // Overrides `waypoints.radius_m` for this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius_m: Option<f64>,
+}
+
+impl Waypoint {
+    pub fn at(&self) -> LatLon {
+        LatLon { lat: self.lat, lon: self.lon }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            bail!("empty name");
+        }
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lon) {
+            bail!("{:?}: {},{} is off the map", self.name, self.lat, self.lon);
+        }
+        if self.radius_m.is_some_and(|r| r <= 0.0) {
+            bail!("{:?}: radius_m must be positive", self.name);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WaypointConfig {
+    // Whether coming within `radius_m` of a waypoint pops up its name.
+    pub alert: bool,
+    pub radius_m: f64,
+    pub alert_secs: u64,
+    pub point: Vec<Waypoint>,
+}
+
+impl Default for WaypointConfig {
+    fn default() -> Self {
+        Self { alert: true, radius_m: 50.0, alert_secs: 8, point: vec![] }
+    }
+}
+
+impl WaypointConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.radius_m <= 0.0 {
+            bail!("waypoints.radius_m: must be positive");
+        }
+        for (i, point) in self.point.iter().enumerate() {
+            if let Err(e) = point.validate() {
+                bail!("waypoints.point[{i}]: {e}");
+            }
+            if self.point[..i].iter().any(|p| p.name == point.name) {
+                bail!("waypoints.point[{i}]: {:?} is defined twice", point.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Waypoints from the config, which stay put, and ones added over HTTP, which
+// are kept with the rest of the saved state.
+struct Store {
+    config: Vec<Waypoint>,
+    added: Vec<Waypoint>,
+}
+
+static STORE: Mutex<Store> = Mutex::new(Store { config: vec![], added: vec![] });
+
+// `added` is what was added over HTTP before the last restart.
+pub fn init(config: &WaypointConfig, added: Vec<Waypoint>) {
+    let mut store = STORE.lock().unwrap();
+    store.config = config.point.clone();
+    store.added = added;
+}
+
+pub fn list() -> Vec<Waypoint> {
+    let store = STORE.lock().unwrap();
+    store.config.iter().chain(&store.added).cloned().collect()
+}
+
+// Adds a waypoint, or moves the added one with the same name.
+pub fn add(waypoint: Waypoint) -> Result<()> {
+    waypoint.validate()?;
+    let mut store = STORE.lock().unwrap();
+    if store.config.iter().any(|p| p.name == waypoint.name) {
+        bail!("{:?} is defined in the config", waypoint.name);
+    }
+    store.added.retain(|p| p.name != waypoint.name);
+    store.added.push(waypoint);
+    let added = store.added.clone();
+    state::update(|state| state.waypoints = added);
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    let mut store = STORE.lock().unwrap();
+    if store.config.iter().any(|p| p.name == name) {
+        bail!("{name:?} is defined in the config");
+    }
+    let before = store.added.len();
+    store.added.retain(|p| p.name != name);
+    if store.added.len() == before {
+        bail!("no waypoint {name:?}");
+    }
+    let added = store.added.clone();
+    state::update(|state| state.waypoints = added);
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::{
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use crossbeam_channel::{bounded, Sender, TrySendError};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::events::{self, HelmetEvent};
+
+// Events waiting to go to one URL while it's being retried. Any more are
+// dropped, rather than piling up through a long outage.
+const QUEUE: usize = 32;
+
+// However many retries there are, none waits longer than this.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Mode,
+    Error,
+    LowBattery,
+    SerialDisconnected,
+}
+
+impl WebhookEvent {
+    fn of(event: &HelmetEvent) -> Option<Self> {
+        match event {
+            HelmetEvent::Mode { .. } => Some(WebhookEvent::Mode),
+            HelmetEvent::Error { .. } => Some(WebhookEvent::Error),
+            HelmetEvent::LowBattery { .. } => Some(WebhookEvent::LowBattery),
+            HelmetEvent::SerialDisconnected { .. } => Some(WebhookEvent::SerialDisconnected),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    pub hook: Vec<Webhook>,
+    // A failed POST is tried again this many times, waiting `backoff_secs`
+    // and then twice as long each time.
+    pub retries: u32,
+    pub backoff_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self { hook: vec![], retries: 5, backoff_secs: 2, timeout_secs: 10 }
+    }
+}
+
+impl WebhooksConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.timeout_secs == 0 {
+            bail!("webhooks.timeout_secs: must be above 0");
+        }
+        for (i, hook) in self.hook.iter().enumerate() {
+            if !(hook.url.starts_with("http://") || hook.url.starts_with("https://")) {
+                bail!("webhooks.hook[{i}].url: must be an http or https URL");
+            }
+            if hook.events.is_empty() {
+                bail!("webhooks.hook[{i}].events: must not be empty");
+            }
+        }
+        Ok(())
+    }
+}
+
+// POSTs events to the configured URLs as JSON, as they're sent on
+// GET /api/v1/events. Each URL gets a thread of its own, so one that's down
+// only holds up its own events.
+pub fn spawn_notifier(config: &WebhooksConfig) {
+    if config.hook.is_empty() {
+        return;
+    }
+    let (_, mut updates) = events::subscribe();
+    let hooks: Vec<(Webhook, Sender<String>)> = config.hook.iter()
+        .map(|hook| {
+            let (tx, rx) = bounded::<String>(QUEUE);
+            let (url, config) = (hook.url.clone(), config.clone());
+            spawn(move || {
+                for body in rx {
+                    post(&url, &body, &config);
+                }
+            });
+            (hook.clone(), tx)
+        })
+        .collect();
+    spawn(move || loop {
+        let event = match updates.blocking_recv() {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                println!("[webhook] Fell behind, missed {missed} events.");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let Some(kind) = WebhookEvent::of(&event) else {
+            continue;
+        };
+        let body = serde_json::to_string(&event).unwrap();
+        for (hook, tx) in hooks.iter().filter(|(hook, _)| hook.events.contains(&kind)) {
+            if let Err(TrySendError::Full(_)) = tx.try_send(body.clone()) {
+                println!("[webhook] {} is backed up, dropping a {kind:?} event.", hook.url);
+            }
+        }
+    });
+}
+
+fn post(url: &str, body: &str, config: &WebhooksConfig) {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(config.timeout_secs)))
+        .build()
+        .new_agent();
+    let mut backoff = Duration::from_secs(config.backoff_secs);
+    for attempt in 0..=config.retries {
+        let result = agent.post(url)
+            .header("Content-Type", "application/json")
+            .send(body);
+        let e = match result {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        if attempt == config.retries {
+            println!("[webhook] POST to {url} failed, giving up: {e}");
+            return;
+        }
+        println!("[webhook] POST to {url} failed, retrying in {backoff:?}: {e}");
+        sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+// The order the panel's shift registers expect pixels in, relative to the
+// rotated frame read row by row from the top left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelOrder {
+    #[default]
+    RowMajor,
+    // Every other row runs right to left, starting with the second.
+    Serpentine,
+    // Top to bottom, then left to right.
+    ColumnMajor,
+    // Whatever `lut` says.
+    Lut,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WiringConfig {
+    pub order: PixelOrder,
+    // For `order = "lut"`: a file of whitespace-separated frame indices, the
+    // n-th being the pixel sent n-th.
+    pub lut: Option<PathBuf>,
+}
+
+// Reorders frames between rotation and bit packing.
+pub struct Wiring {
+    // Frame index of each pixel in send order; `None` sends them as they are.
+    lut: Option<Vec<usize>>,
+}
+
+impl Wiring {
+    // `dims` are those of the rotated frame.
+    pub fn new(config: &WiringConfig, dims: (usize, usize)) -> Result<Self> {
+        let (w, h) = dims;
+        let lut = match config.order {
+            PixelOrder::RowMajor => None,
+            PixelOrder::Serpentine => Some(
+                (0..w * h)
+                    .map(|i| {
+                        let (x, y) = (i % w, i / w);
+                        if y % 2 == 1 { (y * w) + (w - 1 - x) } else { i }
+                    })
+                    .collect(),
+            ),
+            PixelOrder::ColumnMajor => {
+                Some((0..w * h).map(|i| ((i % h) * w) + (i / h)).collect())
+            }
+            PixelOrder::Lut => {
+                let Some(path) = &config.lut else {
+                    bail!("wiring.lut: needed for order = \"lut\"");
+                };
+                let lut = load_lut(path, w * h)
+                    .with_context(|| format!("wiring.lut: {}", path.display()))?;
+                Some(lut)
+            }
+        };
+        if config.order != PixelOrder::RowMajor {
+            println!("[wiring] Sending pixels in {:?} order.", config.order);
+        }
+        Ok(Self { lut })
+    }
+
+    // Reorders `frame` into `out`, reusing its allocation.
+    pub fn apply(&self, frame: &[u8], out: &mut Vec<u8>) {
+        out.clear();
+        match &self.lut {
+            Some(lut) => out.extend(lut.iter().map(|&i| frame[i])),
+            None => out.extend_from_slice(frame),
+        }
+    }
+}
+
+fn load_lut(path: &Path, len: usize) -> Result<Vec<usize>> {
+    let text = fs::read_to_string(path)?;
+    let lut = text
+        .split_whitespace()
+        .map(|word| word.parse::<usize>().with_context(|| format!("bad index {word:?}")))
+        .collect::<Result<Vec<_>>>()?;
+    if lut.len() != len {
+        bail!("has {} indices, the panel {len} pixels", lut.len());
+    }
+    if let Some(i) = lut.iter().find(|&&i| i >= len) {
+        bail!("index {i} is past the last pixel");
+    }
+    Ok(lut)
+}